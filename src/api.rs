@@ -1,21 +1,979 @@
 
 // external imports
 //use std::io::{stdout, Write};
-use std::sync::RwLock;
-use curl::easy::{Easy, List};
-use std::str::from_utf8 as str_from_utf8;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::mem;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use curl;
+use curl::easy::{Easy, Easy2, List};
 use serde_json;
-use serde_json::{Value, Error};
+use serde_json::Value;
+use serde::de::DeserializeOwned;
+
+// submodules, re-exported so everything is reachable as api::*
+mod account;
+mod auth;
+mod batch;
+#[cfg(feature = "async")]
+mod future;
+mod listing;
+mod message;
+mod moderation;
+mod multi;
+mod parallel;
+mod request;
+mod retry;
+mod stream;
+mod subreddit;
+mod user;
+pub use self::account::*;
+pub use self::auth::*;
+pub use self::batch::*;
+#[cfg(feature = "async")]
+pub use self::future::*;
+pub use self::listing::*;
+pub use self::message::*;
+pub use self::moderation::*;
+pub use self::multi::*;
+pub use self::parallel::*;
+pub use self::request::*;
+pub use self::retry::*;
+pub use self::stream::*;
+pub use self::subreddit::*;
+pub use self::user::*;
 
 // internal imports
-use cli::*;
+use config::*;
+use error::{ApiError, RedditError};
+use models::{json_shape, Account, Comment, FromChild, Listing, SubredditInfo, Thing};
+use transport::{log_request, log_response, report_complete, CurlTransport, PreparedRequest,
+  RawResponse, Transport};
 
 pub mod prelude {
   pub use api::{Rreq, Rdata, Initializer, Request};
+  pub use error::RedditError;
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Path level request helpers
+// Everything that talks to reddit funnels through these
+
+/// Generates the full uri for a path such as "/r/rust/top.json", adding
+/// any query parameters switched on through args and finally handing the
+/// path to args.path_rewrite, if set. args.path_prefix goes in front of
+/// the finished path.
+pub fn gen_request_uri(path: &str, args: &Args) -> String {
+  prefixed_url(&args.base_url, &normalize_path(path, args), args)
+}
+
+/// Same as gen_request_uri, but against args.oauth_url
+pub fn gen_oauth_uri(path: &str, args: &Args) -> String {
+  prefixed_url(&args.oauth_url, &normalize_path(path, args), args)
+}
+
+/// host, then args.path_prefix, then path as given; every url this crate
+/// requests is put together here
+pub(crate) fn prefixed_url(host: &str, path: &str, args: &Args) -> String {
+  match args.path_prefix.as_ref().map(|p| p.trim_matches('/')) {
+    Some(prefix) if !prefix.is_empty() => format!("{}/{}/{}",
+      host.trim_end_matches('/'), prefix, path.trim_start_matches('/')),
+    _ => format!("{}{}", host, path),
+  }
+}
+
+fn normalize_path(path: &str, args: &Args) -> String {
+  let mut params = QueryParams::new();
+  if args.sr_detail {
+    params.set("sr_detail", "1");
+  }
+  if args.show_all {
+    params.set("show", "all");
+  }
+  if args.raw_json {
+    params.set("raw_json", "1");
+  }
+  let mut path = params.apply_to(path);
+  if let Some(ref rewrite) = args.path_rewrite {
+    path = rewrite(&path);
+  }
+  path
+}
+
+/// set once the missing User-Agent warning has been logged
+static WARNED_NO_UA: AtomicBool = AtomicBool::new(false);
+
+/// The warning for requests without a User-Agent, which reddit throttles
+/// hard; None when one is set or args.suppress_ua_warning is on
+pub(crate) fn missing_ua_warning(args: &Args) -> Option<&'static str> {
+  let has_ua = args.user_agent.is_some()
+    || args.headers.iter().any(|h| h.0.eq_ignore_ascii_case("User-Agent"));
+  if has_ua || args.suppress_ua_warning {
+    return None;
+  }
+  Some("sending requests without a User-Agent, reddit will throttle them; \
+    set one or use Args::allow_no_user_agent")
+}
+
+/// The header pairs in args, in order, followed by an Accept header for
+/// args.accept unless the pairs already carry one. args.user_agent, when
+/// set, replaces every User-Agent in the pairs.
+pub fn header_pairs(args: &Args) -> Vec<(String, String)> {
+  if let Some(warning) = missing_ua_warning(args) {
+    if !WARNED_NO_UA.swap(true, Ordering::SeqCst) {
+      warn!("{}", warning);
+    }
+  }
+
+  let mut pairs = args.headers.clone();
+  if let Some(ref agent) = args.user_agent {
+    pairs.retain(|h| !h.0.eq_ignore_ascii_case("User-Agent"));
+    pairs.insert(0, ("User-Agent".to_owned(), agent.clone()));
+  }
+
+  let has_accept = args.headers.iter().any(|h| h.0.eq_ignore_ascii_case("Accept"));
+  if !has_accept && !args.accept.is_empty() {
+    pairs.push(("Accept".to_owned(), args.accept.clone()));
+  }
+  pairs
+}
+
+/// Generates a curl::easy::List from header_pairs
+pub fn gen_headers(args: &Args) -> Result<List, RedditError> {
+  header_list(&header_pairs(args))
+}
+
+/// formats header pairs as the "Name: value" lines curl expects, names
+/// and values trimmed; a line curl refuses, e.g. one with a NUL byte in
+/// it, is an error
+pub fn header_list(pairs: &[(String, String)]) -> Result<List, RedditError> {
+  let mut list = List::new();
+  for (name, value) in pairs {
+    list.append(&format!("{}: {}", name.trim(), value.trim()))?;
+  }
+  Ok(list)
+}
+
+/// Takes a formatted curl struct and generates output from a query
+/// sending it back to the caller as a string of JSON
+///
+/// This is the bare curl end of a query. Everything around it, headers,
+/// status handling and parsing, runs the same over a Transport, see
+/// path_query_with and transport::MockTransport for testing without the
+/// network.
+///
+pub fn get_output_from_transfer(easy : &mut Easy) -> Result<String, RedditError> {
+  Ok(get_response_from_transfer(easy)?.body)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Response
+/// The parts of an http response the request helpers look at
+#[derive(Clone, Debug, Default)]
+pub struct Response {
+  pub status : u32,
+  pub headers : Vec<(String, String)>,
+  /// the header lines byte for byte, status line and line endings included;
+  /// only filled in when asked for with args.capture_raw_headers
+  pub raw_headers : Vec<Vec<u8>>,
+  /// where the request ended up after any redirects
+  pub effective_url : String,
+  pub body : String,
+}
+
+impl Response {
+  /// case-insensitive lookup of the first header with this name
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self.headers.iter()
+      .find(|h| h.0.eq_ignore_ascii_case(name))
+      .map(|h| h.1.as_str())
+  }
+}
+
+/// Collects a response body as raw bytes. curl hands the body over in
+/// chunks that can end in the middle of a multi-byte character, so nothing
+/// is decoded until the transfer is over.
+#[derive(Debug, Default)]
+pub(crate) struct BodyBuffer {
+  bytes : Vec<u8>,
+}
+
+impl BodyBuffer {
+  /// appends one chunk, returning how many bytes were taken as curl expects
+  pub(crate) fn write(&mut self, data: &[u8]) -> usize {
+    self.bytes.extend_from_slice(data);
+    data.len()
+  }
+
+  pub(crate) fn into_string(self) -> Result<String, RedditError> {
+    String::from_utf8(self.bytes).map_err(|err| RedditError::Utf8(err.utf8_error()))
+  }
+}
+
+/// Like get_output_from_transfer, but keeps the status and headers too.
+/// When redirects are followed only the final response's headers are kept.
+pub fn get_response_from_transfer(easy : &mut Easy) -> Result<Response, RedditError> {
+  transfer_response(easy, false, &[], None)
+}
+
+/// get_response_from_transfer honoring args.capture_raw_headers,
+/// args.cancel and args.on_complete. Transfers set up by hand rather than
+/// through a Transport only report their method and url to on_complete.
+pub(crate) fn response_for(easy : &mut Easy, method : &str, args : &Args)
+  -> Result<Response, RedditError>
+{
+  response_with_upload(easy, method, args, None)
+}
+
+/// response_for with a request body handed to curl from its read callback
+/// as the transfer asks for it, see transfer_response
+fn response_with_upload(easy : &mut Easy, method : &str, args : &Args, upload : Option<&[u8]>)
+  -> Result<Response, RedditError>
+{
+  let url = easy.effective_url()?.unwrap_or_default().to_owned();
+  log_request(method, &url, None, args);
+  let started = Instant::now();
+  let cancel: Vec<&AtomicBool> = args.cancel.as_deref().into_iter().collect();
+  let result = transfer_response(easy, args.capture_raw_headers, &cancel, upload);
+  log_response(method, &url,
+    result.as_ref().map(|response| (response.status, response.body.as_bytes())),
+    started.elapsed(), args);
+  if args.on_complete.is_none() {
+    return result;
+  }
+
+  let request = PreparedRequest {
+    method : method.to_owned(),
+    url : match result {
+      Ok(ref response) => response.effective_url.clone(),
+      Err(_) => easy.effective_url()?.unwrap_or_default().to_owned(),
+    },
+    ..PreparedRequest::default()
+  };
+  let mut raw_headers = Vec::new();
+  let result = result.map(|mut response| {
+    raw_headers = mem::take(&mut response.raw_headers);
+    RawResponse::from(response)
+  });
+  report_complete(args, &request, &result, started.elapsed());
+
+  let mut response = result?.into_response()?;
+  response.raw_headers = raw_headers;
+  Ok(response)
+}
+
+/// Runs a transfer, keeping the header lines as received when capture_raw
+/// is set. The cancel flags are checked from curl's progress callback,
+/// which runs several times a second even while waiting on a slow server;
+/// any one of them being set stops the transfer. An
+/// upload is read from in place by curl's read callback; the handle should
+/// already be told its size with post_field_size.
+pub(crate) fn transfer_response(easy : &mut Easy, capture_raw : bool,
+  cancel : &[&AtomicBool], upload : Option<&[u8]>) -> Result<Response, RedditError>
+{
+  let mut body = BodyBuffer::default();
+  let mut headers = Vec::new();
+  let mut raw_headers = Vec::new();
+  easy.progress(!cancel.is_empty())?;
+  {
+    let mut transfer = easy.transfer();
+
+    transfer.write_function(|data| Ok(body.write(data)))?;
+
+    transfer.header_function(|raw| {
+        let line = String::from_utf8_lossy(raw);
+        if line.starts_with("HTTP/") {
+          headers.clear();
+          raw_headers.clear();
+        } else if let Some((name, value)) = line.split_once(':') {
+          headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+        if capture_raw {
+          raw_headers.push(raw.to_vec());
+        }
+        true
+        })?;
+
+    if !cancel.is_empty() {
+      transfer.progress_function(|_, _, _, _| !is_cancelled(cancel))?;
+    }
+    if let Some(mut upload) = upload {
+      // reading from a slice never fails, it just runs dry
+      transfer.read_function(move |into| Ok(upload.read(into).unwrap_or(0)))?;
+    }
+
+    let performed = transfer.perform();
+    if let Err(err) = performed {
+      if err.is_aborted_by_callback() && is_cancelled(cancel) {
+        return Err(RedditError::Cancelled);
+      }
+      return Err(err.into());
+    }
+  }
+
+  Ok(Response {
+    status : easy.response_code()?,
+    headers,
+    raw_headers,
+    effective_url : easy.effective_url()?.unwrap_or_default().to_owned(),
+    body : body.into_string()?,
+  })
+}
+
+fn is_cancelled(cancel: &[&AtomicBool]) -> bool {
+  cancel.iter().any(|flag| flag.load(Ordering::SeqCst))
+}
+
+/// Fails with RedditError::Blocked when the response is a Cloudflare
+/// challenge page rather than anything from reddit; that usually means the
+/// User-Agent looks like a bot or requests are coming too fast
+pub fn check_blocked(response: &Response) -> Result<(), RedditError> {
+  let cloudflare = response.header("Server")
+    .is_some_and(|server| server.eq_ignore_ascii_case("cloudflare"));
+  let challenge = ["cf-chl", "cf_chl", "challenge-platform", "cf-browser-verification"]
+    .iter()
+    .any(|marker| response.body.contains(marker));
+
+  if cloudflare && challenge && (response.status == 403 || response.status == 503) {
+    return Err(RedditError::Blocked { reason : "cloudflare_challenge".to_owned() });
+  }
+  Ok(())
+}
+
+/// Fails with RedditError::UnexpectedContentType unless the response was
+/// served as JSON; reddit's HTML error pages sometimes come with a 200.
+/// A response without a Content-Type is given the benefit of the doubt.
+pub fn check_content_type(content_type: Option<&str>) -> Result<(), RedditError> {
+  match content_type {
+    Some(got) if !got.to_lowercase().contains("json") =>
+      Err(RedditError::UnexpectedContentType { got : got.to_owned() }),
+    _ => Ok(()),
+  }
+}
+
+/// Parses a response body, attaching a snippet of the body to any error.
+/// Like serde_json itself this gives up on anything nested deeper than 128
+/// levels rather than recursing without bound.
+pub fn parse_json<T: DeserializeOwned>(body: &str) -> Result<T, RedditError> {
+  serde_json::from_str(body).map_err(|err| RedditError::json_with_body(err, body))
+}
+
+/// parse_json, with args.max_json_depth applied first when it is set
+pub fn parse_json_with<T: DeserializeOwned>(body: &str, args: &Args) -> Result<T, RedditError> {
+  if let Some(max) = args.max_json_depth {
+    check_json_depth(body, max)?;
+  }
+  parse_json(body)
+}
+
+/// Fails with RedditError::Json when arrays and objects in a body nest
+/// more than `max` deep, without parsing it
+pub fn check_json_depth(body: &str, max: usize) -> Result<(), RedditError> {
+  use serde::de::Error;
+
+  let (mut depth, mut in_string, mut escaped) = (0usize, false, false);
+  for byte in body.bytes() {
+    if in_string {
+      match byte {
+        _ if escaped => escaped = false,
+        b'\\' => escaped = true,
+        b'"' => in_string = false,
+        _ => (),
+      }
+      continue;
+    }
+
+    match byte {
+      b'"' => in_string = true,
+      b'[' | b'{' => {
+        depth += 1;
+        if depth > max {
+          return Err(serde_json::Error::custom(
+            format!("response is nested deeper than {} levels", max)).into());
+        }
+      },
+      b']' | b'}' => depth = depth.saturating_sub(1),
+      _ => (),
+    }
+  }
+  Ok(())
+}
+
+/// The connection level options setup_connection sets, implemented for
+/// both kinds of curl handle
+pub trait ConnectionOptions {
+  fn fresh_connect(&mut self, enable: bool) -> Result<(), curl::Error>;
+  fn forbid_reuse(&mut self, enable: bool) -> Result<(), curl::Error>;
+  fn timeout(&mut self, timeout: Duration) -> Result<(), curl::Error>;
+  fn dns_cache_timeout(&mut self, timeout: Duration) -> Result<(), curl::Error>;
+  fn tcp_nodelay(&mut self, enable: bool) -> Result<(), curl::Error>;
+  fn low_speed_limit(&mut self, bytes_per_sec: u32) -> Result<(), curl::Error>;
+  fn low_speed_time(&mut self, window: Duration) -> Result<(), curl::Error>;
+  fn cainfo(&mut self, path: &Path) -> Result<(), curl::Error>;
+  fn accept_encoding(&mut self, encoding: &str) -> Result<(), curl::Error>;
+  fn follow_location(&mut self, enable: bool) -> Result<(), curl::Error>;
+  fn max_redirections(&mut self, max: u32) -> Result<(), curl::Error>;
+  fn connect_timeout(&mut self, timeout: Duration) -> Result<(), curl::Error>;
+  fn proxy(&mut self, url: &str) -> Result<(), curl::Error>;
+}
+
+macro_rules! connection_options {
+  ( $($handle:ty),* ) => {
+    $(
+      impl ConnectionOptions for $handle {
+        fn fresh_connect(&mut self, enable: bool) -> Result<(), curl::Error> {
+          self.fresh_connect(enable)
+        }
+        fn forbid_reuse(&mut self, enable: bool) -> Result<(), curl::Error> {
+          self.forbid_reuse(enable)
+        }
+        fn timeout(&mut self, timeout: Duration) -> Result<(), curl::Error> {
+          self.timeout(timeout)
+        }
+        fn dns_cache_timeout(&mut self, timeout: Duration) -> Result<(), curl::Error> {
+          self.dns_cache_timeout(timeout)
+        }
+        fn tcp_nodelay(&mut self, enable: bool) -> Result<(), curl::Error> {
+          self.tcp_nodelay(enable)
+        }
+        fn low_speed_limit(&mut self, bytes_per_sec: u32) -> Result<(), curl::Error> {
+          self.low_speed_limit(bytes_per_sec)
+        }
+        fn low_speed_time(&mut self, window: Duration) -> Result<(), curl::Error> {
+          self.low_speed_time(window)
+        }
+        fn cainfo(&mut self, path: &Path) -> Result<(), curl::Error> {
+          self.cainfo(path)
+        }
+        fn accept_encoding(&mut self, encoding: &str) -> Result<(), curl::Error> {
+          self.accept_encoding(encoding)
+        }
+        fn follow_location(&mut self, enable: bool) -> Result<(), curl::Error> {
+          self.follow_location(enable)
+        }
+        fn max_redirections(&mut self, max: u32) -> Result<(), curl::Error> {
+          self.max_redirections(max)
+        }
+        fn connect_timeout(&mut self, timeout: Duration) -> Result<(), curl::Error> {
+          self.connect_timeout(timeout)
+        }
+        fn proxy(&mut self, url: &str) -> Result<(), curl::Error> {
+          self.proxy(url)
+        }
+      }
+    )*
+  };
+}
+
+connection_options!(Easy, Easy2<Collector>);
+
+/// Applies the connection level settings in args to a curl handle
+pub fn setup_connection<H: ConnectionOptions>(easy: &mut H, args: &Args)
+  -> Result<(), RedditError>
+{
+  easy.fresh_connect(args.fresh_connect)?;
+  easy.forbid_reuse(args.fresh_connect)?;
+  easy.tcp_nodelay(args.tcp_nodelay)?;
+  if let Some(timeout) = args.timeout {
+    easy.timeout(timeout)?;
+  }
+  if let Some(timeout) = args.connect_timeout {
+    easy.connect_timeout(timeout)?;
+  }
+  easy.follow_location(args.follow_redirects)?;
+  if args.follow_redirects {
+    easy.max_redirections(args.max_redirects)?;
+  }
+  if let Some(ref proxy) = args.proxy {
+    easy.proxy(proxy)?;
+  }
+  if let Some(timeout) = args.dns_cache_timeout {
+    easy.dns_cache_timeout(timeout)?;
+  }
+  if let Some((bytes_per_sec, window)) = args.low_speed_limit {
+    easy.low_speed_limit(bytes_per_sec)?;
+    easy.low_speed_time(window)?;
+  }
+  if args.compression {
+    // an empty string offers every coding curl was built with
+    easy.accept_encoding("")?;
+  }
+  if let Some(ref bundle) = args.ca_bundle {
+    // curl would only say the handshake failed, long after the fact
+    if !bundle.is_file() {
+      return Err(RedditError::InvalidArgument(
+        format!("CA bundle {} does not exist or is not a file", bundle.display())));
+    }
+    easy.cainfo(bundle)?;
+  }
+  Ok(())
+}
+
+/// Queries the reddit api at a path, returns a serde_json::Value
+///
+/// With script app credentials (client_id and friends, but no key) the
+/// request goes to the oauth host with a token from script_token.
+///
+/// The path is a string such as "/r/rust/top/.json" or a RequestBuilder.
+///
+/// Any status outside 2xx is an error: RedditError::Restricted when reddit
+/// says why, e.g. a private or quarantined subreddit, RedditError::Http
+/// otherwise.
+pub fn path_query<P: IntoPath>(path: P, args: Args) -> Result<Value, RedditError> {
+  let path = path.into_path()?;
+  path_query_with(&path, &args, &CurlTransport::new(&args))
+}
+
+/// Queries a path like path_query, deserializing the response straight into
+/// any type, e.g. `path_query_as::<Listing<Post>>("/r/rust/top/.json", args)`
+pub fn path_query_as<T: DeserializeOwned>(path: &str, args: Args) -> Result<T, RedditError> {
+  query_parsed(path, &args, &CurlTransport::new(&args))
+}
+
+/// path_query over a transport of choice, e.g. transport::MockTransport in
+/// tests or a non-curl backend
+pub fn path_query_with<T: Transport>(path: &str, args: &Args, transport: &T)
+  -> Result<Value, RedditError>
+{
+  query_parsed(path, args, transport)
+}
+
+/// Queries a path like path_query, but keeps the json exactly as reddit
+/// sent it (key order, number formatting, whitespace) for forwarding on
+/// without a parse and reserialize round trip. The body is still checked
+/// to be well-formed json within args.max_json_depth.
+#[cfg(feature = "raw_value")]
+pub fn path_query_raw(path: &str, args: Args)
+  -> Result<Box<serde_json::value::RawValue>, RedditError>
+{
+  query_parsed(path, &args, &CurlTransport::new(&args))
+}
+
+/// RedditError::RetryAfter when the response says how long to wait,
+/// RedditError::from_status otherwise
+pub(crate) fn status_error(response: Response) -> RedditError {
+  let after = response.header("Retry-After").and_then(|v| v.trim().parse::<u64>().ok());
+  match after {
+    Some(secs) => RedditError::RetryAfter {
+      status : response.status,
+      after : Duration::from_secs(secs),
+      body : response.body,
+    },
+    None => RedditError::from_status(response.status, response.body),
+  }
+}
+
+/// The GET a read goes out as, and the args to send it with. Script app
+/// credentials are traded for their token here, see with_script_token, so
+/// every read is built through this and none of them can skip it. A path
+/// that is already a full url, e.g. a short link, is requested as is.
+pub(crate) fn prepare_get(path: &str, args: &Args)
+  -> Result<(PreparedRequest, Args), RedditError>
+{
+  let args = with_script_token(args.clone())?;
+  let url = if path.starts_with("https://") || path.starts_with("http://") {
+    path.to_owned()
+  } else {
+    gen_request_uri(path, &args)
+  };
+  let request = PreparedRequest::get(&url, header_pairs(&args));
+  if request.headers.iter().any(|h| h.0.eq_ignore_ascii_case("Authorization")) {
+    check_secure(&request.url, &args)?;
+  }
+  Ok((request, args))
+}
+
+fn query_parsed<R: DeserializeOwned, T: Transport>(path: &str, args: &Args, transport: &T)
+  -> Result<R, RedditError>
+{
+  let (request, ref args) = prepare_get(path, args)?;
+
+  wait_for_ratelimit(&request.url, args);
+  let outcome = transport.execute(&request)
+    .and_then(RawResponse::into_response)
+    .and_then(|response| {
+      record_ratelimit(&request.url, args, &response);
+      check_blocked(&response)?;
+      if !(200..300).contains(&response.status) {
+        return Err(status_error(response));
+      }
+      check_content_type(response.header("Content-Type"))?;
+      parse_json_with(&response.body, args)
+    });
+  outcome.map_err(|err| err.for_request(&request.method, &request.url))
+}
+
+/// Like path_query, but hands back the whole response unparsed and without
+/// looking at its status or content type. With args.capture_raw_headers
+/// the header lines are kept byte for byte as well, e.g. for auditing.
+pub fn path_query_response(path: &str, args: Args) -> Result<Response, RedditError> {
+  let (request, args) = prepare_get(path, &args)?;
+
+  let mut easy = Easy::new();
+  easy.url(&request.url)?;
+  easy.http_headers(header_list(&request.headers)?)?;
+  setup_connection(&mut easy, &args)?;
+
+  response_for(&mut easy, "GET", &args)
+}
+
+/// The pre-Result flavor of path_query, panicking on any failure. It only
+/// exists so code written against the old api keeps compiling; rquery!
+/// expands to it. New code should use path_query or try_rquery! instead,
+/// this will be deprecated once callers have had time to move over.
+pub fn path_query_value(path: &str, args: Args) -> Value {
+  path_query(path, args).expect("rquery! request failed")
+}
+
+/// Queries an endpoint on the oauth host as the account args.key belongs
+/// to, e.g. "/api/v1/me"
+///
+/// A token lacking the scope the endpoint needs is reported as
+/// RedditError::Api with code INSUFFICIENT_SCOPE instead of a bare 403.
+pub fn oauth_query(path: &str, args: Args) -> Result<Value, RedditError> {
+  let mut easy = Easy::new();
+
+  easy.url(&gen_oauth_uri(path, &args))?;
+  easy.http_headers(auth_headers(&args)?)?;
+  setup_connection(&mut easy, &args)?;
+
+  let response = response_for(&mut easy, "GET", &args)?;
+  check_scope(&response)?;
+  if !(200..300).contains(&response.status) {
+    return Err(status_error(response));
+  }
+  check_content_type(easy.content_type()?)?;
+
+  parse_json_with(&response.body, &args)
+}
+
+/// reddit flags a missing scope in the WWW-Authenticate header of a 403
+fn check_scope(response: &Response) -> Result<(), RedditError> {
+  let insufficient = response.status == 403 && response.header("WWW-Authenticate")
+    .is_some_and(|value| value.contains("insufficient_scope"));
+
+  if insufficient {
+    return Err(RedditError::Api(vec![ApiError {
+      code : "INSUFFICIENT_SCOPE".to_owned(),
+      message : "the access token lacks the scope this endpoint requires".to_owned(),
+      field : None,
+    }]));
+  }
+  Ok(())
+}
+
+/// Queries a path and plucks out the value at an RFC 6901 JSON Pointer such
+/// as "/data/children/0/data/title", for when a whole struct is overkill.
+/// A pointer that doesn't resolve fails with RedditError::MissingField.
+pub fn query_pointer(path: &str, json_pointer: &str, args: Args) -> Result<Value, RedditError> {
+  let mut value = path_query(path, args)?;
+  value.pointer_mut(json_pointer)
+    .map(Value::take)
+    .ok_or_else(|| RedditError::MissingField(json_pointer.to_owned()))
+}
+
+/// Parses a value handed to rquery! and friends, naming the key when it
+/// doesn't parse
+#[doc(hidden)]
+pub fn macro_value<T: FromStr>(key: &str, value: &str) -> Result<T, RedditError> {
+  value.trim().parse().map_err(|_| {
+    RedditError::InvalidArgument(format!("{} can't be {:?}", key, value))
+  })
+}
+
+/// Percent-encodes a string for use in a query string or form body
+pub fn url_encode(input: &str) -> String {
+  let mut out = String::with_capacity(input.len());
+  for byte in input.bytes() {
+    match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' =>
+        out.push(byte as char),
+      _ => out.push_str(&format!("%{:02X}", byte)),
+    }
+  }
+  out
+}
+
+/// Undoes the html escaping reddit applies to text without raw_json=1:
+/// "&amp;", "&lt;" and "&gt;" become "&", "<" and ">". Other entities are
+/// left alone, reddit never produces them.
+pub fn unescape_html(input: &str) -> String {
+  let mut out = String::with_capacity(input.len());
+  let mut rest = input;
+  while let Some(at) = rest.find('&') {
+    out.push_str(&rest[..at]);
+    rest = &rest[at..];
+    let entity = [("&amp;", '&'), ("&lt;", '<'), ("&gt;", '>')].iter()
+      .find(|entity| rest.starts_with(entity.0));
+    match entity {
+      Some(&(name, c)) => {
+        out.push(c);
+        rest = &rest[name.len()..];
+      },
+      None => {
+        out.push('&');
+        rest = &rest[1..];
+      },
+    }
+  }
+  out.push_str(rest);
+  out
+}
+
+/// Encodes key/value pairs as an application/x-www-form-urlencoded body
+pub fn form_encode(form: &[(&str, &str)]) -> String {
+  form.iter()
+    .map(|&(key, value)| format!("{}={}", url_encode(key), url_encode(value)))
+    .collect::<Vec<String>>()
+    .join("&")
+}
+
+/// Fails with RedditError::AuthRequired unless the args carry credentials
+pub fn require_auth(args: &Args) -> Result<(), RedditError> {
+  if args.key.is_empty() {
+    return Err(RedditError::AuthRequired);
+  }
+  Ok(())
+}
+
+/// Fails with RedditError::InsecureTransport when credentials would go to
+/// a plain http url, unless args.danger_allow_insecure says that's fine
+pub fn check_secure(url: &str, args: &Args) -> Result<(), RedditError> {
+  if url.starts_with("http://") && !args.danger_allow_insecure {
+    return Err(RedditError::InsecureTransport { url : url.to_owned() });
+  }
+  Ok(())
+}
+
+/// gen_headers plus the bearer token for args.oauth_url, failing without one
+fn auth_headers(args: &Args) -> Result<List, RedditError> {
+  require_auth(args)?;
+  Token::from_access_token(&args.key)?;
+  check_secure(&args.oauth_url, args)?;
+  let mut headers = gen_headers(args)?;
+  headers.append(&format!("Authorization: bearer {}", args.key))?;
+  Ok(headers)
+}
+
+/// POSTs a form to an authenticated endpoint such as "/api/subscribe"
+///
+/// Write endpoints live on the oauth host and need args.key as the bearer
+/// token; the url is built by gen_oauth_uri, the same as for reads. An
+/// empty response body is reported as serde_json::Value::Null.
+pub fn post(path: &str, form: &[(&str, &str)], args: Args) -> Result<Value, RedditError> {
+  let headers = auth_headers(&args)?;
+
+  let mut easy = Easy::new();
+  easy.url(&gen_oauth_uri(path, &args))?;
+  easy.http_headers(headers)?;
+  setup_connection(&mut easy, &args)?;
+  easy.post(true)?;
+  easy.post_fields_copy(form_encode(form).as_bytes())?;
+
+  post_response(&mut easy, None, &args)
+}
+
+/// POSTs a body of any kind, e.g. json, to an authenticated endpoint;
+/// content_type becomes the Content-Type header. The body is streamed
+/// straight from the slice as curl sends it, never copied.
+pub fn post_raw(path: &str, body: &[u8], content_type: &str, args: Args)
+  -> Result<Value, RedditError>
+{
+  let (name, value) = header_pair("Content-Type", content_type)?;
+  let mut headers = auth_headers(&args)?;
+  headers.append(&format!("{}: {}", name, value))?;
+  // no waiting on a 100 Continue before large bodies
+  headers.append("Expect:")?;
+
+  let mut easy = Easy::new();
+  easy.url(&gen_oauth_uri(path, &args))?;
+  easy.http_headers(headers)?;
+  setup_connection(&mut easy, &args)?;
+  easy.post(true)?;
+  easy.post_field_size(body.len() as u64)?;
+
+  post_response(&mut easy, Some(body), &args)
+}
+
+/// runs a prepared POST and reads its reply the way post documents
+fn post_response(easy: &mut Easy, upload: Option<&[u8]>, args: &Args)
+  -> Result<Value, RedditError>
+{
+  let response = response_with_upload(easy, "POST", args, upload)?;
+  if !(200..300).contains(&response.status) {
+    return Err(status_error(response));
+  }
+  let output = response.body;
+
+  if output.trim().is_empty() {
+    return Ok(Value::Null);
+  }
+  check_content_type(easy.content_type()?)?;
+  parse_json_with(&output, args)
+}
+
+/// Turns a non-empty `json.errors` array in a write response into
+/// RedditError::Api; reddit reports these with a 200 status
+pub fn check_json_errors(value: &Value) -> Result<(), RedditError> {
+  let errors = match value["json"]["errors"].as_array() {
+    Some(errors) if !errors.is_empty() => errors,
+    _ => return Ok(()),
+  };
+
+  let field = |entry: &Value, index: usize| entry[index].as_str().map(|s| s.to_owned());
+  Err(RedditError::Api(errors.iter()
+    .map(|entry| ApiError {
+      code : field(entry, 0).unwrap_or_default(),
+      message : field(entry, 1).unwrap_or_default(),
+      field : field(entry, 2),
+    })
+    .collect()))
+}
+
+/// The `json` object reddit's write endpoints answer with (with
+/// api_type=json) once its errors have been checked: whatever `data` held,
+/// and the `data.things` list votes, comments and morechildren fill in
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ApiEnvelope {
+  /// Null when the response has no data
+  pub data : Value,
+  pub things : Vec<Value>,
+}
+
+/// Unwraps a write endpoint's response, failing with RedditError::Api when
+/// `json.errors` is not empty
+pub fn parse_json_envelope(value: &Value) -> Result<ApiEnvelope, RedditError> {
+  check_json_errors(value)?;
+
+  let data = value.pointer("/json/data").cloned().unwrap_or(Value::Null);
+  let things = data.get("things")
+    .and_then(Value::as_array)
+    .cloned()
+    .unwrap_or_default();
+  Ok(ApiEnvelope { data, things })
+}
+
+/// Checks a subreddit name against reddit's rules: 1-21 letters, digits
+/// or underscores
+pub fn validate_subreddit_name(name: &str) -> Result<(), RedditError> {
+  let valid = !name.is_empty()
+    && name.len() <= 21
+    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+  if !valid {
+    return Err(RedditError::InvalidName(name.to_owned()));
+  }
+  Ok(())
+}
+
+/// Like validate_subreddit_name, but also takes the "+" joined form that
+/// reads several subreddits at once, e.g. "rust+programming"
+pub fn validate_subreddit_list(names: &str) -> Result<(), RedditError> {
+  for name in names.split('+') {
+    validate_subreddit_name(name)
+      .map_err(|_| RedditError::InvalidName(names.to_owned()))?;
+  }
+  Ok(())
+}
+
+/// Formats a value as indented JSON for reading, falling back to the
+/// compact form should pretty printing ever fail
+pub fn pretty(value: &Value) -> String {
+  serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+/// Strips every child's data in a listing down to `keep_fields`, e.g. to
+/// archive just ["name", "title", "score"] of millions of posts. The
+/// envelope (after, before, kind) is left as it is; anything that isn't a
+/// listing comes back unchanged.
+pub fn prune_listing(mut value: Value, keep_fields: &[&str]) -> Value {
+  if let Some(children) = value.pointer_mut("/data/children").and_then(Value::as_array_mut) {
+    for child in children {
+      if let Some(data) = child.get_mut("data").and_then(Value::as_object_mut) {
+        data.retain(|key, _| keep_fields.contains(&key.as_str()));
+      }
+    }
+  }
+  value
+}
+
+/// Checks a username against what reddit allows: up to 20 letters,
+/// digits, '_' or '-'
+pub fn validate_username(name: &str) -> Result<(), RedditError> {
+  let valid = !name.is_empty()
+    && name.len() <= 20
+    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+  if !valid {
+    return Err(RedditError::InvalidName(name.to_owned()));
+  }
+  Ok(())
 }
 
 ////////////////////////////////////////////////////////////////////////////////
+/// QueryParams
+/// Query parameters to add to a path. Values are taken as already url
+/// encoded. apply_to parses whatever query string the path carries first,
+/// so a key set here replaces the one in the path instead of repeating it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryParams {
+  pairs : Vec<(String, String)>,
+}
+
+impl QueryParams {
+  pub fn new() -> QueryParams {
+    QueryParams::default()
+  }
+
+  /// sets key to value, replacing an earlier value for the same key
+  pub fn set(&mut self, key: &str, value: &str) -> &mut QueryParams {
+    merge_pair(&mut self.pairs, key, value);
+    self
+  }
+
+  /// the path with these parameters merged into its query string, in the
+  /// order the path had them followed by new keys in the order they were set
+  pub fn apply_to(&self, path: &str) -> String {
+    let (base, query) = match path.find('?') {
+      Some(at) => (&path[..at], &path[at + 1..]),
+      None => (path, ""),
+    };
+
+    let mut pairs = Vec::new();
+    for part in query.split('&').filter(|part| !part.is_empty()) {
+      let mut kv = part.splitn(2, '=');
+      let key = kv.next().unwrap_or("");
+      pairs.push((key.to_owned(), kv.next().unwrap_or("").to_owned()));
+    }
+    for (key, value) in &self.pairs {
+      merge_pair(&mut pairs, key, value);
+    }
+
+    if pairs.is_empty() {
+      return base.to_owned();
+    }
+    let query = pairs.iter()
+      .map(|(key, value)| format!("{}={}", key, value))
+      .collect::<Vec<String>>()
+      .join("&");
+    format!("{}?{}", base, query)
+  }
+}
+
+fn merge_pair(pairs: &mut Vec<(String, String)>, key: &str, value: &str) {
+  match pairs.iter_mut().find(|pair| pair.0 == key) {
+    Some(pair) => pair.1 = value.to_owned(),
+    None => pairs.push((key.to_owned(), value.to_owned())),
+  }
+}
 
+/// sets a single query parameter, minding any existing query string
+pub(crate) fn append_param(path: &str, key: &str, value: &str) -> String {
+  QueryParams::new().set(key, value).apply_to(path)
+}
 
 ////////////////////////////////////////////////////////////////////////////////
 /// Rdata and Rreq struct definitions
@@ -37,10 +995,10 @@ pub struct Rreq {
 /// Initializer
 /// Handles all Rreq Initialization methods
 pub trait Initializer {
-  fn stub(&str) -> Self;
-  fn new(&str, &str) -> Self;
-  fn args(&str, Args) -> Self;
-  fn full(&str, &str, Args) -> Self;
+  fn stub(sub: &str) -> Self;
+  fn new(sub: &str, req: &str) -> Self;
+  fn args(sub: &str, args: Args) -> Self;
+  fn full(sub: &str, req: &str, args: Args) -> Self;
 }
 
 impl Initializer for Rreq {
@@ -69,7 +1027,7 @@ impl Initializer for Rreq {
     Rreq {
       sub : sub.to_owned(),
       req : "".to_owned(),
-      args : args,
+      args,
       data : None,
     }
   }
@@ -79,7 +1037,7 @@ impl Initializer for Rreq {
     Rreq {
       sub : sub.to_owned(),
       req : req.to_owned(),
-      args : args,
+      args,
       data : None,
     }
   }
@@ -88,51 +1046,33 @@ impl Initializer for Rreq {
 ////////////////////////////////////////////////////////////////////////////////
 /// Request 
 /// The baseline request interface used to make calls to reddit
-
 pub trait Request {
+  fn path(&self) -> String;
   fn uri(&self) -> String;
-  fn headers(&self) -> List;
-  fn request(&self, &mut Easy) -> String;
-  fn query(&self) -> Result<Value, Error>;
+  fn headers(&self) -> Result<List, RedditError>;
+  fn request(&self, easy: &mut Easy) -> Result<String, RedditError>;
+  fn query(&self) -> Result<Value, RedditError>;
 }
 
 impl Request for Rreq {
+  /// Generates the request path relative to the reddit host
+  fn path(&self) -> String {
+    format!("/r/{}/{}", self.sub, self.req)
+  }
+
   /// Generates request full uri
-  fn uri(&self) -> String{
-    format!("https://www.reddit.com/r/{}/{}", self.sub, self.req).to_owned()
+  fn uri(&self) -> String {
+    gen_request_uri(&self.path(), &self.args)
   }
 
   /// Generates a curl::easy::List from HashMap, formats headers
-  fn headers(&self) -> List {
-    let mut list = List::new();
-    for header in self.args.headers.split(",") {
-      list.append(header).unwrap();
-    }
-    list
+  fn headers(&self) -> Result<List, RedditError> {
+    gen_headers(&self.args)
   }
 
-  /// Takes a formatted curl struct and generates output from a query
-  /// sending it back to the caller as a string of JSON
-  ///
-  /// Unfortunately, due to the complexity of the code here as well as
-  /// the fact that this workload here is mostly dependent on code in another
-  /// code base, rather than custom unit logic, this remains untested
-  ///
-  fn request(&self, easy : &mut Easy) -> String {
-    let output_locker : RwLock<Vec<String>>= RwLock::new(Vec::new());
-    let mut transfer = easy.transfer();
-
-    transfer.write_function(|data| {
-        let mut write_rwlock = output_locker.write().unwrap();
-        write_rwlock.push(
-            str_from_utf8(data).unwrap().to_string());
-        Ok(data.len())
-        }).unwrap();
-
-    transfer.perform().unwrap();
-
-    let output = output_locker.read().unwrap().clone().join("");
-    output
+  /// Runs the transfer on a prepared curl handle, see get_output_from_transfer
+  fn request(&self, easy : &mut Easy) -> Result<String, RedditError> {
+    get_output_from_transfer(easy)
   }
 
   /// Queries the reddit api with a string, returns a serde_json::Value
@@ -156,72 +1096,493 @@ impl Request for Rreq {
   /// }
   /// ```
   ///
-  fn query(&self) -> Result<Value, Error>  {
+  fn query(&self) -> Result<Value, RedditError>  {
+    validate_subreddit_list(&self.sub)?;
+    path_query(&self.path(), self.args.clone())
+  }
+}
 
-    let mut easy = Easy::new();
+////////////////////////////////////////////////////////////////////////////////
+// Listings and pagination
+// Walks the "after" token of a listing endpoint one page at a time
 
-    easy.url(&self.uri()).unwrap();
-    easy.http_headers(self.headers()).unwrap();
+/// Fetches a single listing page and parses its children
+pub fn get_things<T: FromChild>(path: &str, args: Args) -> Result<Listing<T>, RedditError> {
+  Listing::from_value(path_query(path, args)?)
+}
 
-    let output = self.request(&mut easy);
+/// how a ListingIter fetches a page, path_query or oauth_query
+type QueryFn = fn(&str, Args) -> Result<Value, RedditError>;
 
-    serde_json::from_str(&output)
+/// Iterator over the pages of a listing, see paginate
+pub struct ListingIter<T> {
+  path : String,
+  args : Args,
+  after : Option<String>,
+  pages : u32,
+  /// total children wanted, see paginate_limit
+  limit : Option<usize>,
+  /// children handed out so far
+  count : usize,
+  done : bool,
+  query : QueryFn,
+  marker : ::std::marker::PhantomData<T>,
+}
+
+impl<T: FromChild> ListingIter<T> {
+  /// Restarts a listing from an "after" token saved with cursor, so a job
+  /// that stopped halfway picks up with the next page
+  pub fn resume_from(path: &str, cursor: &str, args: Args) -> ListingIter<T> {
+    let mut iter = paginate(path, args);
+    iter.after = Some(cursor.to_owned());
+    iter
+  }
+
+  /// The "after" token the next page will be fetched with, None before the
+  /// first page and once the listing has run out
+  pub fn cursor(&self) -> Option<String> {
+    if self.done {
+      return None;
+    }
+    self.after.clone()
   }
 }
 
-#[macro_export]
-macro_rules! reddit {
-  ( $sub:expr ) => {{
-    extern crate rust_reddit;
-    use rust_reddit::api::prelude::*;
+impl<T: FromChild> Iterator for ListingIter<T> {
+  type Item = Result<Listing<T>, RedditError>;
 
-    let rreq = Rreq::stub($sub);
-    rreq.query().unwrap()
-  }};
-  ( $sub:expr, $($key:expr => $val:expr),* ) => {{
-    extern crate rust_reddit;
-    use rust_reddit::cli::Args;
-    use rust_reddit::api::prelude::*;
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+    if let Some(max) = self.args.max_pages {
+      if self.pages >= max {
+        return None;
+      }
+    }
 
-    let mut args = Args::default();
-    let mut rreq = Rreq::stub($sub);
-    $(
-        let val = $val.to_string();
-        match $key {
-        "key" => args.key = val,
-        "headers" => args.headers = val,
-        _ => (),
+    let mut params = QueryParams::new();
+    if let Some(ref after) = self.after {
+      params.set("after", after);
+    }
+    if let Some(limit) = self.limit {
+      params.set("limit", &(limit - self.count).min(MAX_PAGE_SIZE).to_string());
+      if self.after.is_some() {
+        params.set("count", &self.count.to_string());
+      }
+    }
+    let path = params.apply_to(&self.path);
+
+    self.pages += 1;
+    let page = (self.query)(&path, self.args.clone())
+      .and_then(Listing::from_value);
+    match page {
+      Ok(mut listing) => {
+        if let Some(limit) = self.limit {
+          listing.children.truncate(limit - self.count);
         }
-    )*
-    rreq.args = args;
-    rreq.query().unwrap()
-  }};
-  ( $sub:expr, $query:expr ) => {{
-    extern crate rust_reddit;
-    use rust_reddit::api::prelude::*;
+        self.count += listing.children.len();
+        self.after = listing.after.clone();
+        self.done = self.after.is_none() || self.limit.is_some_and(|l| self.count >= l);
+        Some(Ok(listing))
+      },
+      Err(err) => {
+        self.done = true;
+        Some(Err(err))
+      },
+    }
+  }
+}
+
+/// Pages through a listing endpoint until reddit stops handing out an
+/// "after" token or args.max_pages pages have been fetched
+///
+/// ```no_run
+/// extern crate rust_reddit;
+/// use rust_reddit::api;
+/// use rust_reddit::config::Args;
+/// use rust_reddit::models::Thing;
+///
+/// fn main() {
+///     for page in api::paginate::<Thing>("/r/rust/new/.json", Args::default()) {
+///         println!("{:?}", page.map(|listing| listing.children.len()));
+///     }
+/// }
+/// ```
+///
+pub fn paginate<T: FromChild>(path: &str, args: Args) -> ListingIter<T> {
+  listing_iter(path, args, |path: &str, args| path_query(path, args))
+}
+
+/// the most children reddit hands out in one listing page
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Pages through a listing like paginate, stopping once `limit` children
+/// have been handed out. Pages ask for at most MAX_PAGE_SIZE children each,
+/// later ones carry the running count along with the after token.
+pub fn paginate_limit<T: FromChild>(path: &str, args: Args, limit: usize) -> ListingIter<T> {
+  let mut iter = paginate(path, args);
+  iter.limit = Some(limit);
+  iter.done = limit == 0;
+  iter
+}
+
+/// paginate for listings on the oauth host, which need args.key
+pub fn paginate_oauth<T: FromChild>(path: &str, args: Args) -> ListingIter<T> {
+  listing_iter(path, args, oauth_query)
+}
+
+fn listing_iter<T: FromChild>(path: &str, args: Args, query: QueryFn) -> ListingIter<T> {
+  ListingIter {
+    path : path.to_owned(),
+    args,
+    after : None,
+    pages : 0,
+    limit : None,
+    count : 0,
+    done : false,
+    query,
+    marker : ::std::marker::PhantomData,
+  }
+}
+
+/// Collects a user's entire overview (posts and comments mixed), walking
+/// every page and dropping any thing already seen on an earlier page
+pub fn user_history(username: &str, args: Args) -> Result<Vec<Thing>, RedditError> {
+  validate_username(username)?;
+  let path = format!("/user/{}/overview/.json", username);
+  let mut seen = HashSet::new();
+  let mut history = Vec::new();
+
+  for page in paginate::<Thing>(&path, args) {
+    for thing in page?.children {
+      let fresh = match thing.fullname() {
+        Some(name) => seen.insert(name.to_owned()),
+        None => true,
+      };
+      if fresh {
+        history.push(thing);
+      }
+    }
+  }
+
+  Ok(history)
+}
+
+/// A user's most recent comments, newest first, paging through their
+/// history until `max` comments have been collected or it runs out
+pub fn user_comments(username: &str, max: usize, args: Args)
+  -> Result<Vec<Comment>, RedditError>
+{
+  validate_username(username)?;
+  let mut comments = Vec::new();
+  if max == 0 {
+    return Ok(comments);
+  }
+
+  let path = format!("/user/{}/comments/.json?limit={}", username, max.min(100));
+  for page in paginate::<Comment>(&path, args) {
+    for comment in page?.children {
+      comments.push(comment);
+      if comments.len() >= max {
+        return Ok(comments);
+      }
+    }
+  }
+  Ok(comments)
+}
+
+/// Whether a username is still free to register. reddit answers with a
+/// bare true or false; a name it rejects outright comes back as errors,
+/// reported as RedditError::InvalidArgument.
+pub fn username_available(name: &str, args: Args) -> Result<bool, RedditError> {
+  let path = format!("/api/username_available.json?user={}", url_encode(name));
+  let value = path_query(&path, args)?;
+
+  if let Some(available) = value.as_bool() {
+    return Ok(available);
+  }
+  match check_json_errors(&value) {
+    Err(RedditError::Api(errors)) => Err(RedditError::InvalidArgument(errors.iter()
+      .map(|e| e.to_string())
+      .collect::<Vec<String>>()
+      .join(", "))),
+    _ => Err(RedditError::UnexpectedShape { expected : "bool", got : json_shape(&value) }),
+  }
+}
+
+/// Looks up another user's profile: karma, icon and account age. Asked
+/// for with raw_json=1 so urls such as icon_img come back unescaped. An
+/// account reddit has nothing on is RedditError::UserNotFound.
+pub fn user_about(username: &str, args: Args) -> Result<Account, RedditError> {
+  validate_username(username)?;
+
+  let path = format!("/user/{}/about/.json?raw_json=1", username);
+  let value = user_query(username, &path, args)?;
+  Ok(Account::from_child(value)?)
+}
+
+/// Lists the subreddits currently popular on reddit, every page of them
+/// unless args.max_pages says otherwise
+pub fn popular_subreddits(args: Args) -> Result<Vec<SubredditInfo>, RedditError> {
+  collect_children(paginate("/subreddits/popular/.json", args))
+}
+
+/// The names of the subreddits reddit is currently featuring as trending.
+/// This endpoint answers with a plain object rather than a listing.
+pub fn trending_subreddits(args: Args) -> Result<Vec<String>, RedditError> {
+  let names = query_pointer("/api/trending_subreddits/.json", "/subreddit_names", args)?;
+  Ok(serde_json::from_value(names)?)
+}
 
-    let rreq = Rreq::new($sub, $query);
-    rreq.query().unwrap()
+/// Lists the default subreddits new accounts are subscribed to
+pub fn default_subreddits(args: Args) -> Result<Vec<SubredditInfo>, RedditError> {
+  collect_children(paginate("/subreddits/default/.json", args))
+}
+
+/// gathers the children of every page of a listing into one Vec
+pub(crate) fn collect_children<T: FromChild>(pages: ListingIter<T>)
+  -> Result<Vec<T>, RedditError>
+{
+  let mut children = Vec::new();
+  for page in pages {
+    children.extend(page?.children);
+  }
+  Ok(children)
+}
+
+/// Writes every child of a listing to `out` as JSON lines, one page at a
+/// time, so even an entire subreddit can be archived in constant memory.
+/// The writer is flushed after each page; args.max_pages is respected.
+/// Returns how many children were written.
+pub fn paginate_to_writer<W: Write>(path: &str, args: Args, out: &mut W)
+  -> Result<usize, RedditError>
+{
+  let mut written = 0;
+  for page in paginate::<Value>(path, args) {
+    for mut child in page?.children {
+      serde_json::to_writer(&mut *out, &child["data"].take())?;
+      out.write_all(b"\n")?;
+      written += 1;
+    }
+    out.flush()?;
+  }
+  Ok(written)
+}
+
+/// Sets one of the macros' `key => value` pairs on args, evaluating to a
+/// Result so try_rquery! can hand a bad value back. Keys the macros don't
+/// know are a compile error instead of being ignored.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rust_reddit_arg {
+  ( $args:ident, "key", $val:expr ) => {{
+    $args.key = $val.to_string();
+    Ok(())
+  }};
+  ( $args:ident, "headers", $val:expr ) => {{
+    $crate::config::Args::parse_headers(&$val.to_string())
+      .map(|headers| $args.headers = headers)
+  }};
+  ( $args:ident, "user_agent", $val:expr ) => {{
+    $args.user_agent = Some($val.to_string());
+    Ok(())
+  }};
+  ( $args:ident, "base_url", $val:expr ) => {{
+    $args.base_url = $val.to_string();
+    Ok(())
+  }};
+  ( $args:ident, "timeout_ms", $val:expr ) => {{
+    $crate::api::macro_value("timeout_ms", &$val.to_string())
+      .map(|ms| $args.timeout = Some(::std::time::Duration::from_millis(ms)))
+  }};
+  ( $args:ident, "connect_timeout_ms", $val:expr ) => {{
+    $crate::api::macro_value("connect_timeout_ms", &$val.to_string())
+      .map(|ms| $args.connect_timeout = Some(::std::time::Duration::from_millis(ms)))
+  }};
+  ( $args:ident, "proxy", $val:expr ) => {{
+    $args.proxy = Some($val.to_string());
+    Ok(())
   }};
-  ( $sub:expr, $query:expr, $($key:expr => $val:expr),* ) => {{
-    extern crate rust_reddit;
-    use rust_reddit::api::prelude::*;
-    use rust_reddit::cli::Args;
+  ( $args:ident, "follow_redirects", $val:expr ) => {{
+    $crate::api::macro_value("follow_redirects", &$val.to_string())
+      .map(|follow| $args.follow_redirects = follow)
+  }};
+  ( $args:ident, $other:tt, $val:expr ) => {
+    compile_error!(concat!("unknown rust_reddit macro key ", stringify!($other),
+      ", expected one of \"key\", \"headers\", \"user_agent\", \"base_url\", \"timeout_ms\", ",
+      "\"connect_timeout_ms\", \"proxy\" or \"follow_redirects\""))
+  };
+}
 
-    let mut args = Args::default();
-    let mut rreq = Rreq::new($sub, $query);
+/// Args::default with the macros' `key => value` pairs applied in order,
+/// stopping at the first value that doesn't parse
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rust_reddit_args {
+  ( $($key:tt => $val:expr),* ) => {{
+    let mut args = $crate::config::Args::default();
+    let mut result: ::std::result::Result<(), $crate::error::RedditError> = Ok(());
     $(
-      let val = $val.to_string();
-      match $key {
-      "key" => args.key = val,
-      "headers" => args.headers = val,
-      _ => (),
+      if result.is_ok() {
+        result = $crate::__rust_reddit_arg!(args, $key, $val);
       }
     )*
-    rreq.args = args;
-    rreq.query().unwrap()
+    result.map(|()| args)
+  }};
+}
+
+/// Queries a subreddit through Rreq, e.g. reddit!("rust", "top/.json"),
+/// panicking on any failure. Takes the same `key => value` pairs as
+/// rquery!.
+#[macro_export]
+macro_rules! reddit {
+  ( $sub:expr ) => {{
+    let rreq: $crate::api::Rreq = $crate::api::Initializer::stub($sub);
+    $crate::api::Request::query(&rreq).unwrap()
+  }};
+  ( $sub:expr, $($key:tt => $val:expr),* ) => {{
+    let args = $crate::__rust_reddit_args!($($key => $val),*).unwrap();
+    let rreq: $crate::api::Rreq = $crate::api::Initializer::args($sub, args);
+    $crate::api::Request::query(&rreq).unwrap()
+  }};
+  ( $sub:expr, $query:expr ) => {{
+    let rreq: $crate::api::Rreq = $crate::api::Initializer::new($sub, $query);
+    $crate::api::Request::query(&rreq).unwrap()
+  }};
+  ( $sub:expr, $query:expr, $($key:tt => $val:expr),* ) => {{
+    let args = $crate::__rust_reddit_args!($($key => $val),*).unwrap();
+    let rreq: $crate::api::Rreq = $crate::api::Initializer::full($sub, $query, args);
+    $crate::api::Request::query(&rreq).unwrap()
+  }};
+}
+
+/// Queries a full path, e.g. "/r/rust/top/.json", panicking on any failure
+///
+/// This is the quick-and-dirty convenience kept for existing callers, it
+/// expands to api::path_query_value; see try_rquery! for the variant that
+/// hands back the Result instead.
+///
+/// Options are given as `key => value` pairs. The keys are "key",
+/// "headers", "user_agent", "base_url", "timeout_ms", "connect_timeout_ms",
+/// "proxy" and "follow_redirects"; values can be any expression that
+/// implements Display. The expansion only names items through `$crate`, so
+/// it works with the crate renamed and doesn't care what else is in scope.
+///
+/// ```no_run
+/// #[macro_use]
+/// extern crate rust_reddit;
+///
+/// fn main() {
+///     let data = rquery!(
+///         "/r/rust/top/.json?count=20",
+///         "headers" => "User-Agent: rust-reddit-test");
+///
+///     println!("{}", data);
+/// }
+/// ```
+///
+/// Renamed, and next to an Args of your own:
+///
+/// ```no_run
+/// #[macro_use]
+/// extern crate rust_reddit as reddit_api;
+///
+/// struct Args;
+///
+/// fn main() {
+///     let _mine = Args;
+///     let version = 2;
+///     let data = rquery!(
+///         "/r/rust/about/.json",
+///         "user_agent" => format!("my-bot/{}", version),
+///         "timeout_ms" => 5000);
+///
+///     println!("{}", data);
+/// }
+/// ```
+///
+/// A misspelled key doesn't compile:
+///
+/// ```compile_fail
+/// #[macro_use]
+/// extern crate rust_reddit;
+///
+/// fn main() {
+///     rquery!("/r/rust/.json", "headres" => "User-Agent: typo");
+/// }
+/// ```
+///
+#[macro_export]
+macro_rules! rquery {
+  ( $path:expr ) => {
+    $crate::api::path_query_value($path, $crate::config::Args::default())
+  };
+  ( $path:expr, $($key:tt => $val:expr),* ) => {
+    $crate::api::path_query_value($path,
+      $crate::__rust_reddit_args!($($key => $val),*).expect("rquery! arguments"))
+  };
+}
+
+/// Like rquery!, but also prints the response as indented JSON to stdout,
+/// handy when exploring the api. The value is handed back as well.
+///
+/// ```no_run
+/// #[macro_use]
+/// extern crate rust_reddit;
+///
+/// fn main() {
+///     rquery_pretty!("/r/rust/about/.json");
+/// }
+/// ```
+///
+#[macro_export]
+macro_rules! rquery_pretty {
+  ( $($arg:tt)* ) => {{
+    let value = $crate::rquery!($($arg)*);
+    println!("{}", $crate::api::pretty(&value));
+    value
   }};
 }
+
+/// Same as rquery!, but returns Result<serde_json::Value, RedditError> so
+/// failures can be handled or propagated with `?`
+///
+/// It takes the same keys as rquery!; a value that does not parse, e.g.
+/// "timeout_ms" => "soon", is an InvalidArgument here rather than a panic.
+///
+/// ```no_run
+/// #[macro_use]
+/// extern crate rust_reddit;
+/// extern crate serde_json;
+///
+/// use rust_reddit::error::RedditError;
+///
+/// fn top_title() -> Result<serde_json::Value, RedditError> {
+///     let data = try_rquery!(
+///         "/r/rust/top/.json?count=1",
+///         "headers" => "User-Agent: rust-reddit-test",
+///         "timeout_ms" => 5000)?;
+///
+///     Ok(data["data"]["children"][0]["data"]["title"].clone())
+/// }
+///
+/// fn main() {
+///     println!("{:?}", top_title());
+/// }
+/// ```
+///
+#[macro_export]
+macro_rules! try_rquery {
+  ( $path:expr ) => {
+    $crate::api::path_query($path, $crate::config::Args::default())
+  };
+  ( $path:expr, $($key:tt => $val:expr),* ) => {
+    $crate::__rust_reddit_args!($($key => $val),*)
+      .and_then(|args| $crate::api::path_query($path, args))
+  };
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 #[cfg(test)]
 mod test_api {
@@ -245,7 +1606,7 @@ mod test_api {
   #[test]
   fn test_gen_request_uri() {
 
-    let expected = "https://www.reddit.com/r/rust/top.json?count=1".to_owned();
+    let expected = "https://www.reddit.com/r/rust/top.json?count=1&raw_json=1".to_owned();
     let rreq = Rreq::new("rust", "top.json?count=1");
     let actual = rreq.uri();
     println!("{}", actual);
@@ -272,22 +1633,27 @@ mod test_api {
 
   #[test]
   fn test_gen_headers() {
-    use cli::Args;
+    use api::gen_headers;
+    use config::Args;
 
     let mut args = Args::default();
     let mut expect_list = List::new();
     expect_list.append("User-Agent: test-user").unwrap();
     expect_list.append("Host: fake.com").unwrap();
+    expect_list.append("Accept: application/json").unwrap();
 
     let mut wrong_list = List::new();
     wrong_list.append("User-Agent: not-user").unwrap();
     wrong_list.append("Host: wrong.org").unwrap();
 
-    args.headers  = "User-Agent: test-user,Host: fake.com".to_owned();
+    args.headers = vec![
+      ("User-Agent".to_owned(), "test-user".to_owned()),
+      ("Host".to_owned(), "fake.com".to_owned()),
+    ];
     let mut rreq = Rreq::stub("rust");
     rreq.args = args;
 
-    let actual_list = rreq.headers();
+    let actual_list = rreq.headers().unwrap();
 
     let actual: String = return_vec_from_list(actual_list).into_iter().collect();
     let expect: String = return_vec_from_list(expect_list).into_iter().collect();
@@ -295,6 +1661,17 @@ mod test_api {
 
     assert!(actual == expect);
     assert!(actual != wrong);
+
+    // commas inside values and stray whitespace, from both forms
+    let mut args = Args::empty();
+    args.headers = Args::parse_headers("Accept:  application/json, text/html ,Cookie: a=1, b=2")
+      .unwrap();
+    args.headers.push(("  X-Custom ".to_owned(), "  spaced out  ".to_owned()));
+    assert_eq!(return_vec_from_list(gen_headers(&args).unwrap()), vec![
+      "Accept: application/json, text/html",
+      "Cookie: a=1, b=2",
+      "X-Custom: spaced out",
+    ]);
   }
 
   #[test]
@@ -305,4 +1682,1566 @@ mod test_api {
     println!("- test_rreq: {}", rreq.uri());
     println!("- test_rreq: {:?}", rreq.query());
   }
+
+  fn listing_page(after: &str, names: &[&str]) -> String {
+    let children: Vec<String> = names.iter()
+      .map(|name| format!(
+          r#"{{"kind": "{}", "data": {{"name": "{}"}}}}"#, &name[..2], name))
+      .collect();
+    let after = if after.is_empty() {
+      "null".to_owned()
+    } else {
+      format!("\"{}\"", after)
+    };
+    format!(
+      r#"{{"kind": "Listing", "data": {{"after": {}, "before": null, "children": [{}]}}}}"#,
+      after, children.join(","))
+  }
+
+  #[test]
+  fn test_user_history_dedupes_overlapping_pages() {
+    use api::user_history;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::json(&listing_page("t1_b", &["t3_a", "t1_b"])),
+      MockResponse::json(&listing_page("", &["t1_b", "t3_c"])),
+    ]);
+    let args = server.args();
+
+    let history = user_history("spez", args).unwrap();
+    let names: Vec<&str> = history.iter()
+      .map(|thing| thing.fullname().unwrap())
+      .collect();
+    assert_eq!(names, vec!["t3_a", "t1_b", "t3_c"]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].path, "/user/spez/overview/.json?raw_json=1");
+    assert_eq!(requests[1].path, "/user/spez/overview/.json?after=t1_b&raw_json=1");
+  }
+
+  #[test]
+  fn test_user_history_respects_max_pages() {
+    use api::user_history;
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::json(&listing_page("t3_b", &["t3_a", "t3_b"])),
+      MockResponse::json(&listing_page("", &["t3_c"])),
+    ]);
+    let args = Args {
+      base_url: server.base_url.clone(),
+      max_pages: Some(1),
+      ..Args::default()
+    };
+
+    let history = user_history("spez", args).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(server.requests().len(), 1);
+  }
+
+  #[test]
+  fn test_user_history_rejects_bad_names() {
+    use api::user_history;
+    use error::RedditError;
+    use mock::MockServer;
+
+    let server = MockServer::start(vec![]);
+    let args = server.args();
+
+    assert!(matches!(user_history("a/b", args), Err(RedditError::InvalidName(_))));
+    assert!(server.requests().is_empty());
+  }
+
+  #[test]
+  fn test_form_encode() {
+    use api::form_encode;
+
+    let body = form_encode(&[("text", "a&b=c\nd e"), ("api_type", "json")]);
+    assert_eq!(body, "text=a%26b%3Dc%0Ad%20e&api_type=json");
+  }
+
+  #[test]
+  fn test_gen_request_uri_raw_json() {
+    use api::gen_request_uri;
+    use config::Args;
+
+    let args = Args::default();
+    assert_eq!(gen_request_uri("/r/rust/.json", &args),
+      "https://www.reddit.com/r/rust/.json?raw_json=1");
+    assert_eq!(gen_request_uri("/r/rust/top/.json?t=week", &args),
+      "https://www.reddit.com/r/rust/top/.json?t=week&raw_json=1");
+    assert_eq!(gen_request_uri("/r/rust/.json?raw_json=1&limit=5", &args),
+      "https://www.reddit.com/r/rust/.json?raw_json=1&limit=5");
+
+    let legacy = Args { raw_json: false, ..Args::default() };
+    assert_eq!(gen_request_uri("/r/rust/top/.json?t=week", &legacy),
+      "https://www.reddit.com/r/rust/top/.json?t=week");
+  }
+
+  #[test]
+  fn test_unescape_html() {
+    use api::unescape_html;
+
+    assert_eq!(unescape_html("fish &amp; chips &lt;3 &gt;_&lt;"), "fish & chips <3 >_<");
+    // only one level is undone, and anything else is kept as it was
+    assert_eq!(unescape_html("&amp;lt; &quot; & &amp"), "&lt; &quot; & &amp");
+    assert_eq!(unescape_html("größe"), "größe");
+  }
+
+  #[test]
+  fn test_selftext_round_trip_with_and_without_raw_json() {
+    use api::{get_things, unescape_html};
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+    use models::Post;
+
+    let page = |selftext: &str| MockResponse::json(&format!(
+      r#"{{"kind": "Listing", "data": {{"children": [
+          {{"kind": "t3", "data": {{"name": "t3_a", "selftext": "{}"}}}}]}}}}"#, selftext));
+    let server = MockServer::start(vec![page("a & b <c>"), page("a &amp; b &lt;c&gt;")]);
+    let args = server.args();
+
+    let raw = get_things::<Post>("/r/rust/new/.json", args.clone()).unwrap();
+    assert_eq!(raw.children[0].selftext, "a & b <c>");
+    assert!(!raw.children[0].selftext.contains("&amp;"));
+
+    let legacy = Args { raw_json: false, ..args };
+    let escaped = get_things::<Post>("/r/rust/new/.json", legacy).unwrap();
+    assert_eq!(unescape_html(&escaped.children[0].selftext), "a & b <c>");
+
+    let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(paths, vec!["/r/rust/new/.json?raw_json=1", "/r/rust/new/.json"]);
+  }
+
+  #[test]
+  fn test_gen_request_uri_sr_detail() {
+    use api::gen_request_uri;
+    use config::Args;
+
+    let args = Args { sr_detail: true, ..Args::default() };
+    assert_eq!(
+      gen_request_uri("/r/rust/.json", &args),
+      "https://www.reddit.com/r/rust/.json?sr_detail=1&raw_json=1");
+    assert_eq!(
+      gen_request_uri("/r/rust/.json?limit=5", &args),
+      "https://www.reddit.com/r/rust/.json?limit=5&sr_detail=1&raw_json=1");
+  }
+
+  #[test]
+  fn test_query_params_merge_existing_query() {
+    use api::QueryParams;
+
+    let mut params = QueryParams::new();
+    params.set("raw_json", "1").set("after", "t3_b").set("count", "25").set("after", "t3_c");
+    assert_eq!(
+      params.apply_to("/r/rust/.json?limit=10&after=t3_a"),
+      "/r/rust/.json?limit=10&after=t3_c&raw_json=1&count=25");
+
+    assert_eq!(params.apply_to("/r/rust/.json?"), "/r/rust/.json?raw_json=1&after=t3_c&count=25");
+    assert_eq!(QueryParams::new().apply_to("/r/rust/.json?&limit=5&"), "/r/rust/.json?limit=5");
+    assert_eq!(QueryParams::new().apply_to("/r/rust/.json"), "/r/rust/.json");
+  }
+
+  #[test]
+  fn test_gen_request_uri_path_prefix() {
+    use api::gen_request_uri;
+    use config::Args;
+
+    let args = Args {
+      base_url : "http://gateway:8080".to_owned(),
+      path_prefix : Some("/reddit-proxy".to_owned()),
+      sr_detail : true,
+      ..Args::default()
+    };
+    assert_eq!(gen_request_uri("/r/rust/.json", &args),
+      "http://gateway:8080/reddit-proxy/r/rust/.json?sr_detail=1&raw_json=1");
+
+    for prefix in &["reddit-proxy", "/reddit-proxy/", "reddit-proxy/"] {
+      let args = Args {
+        base_url : "http://gateway:8080/".to_owned(),
+        path_prefix : Some(prefix.to_string()),
+        ..Args::default()
+      };
+      assert_eq!(gen_request_uri("/r/rust/.json", &args),
+        "http://gateway:8080/reddit-proxy/r/rust/.json?raw_json=1", "{:?}", prefix);
+    }
+
+    let nested = Args { path_prefix: Some("/a/b".to_owned()), ..Args::default() };
+    assert_eq!(gen_request_uri("api/v1/me", &nested),
+      "https://www.reddit.com/a/b/api/v1/me?raw_json=1");
+    let empty = Args { path_prefix: Some("/".to_owned()), ..Args::default() };
+    assert_eq!(gen_request_uri("/r/rust/.json", &empty),
+      "https://www.reddit.com/r/rust/.json?raw_json=1");
+  }
+
+  #[test]
+  fn test_oauth_reads_and_writes_go_under_the_path_prefix() {
+    use api::{authenticate, oauth_query, post};
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"name": "ferris"}"#),
+      MockResponse::json("{}"),
+      MockResponse::json(r#"{"access_token": "abc", "expires_in": 3600}"#),
+    ]);
+    let args = Args {
+      key : "token".to_owned(),
+      base_url : server.base_url.clone(),
+      oauth_url : server.base_url.clone(),
+      path_prefix : Some("/reddit-proxy".to_owned()),
+      client_id : "id".to_owned(),
+      danger_allow_insecure : true,
+      ..Args::default()
+    };
+
+    oauth_query("/api/v1/me", args.clone()).unwrap();
+    post("/api/subscribe", &[("action", "sub")], args.clone()).unwrap();
+    authenticate(&args).unwrap();
+
+    let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(paths, vec![
+      "/reddit-proxy/api/v1/me?raw_json=1",
+      "/reddit-proxy/api/subscribe?raw_json=1",
+      "/reddit-proxy/api/v1/access_token",
+    ]);
+  }
+
+  #[test]
+  fn test_gen_request_uri_show_all() {
+    use api::{gen_request_uri, ListingQuery, Sort, TimeRange};
+    use config::Args;
+
+    let args = Args { show_all: true, ..Args::default() };
+    assert_eq!(
+      gen_request_uri("/r/rust/new/.json", &args),
+      "https://www.reddit.com/r/rust/new/.json?show=all&raw_json=1");
+
+    let path = ListingQuery::new("rust", Sort::Top(TimeRange::Week)).limit(50).path();
+    assert_eq!(
+      gen_request_uri(&path, &Args { sr_detail: true, ..args }),
+      "https://www.reddit.com/r/rust/top/.json?t=week&limit=50&sr_detail=1&show=all&raw_json=1");
+  }
+
+  #[test]
+  fn test_paginate_stops_on_empty_after() {
+    use api::paginate;
+    use mock::{MockServer, MockResponse};
+    use models::Thing;
+
+    let server = MockServer::start(vec![
+      MockResponse::json(&listing_page("t3_a", &["t3_a"])),
+      MockResponse::json(
+        r#"{"kind": "Listing", "data": {"after": "", "children": [
+          {"kind": "t3", "data": {"name": "t3_b"}}]}}"#),
+      MockResponse::json(&listing_page("", &["t3_c"])),
+    ]);
+    let args = server.args();
+
+    let pages: Vec<_> = paginate::<Thing>("/r/rust/new/.json", args).collect();
+    assert_eq!(pages.len(), 2);
+    assert!(pages.iter().all(|page| page.is_ok()));
+    assert_eq!(server.requests().len(), 2);
+  }
+
+  #[test]
+  fn test_gen_request_uri_path_rewrite() {
+    use api::gen_request_uri;
+    use config::Args;
+    use std::sync::Arc;
+
+    let args = Args {
+      sr_detail : true,
+      raw_json : false,
+      path_rewrite : Some(Arc::new(|path: &str| format!("{}&raw_json=1", path))),
+      ..Args::default()
+    };
+    assert_eq!(
+      gen_request_uri("/r/rust/.json", &args),
+      "https://www.reddit.com/r/rust/.json?sr_detail=1&raw_json=1");
+  }
+
+  #[test]
+  fn test_parse_json_envelope() {
+    use api::{parse_json_envelope, ApiEnvelope};
+    use error::RedditError;
+    use serde_json;
+
+    let comment = serde_json::json!({"json": {"errors": [], "data": {"things": [
+      {"kind": "t1", "data": {"name": "t1_new"}}]}}});
+    let envelope = parse_json_envelope(&comment).unwrap();
+    assert_eq!(envelope.things.len(), 1);
+    assert_eq!(envelope.things[0]["data"]["name"], "t1_new");
+
+    let submit = serde_json::json!({"json": {"errors": [], "data": {"name": "t3_abc"}}});
+    let envelope = parse_json_envelope(&submit).unwrap();
+    assert_eq!(envelope.data["name"], "t3_abc");
+    assert!(envelope.things.is_empty());
+
+    let vote = serde_json::json!({});
+    assert_eq!(parse_json_envelope(&vote).unwrap(), ApiEnvelope::default());
+
+    let failed = serde_json::json!({"json": {"errors": [["THREAD_LOCKED", "locked", null]],
+      "data": {"things": []}}});
+    assert!(matches!(parse_json_envelope(&failed), Err(RedditError::Api(_))));
+  }
+
+  #[test]
+  fn test_check_json_errors() {
+    use api::check_json_errors;
+    use error::{ApiError, RedditError};
+    use serde_json;
+
+    let ok = serde_json::from_str(r#"{"json": {"errors": []}}"#).unwrap();
+    assert!(check_json_errors(&ok).is_ok());
+
+    let failed = serde_json::from_str(
+      r#"{"json": {"errors": [["RATELIMIT", "you are doing that too much", "ratelimit"]]}}"#)
+      .unwrap();
+    match check_json_errors(&failed) {
+      Err(RedditError::Api(errors)) => assert_eq!(errors, vec![ApiError {
+        code : "RATELIMIT".to_owned(),
+        message : "you are doing that too much".to_owned(),
+        field : Some("ratelimit".to_owned()),
+      }]),
+      other => panic!("unexpected {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_query_pointer() {
+    use api::query_pointer;
+    use error::RedditError;
+    use mock::{MockServer, MockResponse};
+
+    let page = r#"{"kind": "Listing", "data": {"children": [
+      {"kind": "t3", "data": {"title": "Hello, rust"}}]}}"#;
+    let server = MockServer::start(vec![MockResponse::json(page), MockResponse::json(page)]);
+    let args = server.args();
+
+    let title = query_pointer("/r/rust/.json", "/data/children/0/data/title", args.clone())
+      .unwrap();
+    assert_eq!(title, "Hello, rust");
+
+    let err = query_pointer("/r/rust/.json", "/data/children/1/data/title", args)
+      .unwrap_err();
+    assert!(matches!(err, RedditError::MissingField(ref p) if p == "/data/children/1/data/title"));
+  }
+
+  #[test]
+  fn test_popular_subreddits_pages() {
+    use api::popular_subreddits;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::json(&listing_page("t5_b", &["t5_a", "t5_b"])),
+      MockResponse::json(&listing_page("", &["t5_c"])),
+    ]);
+    let args = server.args();
+
+    let subreddits = popular_subreddits(args).unwrap();
+    let names: Vec<&str> = subreddits.iter().map(|sr| sr.name.as_str()).collect();
+    assert_eq!(names, vec!["t5_a", "t5_b", "t5_c"]);
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/subreddits/popular/.json?raw_json=1");
+    assert_eq!(requests[1].path, "/subreddits/popular/.json?after=t5_b&raw_json=1");
+  }
+
+  #[test]
+  fn test_subreddit_names_reject_path_injection() {
+    use api::{validate_subreddit_name, validate_subreddit_list};
+    use error::RedditError;
+
+    assert!(validate_subreddit_name("rust_gamedev").is_ok());
+    assert!(validate_subreddit_list("rust+programming+golang").is_ok());
+
+    for name in &["", "../api", "foo/../../api/v1/me", "rust?limit=5", "rust%2F..",
+                  "rust+", "+rust", "rust++golang", "a_name_far_too_long_for_reddit"] {
+      assert!(validate_subreddit_list(name).is_err(), "accepted {:?}", name);
+    }
+    assert!(validate_subreddit_name("rust+golang").is_err());
+
+    let err = Rreq::new("foo/../../api", "v1/me").query().unwrap_err();
+    assert!(matches!(err, RedditError::InvalidName(ref name) if name == "foo/../../api"));
+  }
+
+  #[test]
+  fn test_accept_header_overrides() {
+    use api::gen_headers;
+    use config::Args;
+
+    let args = Args { accept: "application/xml".to_owned(), ..Args::empty() };
+    assert_eq!(return_vec_from_list(gen_headers(&args).unwrap()),
+      vec!["Accept: application/xml"]);
+
+    let args = Args {
+      headers: vec![("accept".to_owned(), "*/*".to_owned())],
+      ..Args::empty()
+    };
+    assert_eq!(return_vec_from_list(gen_headers(&args).unwrap()), vec!["accept: */*"]);
+
+    let args = Args { accept: "".to_owned(), ..Args::empty() };
+    assert!(return_vec_from_list(gen_headers(&args).unwrap()).is_empty());
+
+    // set straight on Args, so nothing checked it; curl's refusal comes back
+    let args = Args {
+      headers: vec![("X-Nul".to_owned(), "a\0b".to_owned())],
+      ..Args::empty()
+    };
+    assert!(matches!(gen_headers(&args), Err(RedditError::Curl(_))));
+  }
+
+  #[test]
+  fn test_html_response_is_unexpected_content_type() {
+    use api::path_query;
+    use error::RedditError;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::status(200, "<html>reddit is down</html>")
+        .header("Content-Type", "text/html; charset=UTF-8"),
+    ]);
+    let args = server.args();
+
+    match path_query("/r/rust/.json", args).as_ref().map_err(RedditError::root) {
+      Err(RedditError::UnexpectedContentType { got }) =>
+        assert_eq!(got, "text/html; charset=UTF-8"),
+      other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(server.requests()[0].header("Accept").unwrap(), "application/json");
+  }
+
+  #[cfg(feature = "raw_value")]
+  #[test]
+  fn test_path_query_raw_keeps_bytes() {
+    use api::path_query_raw;
+    use mock::{MockServer, MockResponse};
+
+    let body = r#"{"z": 1.50, "a": [ 1,2 ], "kind":"Listing"}"#;
+    let server = MockServer::start(vec![MockResponse::json(body), MockResponse::json("{")]);
+    let args = server.args();
+
+    assert_eq!(path_query_raw("/r/rust/.json", args.clone()).unwrap().get(), body);
+    assert!(path_query_raw("/r/rust/.json", args).is_err());
+  }
+
+  #[test]
+  fn test_failed_request_error_names_method_and_url() {
+    use api::path_query;
+    use error::RedditError;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::status(200, "<html></html>").header("Content-Type", "text/html"),
+    ]);
+    let args = server.args();
+
+    let err = path_query("/r/rust/.json", args).unwrap_err();
+    let url = format!("{}/r/rust/.json?raw_json=1", server.base_url);
+    match err {
+      RedditError::Request { ref method, url: ref failed, ref source } => {
+        assert_eq!(method, "GET");
+        assert_eq!(failed, &url);
+        assert!(matches!(**source, RedditError::UnexpectedContentType { .. }));
+      },
+      ref other => panic!("unexpected {:?}", other),
+    }
+    assert!(err.to_string().starts_with(&format!("GET {}: ", url)), "{}", err);
+  }
+
+  #[test]
+  fn test_cloudflare_challenge_is_blocked() {
+    use api::path_query;
+    use error::RedditError;
+    use mock::{MockServer, MockResponse};
+
+    let challenge = r#"<!DOCTYPE html><html><head><title>Just a moment...</title></head>
+      <body><div id="cf-browser-verification"></div>
+      <script src="/cdn-cgi/challenge-platform/h/b/orchestrate/chl_page/v1"></script>
+      </body></html>"#;
+    let server = MockServer::start(vec![
+      MockResponse::status(503, challenge)
+        .header("Server", "cloudflare")
+        .header("CF-RAY", "8a1b2c3d4e5f6789-AMS")
+        .header("Content-Type", "text/html; charset=UTF-8"),
+    ]);
+    let args = server.args();
+
+    match path_query("/r/rust/.json", args).as_ref().map_err(RedditError::root) {
+      Err(RedditError::Blocked { reason }) => assert_eq!(reason, "cloudflare_challenge"),
+      other => panic!("unexpected {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_gen_headers_order_is_stable() {
+    use api::gen_headers;
+    use config::ArgsBuilder;
+
+    let args = ArgsBuilder::new()
+      .header("User-Agent", "test-user")
+      .referer("https://example.com/")
+      .header("X-Custom", "1")
+      .accept_language("en-US")
+      .build().unwrap();
+
+    let expected = vec![
+      "User-Agent: test-user",
+      "Referer: https://example.com/",
+      "X-Custom: 1",
+      "Accept-Language: en-US",
+      "Accept: application/json",
+    ];
+    for _ in 0..10 {
+      assert_eq!(return_vec_from_list(gen_headers(&args.clone()).unwrap()), expected);
+    }
+  }
+
+  /// a three post listing with multi-byte titles, gzipped
+  const GZIPPED_LISTING: &[u8] = &[
+    0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xab, 0x56, 0xca, 0xce, 0xcc, 0x4b,
+    0x51, 0xb2, 0x52, 0x50, 0xf2, 0xc9, 0x2c, 0x2e, 0xc9, 0xcc, 0x4b, 0x57, 0xd2, 0x51, 0x50, 0x4a,
+    0x49, 0x2c, 0x49, 0x04, 0x0a, 0x55, 0x2b, 0x25, 0x67, 0x64, 0xe6, 0xa4, 0x14, 0xa5, 0xe6, 0x01,
+    0x39, 0xd1, 0xd5, 0x70, 0x95, 0x25, 0xc6, 0x28, 0x8a, 0xf2, 0x12, 0x73, 0x53, 0x21, 0xc2, 0xf1,
+    0x89, 0x20, 0x89, 0x92, 0xcc, 0x92, 0x1c, 0xb0, 0x40, 0x7a, 0xd1, 0xe1, 0x6d, 0x87, 0xe7, 0xa7,
+    0x2a, 0x1c, 0xde, 0x93, 0x97, 0xa2, 0xf0, 0x61, 0xfe, 0xb2, 0x06, 0xa5, 0xda, 0x5a, 0x1d, 0x85,
+    0xc1, 0x65, 0x4e, 0x6c, 0x6d, 0x2d, 0x00, 0x0b, 0x2f, 0x61, 0x13, 0x03, 0x01, 0x00, 0x00,
+  ];
+
+  #[test]
+  fn test_gzip_response_matches_plain() {
+    use api::path_query;
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+    use std::time::Duration;
+
+    let post = r#"{"kind": "t3", "data": {"name": "t3_a", "title": "größe ünd 🦀"}}"#;
+    let plain = format!(r#"{{"kind": "Listing", "data": {{"children": [{}, {}, {}]}}}}"#,
+      post, post, post);
+    let gzipped = MockResponse {
+      body : GZIPPED_LISTING.to_vec(),
+      ..MockResponse::json("").header("Content-Encoding", "gzip")
+    };
+    // trickled, so the decoded chunks split the multi-byte characters
+    let server = MockServer::start(vec![
+      gzipped.trickled(Duration::from_millis(1)),
+      MockResponse::json(&plain),
+    ]);
+    let args = server.args();
+
+    let decoded = path_query("/r/rust/.json", args.clone()).unwrap();
+    let expected = path_query("/r/rust/.json", Args { compression: false, ..args }).unwrap();
+    assert_eq!(decoded, expected);
+    assert_eq!(decoded["data"]["children"][2]["data"]["title"], "größe ünd 🦀");
+
+    let requests = server.requests();
+    assert!(requests[0].header("Accept-Encoding").unwrap().contains("gzip"));
+    assert_eq!(requests[1].header("Accept-Encoding"), None);
+  }
+
+  #[test]
+  fn test_fresh_connect_queries() {
+    use api::path_query;
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"n": 1}"#),
+      MockResponse::json(r#"{"n": 2}"#),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      fresh_connect : true,
+      ..Args::default()
+    };
+
+    assert_eq!(path_query("/a/.json", args.clone()).unwrap()["n"], 1);
+    assert_eq!(path_query("/b/.json", args).unwrap()["n"], 2);
+    assert_eq!(server.requests().len(), 2);
+  }
+
+  #[test]
+  fn test_paginate_limit_splits_pages() {
+    use api::paginate_limit;
+    use mock::{MockServer, MockResponse};
+    use models::Thing;
+
+    let page = |from: usize, after: &str| {
+      let names: Vec<String> = (from..from + 100).map(|n| format!("t3_{}", n)).collect();
+      let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+      MockResponse::json(&listing_page(after, &names))
+    };
+    let server = MockServer::start(
+      vec![page(0, "t3_99"), page(100, "t3_199"), page(200, "t3_299")]);
+    let args = server.args();
+
+    let pages: Vec<_> = paginate_limit::<Thing>("/r/rust/new/.json?raw_json=1", args, 250)
+      .collect::<Result<_, _>>().unwrap();
+    let total: usize = pages.iter().map(|p| p.len()).sum();
+    assert_eq!(total, 250);
+    assert_eq!(pages[2].children.last().unwrap().fullname(), Some("t3_249"));
+
+    let requests = server.requests();
+    let paths: Vec<&str> = requests.iter().map(|r| r.path.as_str()).collect();
+    assert_eq!(paths, vec![
+      "/r/rust/new/.json?raw_json=1&limit=100",
+      "/r/rust/new/.json?raw_json=1&after=t3_99&limit=100&count=100",
+      "/r/rust/new/.json?raw_json=1&after=t3_199&limit=50&count=200",
+    ]);
+  }
+
+  #[test]
+  fn test_listing_cursor_resume() {
+    use api::{paginate, ListingIter};
+    use mock::{MockServer, MockResponse};
+    use models::Thing;
+
+    let server = MockServer::start(vec![
+      MockResponse::json(&listing_page("t3_b", &["t3_a", "t3_b"])),
+      MockResponse::json(&listing_page("", &["t3_c"])),
+    ]);
+    let args = server.args();
+
+    let mut pages = paginate::<Thing>("/r/rust/new/.json", args.clone());
+    assert_eq!(pages.cursor(), None);
+    pages.next().unwrap().unwrap();
+    let saved = pages.cursor().unwrap();
+    assert_eq!(saved, "t3_b");
+    drop(pages);
+
+    let mut resumed = ListingIter::<Thing>::resume_from("/r/rust/new/.json", &saved, args);
+    let page = resumed.next().unwrap().unwrap();
+    assert_eq!(page.children[0].fullname(), Some("t3_c"));
+    assert_eq!(resumed.cursor(), None);
+    assert!(resumed.next().is_none());
+
+    assert_eq!(server.requests()[1].path, "/r/rust/new/.json?after=t3_b&raw_json=1");
+  }
+
+  #[test]
+  fn test_body_split_inside_multibyte_char() {
+    use api::BodyBuffer;
+
+    let text = "{\"title\": \"caf\u{e9} \u{1f980}\"}";
+    let bytes = text.as_bytes();
+    // 'é' is two bytes, cut between them
+    let cut = text.find('\u{e9}').unwrap() + 1;
+
+    let mut body = BodyBuffer::default();
+    assert_eq!(body.write(&bytes[..cut]), cut);
+    assert_eq!(body.write(&bytes[cut..]), bytes.len() - cut);
+    assert_eq!(body.into_string().unwrap(), text);
+
+    let mut broken = BodyBuffer::default();
+    broken.write(&bytes[..cut]);
+    assert!(matches!(broken.into_string(), Err(RedditError::Utf8(_))));
+  }
+
+  #[test]
+  fn test_path_query_value_matches_path_query() {
+    use api::{path_query, path_query_value};
+    use mock::{MockServer, MockResponse};
+    use serde_json::Value;
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"kind": "Listing"}"#),
+      MockResponse::json(r#"{"kind": "Listing"}"#),
+    ]);
+    let args = server.args();
+
+    let value: Value = path_query_value("/r/rust/.json", args.clone());
+    let result: Result<Value, RedditError> = path_query("/r/rust/.json", args);
+    assert_eq!(value, result.unwrap());
+  }
+
+  #[test]
+  #[should_panic(expected = "rquery! request failed")]
+  fn test_path_query_value_panics_on_failure() {
+    use api::path_query_value;
+    use config::Args;
+    use std::net::TcpListener;
+
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let args = Args { base_url: format!("http://{}", addr), ..Args::default() };
+    path_query_value("/r/rust/.json", args);
+  }
+
+  #[test]
+  fn test_raw_headers_are_opt_in() {
+    use api::path_query_response;
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::json("{}").header("X-Signature", "abc=="),
+      MockResponse::json("{}").header("X-Signature", "abc=="),
+    ]);
+    let args = server.args();
+
+    let plain = path_query_response("/r/rust/.json", args.clone()).unwrap();
+    assert_eq!(plain.header("X-Signature"), Some("abc=="));
+    assert!(plain.raw_headers.is_empty());
+
+    let args = Args { capture_raw_headers: true, ..args };
+    let raw = path_query_response("/r/rust/.json", args).unwrap();
+    assert_eq!(raw.header("X-Signature"), Some("abc=="));
+    assert!(raw.raw_headers[0].starts_with(b"HTTP/1.1 200"));
+    assert!(raw.raw_headers.contains(&b"X-Signature: abc==\r\n".to_vec()));
+  }
+
+  #[test]
+  fn test_user_comments_stops_at_max_and_end() {
+    use api::user_comments;
+    use mock::{MockServer, MockResponse};
+
+    let page = |after: &str, names: &[&str]| {
+      let children: Vec<String> = names.iter()
+        .map(|n| format!(r#"{{"kind": "t1", "data": {{"name": "{}", "body": "hi",
+          "link_id": "t3_x", "parent_id": "t3_x", "replies": ""}}}}"#, n))
+        .collect();
+      MockResponse::json(&format!(r#"{{"kind": "Listing", "data": {{"after": {},
+        "children": [{}]}}}}"#, after, children.join(",")))
+    };
+
+    let server = MockServer::start(vec![
+      page(r#""t1_b""#, &["t1_a", "t1_b"]),
+      page(r#""t1_d""#, &["t1_c", "t1_d"]),
+    ]);
+    let args = server.args();
+    let comments = user_comments("ferris", 3, args).unwrap();
+    let names: Vec<&str> = comments.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["t1_a", "t1_b", "t1_c"]);
+    let paths: Vec<String> = server.requests().iter().map(|r| r.path.clone()).collect();
+    assert_eq!(paths, vec![
+      "/user/ferris/comments/.json?limit=3&raw_json=1",
+      "/user/ferris/comments/.json?limit=3&after=t1_b&raw_json=1",
+    ]);
+
+    let server = MockServer::start(vec![page("null", &["t1_a"])]);
+    let args = server.args();
+    assert_eq!(user_comments("ferris", 50, args).unwrap().len(), 1);
+    assert_eq!(server.requests().len(), 1);
+  }
+
+  #[test]
+  fn test_username_available() {
+    use api::username_available;
+    use error::RedditError;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::json("true"),
+      MockResponse::json("false"),
+      MockResponse::json(
+        r#"{"json": {"errors": [["BAD_USERNAME", "invalid user name", "user"]]}}"#),
+    ]);
+    let args = server.args();
+
+    assert!(username_available("fresh_name", args.clone()).unwrap());
+    assert!(!username_available("spez", args.clone()).unwrap());
+    match username_available("a b", args) {
+      Err(RedditError::InvalidArgument(why)) => assert!(why.contains("BAD_USERNAME")),
+      other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(server.requests()[0].path,
+      "/api/username_available.json?user=fresh_name&raw_json=1");
+    assert_eq!(server.requests()[2].path, "/api/username_available.json?user=a%20b&raw_json=1");
+  }
+
+  #[test]
+  fn test_user_about() {
+    use api::user_about;
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"kind": "t2", "data": {"name": "spez", "id": "1w72",
+        "link_karma": 100, "comment_karma": 250, "total_karma": 350,
+        "icon_img": "https://styles.redditmedia.com/a.png?width=256&s=1",
+        "created_utc": 1118030400.0, "is_suspended": false}}"#),
+      MockResponse::json(r#"{"kind": "t2", "data": {"name": "banned", "is_suspended": true}}"#),
+    ]);
+    let args = server.args();
+
+    let spez = user_about("spez", args.clone()).unwrap();
+    assert_eq!(spez.comment_karma, 250);
+    assert_eq!(spez.total_karma, 350);
+    assert_eq!(spez.icon_img, "https://styles.redditmedia.com/a.png?width=256&s=1");
+    assert_eq!(spez.created_utc, Some(1118030400.0));
+    assert!(!spez.is_suspended);
+    assert_eq!(server.requests()[0].path, "/user/spez/about/.json?raw_json=1");
+
+    let banned = user_about("banned", args).unwrap();
+    assert!(banned.is_suspended);
+    assert_eq!(banned.name, "banned");
+    assert_eq!(banned.created_utc, None);
+
+    assert!(matches!(user_about("no/slash", Args::default()), Err(RedditError::InvalidName(_))));
+  }
+
+  #[test]
+  fn test_missing_ua_warning() {
+    use api::missing_ua_warning;
+    use config::Args;
+
+    assert!(missing_ua_warning(&Args::default()).is_none());
+    assert!(missing_ua_warning(&Args::empty()).is_some());
+
+    let anonymous = Args::empty().allow_no_user_agent();
+    assert!(missing_ua_warning(&anonymous).is_none());
+    assert!(anonymous.headers.iter().any(|h| h.0 == "User-Agent"));
+
+    let cleared = anonymous.with_headers(Vec::new());
+    assert!(missing_ua_warning(&cleared).is_none());
+
+    // an existing User-Agent is left alone
+    let custom = Args::default().with_headers(vec![("user-agent".to_owned(), "bot/1".to_owned())])
+      .allow_no_user_agent();
+    assert_eq!(custom.headers.len(), 1);
+  }
+
+  #[test]
+  fn test_user_agent_field_wins_over_headers() {
+    use api::{header_pairs, missing_ua_warning};
+    use config::{Args, DEFAULT_USER_AGENT};
+
+    let agents = |args: &Args| -> Vec<String> {
+      header_pairs(args).into_iter()
+        .filter(|h| h.0.eq_ignore_ascii_case("User-Agent"))
+        .map(|h| h.1)
+        .collect()
+    };
+
+    assert_eq!(agents(&Args::default()), vec![DEFAULT_USER_AGENT]);
+
+    let field = Args { user_agent : Some("my-bot/1.0".to_owned()), ..Args::default() };
+    assert_eq!(agents(&field), vec!["my-bot/1.0"]);
+
+    let both = Args {
+      user_agent : Some("my-bot/1.0".to_owned()),
+      headers : Args::headers_from_str("user-agent: from-headers,Accept-Language: en"),
+      ..Args::default()
+    };
+    assert_eq!(agents(&both), vec!["my-bot/1.0"]);
+    assert!(header_pairs(&both).iter().any(|h| h.0 == "Accept-Language"));
+
+    let headers_only = Args::default().with_headers(Args::headers_from_str("User-Agent: x/2"));
+    assert_eq!(agents(&headers_only), vec!["x/2"]);
+    assert!(missing_ua_warning(&Args { user_agent : Some("a".to_owned()), ..Args::empty() })
+      .is_none());
+  }
+
+  #[test]
+  fn test_path_query_with_mock_transport() {
+    use api::path_query_with;
+    use config::Args;
+    use transport::{MockTransport, RawResponse};
+
+    let transport = MockTransport::new(vec![
+      RawResponse::json(r#"{"kind": "Listing", "data": {"children": []}}"#),
+      RawResponse { status: 200, headers: Vec::new(), body: vec![0xff, 0xfe],
+        ..RawResponse::default() },
+    ]);
+    let args = Args::default();
+
+    let value = path_query_with("/r/rust/.json", &args, &transport).unwrap();
+    assert_eq!(value["kind"], "Listing");
+    assert_eq!(transport.requests()[0].url, "https://www.reddit.com/r/rust/.json?raw_json=1");
+
+    let err = path_query_with("/r/rust/.json", &args, &transport).unwrap_err();
+    assert!(matches!(*err.root(), RedditError::Utf8(_)));
+  }
+
+  #[test]
+  fn test_path_query_with_sends_assembled_headers() {
+    use api::path_query_with;
+    use config::Args;
+    use transport::{MockTransport, RawResponse};
+
+    let transport = MockTransport::new(vec![RawResponse::json("{}")]);
+    let args = Args {
+      headers : vec![
+        ("User-Agent".to_owned(), "replaced".to_owned()),
+        ("X-Extra".to_owned(), "1".to_owned()),
+      ],
+      user_agent : Some("mock-test/1".to_owned()),
+      ..Args::default()
+    };
+
+    path_query_with("/r/rust/.json", &args, &transport).unwrap();
+    let request = &transport.requests()[0];
+    assert_eq!(request.method, "GET");
+    assert_eq!(request.headers, vec![
+      ("User-Agent".to_owned(), "mock-test/1".to_owned()),
+      ("X-Extra".to_owned(), "1".to_owned()),
+      ("Accept".to_owned(), "application/json".to_owned()),
+    ]);
+  }
+
+  #[test]
+  fn test_status_errors_carry_reddit_reasons() {
+    use api::path_query;
+    use config::Args;
+    use error::Restriction;
+    use mock::{MockServer, MockResponse};
+
+    let error = |status: u16, body: &str| MockResponse::status(status, body)
+      .header("Content-Type", "application/json; charset=UTF-8");
+    let server = MockServer::start(vec![
+      error(404, r#"{"message": "Not Found", "error": 404}"#),
+      error(403, r#"{"reason": "private", "message": "Forbidden", "error": 403}"#),
+      error(404, r#"{"reason": "banned", "message": "Not Found", "error": 404}"#),
+      error(403, r#"{"reason": "quarantined", "quarantine_message": "...",
+        "message": "Forbidden", "error": 403}"#),
+      MockResponse::status(302, "").header("Location", "/login"),
+      MockResponse::json(r#"{"kind": "t5", "data": {"display_name": "rust"}}"#),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      follow_redirects : false,
+      ..Args::default()
+    };
+    let query = || path_query("/r/somewhere/about/.json", args.clone());
+
+    let err = query().unwrap_err();
+    assert!(matches!(*err.root(), RedditError::Http { status : 404, .. }), "{:?}", err);
+    assert!(err.to_string().ends_with("reddit responded with http 404 (Not Found)"), "{}", err);
+
+    let reason = |err: RedditError| match *err.root() {
+      RedditError::Restricted { ref reason, status, .. } => (reason.clone(), status),
+      ref other => panic!("{:?}", other),
+    };
+    assert_eq!(reason(query().unwrap_err()), (Restriction::Private, 403));
+    assert_eq!(reason(query().unwrap_err()), (Restriction::Banned, 404));
+    let quarantined = query().unwrap_err();
+    assert_eq!(quarantined.status_code(), Some(403));
+    assert_eq!(reason(quarantined), (Restriction::Quarantined, 403));
+
+    assert!(matches!(*query().unwrap_err().root(), RedditError::Http { status : 302, .. }));
+    assert_eq!(query().unwrap()["data"]["display_name"], "rust");
+  }
+
+  #[test]
+  fn test_path_query_with_maps_failures() {
+    use api::path_query_with;
+    use config::Args;
+    use std::time::Duration;
+    use transport::{MockTransport, RawResponse};
+
+    let status = |status: u32, headers: &[(&str, &str)], body: &str| RawResponse {
+      status,
+      headers : headers.iter().map(|&(n, v)| (n.to_owned(), v.to_owned())).collect(),
+      body : body.as_bytes().to_vec(),
+      ..RawResponse::default()
+    };
+    let transport = MockTransport::new(vec![
+      status(429, &[("Retry-After", "3")], "{}"),
+      status(503, &[], "down"),
+      status(200, &[("Content-Type", "text/html")], "<html></html>"),
+      status(200, &[("Content-Type", "application/json")], "{\"kind\": "),
+    ]);
+    let args = Args::default();
+    let query = || path_query_with("/r/rust/.json", &args, &transport).unwrap_err();
+
+    let err = query();
+    assert!(err.is_rate_limited());
+    assert_eq!(err.retry_after(), Some(Duration::from_secs(3)));
+    assert!(matches!(*query().root(), RedditError::Http { status : 503, .. }));
+    assert!(matches!(*query().root(), RedditError::UnexpectedContentType { .. }));
+    assert!(matches!(*query().root(), RedditError::Json { .. }));
+    assert_eq!(transport.requests().len(), 4);
+  }
+
+  #[test]
+  fn test_json_depth_limits() {
+    use api::{check_json_depth, parse_json, path_query_with};
+    use config::Args;
+    use serde_json::Value;
+    use transport::{MockTransport, RawResponse};
+
+    let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+
+    // serde_json's own limit applies even without max_json_depth
+    assert!(parse_json::<Value>(&nested(100)).is_ok());
+    assert!(matches!(parse_json::<Value>(&nested(200)), Err(RedditError::Json { .. })));
+
+    assert!(check_json_depth(&nested(10), 10).is_ok());
+    assert!(check_json_depth(&nested(11), 10).is_err());
+    // brackets inside strings don't count
+    assert!(check_json_depth(r#"{"a": "[[[[\"{{{{"}"#, 1).is_ok());
+
+    let transport = MockTransport::new(vec![
+      RawResponse::json(&nested(20)),
+      RawResponse::json(&nested(20)),
+    ]);
+    let strict = Args { max_json_depth: Some(16), ..Args::default() };
+    let err = path_query_with("/r/rust/.json", &strict, &transport).unwrap_err();
+    assert!(matches!(*err.root(), RedditError::Json { .. }));
+    assert!(path_query_with("/r/rust/.json", &Args::default(), &transport).is_ok());
+  }
+
+  #[test]
+  fn test_paginate_to_writer() {
+    use api::paginate_to_writer;
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+    use serde_json;
+    use serde_json::Value;
+    use std::io::{self, Write};
+
+    /// remembers how much had been written at each flush
+    struct Recorder { bytes: Vec<u8>, flushed_at: Vec<usize> }
+
+    impl Write for Recorder {
+      fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.extend_from_slice(buf);
+        Ok(buf.len())
+      }
+
+      fn flush(&mut self) -> io::Result<()> {
+        self.flushed_at.push(self.bytes.len());
+        Ok(())
+      }
+    }
+
+    let server = MockServer::start(vec![
+      MockResponse::json(&listing_page("t3_b", &["t3_a", "t3_b"])),
+      MockResponse::json(&listing_page("t3_c", &["t3_c"])),
+      MockResponse::json(&listing_page("", &["t3_d"])),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      max_pages : Some(2),
+      ..Args::default()
+    };
+
+    let mut out = Recorder { bytes: Vec::new(), flushed_at: Vec::new() };
+    let written = paginate_to_writer("/r/rust/top/.json?t=all", args, &mut out).unwrap();
+    assert_eq!(written, 3);
+    assert_eq!(server.requests().len(), 2);
+
+    let text = String::from_utf8(out.bytes.clone()).unwrap();
+    let names: Vec<String> = text.lines()
+      .map(|line| serde_json::from_str::<Value>(line).unwrap()["name"].to_string())
+      .collect();
+    assert_eq!(names, vec!["\"t3_a\"", "\"t3_b\"", "\"t3_c\""]);
+
+    // one flush per page, the first one after the first page's two lines
+    assert_eq!(out.flushed_at.len(), 2);
+    assert_eq!(out.flushed_at[0], text.lines().take(2).map(|l| l.len() + 1).sum::<usize>());
+  }
+
+  #[test]
+  fn test_trending_subreddits() {
+    use api::trending_subreddits;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"subreddit_names": ["rust", "ferris", "crabs"],
+        "comment_count": 42, "comment_url": "/r/trendingsubreddits/comments/x/"}"#),
+      MockResponse::json(r#"{"comment_count": 0}"#),
+    ]);
+    let args = server.args();
+
+    assert_eq!(trending_subreddits(args.clone()).unwrap(), vec!["rust", "ferris", "crabs"]);
+    assert_eq!(server.requests()[0].path, "/api/trending_subreddits/.json?raw_json=1");
+
+    assert!(matches!(trending_subreddits(args), Err(RedditError::MissingField(_))));
+  }
+
+  #[test]
+  fn test_setup_connection_applies_options() {
+    use api::{setup_connection, ConnectionOptions};
+    use config::Args;
+    use curl;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Recorder { set: Vec<String> }
+
+    impl ConnectionOptions for Recorder {
+      fn fresh_connect(&mut self, enable: bool) -> Result<(), curl::Error> {
+        self.set.push(format!("fresh_connect={}", enable));
+        Ok(())
+      }
+      fn forbid_reuse(&mut self, enable: bool) -> Result<(), curl::Error> {
+        self.set.push(format!("forbid_reuse={}", enable));
+        Ok(())
+      }
+      fn timeout(&mut self, timeout: Duration) -> Result<(), curl::Error> {
+        self.set.push(format!("timeout={}", timeout.as_secs()));
+        Ok(())
+      }
+      fn dns_cache_timeout(&mut self, timeout: Duration) -> Result<(), curl::Error> {
+        self.set.push(format!("dns_cache_timeout={}", timeout.as_secs()));
+        Ok(())
+      }
+      fn tcp_nodelay(&mut self, enable: bool) -> Result<(), curl::Error> {
+        self.set.push(format!("tcp_nodelay={}", enable));
+        Ok(())
+      }
+      fn low_speed_limit(&mut self, bytes_per_sec: u32) -> Result<(), curl::Error> {
+        self.set.push(format!("low_speed_limit={}", bytes_per_sec));
+        Ok(())
+      }
+      fn low_speed_time(&mut self, window: Duration) -> Result<(), curl::Error> {
+        self.set.push(format!("low_speed_time={}", window.as_secs()));
+        Ok(())
+      }
+      fn cainfo(&mut self, path: &Path) -> Result<(), curl::Error> {
+        self.set.push(format!("cainfo={}", path.display()));
+        Ok(())
+      }
+      fn accept_encoding(&mut self, encoding: &str) -> Result<(), curl::Error> {
+        self.set.push(format!("accept_encoding={}", encoding));
+        Ok(())
+      }
+      fn follow_location(&mut self, enable: bool) -> Result<(), curl::Error> {
+        self.set.push(format!("follow_location={}", enable));
+        Ok(())
+      }
+      fn max_redirections(&mut self, max: u32) -> Result<(), curl::Error> {
+        self.set.push(format!("max_redirections={}", max));
+        Ok(())
+      }
+      fn connect_timeout(&mut self, timeout: Duration) -> Result<(), curl::Error> {
+        self.set.push(format!("connect_timeout={}", timeout.as_secs()));
+        Ok(())
+      }
+      fn proxy(&mut self, url: &str) -> Result<(), curl::Error> {
+        self.set.push(format!("proxy={}", url));
+        Ok(())
+      }
+    }
+
+    let mut handle = Recorder::default();
+    setup_connection(&mut handle, &Args::default()).unwrap();
+    assert!(!handle.set.iter().any(|s| s.starts_with("dns_cache_timeout")));
+    assert!(handle.set.contains(&"tcp_nodelay=true".to_owned()));
+    assert!(handle.set.contains(&"accept_encoding=".to_owned()));
+    assert!(handle.set.contains(&"connect_timeout=10".to_owned()));
+    assert!(handle.set.contains(&"follow_location=true".to_owned()));
+    assert!(handle.set.contains(&"max_redirections=5".to_owned()));
+    assert!(!handle.set.iter().any(|s| s.starts_with("proxy")));
+
+    let mut handle = Recorder::default();
+    setup_connection(&mut handle, &Args::empty()).unwrap();
+    assert!(handle.set.contains(&"follow_location=false".to_owned()));
+    assert!(!handle.set.iter().any(|s| s.starts_with("connect_timeout")));
+
+    let mut handle = Recorder::default();
+    let args = Args { tcp_nodelay: false, compression: false, ..Args::default() };
+    setup_connection(&mut handle, &args).unwrap();
+    assert!(handle.set.contains(&"tcp_nodelay=false".to_owned()));
+    assert!(!handle.set.iter().any(|s| s.starts_with("accept_encoding")));
+
+    let args = Args { dns_cache_timeout: Some(Duration::from_secs(5)), ..Args::default() };
+    let mut handle = Recorder::default();
+    setup_connection(&mut handle, &args).unwrap();
+    assert!(handle.set.contains(&"dns_cache_timeout=5".to_owned()));
+    assert!(handle.set.contains(&"timeout=30".to_owned()));
+    assert!(!handle.set.iter().any(|s| s.starts_with("low_speed")));
+
+    let args = Args { low_speed_limit: Some((100, Duration::from_secs(2))), ..Args::default() };
+    let mut handle = Recorder::default();
+    setup_connection(&mut handle, &args).unwrap();
+    assert!(handle.set.contains(&"low_speed_limit=100".to_owned()));
+    assert!(handle.set.contains(&"low_speed_time=2".to_owned()));
+  }
+
+  #[test]
+  fn test_ca_bundle_must_exist() {
+    use api::{path_query, setup_connection};
+    use config::Args;
+    use curl::easy::Easy;
+    use error::RedditError;
+    use std::env;
+    use std::fs;
+
+    let bundle = env::temp_dir().join(format!("rust_reddit_ca_{}.pem", std::process::id()));
+    fs::write(&bundle, "").unwrap();
+    let args = Args { ca_bundle: Some(bundle.clone()), ..Args::default() };
+    assert!(setup_connection(&mut Easy::new(), &args).is_ok());
+    fs::remove_file(&bundle).unwrap();
+
+    let args = Args { ca_bundle: Some(bundle.clone()), ..Args::default() };
+    match path_query("/r/rust/.json", args).as_ref().map_err(RedditError::root) {
+      Err(RedditError::InvalidArgument(why)) =>
+        assert!(why.contains(&bundle.display().to_string()), "{}", why),
+      other => panic!("unexpected {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_low_speed_limit_aborts_trickling_transfer() {
+    use api::path_query;
+    use config::Args;
+    use error::RedditError;
+    use mock::{MockServer, MockResponse};
+    use std::time::{Duration, Instant};
+
+    let body = "{\"data\": {\"children\": []}}";
+    let server = MockServer::start(vec![
+      MockResponse::json(body).trickled(Duration::from_millis(300)),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      low_speed_limit : Some((100, Duration::from_secs(1))),
+      ..Args::default()
+    };
+
+    let started = Instant::now();
+    let err = path_query("/r/rust/.json", args).unwrap_err();
+    assert!(matches!(*err.root(), RedditError::Timeout(_)), "{:?}", err);
+    assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+  }
+
+  #[test]
+  fn test_pretty() {
+    use api::pretty;
+    use serde_json;
+
+    let value: serde_json::Value = serde_json::from_str(r#"{"kind": "t3", "data": {"score": 1}}"#)
+      .unwrap();
+    let text = pretty(&value);
+    assert!(text.contains("\n  \"data\": {\n    \"score\": 1\n  }"));
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&text).unwrap(), value);
+  }
+
+  #[test]
+  fn test_prune_listing() {
+    use api::prune_listing;
+    use serde_json;
+
+    let listing = serde_json::json!({"kind": "Listing", "data": {"after": "t3_b", "children": [
+      {"kind": "t3", "data": {"name": "t3_a", "title": "hi", "score": 3, "selftext": "long"}},
+      {"kind": "t3", "data": {"name": "t3_b", "preview": {"images": []}}},
+    ]}});
+
+    let pruned = prune_listing(listing, &["name", "score"]);
+    assert_eq!(pruned, serde_json::json!({"kind": "Listing", "data": {"after": "t3_b",
+      "children": [
+        {"kind": "t3", "data": {"name": "t3_a", "score": 3}},
+        {"kind": "t3", "data": {"name": "t3_b"}},
+      ]}}));
+
+    let not_a_listing = serde_json::json!([1, 2]);
+    assert_eq!(prune_listing(not_a_listing.clone(), &["name"]), not_a_listing);
+  }
+
+  #[test]
+  fn test_cancel_flag_aborts_transfer() {
+    use api::path_query;
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    let server = MockServer::start(vec![
+      MockResponse::json("{}").delayed(Duration::from_secs(5)),
+    ]);
+    let cancel = Arc::new(AtomicBool::new(false));
+    let args = Args {
+      base_url : server.base_url.clone(),
+      cancel : Some(cancel.clone()),
+      ..Args::default()
+    };
+
+    let flag = cancel.clone();
+    let canceller = thread::spawn(move || {
+      thread::sleep(Duration::from_millis(200));
+      flag.store(true, Ordering::SeqCst);
+    });
+
+    let started = Instant::now();
+    let err = path_query("/r/rust/new/.json", args).unwrap_err();
+    canceller.join().unwrap();
+    assert!(matches!(*err.root(), RedditError::Cancelled), "{:?}", err);
+    assert!(started.elapsed() < Duration::from_secs(4));
+  }
+
+  #[test]
+  fn test_on_complete_reports_form_posts() {
+    use api::post;
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+    use std::sync::{Arc, Mutex};
+    use transport::{PreparedRequest, RawResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::status(500, "oops"),
+    ]);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    let args = Args {
+      key : "token".to_owned(),
+      oauth_url : server.base_url.clone(),
+      danger_allow_insecure : true,
+      on_complete : Some(Arc::new(move |req: &PreparedRequest, result: &Result<_, _>, _| {
+        let status = result.as_ref().map(|raw: &RawResponse| raw.status).ok();
+        log.lock().unwrap().push((req.method.clone(), req.url.clone(), status));
+      })),
+      ..Args::default()
+    };
+
+    let err = post("/api/subscribe", &[("action", "sub")], args).unwrap_err();
+    assert_eq!(err.status_code(), Some(500));
+    assert_eq!(*seen.lock().unwrap(), vec![
+      ("POST".to_owned(), format!("{}/api/subscribe?raw_json=1", server.base_url),
+        Some(500)),
+    ]);
+  }
+
+  #[test]
+  fn test_path_query_as_typed_top_listing() {
+    use api::path_query_as;
+    use mock::{MockServer, MockResponse};
+    use models::{Listing, Post, Thing};
+
+    // trimmed from a real /r/rust/top/.json, extra fields left in on purpose
+    const TOP: &str = r#"{"kind": "Listing", "data": {"after": "t3_1b2c3d", "dist": 2,
+      "modhash": "", "geo_filter": "", "before": null, "children": [
+      {"kind": "t3", "data": {"approved_at_utc": null, "subreddit": "rust",
+        "selftext": "", "author_fullname": "t2_6l4z3", "saved": false, "gilded": 0,
+        "title": "Announcing Rust 1.77.0", "subreddit_name_prefixed": "r/rust",
+        "name": "t3_1bi8xmq", "upvote_ratio": 0.99, "ups": 587, "score": 587,
+        "thumbnail": "", "over_18": false, "author": "rustacean", "num_comments": 64,
+        "permalink": "/r/rust/comments/1bi8xmq/announcing_rust_1770/",
+        "url": "https://blog.rust-lang.org/2024/03/21/Rust-1.77.0.html",
+        "subreddit_id": "t5_2s7lj", "id": "1bi8xmq", "created_utc": 1711036800.0,
+        "all_awardings": [], "media": null, "is_video": false}},
+      {"kind": "t3", "data": {"subreddit": "rust", "selftext": "What are you working on?",
+        "title": "What's everyone working on this week?", "name": "t3_1b2c3d",
+        "score": "12", "over_18": true, "author": "llogiq", "num_comments": 30,
+        "permalink": "/r/rust/comments/1b2c3d/whats_everyone_working_on/",
+        "url": "https://www.reddit.com/r/rust/comments/1b2c3d/whats_everyone_working_on/",
+        "id": "1b2c3d", "created_utc": 1710720000, "stickied": true,
+        "link_flair_richtext": [{"e": "text", "t": "weekly"}]}}]}}"#;
+
+    let server = MockServer::start(vec![MockResponse::json(TOP), MockResponse::json(TOP)]);
+    let args = server.args();
+
+    let top: Listing<Post> = path_query_as("/r/rust/top/.json", args.clone()).unwrap();
+    assert_eq!(top.after, Some("t3_1b2c3d".to_owned()));
+    assert_eq!(top.len(), 2);
+    assert_eq!(top.children[0].title, "Announcing Rust 1.77.0");
+    assert_eq!(top.children[0].author, "rustacean");
+    assert_eq!(top.children[0].score, 587);
+    assert_eq!(top.children[0].num_comments, 64);
+    assert!(!top.children[0].over_18);
+    assert_eq!(top.children[1].score, 12);
+    assert!(top.children[1].over_18);
+    assert_eq!(top.children[1].created_utc, 1710720000.0);
+
+    let things: Listing<Thing> = path_query_as("/r/rust/top/.json", args).unwrap();
+    assert!(things.iter().all(|t| matches!(*t, Thing::Post(_))));
+  }
+
+  #[test]
+  fn test_post_raw_sends_body_with_content_type() {
+    use api::post_raw;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![MockResponse::json(r#"{"ok": true}"#)]);
+    let args = server.oauth_args();
+
+    let body = br#"{"text": "hello", "richtext": []}"#;
+    let value = post_raw("/api/v1/raw", body, "application/json", args).unwrap();
+    assert_eq!(value["ok"], true);
+
+    let request = &server.requests()[0];
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.body, body.to_vec());
+    assert_eq!(request.header("Content-Type").unwrap(), "application/json");
+    assert_eq!(request.header("Authorization").unwrap(), "bearer token");
+  }
+
+  #[test]
+  fn test_post_raw_streams_large_bodies() {
+    use api::post_raw;
+    use error::RedditError;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![MockResponse::echo()]);
+    let args = server.oauth_args();
+
+    // well past curl's 64k read buffer, so the body goes out in many reads
+    let text = "x".repeat(1 << 20);
+    let body = format!(r#"{{"text": "{}"}}"#, text);
+    let echoed = post_raw("/api/v1/raw", body.as_bytes(), "application/json", args.clone())
+      .unwrap();
+    assert_eq!(echoed["text"].as_str().unwrap().len(), text.len());
+    assert_eq!(server.requests()[0].header("Content-Length").unwrap(), body.len().to_string());
+
+    let err = post_raw("/api/v1/raw", b"{}", "application/json\r\nX-Evil: 1", args).unwrap_err();
+    assert!(matches!(err, RedditError::InvalidArgument(_)));
+  }
+
+  #[test]
+  fn test_post_refusal_carries_the_reason() {
+    use api::post;
+    use error::{RedditError, Restriction};
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::status(403, r#"{"reason": "banned", "message": "Forbidden", "error": 403}"#)
+        .header("Content-Type", "application/json"),
+    ]);
+    let args = server.oauth_args();
+
+    let err = post("/api/subscribe", &[("sr_name", "rust")], args).unwrap_err();
+    assert!(matches!(err, RedditError::Restricted { status : 403, reason : Restriction::Banned,
+      .. }), "{:?}", err);
+  }
+
+  #[test]
+  fn test_credentials_need_https() {
+    use api::{oauth_query, path_query_with};
+    use config::Args;
+    use error::RedditError;
+    use transport::{MockTransport, RawResponse};
+
+    let bearer = vec![("Authorization".to_owned(), "bearer token".to_owned())];
+    let transport = MockTransport::new(vec![RawResponse::json("{}"), RawResponse::json("{}")]);
+
+    let plain = Args {
+      base_url : "http://reddit.example".to_owned(),
+      headers : bearer.clone(),
+      ..Args::default()
+    };
+    let err = path_query_with("/r/rust/.json", &plain, &transport).unwrap_err();
+    assert!(matches!(err, RedditError::InsecureTransport { .. }), "{:?}", err);
+    assert!(transport.requests().is_empty());
+
+    let err = oauth_query("/api/v1/me", Args {
+      key : "token".to_owned(),
+      oauth_url : "http://oauth.reddit.example".to_owned(),
+      ..Args::default()
+    }).unwrap_err();
+    assert!(matches!(err, RedditError::InsecureTransport { .. }), "{:?}", err);
+
+    let secure = Args { headers : bearer, ..Args::default() };
+    path_query_with("/r/rust/.json", &secure, &transport).unwrap();
+    path_query_with("/r/rust/.json", &Args { danger_allow_insecure : true, ..plain }, &transport)
+      .unwrap();
+    assert_eq!(transport.requests().len(), 2);
+  }
+
+  #[test]
+  fn test_response_effective_url_after_redirect() {
+    use api::path_query_response;
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::status(301, "").header("Location", "/r/rust/comments/abc/a_thread/.json"),
+      MockResponse::json("[]"),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      follow_redirects : true,
+      ..Args::default()
+    };
+
+    let response = path_query_response("/comments/abc/.json", args).unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.effective_url,
+      format!("{}/r/rust/comments/abc/a_thread/.json", server.base_url));
+  }
+
+  #[test]
+  fn test_macros_work_inside_the_crate() {
+    use mock::{MockServer, MockResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"ok": true}"#),
+      MockResponse::json(r#"{"ok": 1}"#),
+    ]);
+    let agent = format!("macro-test/{}", 1);
+    let value = try_rquery!("/r/rust/.json",
+      "base_url" => server.base_url,
+      "user_agent" => agent,
+      "timeout_ms" => 5000).unwrap();
+    assert_eq!(value["ok"], true);
+    assert_eq!(rquery!("/r/rust/.json", "base_url" => server.base_url)["ok"], 1);
+
+    let requests = server.requests();
+    assert_eq!(requests[0].header("User-Agent").unwrap(), "macro-test/1");
+    assert_eq!(requests[0].path, "/r/rust/.json?raw_json=1");
+  }
+
+  #[test]
+  fn test_try_rquery_rejects_bad_values() {
+    use error::RedditError;
+
+    let err = try_rquery!("/r/rust/.json", "timeout_ms" => "soon").unwrap_err();
+    assert!(matches!(err, RedditError::InvalidArgument(_)), "{:?}", err);
+    let err = try_rquery!("/r/rust/.json", "headers" => "bogus").unwrap_err();
+    assert!(matches!(err, RedditError::InvalidArgument(_)), "{:?}", err);
+  }
+
+  #[test]
+  fn test_redirects_follow_by_default_up_to_max_redirects() {
+    use api::path_query_response;
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+
+    let moved = || MockResponse::status(301, "").header("Location", "/r/rust/.json");
+    let server = MockServer::start(vec![moved(), MockResponse::json("{}"), moved()]);
+    let args = server.args();
+    assert_eq!(path_query_response("/r/Rust/.json", args.clone()).unwrap().status, 200);
+
+    // with no redirects allowed curl gives up on the 301 instead
+    let args = Args { max_redirects : 0, ..args };
+    assert!(path_query_response("/r/Rust/.json", args).is_err());
+  }
+
+  #[test]
+  fn test_requests_go_through_proxy() {
+    use api::path_query;
+    use config::Args;
+    use mock::{MockServer, MockResponse};
+
+    let proxy = MockServer::start(vec![MockResponse::json("{}")]);
+    let args = Args {
+      base_url : "http://reddit.invalid".to_owned(),
+      proxy : Some(proxy.base_url.clone()),
+      ..Args::default()
+    };
+
+    path_query("/r/rust/.json", args).unwrap();
+    let requests = proxy.requests();
+    assert!(requests[0].path.starts_with("http://reddit.invalid/r/rust/.json"),
+      "{}", requests[0].path);
+  }
+
+  #[test]
+  fn test_timeout_aborts_slow_response() {
+    use api::path_query;
+    use config::Args;
+    use error::RedditError;
+    use mock::{MockServer, MockResponse};
+    use std::time::{Duration, Instant};
+
+    let server = MockServer::start(vec![
+      MockResponse::json("{}").delayed(Duration::from_secs(3)),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      timeout : Some(Duration::from_millis(500)),
+      ..Args::default()
+    };
+
+    let started = Instant::now();
+    let err = path_query("/r/rust/.json", args).unwrap_err();
+    assert!(matches!(*err.root(), RedditError::Timeout(_)), "{:?}", err);
+    assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+  }
 }
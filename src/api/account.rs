@@ -0,0 +1,563 @@
+
+// external imports
+use std::sync::Mutex;
+use serde_json::Value;
+
+// internal imports
+use api::{check_json_errors, collect_children, oauth_query, paginate_oauth,
+  parse_json_envelope, post, require_auth, require_granted_scope, validate_subreddit_name,
+  validate_username};
+use config::Args;
+use error::RedditError;
+use models::{Account, Comment, Fullname, Kind, Thing};
+
+////////////////////////////////////////////////////////////////////////////////
+// Account actions
+// Authenticated endpoints acting on behalf of the logged in account
+
+/// usernames already looked up through /api/v1/me, keyed by access token
+static USERNAMES: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// The name of the account args.key belongs to. /api/v1/me is only asked
+/// once per token, later calls are answered from memory.
+pub fn my_username(args: &Args) -> Result<String, RedditError> {
+  if let Some((_, name)) = USERNAMES.lock().unwrap().iter().find(|e| e.0 == args.key) {
+    return Ok(name.clone());
+  }
+
+  let me = oauth_query("/api/v1/me", args.clone())?;
+  let name = me["name"].as_str()
+    .ok_or_else(|| RedditError::MissingField("/name".to_owned()))?
+    .to_owned();
+  USERNAMES.lock().unwrap().push((args.key.clone(), name.clone()));
+  Ok(name)
+}
+
+/// The profile of the account args.key belongs to
+pub fn me(args: Args) -> Result<Account, RedditError> {
+  Account::from_value(oauth_query("/api/v1/me", args)?)
+}
+
+/// Everything the authenticated account has saved, posts and comments
+pub fn my_saved(args: Args) -> Result<Vec<Thing>, RedditError> {
+  my_listing("saved", args)
+}
+
+/// Everything the authenticated account has upvoted
+pub fn my_upvoted(args: Args) -> Result<Vec<Thing>, RedditError> {
+  my_listing("upvoted", args)
+}
+
+/// Everything the authenticated account has hidden
+pub fn my_hidden(args: Args) -> Result<Vec<Thing>, RedditError> {
+  my_listing("hidden", args)
+}
+
+fn my_listing(which: &str, args: Args) -> Result<Vec<Thing>, RedditError> {
+  let path = format!("/user/{}/{}", my_username(&args)?, which);
+  collect_children(paginate_oauth(&path, args))
+}
+
+/// Subscribes the authenticated account to a subreddit
+pub fn subscribe(subreddit: &str, args: Args) -> Result<(), RedditError> {
+  set_subscription(subreddit, "sub", args)
+}
+
+/// Unsubscribes the authenticated account from a subreddit
+pub fn unsubscribe(subreddit: &str, args: Args) -> Result<(), RedditError> {
+  set_subscription(subreddit, "unsub", args)
+}
+
+fn set_subscription(subreddit: &str, action: &str, args: Args) -> Result<(), RedditError> {
+  validate_subreddit_name(subreddit)?;
+  post("/api/subscribe", &[("action", action), ("sr_name", subreddit)], args)?;
+  Ok(())
+}
+
+/// Blocks a user for the authenticated account
+pub fn block_user(username: &str, args: Args) -> Result<(), RedditError> {
+  validate_username(username)?;
+  let value = post("/api/block_user", &[("api_type", "json"), ("name", username)], args)?;
+  parse_json_envelope(&value)?;
+  Ok(())
+}
+
+/// Lifts a block set with block_user; reddit keeps blocks as "enemy"
+/// relationships, so this goes through /api/unfriend
+pub fn unblock_user(username: &str, args: Args) -> Result<(), RedditError> {
+  validate_username(username)?;
+  let value = post("/api/unfriend", &[("api_type", "json"), ("name", username),
+    ("type", "enemy")], args)?;
+  parse_json_envelope(&value)?;
+  Ok(())
+}
+
+/// Which way a vote goes; Clear takes back an earlier vote
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VoteDirection {
+  Up,
+  Down,
+  Clear,
+}
+
+impl VoteDirection {
+  fn as_str(&self) -> &'static str {
+    match *self {
+      VoteDirection::Up => "1",
+      VoteDirection::Down => "-1",
+      VoteDirection::Clear => "0",
+    }
+  }
+}
+
+/// hide and unhide take at most this many posts at once
+pub const MAX_HIDE: usize = 50;
+
+/// Parses the fullname of a post, or of a comment too when comments are
+/// allowed. A bare id gets pointed at its missing prefix, since reddit
+/// would quietly do nothing with it.
+fn thing_fullname(name: &str, comments: bool) -> Result<Fullname, RedditError> {
+  if !name.is_empty() && !name.contains('_') && name.chars().all(|c| c.is_ascii_alphanumeric()) {
+    return Err(RedditError::InvalidArgument(format!(
+      "{:?} is a bare id, a fullname needs its kind too, e.g. \"t3_{}\"", name, name)));
+  }
+  let fullname: Fullname = name.parse()?;
+  match fullname.kind() {
+    Kind::Link => Ok(fullname),
+    Kind::Comment if comments => Ok(fullname),
+    _ => Err(RedditError::InvalidName(fullname.into())),
+  }
+}
+
+/// posts one of the thing actions, which answer with {} when they worked
+/// and a json.errors array, e.g. RATELIMIT, when they didn't; a token known
+/// to lack the action's scope fails before anything is sent
+fn thing_action(path: &str, scope: &str, form: &[(&str, &str)], args: Args)
+  -> Result<(), RedditError>
+{
+  require_granted_scope(&args, scope)?;
+  check_json_errors(&post(path, form, args)?)
+}
+
+/// Votes on a post or comment, by fullname, as the authenticated account
+pub fn vote<F: AsRef<str>>(fullname: F, direction: VoteDirection, args: Args)
+  -> Result<(), RedditError>
+{
+  let fullname = thing_fullname(fullname.as_ref(), true)?;
+  thing_action("/api/vote", "vote", &[("dir", direction.as_str()), ("id", fullname.as_str())], args)
+}
+
+/// Saves a post or comment, optionally into one of the account's save
+/// categories (a reddit premium feature)
+pub fn save<F: AsRef<str>>(fullname: F, category: Option<&str>, args: Args)
+  -> Result<(), RedditError>
+{
+  let fullname = thing_fullname(fullname.as_ref(), true)?;
+  match category {
+    Some(category) =>
+      thing_action("/api/save", "save", &[("category", category), ("id", fullname.as_str())], args),
+    None => thing_action("/api/save", "save", &[("id", fullname.as_str())], args),
+  }
+}
+
+/// Takes a post or comment out of the account's saved things
+pub fn unsave<F: AsRef<str>>(fullname: F, args: Args) -> Result<(), RedditError> {
+  let fullname = thing_fullname(fullname.as_ref(), true)?;
+  thing_action("/api/unsave", "save", &[("id", fullname.as_str())], args)
+}
+
+/// Hides up to MAX_HIDE posts from the account's listings
+pub fn hide<F: AsRef<str>>(fullnames: &[F], args: Args) -> Result<(), RedditError> {
+  set_hidden("/api/hide", fullnames, args)
+}
+
+/// Brings back posts hidden with hide
+pub fn unhide<F: AsRef<str>>(fullnames: &[F], args: Args) -> Result<(), RedditError> {
+  set_hidden("/api/unhide", fullnames, args)
+}
+
+fn set_hidden<F: AsRef<str>>(path: &str, fullnames: &[F], args: Args)
+  -> Result<(), RedditError>
+{
+  require_auth(&args)?;
+  if fullnames.len() > MAX_HIDE {
+    return Err(RedditError::InvalidArgument(format!(
+      "{} posts at once, reddit takes at most {}", fullnames.len(), MAX_HIDE)));
+  }
+  let ids = fullnames.iter()
+    .map(|name| thing_fullname(name.as_ref(), false).map(String::from))
+    .collect::<Result<Vec<String>, RedditError>>()?;
+  if ids.is_empty() {
+    return Ok(());
+  }
+  thing_action(path, "report", &[("id", &ids.join(","))], args)
+}
+
+/// What a new post links to: text of its own, or an outside url
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubmitKind {
+  SelfPost(String),
+  Link(String),
+}
+
+/// The post reddit created for a submit
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubmitResult {
+  /// fullname, e.g. "t3_abc123"
+  pub name : String,
+  pub url : String,
+  pub id : String,
+}
+
+impl SubmitResult {
+  /// reads the `json.data` of a submit response, failing with
+  /// RedditError::Api when `json.errors` is not empty
+  pub fn from_value(value: &Value) -> Result<SubmitResult, RedditError> {
+    let envelope = parse_json_envelope(value)?;
+
+    let field = |name: &str| envelope.data[name].as_str()
+      .map(|s| s.to_owned())
+      .ok_or_else(|| RedditError::MissingField(format!("/json/data/{}", name)));
+    Ok(SubmitResult { name : field("name")?, url : field("url")?, id : field("id")? })
+  }
+}
+
+/// Submits a new post to a subreddit as the authenticated account
+pub fn submit(subreddit: &str, title: &str, kind: SubmitKind, args: Args)
+  -> Result<SubmitResult, RedditError>
+{
+  validate_subreddit_name(subreddit)?;
+
+  let content = match kind {
+    SubmitKind::SelfPost(ref text) => [("kind", "self"), ("text", text.as_str())],
+    SubmitKind::Link(ref url) => [("kind", "link"), ("url", url.as_str())],
+  };
+  let form = [("api_type", "json"), content[0], ("sr", subreddit), ("title", title), content[1]];
+
+  require_granted_scope(&args, "submit")?;
+  SubmitResult::from_value(&post("/api/submit", &form, args)?)
+}
+
+/// submit for callers holding either a url or the text of a post: anything
+/// starting with http:// or https:// becomes a link post, the rest a self post
+pub fn submit_post(subreddit: &str, title: &str, url_or_selftext: &str, args: Args)
+  -> Result<SubmitResult, RedditError>
+{
+  let content = url_or_selftext.to_owned();
+  let kind = if content.starts_with("https://") || content.starts_with("http://") {
+    SubmitKind::Link(content)
+  } else {
+    SubmitKind::SelfPost(content)
+  };
+  submit(subreddit, title, kind, args)
+}
+
+/// Replies to a post or comment, given its fullname ("t3_..." or "t1_..."),
+/// as the authenticated account; hands back the comment reddit created
+pub fn submit_comment<F: AsRef<str>>(parent: F, text: &str, args: Args)
+  -> Result<Comment, RedditError>
+{
+  let parent: Fullname = parent.as_ref().parse()?;
+  if parent.kind() != Kind::Link && parent.kind() != Kind::Comment {
+    return Err(RedditError::InvalidName(parent.into()));
+  }
+
+  require_granted_scope(&args, "submit")?;
+  let value = post("/api/comment", &[("api_type", "json"), ("thing_id", parent.as_str()),
+    ("text", text)], args)?;
+  let mut things = parse_json_envelope(&value)?.things;
+  if things.is_empty() {
+    return Err(RedditError::MissingField("/json/data/things/0".to_owned()));
+  }
+  Comment::from_value(things.swap_remove(0))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_account {
+
+  use api::*;
+  use config::Args;
+  use error::RedditError;
+  use mock::{MockServer, MockResponse};
+
+  fn auth_args_offline() -> Args {
+    Args { key: "token".to_owned(), ..Args::default() }
+  }
+
+  #[test]
+  fn test_subscribe_form() {
+    let server = MockServer::start(vec![MockResponse::json("{}")]);
+
+    subscribe("rust", server.oauth_args()).unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/api/subscribe?raw_json=1");
+    assert_eq!(request.header("Authorization").unwrap(), "bearer token");
+    assert_eq!(String::from_utf8_lossy(&request.body), "action=sub&sr_name=rust");
+  }
+
+  #[test]
+  fn test_unsubscribe_form_with_empty_body() {
+    let server = MockServer::start(vec![MockResponse::status(200, "")]);
+
+    unsubscribe("rust", server.oauth_args()).unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!(String::from_utf8_lossy(&request.body), "action=unsub&sr_name=rust");
+  }
+
+  #[test]
+  fn test_block_and_unblock_user_forms() {
+    let server = MockServer::start(vec![MockResponse::json("{}"), MockResponse::json("{}")]);
+
+    block_user("spammer", server.oauth_args()).unwrap();
+    unblock_user("spammer", server.oauth_args()).unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/api/block_user?raw_json=1");
+    assert_eq!(String::from_utf8_lossy(&requests[0].body), "api_type=json&name=spammer");
+    assert_eq!(requests[1].path, "/api/unfriend?raw_json=1");
+    assert_eq!(
+      String::from_utf8_lossy(&requests[1].body), "api_type=json&name=spammer&type=enemy");
+  }
+
+  #[test]
+  fn test_block_user_checks_name_auth_and_errors() {
+    assert!(matches!(block_user("not valid!", Args { key: "token".to_owned(), ..Args::default() }),
+      Err(RedditError::InvalidName(_))));
+    assert!(matches!(unblock_user("spammer", Args::default()), Err(RedditError::AuthRequired)));
+
+    let server = MockServer::start(vec![MockResponse::json(
+      r#"{"json": {"errors": [["USER_DOESNT_EXIST", "that user doesn't exist", "name"]]}}"#)]);
+    match block_user("ghost", server.oauth_args()) {
+      Err(RedditError::Api(errors)) => assert_eq!(errors[0].code, "USER_DOESNT_EXIST"),
+      other => panic!("unexpected {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_me_flat_account() {
+    let server = MockServer::start(vec![MockResponse::json(r#"{"is_employee": false,
+      "name": "archiver", "id": "1w72", "link_karma": 12, "comment_karma": 340,
+      "total_karma": 352, "verified": true, "created_utc": 1500000000.0,
+      "features": {"chat": true}, "pref_nightmode": true}"#)]);
+
+    let account = me(server.oauth_args()).unwrap();
+    assert_eq!(account.name, "archiver");
+    assert_eq!(account.id, "1w72");
+    assert_eq!(account.comment_karma, 340);
+    assert!(account.verified);
+    assert_eq!(account.created_utc, Some(1500000000.0));
+    assert_eq!(server.requests()[0].path, "/api/v1/me?raw_json=1");
+  }
+
+  #[test]
+  fn test_subscribe_requires_auth() {
+    let err = subscribe("rust", Args::default()).unwrap_err();
+    assert!(matches!(err, RedditError::AuthRequired));
+  }
+
+  #[test]
+  fn test_subscribe_rejects_invalid_name() {
+    let err = subscribe("not a/sub", Args { key: "token".to_owned(), ..Args::default() })
+      .unwrap_err();
+    assert!(matches!(err, RedditError::InvalidName(_)));
+  }
+
+  #[test]
+  fn test_submit_parses_new_post() {
+    let server = MockServer::start(vec![MockResponse::json(r#"{"json": {"errors": [],
+      "data": {"url": "https://www.reddit.com/r/rust/comments/abc123/hello/",
+        "drafts_count": 0, "id": "abc123", "name": "t3_abc123"}}}"#)]);
+
+    let result = submit("rust", "hello", SubmitKind::SelfPost("first!".to_owned()),
+      server.oauth_args()).unwrap();
+    assert_eq!(result, SubmitResult {
+      name : "t3_abc123".to_owned(),
+      url : "https://www.reddit.com/r/rust/comments/abc123/hello/".to_owned(),
+      id : "abc123".to_owned(),
+    });
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/api/submit?raw_json=1");
+    assert_eq!(
+      String::from_utf8_lossy(&request.body),
+      "api_type=json&kind=self&sr=rust&title=hello&text=first%21");
+  }
+
+  #[test]
+  fn test_submit_surfaces_api_errors() {
+    let server = MockServer::start(vec![MockResponse::json(
+      r#"{"json": {"errors": [["SUBREDDIT_NOEXIST", "that subreddit doesn't exist", "sr"]]}}"#)]);
+
+    let err = submit("rust", "hello", SubmitKind::Link("https://example.com".to_owned()),
+      server.oauth_args()).unwrap_err();
+    match err {
+      RedditError::Api(errors) => assert_eq!(errors[0].field, Some("sr".to_owned())),
+      other => panic!("unexpected {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_submit_comment_encodes_text() {
+    let server = MockServer::start(vec![MockResponse::json(r#"{"json": {"errors": [],
+      "data": {"things": [{"kind": "t1", "data": {"id": "c1", "name": "t1_c1",
+        "author": "archiver", "body": "a & b = c\nthanks"}}]}}}"#)]);
+
+    let comment = submit_comment("t3_abc123", "a & b = c\nthanks", server.oauth_args()).unwrap();
+    assert_eq!(comment.name, "t1_c1");
+    assert_eq!(comment.body, "a & b = c\nthanks");
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/api/comment?raw_json=1");
+    assert_eq!(String::from_utf8_lossy(&request.body),
+      "api_type=json&thing_id=t3_abc123&text=a%20%26%20b%20%3D%20c%0Athanks");
+  }
+
+  #[test]
+  fn test_submit_comment_errors() {
+    assert!(matches!(submit_comment("t3_abc", "hi", Args::default()),
+      Err(RedditError::AuthRequired)));
+    assert!(matches!(submit_comment("t5_abc", "hi", auth_args_offline()),
+      Err(RedditError::InvalidName(_))));
+
+    let server = MockServer::start(vec![MockResponse::json(
+      r#"{"json": {"errors": [["TOO_OLD", "that's archived", "parent"]]}}"#)]);
+    match submit_comment("t3_abc", "hi", server.oauth_args()) {
+      Err(RedditError::Api(errors)) => assert_eq!(errors[0].code, "TOO_OLD"),
+      other => panic!("unexpected {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_vote_save_and_hide_forms() {
+    let responses = (0..6).map(|_| MockResponse::json("{}")).collect();
+    let server = MockServer::start(responses);
+
+    vote("t3_abc", VoteDirection::Up, server.oauth_args()).unwrap();
+    vote("t1_def", VoteDirection::Clear, server.oauth_args()).unwrap();
+    save("t3_abc", Some("to read"), server.oauth_args()).unwrap();
+    unsave("t1_def", server.oauth_args()).unwrap();
+    hide(&["t3_a", "t3_b"], server.oauth_args()).unwrap();
+    unhide(&["t3_a"], server.oauth_args()).unwrap();
+
+    let sent: Vec<(String, String)> = server.requests().iter()
+      .map(|r| (r.path.clone(), String::from_utf8(r.body.clone()).unwrap()))
+      .collect();
+    let expected = [
+      ("/api/vote?raw_json=1", "dir=1&id=t3_abc"),
+      ("/api/vote?raw_json=1", "dir=0&id=t1_def"),
+      ("/api/save?raw_json=1", "category=to%20read&id=t3_abc"),
+      ("/api/unsave?raw_json=1", "id=t1_def"),
+      ("/api/hide?raw_json=1", "id=t3_a%2Ct3_b"),
+      ("/api/unhide?raw_json=1", "id=t3_a"),
+    ];
+    assert_eq!(sent, expected.iter()
+      .map(|&(path, body)| (path.to_owned(), body.to_owned()))
+      .collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn test_thing_actions_reject_bad_fullnames() {
+    match vote("abc123", VoteDirection::Down, auth_args_offline()) {
+      Err(RedditError::InvalidArgument(why)) => assert!(why.contains("t3_abc123"), "{}", why),
+      other => panic!("unexpected {:?}", other),
+    }
+    assert!(matches!(vote("t5_abc", VoteDirection::Up, auth_args_offline()),
+      Err(RedditError::InvalidName(_))));
+    // only posts can be hidden
+    assert!(matches!(hide(&["t1_abc"], auth_args_offline()), Err(RedditError::InvalidName(_))));
+    let many: Vec<String> = (0..MAX_HIDE + 1).map(|i| format!("t3_{}", i)).collect();
+    assert!(matches!(hide(&many, auth_args_offline()), Err(RedditError::InvalidArgument(_))));
+    assert!(matches!(save("t3_abc", None, Args::default()), Err(RedditError::AuthRequired)));
+  }
+
+  #[test]
+  fn test_vote_surfaces_ratelimit() {
+    let server = MockServer::start(vec![MockResponse::json(r#"{"json": {"errors": [
+      ["RATELIMIT", "you are doing that too much. try again in 5 minutes.", "ratelimit"]]}}"#)]);
+
+    let err = vote("t3_abc", VoteDirection::Up, server.oauth_args()).unwrap_err();
+    assert!(err.is_rate_limited(), "{:?}", err);
+  }
+
+  #[test]
+  fn test_writes_check_the_granted_scope_first() {
+    let server = MockServer::start(vec![MockResponse::json("{}")]);
+    let token = Token { scope : "identity read".to_owned(), ..Token::from_access_token("abc")
+      .unwrap() };
+    let args = token.authorize(server.oauth_args());
+    assert_eq!(args.scope.as_deref(), Some("identity read"));
+
+    let missing = |result: Result<(), RedditError>| match result {
+      Err(RedditError::MissingScope { needed }) => needed,
+      other => panic!("unexpected {:?}", other),
+    };
+    assert_eq!(missing(vote("t3_abc", VoteDirection::Up, args.clone())), "vote");
+    assert_eq!(missing(save("t3_abc", None, args.clone())), "save");
+    assert_eq!(missing(submit("rust", "hi", SubmitKind::SelfPost("x".to_owned()), args.clone())
+      .map(|_| ())), "submit");
+    assert!(server.requests().is_empty());
+
+    let voter = Token { scope : "vote".to_owned(), ..token }.authorize(args);
+    vote("t3_abc", VoteDirection::Up, voter).unwrap();
+    assert_eq!(server.requests().len(), 1);
+  }
+
+  #[test]
+  fn test_submit_post_picks_kind() {
+    let ok = r#"{"json": {"errors": [], "data": {"url": "u", "id": "x", "name": "t3_x"}}}"#;
+    let server = MockServer::start(vec![MockResponse::json(ok), MockResponse::json(ok)]);
+
+    submit_post("rust", "a link", "https://example.com", server.oauth_args()).unwrap();
+    submit_post("rust", "a question", "how do I borrow?", server.oauth_args()).unwrap();
+
+    let requests = server.requests();
+    assert!(String::from_utf8_lossy(&requests[0].body).contains("kind=link"));
+    assert!(String::from_utf8_lossy(&requests[1].body).contains("kind=self"));
+  }
+
+  #[test]
+  fn test_my_saved_and_upvoted_share_username_lookup() {
+    let page = r#"{"kind": "Listing", "data": {"after": null, "children": [
+      {"kind": "t3", "data": {"name": "t3_a"}},
+      {"kind": "t1", "data": {"name": "t1_b"}}]}}"#;
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"name": "archiver", "id": "1w72"}"#),
+      MockResponse::json(page),
+      MockResponse::json(page),
+    ]);
+    let args = Args { key: "my-saved-token".to_owned(), ..server.oauth_args() };
+
+    let saved = my_saved(args.clone()).unwrap();
+    assert_eq!(saved.len(), 2);
+    my_upvoted(args).unwrap();
+
+    let requests = server.requests();
+    let paths: Vec<&str> = requests.iter().map(|r| r.path.as_str()).collect();
+    assert_eq!(paths, vec![
+      "/api/v1/me?raw_json=1",
+      "/user/archiver/saved?raw_json=1",
+      "/user/archiver/upvoted?raw_json=1",
+    ]);
+    assert_eq!(requests[1].header("Authorization").unwrap(), "bearer my-saved-token");
+  }
+
+  #[test]
+  fn test_my_hidden_insufficient_scope() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"name": "archiver"}"#),
+      MockResponse::status(403, r#"{"message": "Forbidden", "error": 403}"#)
+        .header("Content-Type", "application/json")
+        .header("WWW-Authenticate", r#"Bearer realm="reddit", error="insufficient_scope""#),
+    ]);
+    let args = Args { key: "my-hidden-token".to_owned(), ..server.oauth_args() };
+
+    match my_hidden(args) {
+      Err(RedditError::Api(errors)) => assert_eq!(errors[0].code, "INSUFFICIENT_SCOPE"),
+      other => panic!("unexpected {:?}", other),
+    }
+  }
+}
@@ -0,0 +1,507 @@
+
+// external imports
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use curl::easy::{Auth, Easy};
+use serde_json;
+use serde_json::Value;
+
+// internal imports
+use api::{check_secure, form_encode, gen_headers, parse_json, path_query, prefixed_url,
+  response_for, setup_connection, status_error};
+use config::Args;
+use error::RedditError;
+
+////////////////////////////////////////////////////////////////////////////////
+/// Token
+/// What reddit's token endpoint hands back; access_token goes in args.key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Token {
+  pub access_token : String,
+  #[serde(default)]
+  pub token_type : String,
+  #[serde(default)]
+  pub expires_in : u64,
+  #[serde(default)]
+  pub scope : String,
+}
+
+impl Token {
+  /// Wraps an access token obtained elsewhere, rejecting ones that are
+  /// blank or would break out of the Authorization header (CR, LF or any
+  /// other control character)
+  pub fn from_access_token(token: &str) -> Result<Token, RedditError> {
+    if token.trim().is_empty() {
+      return Err(RedditError::InvalidArgument("access token is empty".to_owned()));
+    }
+    if token.chars().any(|c| c.is_control()) {
+      return Err(RedditError::InvalidArgument(
+        "access token contains control characters".to_owned()));
+    }
+
+    Ok(Token {
+      access_token : token.to_owned(),
+      token_type : "bearer".to_owned(),
+      expires_in : 0,
+      scope : String::new(),
+    })
+  }
+
+  /// Whether the token was granted a scope, going by the space separated
+  /// scope list reddit returned with it; "*" grants every scope
+  pub fn has_scope(&self, scope: &str) -> bool {
+    self.scope.split_whitespace().any(|granted| granted == "*" || granted == scope)
+  }
+
+  /// Fails with RedditError::MissingScope before anything is sent when the
+  /// token lacks a scope an endpoint needs
+  pub fn require_scope(&self, scope: &str) -> Result<(), RedditError> {
+    if !self.has_scope(scope) {
+      return Err(RedditError::MissingScope { needed : scope.to_owned() });
+    }
+    Ok(())
+  }
+
+  /// args signed in with this token: access_token as args.key and, when
+  /// reddit said what it granted, the scope as args.scope
+  pub fn authorize(&self, args: Args) -> Args {
+    Args {
+      key : self.access_token.clone(),
+      scope : Some(self.scope.clone()).filter(|scope| !scope.is_empty()),
+      ..args
+    }
+  }
+}
+
+/// Token::require_scope for the token in args, when args.scope says what
+/// it was granted; with no scope on record the request is left to reddit
+pub fn require_granted_scope(args: &Args, scope: &str) -> Result<(), RedditError> {
+  match args.scope {
+    Some(ref granted) => Token {
+      scope : granted.clone(),
+      ..Token::from_access_token(&args.key)?
+    }.require_scope(scope),
+    None => Ok(()),
+  }
+}
+
+/// Every oauth scope reddit knows about, e.g. "identity", "read", "vote"
+pub fn scopes(args: Args) -> Result<Vec<String>, RedditError> {
+  let value = path_query("/api/v1/scopes", args)?;
+  match value.as_object() {
+    Some(scopes) => Ok(scopes.keys().cloned().collect()),
+    None => Err(RedditError::MissingField("/".to_owned())),
+  }
+}
+
+/// Fetches a bearer token for a script app with the password grant, or
+/// the refresh_token grant when args.refresh_token is set
+///
+/// The client id and secret are handed to curl as HTTP Basic credentials
+/// rather than as a hand built Authorization header. The token endpoint
+/// lives on args.base_url, not the oauth host, under args.path_prefix like
+/// everything else; the path is otherwise left as is.
+pub fn authenticate(args: &Args) -> Result<Token, RedditError> {
+  check_secure(&args.base_url, args)?;
+
+  let mut easy = Easy::new();
+  easy.url(&prefixed_url(&args.base_url, "/api/v1/access_token", args))?;
+  easy.http_headers(gen_headers(args)?)?;
+  setup_connection(&mut easy, args)?;
+  easy.username(&args.client_id)?;
+  easy.password(&args.client_secret)?;
+  let mut auth = Auth::new();
+  auth.basic(true);
+  easy.http_auth(&auth)?;
+
+  let form = if args.refresh_token.is_empty() {
+    form_encode(&[
+      ("grant_type", "password"),
+      ("username", &args.username),
+      ("password", &args.password),
+    ])
+  } else {
+    form_encode(&[("grant_type", "refresh_token"), ("refresh_token", &args.refresh_token)])
+  };
+  easy.post(true)?;
+  easy.post_fields_copy(form.as_bytes())?;
+
+  let response = response_for(&mut easy, "POST", args)?;
+  if let Some(reason) = token_refusal(&response.body) {
+    return Err(RedditError::AuthFailed { reason });
+  }
+  if !(200..300).contains(&response.status) {
+    return Err(status_error(response));
+  }
+  parse_json(&response.body)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// CachedToken
+/// A token from authenticate along with when it stops working
+#[derive(Clone, Debug, PartialEq)]
+pub struct CachedToken {
+  pub token : Token,
+  pub expires_at : SystemTime,
+}
+
+/// how long before it expires a cached token gets replaced
+pub const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+impl CachedToken {
+  pub fn new(token: Token, now: SystemTime) -> CachedToken {
+    let expires_at = now + Duration::from_secs(token.expires_in);
+    CachedToken { token, expires_at }
+  }
+
+  /// whether the token is within TOKEN_REFRESH_MARGIN of expiring
+  pub fn needs_refresh(&self, now: SystemTime) -> bool {
+    now + TOKEN_REFRESH_MARGIN >= self.expires_at
+  }
+}
+
+/// tokens handed out by script_token, keyed by the credentials they were
+/// granted for
+static SCRIPT_TOKENS: Mutex<Vec<(String, CachedToken)>> = Mutex::new(Vec::new());
+
+/// Whether args carry script app credentials but no token of their own,
+/// i.e. requests should be made with a token from script_token
+pub fn has_script_credentials(args: &Args) -> bool {
+  args.key.is_empty() && !args.client_id.is_empty()
+}
+
+/// A bearer token for the script app credentials in args. The token is
+/// fetched once and reused until it is within a minute of expiring, at
+/// which point the next call fetches a new one.
+pub fn script_token(args: &Args, now: SystemTime) -> Result<Token, RedditError> {
+  let key = format!("{}\n{}\n{}\n{}",
+    args.base_url, args.client_id, args.username, args.refresh_token);
+
+  // the lock is never held while talking to reddit
+  {
+    let tokens = SCRIPT_TOKENS.lock().unwrap();
+    if let Some((_, cached)) = tokens.iter().find(|e| e.0 == key) {
+      if !cached.needs_refresh(now) {
+        return Ok(cached.token.clone());
+      }
+    }
+  }
+
+  let cached = CachedToken::new(authenticate(args)?, now);
+  let token = cached.token.clone();
+  let mut tokens = SCRIPT_TOKENS.lock().unwrap();
+  tokens.retain(|e| e.0 != key);
+  tokens.push((key, cached));
+  Ok(token)
+}
+
+/// Args for a request made with the script app's token: the token goes in
+/// key and the Authorization header, and requests go to args.oauth_url.
+/// Anything else is handed back unchanged.
+pub fn with_script_token(args: Args) -> Result<Args, RedditError> {
+  if !has_script_credentials(&args) {
+    return Ok(args);
+  }
+
+  let token = script_token(&args, SystemTime::now())?;
+  Token::from_access_token(&token.access_token)?;
+  let mut headers = args.headers.clone();
+  headers.push(("Authorization".to_owned(), format!("bearer {}", token.access_token)));
+  Ok(token.authorize(Args {
+    base_url : args.oauth_url.clone(),
+    headers,
+    ..args
+  }))
+}
+
+/// Why the token endpoint refused, if it did. Bad credentials come back as
+/// {"error": "invalid_grant"}, often with a 200; suspended accounts carry a
+/// reason such as "ACCOUNT_SUSPENDED" instead. A numeric error (e.g. a 401
+/// for a wrong client id) is left to the status check.
+fn token_refusal(body: &str) -> Option<String> {
+  let value: Value = serde_json::from_str(body).ok()?;
+  let reason = value["error"].as_str().or_else(|| value["reason"].as_str())?;
+
+  match value["message"].as_str().or_else(|| value["error_description"].as_str()) {
+    Some(detail) if !detail.is_empty() => Some(format!("{}: {}", reason, detail)),
+    _ => Some(reason.to_owned()),
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_auth {
+
+  use api::*;
+  use config::Args;
+  use mock::{MockServer, MockResponse};
+
+  #[test]
+  fn test_authenticate_uses_basic_auth() {
+    let server = MockServer::start(vec![MockResponse::json(
+      r#"{"access_token": "abc", "token_type": "bearer", "expires_in": 3600,
+          "scope": "*"}"#)]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      client_id : "id".to_owned(),
+      client_secret : "secret".to_owned(),
+      username : "bot".to_owned(),
+      password : "hunter2".to_owned(),
+      danger_allow_insecure : true,
+      ..Args::default()
+    };
+
+    let token = authenticate(&args).unwrap();
+    assert_eq!(token.access_token, "abc");
+    assert_eq!(token.expires_in, 3600);
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/api/v1/access_token");
+    // base64 of "id:secret"
+    assert_eq!(request.header("Authorization").unwrap(), "Basic aWQ6c2VjcmV0");
+    assert_eq!(String::from_utf8_lossy(&request.body),
+      "grant_type=password&username=bot&password=hunter2");
+  }
+
+  #[test]
+  fn test_authenticate_refused_credentials() {
+    use error::RedditError;
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"error": "invalid_grant"}"#),
+      MockResponse::status(403, r#"{"reason": "ACCOUNT_SUSPENDED",
+        "message": "this account has been suspended"}"#)
+        .header("Content-Type", "application/json"),
+      MockResponse::status(401, r#"{"message": "Unauthorized", "error": 401}"#)
+        .header("Content-Type", "application/json"),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      danger_allow_insecure : true,
+      ..Args::default()
+    };
+
+    match authenticate(&args) {
+      Err(RedditError::AuthFailed { reason }) => assert_eq!(reason, "invalid_grant"),
+      other => panic!("unexpected {:?}", other),
+    }
+    match authenticate(&args) {
+      Err(RedditError::AuthFailed { reason }) =>
+        assert_eq!(reason, "ACCOUNT_SUSPENDED: this account has been suspended"),
+      other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(authenticate(&args).unwrap_err().status_code(), Some(401));
+  }
+
+  #[test]
+  fn test_path_query_with_script_credentials() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"access_token": "script-abc", "token_type": "bearer",
+        "expires_in": 3600, "scope": "*"}"#),
+      MockResponse::json(r#"{"kind": "Listing", "data": {"children": []}}"#),
+      MockResponse::json(r#"{"kind": "Listing", "data": {"children": []}}"#),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      oauth_url : format!("{}/oauth", server.base_url),
+      client_id : "script-id".to_owned(),
+      client_secret : "secret".to_owned(),
+      username : "bot".to_owned(),
+      password : "hunter2".to_owned(),
+      danger_allow_insecure : true,
+      ..Args::default()
+    };
+
+    path_query("/r/rust/.json", args.clone()).unwrap();
+    path_query("/r/rust/new/.json", args).unwrap();
+
+    // one token request, reused for both queries
+    let requests = server.requests();
+    let paths: Vec<&str> = requests.iter().map(|r| r.path.as_str()).collect();
+    assert_eq!(paths, vec![
+      "/api/v1/access_token",
+      "/oauth/r/rust/.json?raw_json=1",
+      "/oauth/r/rust/new/.json?raw_json=1",
+    ]);
+    assert_eq!(requests[1].header("Authorization").unwrap(), "bearer script-abc");
+    assert_eq!(requests[2].header("Authorization").unwrap(), "bearer script-abc");
+  }
+
+  #[cfg(feature = "raw_value")]
+  #[test]
+  fn test_path_query_raw_with_script_credentials() {
+    use api::path_query_raw;
+
+    let body = r#"{"kind": "Listing", "data": {"children": []}}"#;
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"access_token": "script-raw", "expires_in": 3600}"#),
+      MockResponse::json(body),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      oauth_url : format!("{}/oauth", server.base_url),
+      client_id : "raw-script-id".to_owned(),
+      client_secret : "secret".to_owned(),
+      username : "bot".to_owned(),
+      password : "hunter2".to_owned(),
+      danger_allow_insecure : true,
+      ..Args::default()
+    };
+
+    assert_eq!(path_query_raw("/r/rust/.json", args).unwrap().get(), body);
+
+    let requests = server.requests();
+    assert_eq!(requests[1].path, "/oauth/r/rust/.json?raw_json=1");
+    assert_eq!(requests[1].header("Authorization").unwrap(), "bearer script-raw");
+  }
+
+  #[test]
+  fn test_script_token_refreshes_near_expiry() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"access_token": "first", "expires_in": 3600}"#),
+      MockResponse::json(r#"{"access_token": "second", "expires_in": 3600}"#),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      client_id : "refresh-id".to_owned(),
+      refresh_token : "refresh-me".to_owned(),
+      danger_allow_insecure : true,
+      ..Args::default()
+    };
+
+    let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    assert_eq!(script_token(&args, start).unwrap().access_token, "first");
+    let later = start + Duration::from_secs(3000);
+    assert_eq!(script_token(&args, later).unwrap().access_token, "first");
+    // within a minute of the hour running out
+    let nearly = start + Duration::from_secs(3550);
+    assert_eq!(script_token(&args, nearly).unwrap().access_token, "second");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(String::from_utf8_lossy(&requests[0].body),
+      "grant_type=refresh_token&refresh_token=refresh-me");
+  }
+
+  #[test]
+  fn test_every_read_uses_the_script_token() {
+    use client::RedditClient;
+
+    let listing = || MockResponse::json(r#"{"kind": "Listing", "data": {"children": []}}"#);
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"access_token": "script-all", "expires_in": 3600}"#),
+      listing(),
+      listing(),
+      listing(),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      oauth_url : format!("{}/oauth", server.base_url),
+      client_id : "every-read-id".to_owned(),
+      client_secret : "secret".to_owned(),
+      username : "bot".to_owned(),
+      password : "hunter2".to_owned(),
+      danger_allow_insecure : true,
+      ..Args::default()
+    };
+
+    assert_eq!(path_query_response("/r/rust/about/.json", args.clone()).unwrap().status, 200);
+    assert!(query_many(&["/r/rust/new/.json"], args.clone()).unwrap()[0].is_ok());
+    RedditClient::new(args).query("/r/rust/hot/.json").unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 4);
+    for request in &requests[1..] {
+      assert!(request.path.starts_with("/oauth/r/rust/"), "{}", request.path);
+      assert_eq!(request.header("Authorization").unwrap(), "bearer script-all");
+    }
+  }
+
+  #[test]
+  fn test_without_auth_sends_no_token_request() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"kind": "Listing", "data": {"children": []}}"#),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      oauth_url : format!("{}/oauth", server.base_url),
+      client_id : "public-id".to_owned(),
+      client_secret : "secret".to_owned(),
+      username : "bot".to_owned(),
+      password : "hunter2".to_owned(),
+      danger_allow_insecure : true,
+      ..Args::default()
+    };
+
+    path_query("/r/rust/.json", args.without_auth()).unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "/r/rust/.json?raw_json=1");
+    assert_eq!(requests[0].header("Authorization"), None);
+  }
+
+  #[test]
+  fn test_without_credentials_args_are_unchanged() {
+    let args = with_script_token(Args::default()).unwrap();
+    assert_eq!(args.base_url, "https://www.reddit.com");
+    assert!(!args.headers.iter().any(|h| h.0 == "Authorization"));
+  }
+
+  #[test]
+  fn test_from_access_token_rejects_injection() {
+    use error::RedditError;
+
+    assert_eq!(Token::from_access_token("abc-123_XYZ").unwrap().access_token, "abc-123_XYZ");
+
+    for bad in &["", "   ", "abc\r\nX-Injected: 1", "abc\n", "abc\u{0}"] {
+      assert!(matches!(Token::from_access_token(bad), Err(RedditError::InvalidArgument(_))),
+        "accepted {:?}", bad);
+    }
+  }
+
+  #[test]
+  fn test_injected_key_never_reaches_the_wire() {
+    use error::RedditError;
+
+    let args = Args { key: "abc\r\nX-Injected: 1".to_owned(), ..Args::default() };
+    let err = oauth_query("/api/v1/me", args).unwrap_err();
+    assert!(matches!(err, RedditError::InvalidArgument(_)));
+  }
+
+  #[test]
+  fn test_scopes() {
+    let server = MockServer::start(vec![MockResponse::json(r#"{
+      "read": {"id": "read", "name": "Read Content", "description": "..."},
+      "identity": {"id": "identity", "name": "My Identity", "description": "..."},
+      "vote": {"id": "vote", "name": "Vote", "description": "..."}}"#)]);
+    let args = server.args();
+
+    let mut all = scopes(args).unwrap();
+    all.sort();
+    assert_eq!(all, vec!["identity", "read", "vote"]);
+    assert_eq!(server.requests()[0].path, "/api/v1/scopes?raw_json=1");
+  }
+
+  #[test]
+  fn test_token_scope_checks() {
+    use error::RedditError;
+
+    let token = Token {
+      scope : "identity read".to_owned(),
+      ..Token::from_access_token("t").unwrap()
+    };
+    assert!(token.has_scope("read"));
+    assert!(!token.has_scope("vote"));
+    assert!(!token.has_scope("rea"));
+    match token.require_scope("submit") {
+      Err(RedditError::MissingScope { needed }) => assert_eq!(needed, "submit"),
+      other => panic!("unexpected {:?}", other),
+    }
+
+    let everything = Token { scope: "*".to_owned(), ..token };
+    assert!(everything.require_scope("modflair").is_ok());
+  }
+}
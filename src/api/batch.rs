@@ -0,0 +1,432 @@
+
+// external imports
+use std::cmp::min;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use curl::easy::{Easy2, Handler, WriteError};
+use curl::multi::{Easy2Handle, Multi};
+use std::str::from_utf8 as str_from_utf8;
+use serde_json::Value;
+
+// internal imports
+use api::{check_content_type, check_json_depth, header_list, parse_json, prepare_get,
+  setup_connection};
+use config::Args;
+use error::RedditError;
+use transport::{report_complete, PreparedRequest, RawResponse};
+
+////////////////////////////////////////////////////////////////////////////////
+/// Collector
+/// Per-transfer state for a batch: each easy handle buffers its own body
+/// and the headers of the last response, for args.on_complete
+pub struct Collector {
+  body : Vec<u8>,
+  headers : Vec<(String, String)>,
+  open : Arc<AtomicUsize>,
+}
+
+impl Collector {
+  fn new(open: Arc<AtomicUsize>) -> Collector {
+    open.fetch_add(1, Ordering::SeqCst);
+    Collector { body : Vec::new(), headers : Vec::new(), open }
+  }
+}
+
+impl Handler for Collector {
+  fn write(&mut self, data: &[u8]) -> Result<usize, WriteError> {
+    self.body.extend_from_slice(data);
+    Ok(data.len())
+  }
+
+  fn header(&mut self, data: &[u8]) -> bool {
+    let line = String::from_utf8_lossy(data);
+    if line.starts_with("HTTP/") {
+      self.headers.clear();
+    } else if let Some((name, value)) = line.split_once(':') {
+      self.headers.push((name.trim().to_owned(), value.trim().to_owned()));
+    }
+    true
+  }
+}
+
+impl Drop for Collector {
+  fn drop(&mut self) {
+    self.open.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Batch
+/// Drives several queries concurrently over a curl Multi handle. Whatever
+/// is still in flight when the batch is finished early or dropped is
+/// removed from the Multi, so cancelled batches never leak handles.
+pub struct Batch {
+  multi : Multi,
+  handles : Vec<Option<Easy2Handle<Collector>>>,
+  results : Vec<Option<Result<Value, RedditError>>>,
+  open : Arc<AtomicUsize>,
+  max_json_depth : Option<usize>,
+  /// what each transfer reports to args.on_complete as its request
+  requests : Vec<PreparedRequest>,
+  args : Args,
+  started : Option<Instant>,
+}
+
+impl Batch {
+  /// Prepares one transfer per path; nothing is sent until perform. At most
+  /// args.concurrency transfers run at once, and no more than
+  /// args.max_host_connections to any one host; the rest wait their turn.
+  pub fn new(paths: &[&str], args: &Args) -> Result<Batch, RedditError> {
+    if args.concurrency == 0 {
+      return Err(RedditError::InvalidArgument("concurrency must be at least 1".to_owned()));
+    }
+
+    let mut multi = Multi::new();
+    multi.set_max_total_connections(args.concurrency)?;
+    multi.set_max_host_connections(args.max_host_connections)?;
+    let open = Arc::new(AtomicUsize::new(0));
+    let mut handles = Vec::with_capacity(paths.len());
+    let mut requests = Vec::with_capacity(paths.len());
+
+    for path in paths {
+      let mut easy = Easy2::new(Collector::new(open.clone()));
+      let (request, args) = prepare_get(path, args)?;
+      easy.url(&request.url)?;
+      easy.http_headers(header_list(&request.headers)?)?;
+      setup_connection(&mut easy, &args)?;
+      handles.push(Some(multi.add2(easy)?));
+      requests.push(request);
+    }
+
+    Ok(Batch {
+      multi,
+      results : paths.iter().map(|_| None).collect(),
+      handles,
+      open,
+      max_json_depth : args.max_json_depth,
+      requests,
+      args : args.clone(),
+      started : None,
+    })
+  }
+
+  /// number of transfers that have not completed yet
+  pub fn in_flight(&self) -> usize {
+    self.handles.iter().filter(|h| h.is_some()).count()
+  }
+
+  /// A shared count of the easy handles this batch still owns. It stays
+  /// readable after the batch itself is gone, which makes leaks visible.
+  pub fn open_handles(&self) -> Arc<AtomicUsize> {
+    self.open.clone()
+  }
+
+  /// Runs the transfers for at most `timeout`, returning true once every
+  /// transfer has completed
+  pub fn perform(&mut self, timeout: Duration) -> Result<bool, RedditError> {
+    let deadline = Instant::now() + timeout;
+    self.started.get_or_insert_with(Instant::now);
+
+    loop {
+      self.multi.perform()?;
+      self.collect_finished()?;
+
+      if self.in_flight() == 0 {
+        return Ok(true);
+      }
+
+      let now = Instant::now();
+      if now >= deadline {
+        return Ok(false);
+      }
+      self.multi.wait(&mut [], min(deadline - now, Duration::from_millis(100)))?;
+    }
+  }
+
+  /// Stops the batch, returning results in input order; anything that had
+  /// not completed comes back as RedditError::Aborted
+  pub fn finish(mut self) -> Vec<Result<Value, RedditError>> {
+    self.detach_all();
+    self.results.drain(..)
+      .map(|slot| slot.unwrap_or(Err(RedditError::Aborted)))
+      .collect()
+  }
+
+  /// moves every completed transfer's outcome out of the Multi, handing
+  /// each to args.on_complete
+  fn collect_finished(&mut self) -> Result<(), RedditError> {
+    let mut finished = Vec::new();
+    {
+      let handles = &self.handles;
+      self.multi.messages(|msg| {
+        for (index, slot) in handles.iter().enumerate() {
+          if let Some(ref handle) = *slot {
+            if let Some(result) = msg.result_for2(handle) {
+              finished.push((index, result));
+            }
+          }
+        }
+      });
+    }
+
+    for (index, result) in finished {
+      if let Some(handle) = self.handles[index].take() {
+        let easy = self.multi.remove2(handle)?;
+        let status = easy.response_code()?;
+        if self.args.on_complete.is_some() {
+          let raw = result.clone().map(|()| RawResponse {
+            status,
+            headers : easy.get_ref().headers.clone(),
+            effective_url : easy.effective_url().ok().flatten().unwrap_or_default().to_owned(),
+            body : easy.get_ref().body.clone(),
+          }).map_err(RedditError::from);
+          self.report(index, &raw);
+        }
+        self.results[index] = Some(match result {
+          Ok(()) if !(200..300).contains(&status) => Err(RedditError::from_status(status,
+            String::from_utf8_lossy(&easy.get_ref().body).into_owned())),
+          Ok(()) => check_content_type(easy.content_type()?)
+            .and_then(|_| parse_body(&easy.get_ref().body, self.max_json_depth)),
+          Err(err) => Err(RedditError::from(err)),
+        });
+      }
+    }
+    Ok(())
+  }
+
+  /// removes whatever is still in flight, reporting it to args.on_complete
+  /// as RedditError::Aborted
+  fn detach_all(&mut self) {
+    for index in 0..self.handles.len() {
+      if let Some(handle) = self.handles[index].take() {
+        let _ = self.multi.remove2(handle);
+        self.report(index, &Err(RedditError::Aborted));
+      }
+    }
+  }
+
+  fn report(&self, index: usize, result: &Result<RawResponse, RedditError>) {
+    let elapsed = self.started.map(|started| started.elapsed()).unwrap_or_default();
+    report_complete(&self.args, &self.requests[index], result, elapsed);
+  }
+}
+
+fn parse_body(body: &[u8], max_depth: Option<usize>) -> Result<Value, RedditError> {
+  let body = str_from_utf8(body)?;
+  if let Some(max) = max_depth {
+    check_json_depth(body, max)?;
+  }
+  parse_json(body)
+}
+
+impl Drop for Batch {
+  fn drop(&mut self) {
+    self.detach_all();
+  }
+}
+
+/// Queries every path concurrently, results come back in input order
+pub fn query_many(paths: &[&str], args: Args)
+  -> Result<Vec<Result<Value, RedditError>>, RedditError>
+{
+  let mut batch = Batch::new(paths, &args)?;
+  while !batch.perform(Duration::from_secs(60))? {}
+  Ok(batch.finish())
+}
+
+/// Queries every path concurrently, at most args.concurrency at a time,
+/// with one Result per path in input order. Unlike query_many a failure to
+/// set the batch up is not returned on its own: the first slot carries it
+/// and the others come back as RedditError::Aborted.
+pub fn multi_query(paths: &[&str], args: Args) -> Vec<Result<Value, RedditError>> {
+  match query_many(paths, args) {
+    Ok(results) => results,
+    Err(err) => {
+      let mut results = vec![Err(err)];
+      results.extend(paths.iter().skip(1).map(|_| Err(RedditError::Aborted)));
+      results.truncate(paths.len());
+      results
+    },
+  }
+}
+
+/// Like query_many, but gives up after `timeout`; transfers still running
+/// at that point are cancelled and reported as RedditError::Aborted
+pub fn query_many_timeout(paths: &[&str], args: Args, timeout: Duration)
+  -> Result<Vec<Result<Value, RedditError>>, RedditError>
+{
+  let mut batch = Batch::new(paths, &args)?;
+  batch.perform(timeout)?;
+  Ok(batch.finish())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_batch {
+
+  use api::*;
+  use config::Args;
+  use error::RedditError;
+  use mock::{MockServer, MockResponse};
+  use std::sync::atomic::Ordering;
+  use std::time::{Duration, Instant};
+
+  #[test]
+  fn test_query_many_collects_every_result() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"n": 1}"#),
+      MockResponse::json(r#"{"n": 1}"#),
+    ]);
+
+    let results = query_many(&["/a/.json", "/b/.json"], server.args()).unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|r| r.as_ref().unwrap()["n"] == 1));
+  }
+
+  #[test]
+  fn test_timeout_returns_partial_results() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"n": 1}"#),
+      MockResponse::json(r#"{"n": 2}"#).delayed(Duration::from_secs(3)),
+    ]);
+
+    let results = query_many_timeout(
+      &["/a/.json", "/b/.json"], server.args(), Duration::from_millis(500))
+      .unwrap();
+
+    let done = results.iter().filter(|r| r.is_ok()).count();
+    let aborted = results.iter()
+      .filter(|r| matches!(**r, Err(RedditError::Aborted)))
+      .count();
+    assert_eq!((done, aborted), (1, 1));
+  }
+
+  #[test]
+  fn test_on_complete_sees_every_batch_transfer() {
+    use std::sync::{Arc, Mutex};
+    use transport::{PreparedRequest, RawResponse};
+
+    let server = MockServer::start(vec![
+      MockResponse::status(404, "{}"),
+      MockResponse::json("{}").delayed(Duration::from_secs(3)),
+    ]);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    let args = Args {
+      concurrency : 1,
+      on_complete : Some(Arc::new(move |req: &PreparedRequest, result: &Result<_, _>, _| {
+        let outcome = match *result {
+          Ok(ref raw) => format!("{}", (raw as &RawResponse).status),
+          Err(ref err) => format!("{:?}", err),
+        };
+        log.lock().unwrap().push((req.url.clone(), outcome));
+      })),
+      ..server.args()
+    };
+
+    let results = query_many_timeout(&["/a/.json", "/b/.json"], args,
+      Duration::from_millis(500)).unwrap();
+    assert_eq!(results[0].as_ref().unwrap_err().status_code(), Some(404));
+    assert_eq!(*seen.lock().unwrap(), vec![
+      (format!("{}/a/.json?raw_json=1", server.base_url), "404".to_owned()),
+      (format!("{}/b/.json?raw_json=1", server.base_url), "Aborted".to_owned()),
+    ]);
+  }
+
+  #[test]
+  fn test_dropping_batch_mid_flight_releases_handles() {
+    let server = MockServer::start(vec![
+      MockResponse::json("{}").delayed(Duration::from_secs(3)),
+      MockResponse::json("{}"),
+    ]);
+
+    let mut batch = Batch::new(&["/a/.json", "/b/.json"], &server.args()).unwrap();
+    let open = batch.open_handles();
+    assert_eq!(open.load(Ordering::SeqCst), 2);
+
+    assert!(!batch.perform(Duration::from_millis(200)).unwrap());
+    assert_eq!(batch.in_flight(), 2);
+
+    drop(batch);
+    assert_eq!(open.load(Ordering::SeqCst), 0);
+  }
+
+  #[test]
+  fn test_concurrency_caps_overlapping_requests() {
+    let responses = || (0..3)
+      .map(|_| MockResponse::json("{}").delayed(Duration::from_millis(200)))
+      .collect::<Vec<_>>();
+    let paths = ["/a/.json", "/b/.json", "/c/.json"];
+
+    let serial = MockServer::start_concurrent(responses());
+    let args = Args { concurrency: 1, ..serial.args() };
+    assert!(query_many(&paths, args).unwrap().iter().all(|r| r.is_ok()));
+    assert_eq!(serial.peak_concurrency(), 1);
+
+    let parallel = MockServer::start_concurrent(responses());
+    let args = Args { concurrency: 3, ..parallel.args() };
+    assert!(query_many(&paths, args).unwrap().iter().all(|r| r.is_ok()));
+    assert!(parallel.peak_concurrency() > 1);
+  }
+
+  #[test]
+  fn test_host_connections_cap_overlapping_requests() {
+    let server = MockServer::start_concurrent((0..3)
+      .map(|_| MockResponse::json("{}").delayed(Duration::from_millis(200)))
+      .collect());
+    let args = Args { concurrency: 3, max_host_connections: 1, ..server.args() };
+
+    let results = query_many(&["/a/.json", "/b/.json", "/c/.json"], args).unwrap();
+    assert!(results.iter().all(|r| r.is_ok()));
+    assert_eq!(server.peak_concurrency(), 1);
+  }
+
+  #[test]
+  fn test_multi_query_overlaps_latency() {
+    let server = MockServer::start_concurrent((0..4)
+      .map(|n| match n {
+        2 => MockResponse::status(500, "oops").delayed(Duration::from_millis(300)),
+        _ => MockResponse::json("{}").delayed(Duration::from_millis(300)),
+      })
+      .collect());
+    let paths = ["/a/.json", "/b/.json", "/c/.json", "/d/.json"];
+
+    let started = Instant::now();
+    let results = multi_query(&paths, server.args());
+    // four 300ms responses, about as long as the slowest one
+    assert!(started.elapsed() < Duration::from_millis(900), "{:?}", started.elapsed());
+
+    assert_eq!(results.len(), 4);
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 3);
+    assert_eq!(results.iter().filter(|r| r.as_ref().err()
+      .and_then(|e| e.status_code()) == Some(500)).count(), 1);
+  }
+
+  #[test]
+  fn test_multi_query_keeps_input_order() {
+    let server = MockServer::start((0..3)
+      .map(|n| MockResponse::json(&format!(r#"{{"n": {}}}"#, n)))
+      .collect());
+    let args = Args { concurrency: 1, ..server.args() };
+
+    let results = multi_query(&["/a/.json", "/b/.json", "/c/.json"], args);
+    let order: Vec<_> = results.iter().map(|r| r.as_ref().unwrap()["n"].clone()).collect();
+    assert_eq!(order, vec![0, 1, 2]);
+  }
+
+  #[test]
+  fn test_multi_query_setup_failure_fills_every_slot() {
+    let args = Args { concurrency: 0, ..Args::default() };
+    let results = multi_query(&["/a/.json", "/b/.json"], args);
+    assert!(matches!(results[0], Err(RedditError::InvalidArgument(_))));
+    assert!(matches!(results[1], Err(RedditError::Aborted)));
+    assert!(multi_query(&[], Args { concurrency: 0, ..Args::default() }).is_empty());
+  }
+
+  #[test]
+  fn test_zero_concurrency_is_rejected() {
+    let args = Args { concurrency: 0, ..Args::default() };
+    assert!(matches!(Batch::new(&["/a/.json"], &args), Err(RedditError::InvalidArgument(_))));
+  }
+}
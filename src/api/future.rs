@@ -0,0 +1,202 @@
+
+// external imports
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use serde_json::Value;
+
+// internal imports
+use api::path_query_with;
+use config::Args;
+use error::RedditError;
+use transport::CurlTransport;
+
+////////////////////////////////////////////////////////////////////////////////
+/// QueryFuture
+/// path_query as a future, for async executors such as tokio. The transfer
+/// runs on a helper thread that owns its curl handle and everything the
+/// response is collected into, so the executor's own threads never block.
+///
+/// Dropping the future before it resolves cancels the transfer with a flag
+/// of the future's own, checked alongside args.cancel, and the helper
+/// thread frees the handle on its way out.
+pub struct QueryFuture {
+  shared : Arc<Mutex<Shared>>,
+  cancel : Arc<AtomicBool>,
+  done : bool,
+}
+
+/// what the helper thread and the future both get to
+struct Shared {
+  result : Option<Result<Value, RedditError>>,
+  waker : Option<Waker>,
+}
+
+/// Starts path_query on a helper thread, see QueryFuture
+pub fn path_query_async(path: &str, args: Args) -> QueryFuture {
+  let shared = Arc::new(Mutex::new(Shared { result : None, waker : None }));
+  let cancel = Arc::new(AtomicBool::new(false));
+
+  let path = path.to_owned();
+  let mut transport = CurlTransport::new(&args);
+  transport.set_cancel(cancel.clone());
+  let finished = shared.clone();
+  thread::spawn(move || {
+    let result = path_query_with(&path, &args, &transport);
+    let mut shared = finished.lock().unwrap();
+    shared.result = Some(result);
+    if let Some(waker) = shared.waker.take() {
+      waker.wake();
+    }
+  });
+
+  QueryFuture { shared, cancel, done : false }
+}
+
+impl Future for QueryFuture {
+  type Output = Result<Value, RedditError>;
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+    let result = {
+      let mut shared = self.shared.lock().unwrap();
+      match shared.result.take() {
+        Some(result) => result,
+        None => {
+          shared.waker = Some(cx.waker().clone());
+          return Poll::Pending;
+        },
+      }
+    };
+    self.done = true;
+    Poll::Ready(result)
+  }
+}
+
+impl Drop for QueryFuture {
+  fn drop(&mut self) {
+    if !self.done {
+      self.cancel.store(true, Ordering::SeqCst);
+    }
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_future {
+
+  use api::*;
+  use config::Args;
+  use mock::{MockServer, MockResponse};
+  use std::future::Future;
+  use std::pin::Pin;
+  use std::sync::Arc;
+  use std::task::{Context, Poll, Wake};
+  use std::thread::{self, Thread};
+  use std::time::{Duration, Instant};
+
+  type QueryResult = Result<::serde_json::Value, ::error::RedditError>;
+
+  struct Unpark(Thread);
+
+  impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+      self.0.unpark();
+    }
+  }
+
+  /// a bare bones executor: polls both futures on this thread until done
+  fn join(mut a: QueryFuture, mut b: QueryFuture) -> (Poll<QueryResult>, Poll<QueryResult>) {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let (mut first, mut second) = (Poll::Pending, Poll::Pending);
+    while first.is_pending() || second.is_pending() {
+      if first.is_pending() {
+        first = Pin::new(&mut a).poll(&mut cx);
+      }
+      if second.is_pending() {
+        second = Pin::new(&mut b).poll(&mut cx);
+      }
+      if first.is_pending() || second.is_pending() {
+        thread::park_timeout(Duration::from_millis(100));
+      }
+    }
+    (first, second)
+  }
+
+  #[test]
+  fn test_two_queries_overlap() {
+    let slow = || MockResponse::json(r#"{"ok": true}"#).delayed(Duration::from_millis(400));
+    let server = MockServer::start_concurrent(vec![slow(), slow()]);
+    let args = server.args();
+
+    let started = Instant::now();
+    let (a, b) = join(path_query_async("/r/rust/.json", args.clone()),
+      path_query_async("/r/programming/.json", args));
+    match (a, b) {
+      (Poll::Ready(Ok(a)), Poll::Ready(Ok(b))) => assert!(a["ok"] == true && b["ok"] == true),
+      other => panic!("unexpected {:?}", other),
+    }
+    // one after the other would take 800ms
+    assert!(started.elapsed() < Duration::from_millis(750), "{:?}", started.elapsed());
+    assert_eq!(server.peak_concurrency(), 2);
+  }
+
+  #[test]
+  fn test_dropping_the_future_cancels_the_transfer() {
+    // three seconds of body at the trickle rate, were the transfer left alone
+    let body = format!("{{\"pad\": \"{}\"}}", "x".repeat(20));
+    let server = MockServer::start(vec![
+      MockResponse::json(&body).trickled(Duration::from_millis(100)),
+    ]);
+    let args = server.args();
+
+    let future = path_query_async("/r/rust/.json", args);
+    let shared = Arc::downgrade(&future.shared);
+    while server.requests().is_empty() {
+      thread::sleep(Duration::from_millis(10));
+    }
+    let dropped = Instant::now();
+    drop(future);
+
+    // the helper thread lets go of the shared state, and with it the curl
+    // handle, once the transfer has stopped
+    while shared.upgrade().is_some() {
+      assert!(dropped.elapsed() < Duration::from_secs(1), "transfer still running");
+      thread::sleep(Duration::from_millis(10));
+    }
+  }
+
+  #[test]
+  fn test_callers_cancel_flag_still_works() {
+    use error::RedditError;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let server = MockServer::start(vec![
+      MockResponse::json("{}").delayed(Duration::from_secs(3)),
+    ]);
+    let cancel = Arc::new(AtomicBool::new(false));
+    let args = Args {
+      base_url : server.base_url.clone(),
+      cancel : Some(cancel.clone()),
+      ..Args::default()
+    };
+
+    let mut future = path_query_async("/r/rust/.json", args);
+    let started = Instant::now();
+    cancel.store(true, Ordering::SeqCst);
+
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let result = loop {
+      match Pin::new(&mut future).poll(&mut cx) {
+        Poll::Ready(result) => break result,
+        Poll::Pending => thread::park_timeout(Duration::from_millis(100)),
+      }
+    };
+    assert!(matches!(*result.unwrap_err().root(), RedditError::Cancelled));
+    assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+  }
+}
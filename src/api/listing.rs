@@ -0,0 +1,606 @@
+
+// external imports
+use std::str::FromStr;
+use serde_json::Value;
+
+// internal imports
+use api::{append_param, get_things, paginate, url_encode, validate_subreddit_list, ListingIter};
+use config::Args;
+use error::RedditError;
+use models::{Listing, Post};
+
+/// Country codes reddit accepts for the g= geo filter, plus GLOBAL
+const GEO_REGIONS: &[&str] = &[
+  "GLOBAL", "AR", "AU", "BG", "CA", "CL", "CO", "CZ", "DE", "ES", "FI", "FR", "GB", "GR",
+  "HR", "HU", "IE", "IN", "IS", "IT", "JP", "MX", "MY", "NZ", "PH", "PL", "PR", "PT",
+  "RO", "RS", "SE", "SG", "TH", "TR", "TW", "US",
+];
+
+/// US states and DC, accepted as "US_" followed by the state code
+const US_STATES: &[&str] = &[
+  "AL", "AK", "AZ", "AR", "CA", "CO", "CT", "DC", "DE", "FL", "GA", "HI", "ID", "IL",
+  "IN", "IA", "KS", "KY", "LA", "ME", "MD", "MA", "MI", "MN", "MS", "MO", "MT", "NE",
+  "NV", "NH", "NJ", "NM", "NY", "NC", "ND", "OH", "OK", "OR", "PA", "RI", "SC", "SD",
+  "TN", "TX", "UT", "VT", "VA", "WA", "WV", "WI", "WY",
+];
+
+/// whether reddit knows a region code, e.g. "GLOBAL", "GB" or "US_WA"
+fn known_region(region: &str) -> bool {
+  match region.strip_prefix("US_") {
+    Some(state) => US_STATES.contains(&state),
+    None => GEO_REGIONS.contains(&region),
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Sort
+/// How a subreddit listing is ordered; top and controversial also take the
+/// time range they look back over
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sort {
+  Hot,
+  New,
+  Rising,
+  Top(TimeRange),
+  Controversial(TimeRange),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeRange {
+  Hour,
+  Day,
+  Week,
+  Month,
+  Year,
+  All,
+}
+
+impl Sort {
+  pub(crate) fn as_str(&self) -> &'static str {
+    match *self {
+      Sort::Hot => "hot",
+      Sort::New => "new",
+      Sort::Rising => "rising",
+      Sort::Top(_) => "top",
+      Sort::Controversial(_) => "controversial",
+    }
+  }
+
+  pub(crate) fn time_range(&self) -> Option<TimeRange> {
+    match *self {
+      Sort::Top(range) | Sort::Controversial(range) => Some(range),
+      _ => None,
+    }
+  }
+}
+
+impl TimeRange {
+  pub(crate) fn as_str(&self) -> &'static str {
+    match *self {
+      TimeRange::Hour => "hour",
+      TimeRange::Day => "day",
+      TimeRange::Week => "week",
+      TimeRange::Month => "month",
+      TimeRange::Year => "year",
+      TimeRange::All => "all",
+    }
+  }
+}
+
+/// How search results are ordered; top looks back over a time range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchSort {
+  Relevance,
+  Hot,
+  New,
+  Top(TimeRange),
+  Comments,
+}
+
+impl SearchSort {
+  fn as_str(&self) -> &'static str {
+    match *self {
+      SearchSort::Relevance => "relevance",
+      SearchSort::Hot => "hot",
+      SearchSort::New => "new",
+      SearchSort::Top(_) => "top",
+      SearchSort::Comments => "comments",
+    }
+  }
+}
+
+/// Parses the names reddit uses in urls, e.g. from a config file. "top" and
+/// "controversial" look back over a day, reddit's own default; use
+/// Sort::Top(range) for anything else.
+impl FromStr for Sort {
+  type Err = RedditError;
+
+  fn from_str(s: &str) -> Result<Sort, RedditError> {
+    match s.to_ascii_lowercase().as_str() {
+      "hot" => Ok(Sort::Hot),
+      "new" => Ok(Sort::New),
+      "rising" => Ok(Sort::Rising),
+      "top" => Ok(Sort::Top(TimeRange::Day)),
+      "controversial" => Ok(Sort::Controversial(TimeRange::Day)),
+      _ => Err(RedditError::InvalidSort(s.to_owned())),
+    }
+  }
+}
+
+/// Parses the sort= values search takes; "top" looks back over all time,
+/// which is what reddit does for search without a t=
+impl FromStr for SearchSort {
+  type Err = RedditError;
+
+  fn from_str(s: &str) -> Result<SearchSort, RedditError> {
+    match s.to_ascii_lowercase().as_str() {
+      "relevance" => Ok(SearchSort::Relevance),
+      "hot" => Ok(SearchSort::Hot),
+      "new" => Ok(SearchSort::New),
+      "top" => Ok(SearchSort::Top(TimeRange::All)),
+      "comments" => Ok(SearchSort::Comments),
+      _ => Err(RedditError::InvalidSort(s.to_owned())),
+    }
+  }
+}
+
+/// parses the t= values reddit accepts, "hour" through "all"
+impl FromStr for TimeRange {
+  type Err = RedditError;
+
+  fn from_str(s: &str) -> Result<TimeRange, RedditError> {
+    match s.to_ascii_lowercase().as_str() {
+      "hour" => Ok(TimeRange::Hour),
+      "day" => Ok(TimeRange::Day),
+      "week" => Ok(TimeRange::Week),
+      "month" => Ok(TimeRange::Month),
+      "year" => Ok(TimeRange::Year),
+      "all" => Ok(TimeRange::All),
+      _ => Err(RedditError::InvalidTimeRange(s.to_owned())),
+    }
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// ListingQuery
+/// Builds the path of a sorted subreddit listing, e.g.
+/// ListingQuery::new("rust", Sort::Top(TimeRange::Week)).limit(50)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListingQuery {
+  pub subreddit : String,
+  pub sort : Sort,
+  pub limit : Option<u32>,
+  pub geo : Option<String>,
+  pub geo_filter : Option<String>,
+}
+
+impl ListingQuery {
+  pub fn new(subreddit: &str, sort: Sort) -> ListingQuery {
+    ListingQuery {
+      subreddit : subreddit.to_owned(),
+      sort,
+      limit : None,
+      geo : None,
+      geo_filter : None,
+    }
+  }
+
+  /// how many posts each page holds, reddit caps this at 100
+  pub fn limit(mut self, limit: u32) -> ListingQuery {
+    self.limit = Some(limit);
+    self
+  }
+
+  /// Restricts the listing to a region, e.g. "US", "GB" or "US_WA". Reddit
+  /// only honors this on a few listings, mostly r/popular. Unknown codes are
+  /// rejected with RedditError::InvalidArgument.
+  pub fn geo(mut self, region: &str) -> Result<ListingQuery, RedditError> {
+    if !known_region(region) {
+      return Err(RedditError::InvalidArgument(format!("unknown geo region {:?}", region)));
+    }
+
+    self.geo = Some(region.to_owned());
+    Ok(self)
+  }
+
+  /// The geo_filter parameter newer listings such as r/popular use in
+  /// place of g=, taking the same region codes. Unknown codes are rejected
+  /// with RedditError::InvalidName.
+  pub fn geo_filter(mut self, region: &str) -> Result<ListingQuery, RedditError> {
+    if !known_region(region) {
+      return Err(RedditError::InvalidName(region.to_owned()));
+    }
+
+    self.geo_filter = Some(region.to_owned());
+    Ok(self)
+  }
+
+  /// the request path for this listing, e.g. "/r/rust/top/.json?t=week"
+  pub fn path(&self) -> String {
+    let mut path = format!("/r/{}/{}/.json", self.subreddit, self.sort.as_str());
+    if let Some(range) = self.sort.time_range() {
+      path = append_param(&path, "t", range.as_str());
+    }
+    if let Some(limit) = self.limit {
+      path = append_param(&path, "limit", &limit.to_string());
+    }
+    if let Some(ref geo) = self.geo {
+      path = append_param(&path, "g", geo);
+    }
+    if let Some(ref geo) = self.geo_filter {
+      path = append_param(&path, "geo_filter", geo);
+    }
+    path
+  }
+
+  /// pages through the listing, see paginate
+  pub fn pages(&self, args: Args) -> Result<ListingIter<Post>, RedditError> {
+    validate_subreddit_list(&self.subreddit)?;
+    Ok(paginate(&self.path(), args))
+  }
+}
+
+/// The titles of the first `limit` posts of a sorted subreddit listing, in
+/// listing order; a single request, so reddit's cap of 100 applies
+pub fn titles(subreddit: &str, sort: Sort, limit: u32, args: Args)
+  -> Result<Vec<String>, RedditError>
+{
+  validate_subreddit_list(subreddit)?;
+  let path = ListingQuery::new(subreddit, sort).limit(limit).path();
+  let listing = get_things::<Post>(&path, args)?;
+  Ok(listing.into_iter().map(|post| post.title).collect())
+}
+
+/// Every post made in a subreddit after `since` (seconds since the epoch,
+/// like created_utc), newest first. /new is ordered by age, so paging stops
+/// at the first post that is not newer instead of reading the whole listing.
+pub fn new_since(subreddit: &str, since: f64, args: Args) -> Result<Vec<Post>, RedditError> {
+  let pages = ListingQuery::new(subreddit, Sort::New).limit(100).pages(args)?;
+
+  let mut posts = Vec::new();
+  for page in pages {
+    for post in page? {
+      if post.created_utc <= since {
+        return Ok(posts);
+      }
+      posts.push(post);
+    }
+  }
+  Ok(posts)
+}
+
+/// Roughly how many results a search has. Reddit never reports a total,
+/// so this pages through at most `max_pages` pages and counts what came
+/// back; anything past the last page read is not counted.
+pub fn search_count(query: &str, subreddit: Option<&str>, max_pages: u32, args: Args)
+  -> Result<usize, RedditError>
+{
+  let path = search_path(query, subreddit)?;
+  let args = Args { max_pages : Some(max_pages), ..args };
+  let mut count = 0;
+  for page in paginate::<Value>(&path, args) {
+    count += page?.children.len();
+  }
+  Ok(count)
+}
+
+/// Every post a search finds, paging as far as the iterator is driven, so
+/// search_iter(..).take(100) reads only the pages those 100 posts are on.
+/// An invalid subreddit comes back as the only item.
+pub fn search_iter(query: &str, subreddit: Option<&str>, sort: SearchSort, args: Args)
+  -> impl Iterator<Item = Result<Post, RedditError>>
+{
+  let pages = search_path(query, subreddit).map(|path| {
+    let mut path = append_param(&path, "sort", sort.as_str());
+    if let SearchSort::Top(range) = sort {
+      path = append_param(&path, "t", range.as_str());
+    }
+    paginate::<Post>(&path, args)
+  });
+
+  let (pages, invalid) = match pages {
+    Ok(pages) => (Some(pages), None),
+    Err(err) => (None, Some(Err(err))),
+  };
+  invalid.into_iter().chain(pages.into_iter().flatten().flat_map(|page| match page {
+    Ok(listing) => listing.children.into_iter().map(Ok).collect::<Vec<_>>(),
+    Err(err) => vec![Err(err)],
+  }))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// SearchRequest
+/// Builds a search, e.g.
+/// SearchRequest::new("borrow checker").subreddit("rust").sort(SearchSort::New).limit(50)
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchRequest {
+  pub query : String,
+  pub subreddit : Option<String>,
+  pub sort : Option<SearchSort>,
+  pub limit : Option<u32>,
+}
+
+/// the most results reddit returns for one search request
+pub const MAX_SEARCH_LIMIT: u32 = 100;
+
+impl SearchRequest {
+  pub fn new(query: &str) -> SearchRequest {
+    SearchRequest { query : query.to_owned(), subreddit : None, sort : None, limit : None }
+  }
+
+  /// only searches this subreddit
+  pub fn subreddit(mut self, subreddit: &str) -> SearchRequest {
+    self.subreddit = Some(subreddit.to_owned());
+    self
+  }
+
+  pub fn sort(mut self, sort: SearchSort) -> SearchRequest {
+    self.sort = Some(sort);
+    self
+  }
+
+  /// how many results to ask for, at most MAX_SEARCH_LIMIT
+  pub fn limit(mut self, limit: u32) -> SearchRequest {
+    self.limit = Some(limit);
+    self
+  }
+
+  /// The request path, e.g. "/r/rust/search/.json?q=borrow%20checker&restrict_sr=on".
+  /// An empty query or a limit over MAX_SEARCH_LIMIT fails with
+  /// RedditError::InvalidArgument, since reddit would only answer with a 400.
+  pub fn path(&self) -> Result<String, RedditError> {
+    if self.query.trim().is_empty() {
+      return Err(RedditError::InvalidArgument("search query is empty".to_owned()));
+    }
+    if self.limit.is_some_and(|limit| limit > MAX_SEARCH_LIMIT) {
+      return Err(RedditError::InvalidArgument(
+        format!("search limit may be at most {}", MAX_SEARCH_LIMIT)));
+    }
+
+    let mut path = search_path(&self.query, self.subreddit.as_deref())?;
+    if let Some(sort) = self.sort {
+      path = append_param(&path, "sort", sort.as_str());
+      if let SearchSort::Top(range) = sort {
+        path = append_param(&path, "t", range.as_str());
+      }
+    }
+    if let Some(limit) = self.limit {
+      path = append_param(&path, "limit", &limit.to_string());
+    }
+    Ok(path)
+  }
+
+  /// runs the search, one page of results
+  pub fn send(&self, args: Args) -> Result<Listing<Post>, RedditError> {
+    get_things(&self.path()?, args)
+  }
+}
+
+/// Searches all of reddit, see SearchRequest for narrowing it down
+pub fn search(query: &str, args: Args) -> Result<Listing<Post>, RedditError> {
+  SearchRequest::new(query).send(args)
+}
+
+/// the search path for a query, restricted to one subreddit when given
+fn search_path(query: &str, subreddit: Option<&str>) -> Result<String, RedditError> {
+  match subreddit {
+    Some(sub) => {
+      validate_subreddit_list(sub)?;
+      Ok(format!("/r/{}/search/.json?q={}&restrict_sr=on", sub, url_encode(query)))
+    },
+    None => Ok(format!("/search/.json?q={}", url_encode(query))),
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_listing {
+
+  use api::*;
+  use config::Args;
+  use error::RedditError;
+  use mock::{MockServer, MockResponse};
+
+  #[test]
+  fn test_sort_and_time_range_from_str() {
+    assert_eq!("hot".parse::<Sort>().unwrap(), Sort::Hot);
+    assert_eq!("Rising".parse::<Sort>().unwrap(), Sort::Rising);
+    assert_eq!("top".parse::<Sort>().unwrap(), Sort::Top(TimeRange::Day));
+    assert!(matches!("tpo".parse::<Sort>(), Err(RedditError::InvalidSort(ref s)) if s == "tpo"));
+
+    assert_eq!("week".parse::<TimeRange>().unwrap(), TimeRange::Week);
+    assert_eq!("ALL".parse::<TimeRange>().unwrap(), TimeRange::All);
+    assert!(matches!("fortnight".parse::<TimeRange>(), Err(RedditError::InvalidTimeRange(_))));
+
+    assert_eq!("top".parse::<SearchSort>().unwrap(), SearchSort::Top(TimeRange::All));
+    assert_eq!("Comments".parse::<SearchSort>().unwrap(), SearchSort::Comments);
+    assert!(matches!("best".parse::<SearchSort>(), Err(RedditError::InvalidSort(_))));
+  }
+
+  #[test]
+  fn test_search_request_paths() {
+    assert_eq!(SearchRequest::new("borrow checker").path().unwrap(),
+      "/search/.json?q=borrow%20checker");
+    assert_eq!(
+      SearchRequest::new("borrow checker").subreddit("rust")
+        .sort(SearchSort::Top(TimeRange::Month)).limit(50).path().unwrap(),
+      "/r/rust/search/.json?q=borrow%20checker&restrict_sr=on&sort=top&t=month&limit=50");
+    assert_eq!(SearchRequest::new("c# & f#").sort(SearchSort::New).path().unwrap(),
+      "/search/.json?q=c%23%20%26%20f%23&sort=new");
+    assert_eq!(SearchRequest::new("größe").path().unwrap(), "/search/.json?q=gr%C3%B6%C3%9Fe");
+    assert_eq!(SearchRequest::new("x").limit(100).path().unwrap(), "/search/.json?q=x&limit=100");
+
+    assert!(matches!(SearchRequest::new("  ").path(), Err(RedditError::InvalidArgument(_))));
+    assert!(matches!(SearchRequest::new("x").limit(101).path(),
+      Err(RedditError::InvalidArgument(_))));
+    assert!(matches!(SearchRequest::new("x").subreddit("bad name").path(),
+      Err(RedditError::InvalidName(_))));
+  }
+
+  #[test]
+  fn test_search_sends_request() {
+    let server = MockServer::start(vec![MockResponse::json(r#"{"kind": "Listing", "data": {
+      "children": [{"kind": "t3", "data": {"name": "t3_a", "title": "found"}}]}}"#)]);
+    let args = server.args();
+
+    assert_eq!(search("lifetimes", args).unwrap().children[0].title, "found");
+    assert_eq!(server.requests()[0].path, "/search/.json?q=lifetimes&raw_json=1");
+  }
+
+  #[test]
+  fn test_titles() {
+    let server = MockServer::start(vec![MockResponse::json(r#"{"kind": "Listing", "data": {
+      "children": [
+        {"kind": "t3", "data": {"name": "t3_a", "title": "first"}},
+        {"kind": "t3", "data": {"name": "t3_b", "title": "second"}},
+        {"kind": "t3", "data": {"name": "t3_c", "title": "third"}}]}}"#)]);
+    let args = server.args();
+
+    let titles = titles("rust", Sort::Top(TimeRange::All), 3, args).unwrap();
+    assert_eq!(titles, vec!["first", "second", "third"]);
+    assert_eq!(server.requests()[0].path, "/r/rust/top/.json?t=all&limit=3&raw_json=1");
+  }
+
+  #[test]
+  fn test_new_since_stops_at_boundary() {
+    let page = |after: &str, times: &[u32]| {
+      let children: Vec<String> = times.iter()
+        .map(|t| format!(r#"{{"kind": "t3", "data": {{"name": "t3_{}", "created_utc": {}}}}}"#,
+          t, t))
+        .collect();
+      MockResponse::json(&format!(r#"{{"kind": "Listing", "data": {{"after": {},
+        "children": [{}]}}}}"#, after, children.join(",")))
+    };
+    let server = MockServer::start(vec![
+      page(r#""t3_300""#, &[500, 400, 300]),
+      page(r#""t3_100""#, &[250, 200, 100]),
+      page("null", &[50]),
+    ]);
+    let args = server.args();
+
+    let posts = new_since("rust", 200.0, args).unwrap();
+    let names: Vec<&str> = posts.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, vec!["t3_500", "t3_400", "t3_300", "t3_250"]);
+
+    let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(paths, vec!["/r/rust/new/.json?limit=100&raw_json=1",
+      "/r/rust/new/.json?limit=100&after=t3_300&raw_json=1"]);
+  }
+
+  #[test]
+  fn test_search_iter_spans_pages() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"kind": "Listing", "data": {"after": "t3_b", "children": [
+        {"kind": "t3", "data": {"name": "t3_a"}}, {"kind": "t3", "data": {"name": "t3_b"}}]}}"#),
+      MockResponse::json(r#"{"kind": "Listing", "data": {"after": null, "children": [
+        {"kind": "t3", "data": {"name": "t3_c"}}]}}"#),
+    ]);
+    let args = server.args();
+
+    let posts: Vec<String> = search_iter("rust & c++", Some("rust"),
+      SearchSort::Top(TimeRange::Year), args)
+      .take(100)
+      .map(|post| post.unwrap().name)
+      .collect();
+    assert_eq!(posts, vec!["t3_a", "t3_b", "t3_c"]);
+
+    let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(paths, vec![
+      "/r/rust/search/.json?q=rust%20%26%20c%2B%2B&restrict_sr=on&sort=top&t=year&raw_json=1",
+      concat!("/r/rust/search/.json?q=rust%20%26%20c%2B%2B&restrict_sr=on&sort=top&t=year",
+        "&after=t3_b&raw_json=1"),
+    ]);
+
+    let mut invalid = search_iter("x", Some("not valid"), SearchSort::New, Args::default());
+    assert!(matches!(invalid.next(), Some(Err(RedditError::InvalidName(_)))));
+    assert!(invalid.next().is_none());
+  }
+
+  #[test]
+  fn test_listing_query_path() {
+    let query = ListingQuery::new("rust", Sort::Top(TimeRange::Week)).limit(50);
+    assert_eq!(query.path(), "/r/rust/top/.json?t=week&limit=50");
+
+    assert_eq!(ListingQuery::new("rust", Sort::New).path(), "/r/rust/new/.json");
+  }
+
+  #[test]
+  fn test_geo_filter() {
+    let query = ListingQuery::new("popular", Sort::Hot).geo("US").unwrap();
+    assert_eq!(query.path(), "/r/popular/hot/.json?g=US");
+
+    let state = ListingQuery::new("popular", Sort::Hot).geo("US_WA").unwrap();
+    assert_eq!(state.geo, Some("US_WA".to_owned()));
+
+    for unknown in &["us", "ZZ", "US_ZZ", ""] {
+      let err = ListingQuery::new("popular", Sort::Hot).geo(unknown).unwrap_err();
+      assert!(matches!(err, RedditError::InvalidArgument(_)), "{:?}", unknown);
+    }
+  }
+
+  #[test]
+  fn test_geo_filter_param() {
+    let query = ListingQuery::new("popular", Sort::Hot).limit(10).geo_filter("GLOBAL").unwrap();
+    assert_eq!(query.path(), "/r/popular/hot/.json?limit=10&geo_filter=GLOBAL");
+
+    let err = ListingQuery::new("popular", Sort::Hot).geo_filter("Atlantis").unwrap_err();
+    assert!(matches!(err, RedditError::InvalidName(ref name) if name == "Atlantis"));
+  }
+
+  #[test]
+  fn test_listing_query_pages() {
+    let server = MockServer::start(vec![MockResponse::json(
+      r#"{"kind": "Listing", "data": {"after": null, "children": [
+        {"kind": "t3", "data": {"name": "t3_a"}}]}}"#)]);
+    let args = server.args();
+
+    let query = ListingQuery::new("popular", Sort::Hot).geo("GB").unwrap();
+    let posts: Vec<_> = query.pages(args).unwrap().collect();
+    assert_eq!(posts.len(), 1);
+    assert_eq!(server.requests()[0].path, "/r/popular/hot/.json?g=GB&raw_json=1");
+
+    assert!(ListingQuery::new("not a sub", Sort::Hot).pages(Args::default()).is_err());
+  }
+
+  fn page(after: &str, count: usize) -> String {
+    let children: Vec<String> = (0..count)
+      .map(|i| format!(r#"{{"kind": "t3", "data": {{"name": "t3_{}"}}}}"#, i))
+      .collect();
+    format!(r#"{{"kind": "Listing", "data": {{"after": {}, "children": [{}]}}}}"#,
+      if after.is_empty() { "null".to_owned() } else { format!("{:?}", after) },
+      children.join(","))
+  }
+
+  #[test]
+  fn test_search_count_stops_at_cap() {
+    let server = MockServer::start(vec![
+      MockResponse::json(&page("t3_a", 25)),
+      MockResponse::json(&page("t3_b", 25)),
+      MockResponse::json(&page("t3_c", 25)),
+    ]);
+    let args = server.args();
+
+    assert_eq!(search_count("rust lang", Some("rust"), 2, args).unwrap(), 50);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].path, "/r/rust/search/.json?q=rust%20lang&restrict_sr=on&raw_json=1");
+    assert_eq!(requests[1].path,
+      "/r/rust/search/.json?q=rust%20lang&restrict_sr=on&after=t3_a&raw_json=1");
+  }
+
+  #[test]
+  fn test_search_count_stops_at_end_of_results() {
+    let server = MockServer::start(vec![
+      MockResponse::json(&page("t3_a", 25)),
+      MockResponse::json(&page("", 7)),
+    ]);
+    let args = server.args();
+
+    assert_eq!(search_count("ferris", None, 10, args).unwrap(), 32);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].path, "/search/.json?q=ferris&raw_json=1");
+  }
+}
@@ -0,0 +1,181 @@
+
+// internal imports
+use api::{oauth_query, parse_json_envelope, post, require_auth, validate_subreddit_name,
+  validate_username};
+use config::Args;
+use error::RedditError;
+use models::{Fullname, Kind, Listing, Thing};
+
+////////////////////////////////////////////////////////////////////////////////
+// Messages
+// The authenticated account's inbox, and private messages to other users
+
+/// Which part of the inbox to read
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InboxFilter {
+  /// everything received: private messages and replies
+  Inbox,
+  /// what has not been marked read yet
+  Unread,
+  /// private messages the account sent
+  Sent,
+}
+
+impl InboxFilter {
+  fn as_str(&self) -> &'static str {
+    match *self {
+      InboxFilter::Inbox => "inbox",
+      InboxFilter::Unread => "unread",
+      InboxFilter::Sent => "sent",
+    }
+  }
+}
+
+/// The newest page of the authenticated account's inbox. Private messages
+/// come back as Thing::Message and replies to the account's posts and
+/// comments as Thing::Comment, mixed in the same listing.
+pub fn inbox(filter: InboxFilter, args: Args) -> Result<Listing<Thing>, RedditError> {
+  Listing::from_value(oauth_query(&format!("/message/{}", filter.as_str()), args)?)
+}
+
+/// Marks messages and comment replies, by fullname, as read
+pub fn mark_read<F: AsRef<str>>(fullnames: &[F], args: Args) -> Result<(), RedditError> {
+  require_auth(&args)?;
+  let mut ids = Vec::new();
+  for fullname in fullnames {
+    let fullname: Fullname = fullname.as_ref().parse()?;
+    if fullname.kind() != Kind::Message && fullname.kind() != Kind::Comment {
+      return Err(RedditError::InvalidName(fullname.into()));
+    }
+    ids.push(fullname.as_str().to_owned());
+  }
+  if ids.is_empty() {
+    return Ok(());
+  }
+
+  post("/api/read_message", &[("id", &ids.join(","))], args)?;
+  Ok(())
+}
+
+/// Sends a private message as the authenticated account. `to` is a
+/// username, or "/r/<subreddit>" to message its moderators.
+pub fn compose(to: &str, subject: &str, body: &str, args: Args) -> Result<(), RedditError> {
+  match to.strip_prefix("/r/") {
+    Some(subreddit) => validate_subreddit_name(subreddit)?,
+    None => validate_username(to)?,
+  }
+  if subject.trim().is_empty() {
+    return Err(RedditError::InvalidArgument("a message needs a subject".to_owned()));
+  }
+
+  let value = post("/api/compose", &[("api_type", "json"), ("to", to), ("subject", subject),
+    ("text", body)], args)?;
+  parse_json_envelope(&value)?;
+  Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_message {
+
+  use api::*;
+  use config::Args;
+  use error::RedditError;
+  use mock::{MockServer, MockResponse};
+  use models::Thing;
+
+  const INBOX: &str = r#"{"kind": "Listing", "data": {"after": null, "children": [
+    {"kind": "t4", "data": {"id": "m1", "name": "t4_m1", "author": "ferris", "dest": "bot",
+      "subject": "hello", "body": "are you a bot?", "new": true, "parent_id": null,
+      "subreddit": null, "created_utc": 1700000000.0}},
+    {"kind": "t1", "data": {"id": "c1", "name": "t1_c1", "author": "crab", "body": "thanks!",
+      "parent_id": "t1_c0", "link_title": "weekly thread", "subject": "comment reply",
+      "was_comment": true, "new": false, "replies": "", "created_utc": 1700000100.0}},
+    {"kind": "t4", "data": {"id": "m2", "name": "t4_m2", "author": null, "dest": "bot",
+      "subject": "welcome", "body": "rules", "new": false, "parent_id": "t4_m0",
+      "subreddit": "rust", "created_utc": "1700000200"}}]}}"#;
+
+  #[test]
+  fn test_inbox_mixes_messages_and_replies() {
+    let server = MockServer::start(vec![MockResponse::json(INBOX)]);
+
+    let inbox = inbox(InboxFilter::Unread, server.oauth_args()).unwrap();
+    assert_eq!(server.requests()[0].path, "/message/unread?raw_json=1");
+    assert_eq!(inbox.children.len(), 3);
+    match inbox.children[0] {
+      Thing::Message(ref message) => {
+        assert_eq!(message.subject, "hello");
+        assert!(message.new);
+        assert_eq!(message.parent_id, None);
+      },
+      ref other => panic!("{:?}", other),
+    }
+    match inbox.children[1] {
+      Thing::Comment(ref reply) => assert_eq!(reply.body, "thanks!"),
+      ref other => panic!("{:?}", other),
+    }
+    match inbox.children[2] {
+      Thing::Message(ref message) => {
+        assert_eq!(message.author, "");
+        assert_eq!(message.subreddit.as_deref(), Some("rust"));
+        assert_eq!(message.created_utc, 1700000200.0);
+      },
+      ref other => panic!("{:?}", other),
+    }
+    assert_eq!(inbox.children[2].fullname(), Some("t4_m2"));
+  }
+
+  #[test]
+  fn test_compose_form() {
+    let server = MockServer::start(vec![MockResponse::json(r#"{"json": {"errors": []}}"#)]);
+
+    compose("ferris", "héllo & bye", "line one\nline two 🦀", server.oauth_args()).unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.path, "/api/compose?raw_json=1");
+    assert_eq!(String::from_utf8(request.body.clone()).unwrap(),
+      "api_type=json&to=ferris&subject=h%C3%A9llo%20%26%20bye\
+      &text=line%20one%0Aline%20two%20%F0%9F%A6%80");
+  }
+
+  #[test]
+  fn test_compose_surfaces_api_errors() {
+    let server = MockServer::start(vec![MockResponse::json(
+      r#"{"json": {"errors": [["USER_DOESNT_EXIST", "that user doesn't exist", "to"]]}}"#)]);
+
+    let err = compose("nobody", "hi", "text", server.oauth_args()).unwrap_err();
+    match err {
+      RedditError::Api(ref errors) => assert_eq!(errors[0].code, "USER_DOESNT_EXIST"),
+      ref other => panic!("{:?}", other),
+    }
+    assert!(matches!(compose("two words", "hi", "", server.oauth_args()),
+      Err(RedditError::InvalidName(_))));
+    assert!(matches!(compose("/r/rust", " ", "", server.oauth_args()),
+      Err(RedditError::InvalidArgument(_))));
+  }
+
+  #[test]
+  fn test_mark_read_form() {
+    let server = MockServer::start(vec![MockResponse::json("{}")]);
+
+    mark_read(&["t4_m1", "t1_c1"], server.oauth_args()).unwrap();
+    assert_eq!(String::from_utf8(server.requests()[0].body.clone()).unwrap(),
+      "id=t4_m1%2Ct1_c1");
+
+    assert!(matches!(mark_read(&["t3_post"], server.oauth_args()),
+      Err(RedditError::InvalidName(_))));
+    mark_read::<&str>(&[], server.oauth_args()).unwrap();
+    assert_eq!(server.requests().len(), 1);
+  }
+
+  #[test]
+  fn test_messages_need_credentials() {
+    // nothing listens here, so any request that went out would fail differently
+    let args = Args { oauth_url : "http://127.0.0.1:9".to_owned(), ..Args::default() };
+
+    assert!(matches!(inbox(InboxFilter::Inbox, args.clone()), Err(RedditError::AuthRequired)));
+    assert!(matches!(mark_read(&["t4_m1"], args.clone()), Err(RedditError::AuthRequired)));
+    assert!(matches!(compose("ferris", "hi", "there", args), Err(RedditError::AuthRequired)));
+  }
+}
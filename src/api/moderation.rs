@@ -0,0 +1,182 @@
+
+// external imports
+use serde_json;
+
+// internal imports
+use api::{oauth_query, parse_json_envelope, post, validate_subreddit_name};
+use config::Args;
+use error::RedditError;
+use models::{expect_array, FlairTemplate, Fullname, Kind};
+
+////////////////////////////////////////////////////////////////////////////////
+// Moderation
+// Endpoints that need the authenticated account to moderate the subreddit
+
+/// What a flair gets assigned to: a user by name, or a link by fullname
+#[derive(Clone, Debug, PartialEq)]
+pub enum FlairTarget {
+  User(String),
+  Link(String),
+}
+
+/// Sets the flair text of a user or link in a subreddit
+pub fn set_flair(subreddit: &str, target: FlairTarget, text: &str, args: Args)
+  -> Result<(), RedditError>
+{
+  validate_subreddit_name(subreddit)?;
+
+  let path = format!("/r/{}/api/selectflair", subreddit);
+  let target = match target {
+    FlairTarget::User(ref name) => ("name", name.as_str()),
+    FlairTarget::Link(ref fullname) => ("link", fullname.as_str()),
+  };
+
+  let value = post(&path, &[("api_type", "json"), target, ("text", text)], args)?;
+  parse_json_envelope(&value)?;
+  Ok(())
+}
+
+/// The link flairs a subreddit offers to pick from when posting
+pub fn link_flair_templates(subreddit: &str, args: Args)
+  -> Result<Vec<FlairTemplate>, RedditError>
+{
+  validate_subreddit_name(subreddit)?;
+
+  // a bare array of templates, not a listing
+  let value = oauth_query(&format!("/r/{}/api/link_flair_v2", subreddit), args)?;
+  expect_array(&value)?;
+  Ok(serde_json::from_value(value)?)
+}
+
+/// the longest report reason reddit accepts
+const MAX_REPORT_REASON: usize = 100;
+
+/// Reports a post or comment, given its fullname ("t3_..." or "t1_..." as a
+/// string or a Fullname), to the subreddit's moderators. Reasons over 100
+/// characters are refused with RedditError::InvalidArgument before anything
+/// is sent.
+pub fn report<F: AsRef<str>>(fullname: F, reason: &str, args: Args)
+  -> Result<(), RedditError>
+{
+  let fullname: Fullname = fullname.as_ref().parse()?;
+  if fullname.kind() != Kind::Link && fullname.kind() != Kind::Comment {
+    return Err(RedditError::InvalidName(fullname.into()));
+  }
+  if reason.chars().count() > MAX_REPORT_REASON {
+    return Err(RedditError::InvalidArgument(
+      format!("report reasons are limited to {} characters", MAX_REPORT_REASON)));
+  }
+
+  let value = post("/api/report", &[("api_type", "json"), ("thing_id", fullname.as_str()),
+    ("reason", reason)], args)?;
+  parse_json_envelope(&value)?;
+  Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_moderation {
+
+  use api::*;
+  use config::Args;
+  use error::RedditError;
+  use mock::{MockServer, MockResponse};
+  use models::Fullname;
+
+  #[test]
+  fn test_set_user_flair_form() {
+    let server = MockServer::start(vec![MockResponse::json(r#"{"json": {"errors": []}}"#)]);
+
+    set_flair("rust", FlairTarget::User("ferris".to_owned()), "crab", server.oauth_args())
+      .unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/r/rust/api/selectflair?raw_json=1");
+    assert_eq!(
+      String::from_utf8_lossy(&request.body),
+      "api_type=json&name=ferris&text=crab");
+  }
+
+  #[test]
+  fn test_set_link_flair_form() {
+    let server = MockServer::start(vec![MockResponse::json(r#"{"json": {"errors": []}}"#)]);
+
+    set_flair("rust", FlairTarget::Link("t3_abc".to_owned()), "solved!", server.oauth_args())
+      .unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!(
+      String::from_utf8_lossy(&request.body),
+      "api_type=json&link=t3_abc&text=solved%21");
+  }
+
+  #[test]
+  fn test_link_flair_templates() {
+    use models::FlairTemplate;
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"[
+        {"id": "a1b2", "text": "Discussion", "css_class": "discussion",
+          "text_editable": false, "type": "text", "text_color": "dark"},
+        {"id": "c3d4", "text": "", "css_class": "", "text_editable": true}
+      ]"#),
+      MockResponse::json(r#"{"message": "Not Found", "error": 404}"#),
+    ]);
+
+    let templates = link_flair_templates("rust", server.oauth_args()).unwrap();
+    assert_eq!(templates, vec![
+      FlairTemplate {
+        id : "a1b2".to_owned(),
+        text : "Discussion".to_owned(),
+        css_class : "discussion".to_owned(),
+        editable : false,
+      },
+      FlairTemplate { id : "c3d4".to_owned(), editable : true, ..FlairTemplate::default() },
+    ]);
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/r/rust/api/link_flair_v2?raw_json=1");
+    assert_eq!(request.header("Authorization").unwrap(), "bearer token");
+
+    assert!(matches!(link_flair_templates("rust", server.oauth_args()),
+      Err(RedditError::UnexpectedShape { expected: "array", .. })));
+    assert!(matches!(link_flair_templates("rust", Args::default()),
+      Err(RedditError::AuthRequired)));
+  }
+
+  #[test]
+  fn test_report_form() {
+    let server = MockServer::start(vec![MockResponse::json(r#"{"json": {"errors": []}}"#)]);
+
+    report(Fullname::post("abc").unwrap(), "spam", server.oauth_args()).unwrap();
+
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/api/report?raw_json=1");
+    assert_eq!(
+      String::from_utf8_lossy(&request.body),
+      "api_type=json&thing_id=t3_abc&reason=spam");
+  }
+
+  #[test]
+  fn test_report_validation() {
+    let args = Args { key: "token".to_owned(), ..Args::default() };
+    let err = report("t3_abc", &"x".repeat(101), args.clone()).unwrap_err();
+    assert!(matches!(err, RedditError::InvalidArgument(_)));
+
+    let err = report("abc", "spam", args.clone()).unwrap_err();
+    assert!(matches!(err, RedditError::InvalidName(_)));
+
+    // a well-formed fullname of something that can not be reported
+    let err = report("t2_abc", "spam", args).unwrap_err();
+    assert!(matches!(err, RedditError::InvalidName(_)));
+  }
+
+  #[test]
+  fn test_set_flair_surfaces_api_errors() {
+    let server = MockServer::start(vec![MockResponse::json(
+      r#"{"json": {"errors": [["MOD_REQUIRED", "you must be a moderator", null]]}}"#)]);
+
+    let err = set_flair("rust", FlairTarget::User("ferris".to_owned()), "crab",
+      server.oauth_args()).unwrap_err();
+    assert!(matches!(err, RedditError::Api(_)));
+  }
+}
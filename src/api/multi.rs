@@ -0,0 +1,208 @@
+
+// external imports
+use serde_json::{self, Value};
+
+// internal imports
+use api::{append_param, oauth_query, path_query, validate_username, ListingQuery, Sort};
+use config::Args;
+use error::{RedditError, Restriction};
+use models::{Listing, Multireddit, Post};
+
+////////////////////////////////////////////////////////////////////////////////
+// Multireddits and the front page
+// Listings built from several subreddits at once: a user's named multis,
+// and the home feed of the account args.key belongs to
+
+/// Checks a multireddit name against reddit's rules: 1-50 letters, digits
+/// or underscores
+pub fn validate_multi_name(name: &str) -> Result<(), RedditError> {
+  let valid = !name.is_empty()
+    && name.len() <= 50
+    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+  if !valid {
+    return Err(RedditError::InvalidName(name.to_owned()));
+  }
+  Ok(())
+}
+
+/// The path of a sorted multireddit listing, e.g.
+/// "/user/ferris/m/rustlang/top/.json?t=week"; hand it to paginate to read
+/// past the first page
+pub fn multi_path(user: &str, multi_name: &str, listing: Sort) -> Result<String, RedditError> {
+  validate_username(user)?;
+  validate_multi_name(multi_name)?;
+  Ok(sorted_path(&format!("/user/{}/m/{}", user, multi_name), listing))
+}
+
+/// The first page of one of a user's multireddits. A private multi fails
+/// with RedditError::Restricted, reason Restriction::Private.
+pub fn multi(user: &str, multi_name: &str, listing: Sort, args: Args)
+  -> Result<Listing<Post>, RedditError>
+{
+  Listing::from_value(multi_query(&multi_path(user, multi_name, listing)?, args)?)
+}
+
+/// A multireddit's description and the subreddits in it
+pub fn multi_info(user: &str, multi_name: &str, args: Args)
+  -> Result<Multireddit, RedditError>
+{
+  validate_username(user)?;
+  validate_multi_name(multi_name)?;
+  Multireddit::from_value(multi_query(&format!("/api/multi/user/{}/m/{}", user, multi_name),
+    args)?)
+}
+
+/// The path of the home feed on the oauth host, e.g. "/top/.json?t=day";
+/// hand it to paginate_oauth to read past the first page
+pub fn front_page_path(listing: Sort) -> String {
+  sorted_path("", listing)
+}
+
+/// The first page of the front page: the home feed of the account args.key
+/// belongs to, or r/popular, which is what reddit shows logged out
+/// visitors, when there is no key
+pub fn front_page(listing: Sort, args: Args) -> Result<Listing<Post>, RedditError> {
+  if args.key.is_empty() {
+    let popular = ListingQuery::new("popular", listing).path();
+    return Listing::from_value(path_query(popular.as_str(), args)?);
+  }
+  Listing::from_value(oauth_query(&front_page_path(listing), args)?)
+}
+
+fn sorted_path(base: &str, listing: Sort) -> String {
+  let path = format!("{}/{}/.json", base, listing.as_str());
+  match listing.time_range() {
+    Some(range) => append_param(&path, "t", range.as_str()),
+    None => path,
+  }
+}
+
+/// path_query for a multi's pages. Reddit answers a private multi with a
+/// bare {"message": "Forbidden", "error": 403}, which becomes a
+/// RedditError::Restricted so it reads like any other private listing.
+fn multi_query(path: &str, args: Args) -> Result<Value, RedditError> {
+  match path_query(path, args) {
+    Err(ref err) if matches!(*err.root(), RedditError::Http { status : 403, .. }) => {
+      let message = match *err.root() {
+        RedditError::Http { ref body, .. } => serde_json::from_str::<Value>(body).ok()
+          .and_then(|value| value["message"].as_str().map(|message| message.to_owned())),
+        _ => None,
+      };
+      Err(RedditError::Restricted {
+        status : 403,
+        reason : Restriction::Private,
+        message : message.unwrap_or_else(|| "Forbidden".to_owned()),
+      })
+    },
+    result => result,
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_multi {
+
+  use api::*;
+  use config::Args;
+  use error::{RedditError, Restriction};
+  use mock::{MockServer, MockResponse};
+
+  const LISTING: &str = r#"{"kind": "Listing", "data": {"after": "t3_b", "children": [
+    {"kind": "t3", "data": {"name": "t3_a", "title": "async in 2026", "author": "ferris",
+      "subreddit": "rust", "score": 40}},
+    {"kind": "t3", "data": {"name": "t3_b", "title": "zig 1.0", "author": "crab",
+      "subreddit": "Zig", "score": 12}}]}}"#;
+
+  const MULTI: &str = r#"{"kind": "LabeledMulti", "data": {"can_edit": false,
+    "display_name": "Systems", "name": "systems", "description_md": "low level **stuff**",
+    "owner": "ferris", "visibility": "public", "path": "/user/ferris/m/systems/",
+    "created_utc": 1600000000.0, "icon_url": "https://www.redditstatic.com/multi.png",
+    "subreddits": [{"name": "rust"}, {"name": "Zig"}, {"name": "C_Programming"}]}}"#;
+
+  const FORBIDDEN: &str = r#"{"message": "Forbidden", "error": 403}"#;
+
+  #[test]
+  fn test_multi_and_front_page_paths() {
+    assert_eq!(multi_path("ferris", "systems", Sort::Hot).unwrap(),
+      "/user/ferris/m/systems/hot/.json");
+    assert_eq!(multi_path("some-user_1", "my_multi2", Sort::Top(TimeRange::Week)).unwrap(),
+      "/user/some-user_1/m/my_multi2/top/.json?t=week");
+    assert_eq!(front_page_path(Sort::New), "/new/.json");
+    assert_eq!(front_page_path(Sort::Top(TimeRange::Day)), "/top/.json?t=day");
+
+    assert!(matches!(multi_path("two words", "systems", Sort::Hot),
+      Err(RedditError::InvalidName(_))));
+    assert!(matches!(multi_path("ferris", "../../api/me", Sort::Hot),
+      Err(RedditError::InvalidName(_))));
+    assert!(matches!(multi_path("ferris", "", Sort::Hot), Err(RedditError::InvalidName(_))));
+    assert!(matches!(multi_info("ferris", "sys-tems", Args::default()),
+      Err(RedditError::InvalidName(_))));
+  }
+
+  #[test]
+  fn test_multi_listing() {
+    let server = MockServer::start(vec![MockResponse::json(LISTING)]);
+
+    let posts = multi("ferris", "systems", Sort::New, server.args()).unwrap();
+    assert_eq!(server.requests()[0].path, "/user/ferris/m/systems/new/.json?raw_json=1");
+    assert_eq!(posts.after.as_deref(), Some("t3_b"));
+    assert_eq!(posts.children.iter().map(|p| p.subreddit.as_str()).collect::<Vec<_>>(),
+      ["rust", "Zig"]);
+  }
+
+  #[test]
+  fn test_multi_info() {
+    let server = MockServer::start(vec![MockResponse::json(MULTI)]);
+
+    let info = multi_info("ferris", "systems", server.args()).unwrap();
+    assert_eq!(server.requests()[0].path, "/api/multi/user/ferris/m/systems?raw_json=1");
+    assert_eq!(info.display_name, "Systems");
+    assert_eq!(info.description_md, "low level **stuff**");
+    assert_eq!(info.subreddits, ["rust", "Zig", "C_Programming"]);
+    assert_eq!(info.created_utc, 1600000000.0);
+  }
+
+  #[test]
+  fn test_private_multi_is_restricted() {
+    let forbidden = || MockResponse::status(403, FORBIDDEN)
+      .header("Content-Type", "application/json; charset=UTF-8");
+    let server = MockServer::start(vec![forbidden(), forbidden()]);
+
+    let restriction = |err: RedditError| match err {
+      RedditError::Restricted { status, reason, message } => (status, reason, message),
+      other => panic!("{:?}", other),
+    };
+    let expected = (403, Restriction::Private, "Forbidden".to_owned());
+    assert_eq!(restriction(multi("ferris", "secret", Sort::Hot, server.args())
+      .unwrap_err()), expected);
+    assert_eq!(restriction(multi_info("ferris", "secret", server.args()).unwrap_err()),
+      expected);
+  }
+
+  #[test]
+  fn test_front_page() {
+    let server = MockServer::start(vec![
+      MockResponse::json(LISTING),
+      MockResponse::json(LISTING),
+    ]);
+    let args = Args {
+      key : "token".to_owned(),
+      base_url : server.base_url.clone(),
+      oauth_url : server.base_url.clone(),
+      danger_allow_insecure : true,
+      ..Args::default()
+    };
+
+    let home = front_page(Sort::Hot, args.clone()).unwrap();
+    assert_eq!(home.children[0].title, "async in 2026");
+    let popular = front_page(Sort::Top(TimeRange::Week), args.without_auth()).unwrap();
+    assert_eq!(popular.children.len(), 2);
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/hot/.json?raw_json=1");
+    assert_eq!(requests[0].header("Authorization").unwrap(), "bearer token");
+    assert_eq!(requests[1].header("Authorization"), None);
+    assert_eq!(requests[1].path, "/r/popular/top/.json?t=week&raw_json=1");
+  }
+}
@@ -0,0 +1,277 @@
+
+// external imports
+use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// internal imports
+use api::{check_blocked, check_content_type, parse_json_with, path_query_response,
+  status_error, validate_subreddit_name, Response};
+use config::Args;
+use error::RedditError;
+use models::{Listing, Post};
+
+////////////////////////////////////////////////////////////////////////////////
+/// RateLimitState
+/// What reddit last said about the rate limit through the X-Ratelimit-*
+/// headers. Shared between workers so they slow down together instead of
+/// each spending the same allowance.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimitState {
+  /// requests left in the current window, None until reddit reports it
+  pub remaining : Option<f64>,
+  /// requests made in the current window
+  pub used : Option<f64>,
+  /// when the current window ends
+  pub reset_at : Option<Instant>,
+}
+
+impl RateLimitState {
+  /// picks up X-Ratelimit-Remaining, X-Ratelimit-Used and X-Ratelimit-Reset
+  /// (seconds until the window ends) from a response; responses without
+  /// them change nothing
+  pub fn update(&mut self, response: &Response) {
+    let number = |name: &str| response.header(name).and_then(|v| v.trim().parse::<f64>().ok());
+    if let Some(remaining) = number("X-Ratelimit-Remaining") {
+      self.remaining = Some(remaining);
+    }
+    if let Some(used) = number("X-Ratelimit-Used") {
+      self.used = Some(used);
+    }
+    if let Some(reset) = number("X-Ratelimit-Reset") {
+      self.reset_at = Some(Instant::now() + Duration::from_secs_f64(reset.max(0.0)));
+    }
+  }
+
+  /// Claims one request from the allowance. Returns how long to wait first
+  /// when it is used up and the window has not ended yet.
+  pub fn acquire(&mut self, now: Instant) -> Option<Duration> {
+    match (self.remaining, self.reset_at) {
+      (Some(remaining), Some(reset_at)) if remaining < 1.0 && reset_at > now => {
+        Some(reset_at - now)
+      },
+      (Some(remaining), _) => {
+        self.remaining = Some(remaining - 1.0);
+        None
+      },
+      _ => None,
+    }
+  }
+}
+
+/// what reddit last reported to path_query and friends, whichever thread
+/// made the request, kept per ratelimit_key
+static RATELIMITS: Mutex<BTreeMap<(String, u64), RateLimitState>> = Mutex::new(BTreeMap::new());
+
+/// Reddit counts the allowance per access token, or per client when there
+/// is none, and every host keeps its own count; so one spent token never
+/// holds up another. The token is only kept as a hash.
+fn ratelimit_key(url: &str, args: &Args) -> (String, u64) {
+  let authority = url.find("://").map_or(0, |scheme| scheme + 3);
+  let host = match url[authority..].find(['/', '?', '#']) {
+    Some(end) => &url[..authority + end],
+    None => url,
+  };
+  let mut hasher = DefaultHasher::new();
+  args.key.hash(&mut hasher);
+  (host.to_ascii_lowercase(), hasher.finish())
+}
+
+/// The rate limit as of the last response that reported one, for requests
+/// to url's host (e.g. "https://oauth.reddit.com") with the token in
+/// args.key
+pub fn ratelimit_status(url: &str, args: &Args) -> RateLimitState {
+  RATELIMITS.lock().unwrap().get(&ratelimit_key(url, args)).cloned().unwrap_or_default()
+}
+
+/// Sleeps until the window resets when the allowance for url's host and
+/// args.key is spent, unless args.respect_ratelimit is off
+pub(crate) fn wait_for_ratelimit(url: &str, args: &Args) {
+  if !args.respect_ratelimit {
+    return;
+  }
+  let wait = RATELIMITS.lock().unwrap().get_mut(&ratelimit_key(url, args))
+    .and_then(|state| state.acquire(Instant::now()));
+  if let Some(wait) = wait {
+    info!("rate limit allowance spent, waiting {:?} for the window to reset", wait);
+    thread::sleep(wait);
+  }
+}
+
+/// feeds a response's X-Ratelimit headers into ratelimit_status
+pub(crate) fn record_ratelimit(url: &str, args: &Args, response: &Response) {
+  RATELIMITS.lock().unwrap().entry(ratelimit_key(url, args)).or_default().update(response);
+}
+
+/// Fetches the front page listing of every subreddit on `threads` worker
+/// threads, results in input order. The workers share one RateLimitState:
+/// once reddit reports the allowance as spent they all wait for the window
+/// to reset.
+pub fn subreddits_parallel(names: &[&str], threads: usize, args: Args)
+  -> Result<Vec<Result<Listing<Post>, RedditError>>, RedditError>
+{
+  if threads == 0 {
+    return Err(RedditError::InvalidArgument("threads must be at least 1".to_owned()));
+  }
+
+  let state = Arc::new(Mutex::new(RateLimitState::default()));
+  let next = AtomicUsize::new(0);
+  let results = Mutex::new(names.iter().map(|_| None).collect::<Vec<_>>());
+
+  thread::scope(|scope| {
+    for _ in 0..threads.min(names.len()) {
+      scope.spawn(|| loop {
+        let index = next.fetch_add(1, Ordering::SeqCst);
+        if index >= names.len() {
+          break;
+        }
+        let result = fetch_subreddit(names[index], &state, &args);
+        results.lock().unwrap()[index] = Some(result);
+      });
+    }
+  });
+
+  Ok(results.into_inner().unwrap().into_iter()
+    .map(|slot| slot.unwrap_or(Err(RedditError::Aborted)))
+    .collect())
+}
+
+fn fetch_subreddit(name: &str, state: &Mutex<RateLimitState>, args: &Args)
+  -> Result<Listing<Post>, RedditError>
+{
+  validate_subreddit_name(name)?;
+
+  // the lock is only held to read the state, never while sleeping
+  loop {
+    let wait = state.lock().unwrap().acquire(Instant::now());
+    match wait {
+      Some(wait) => {
+        info!("rate limit allowance spent, waiting {:?} for the window to reset", wait);
+        thread::sleep(wait);
+      },
+      None => break,
+    }
+  }
+
+  let response = path_query_response(&format!("/r/{}/.json", name), args.clone())?;
+  state.lock().unwrap().update(&response);
+
+  check_blocked(&response)?;
+  if !(200..300).contains(&response.status) {
+    return Err(status_error(response));
+  }
+  check_content_type(response.header("Content-Type"))?;
+  parse_json_with(&response.body, args)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_parallel {
+
+  use api::*;
+  use config::Args;
+  use error::RedditError;
+  use mock::{MockServer, MockResponse};
+  use std::time::{Duration, Instant};
+
+  const PAGE: &str = r#"{"kind": "Listing", "data": {"children": [
+    {"kind": "t3", "data": {"name": "t3_a", "title": "hi"}}]}}"#;
+
+  #[test]
+  fn test_subreddits_parallel_in_order() {
+    let server = MockServer::start_concurrent((0..3).map(|_| MockResponse::json(PAGE)).collect());
+
+    let results = subreddits_parallel(&["rust", "programming", "bad name"], 2,
+      server.args()).unwrap();
+    assert_eq!(results[0].as_ref().unwrap().children[0].title, "hi");
+    assert!(results[1].is_ok());
+    assert!(matches!(results[2], Err(RedditError::InvalidName(_))));
+  }
+
+  #[test]
+  fn test_workers_back_off_on_low_remaining() {
+    let spent = || MockResponse::json(PAGE)
+      .header("X-Ratelimit-Remaining", "0.0")
+      .header("X-Ratelimit-Reset", "1");
+    let server = MockServer::start(vec![spent(), spent(), MockResponse::json(PAGE)]);
+
+    let started = Instant::now();
+    let results = subreddits_parallel(&["a", "b", "c"], 2, server.args()).unwrap();
+    assert!(results.iter().all(|r| r.is_ok()));
+    // the third request had to wait for the window reported by the first two
+    assert!(started.elapsed() >= Duration::from_millis(900), "{:?}", started.elapsed());
+  }
+
+  #[test]
+  fn test_path_query_waits_out_spent_allowance() {
+    let server = MockServer::start(vec![
+      MockResponse::json("{}")
+        .header("X-Ratelimit-Remaining", "0.0")
+        .header("X-Ratelimit-Used", "600")
+        .header("X-Ratelimit-Reset", "0.5"),
+      MockResponse::json("{}"),
+    ]);
+
+    path_query("/r/rust/about.json", server.args()).unwrap();
+    let status = ratelimit_status(&server.base_url, &server.args());
+    assert_eq!(status.used, Some(600.0));
+    assert!(status.reset_at.is_some());
+
+    let started = Instant::now();
+    path_query("/r/rust/about.json", server.args()).unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(400), "{:?}", started.elapsed());
+  }
+
+  #[test]
+  fn test_spent_allowance_is_kept_per_host_and_token() {
+    let spent = || MockResponse::json("{}")
+      .header("X-Ratelimit-Remaining", "0.0")
+      .header("X-Ratelimit-Used", "100")
+      .header("X-Ratelimit-Reset", "30");
+    let server = MockServer::start(vec![spent(), MockResponse::json("{}")]);
+    let other = MockServer::start(vec![MockResponse::json("{}")]);
+    let token = Args { key : "token".to_owned(), ..server.args() };
+
+    path_query("/r/rust/.json", token.clone()).unwrap();
+    assert_eq!(ratelimit_status(&server.base_url, &token).used, Some(100.0));
+    assert_eq!(ratelimit_status(&server.base_url, &server.args()).used, None);
+    assert_eq!(ratelimit_status(&other.base_url, &token).used, None);
+
+    // neither another host nor the same host without the token waits 30s
+    let started = Instant::now();
+    path_query("/r/rust/.json", other.args()).unwrap();
+    path_query("/r/rust/.json", server.args()).unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+  }
+
+  #[test]
+  fn test_too_many_requests_is_rate_limited() {
+    let server = MockServer::start(vec![MockResponse::status(429, "<html>slow down</html>")]);
+
+    let err = path_query("/r/rust/.json", server.args()).unwrap_err();
+    assert!(err.is_rate_limited());
+  }
+
+  #[test]
+  fn test_rate_limit_state_acquire() {
+    let now = Instant::now();
+    let mut state = RateLimitState { remaining : Some(1.0), reset_at : Some(now), used : None };
+    assert_eq!(state.acquire(now), None);
+    assert_eq!(state.remaining, Some(0.0));
+
+    state.reset_at = Some(now + Duration::from_secs(5));
+    assert_eq!(state.acquire(now), Some(Duration::from_secs(5)));
+
+    assert_eq!(RateLimitState::default().acquire(now), None);
+  }
+
+  #[test]
+  fn test_zero_threads_is_rejected() {
+    assert!(matches!(subreddits_parallel(&["rust"], 0, Args::default()),
+      Err(RedditError::InvalidArgument(_))));
+  }
+}
@@ -0,0 +1,226 @@
+
+// internal imports
+use api::{validate_subreddit_name, QueryParams, Sort};
+use error::RedditError;
+
+////////////////////////////////////////////////////////////////////////////////
+/// RequestBuilder
+/// Builds a request path out of parts, percent-encoding each one, e.g.
+/// `RequestBuilder::subreddit("rust").listing(Sort::Top(TimeRange::Week))
+/// .param("limit", "100")` for "/r/rust/top/.json?t=week&limit=100".
+///
+/// Percent escapes already in the input are kept as they are, so "%20"
+/// stays "%20" rather than turning into "%2520". A param set twice keeps
+/// the later value, and params with an empty value are left out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestBuilder {
+  base : Base,
+  segments : Vec<String>,
+  params : Vec<(String, String)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Base {
+  Subreddit(String),
+  Raw(String),
+}
+
+impl RequestBuilder {
+  /// a request under /r/{name}/, ending in /.json
+  pub fn subreddit(name: &str) -> RequestBuilder {
+    RequestBuilder::with_base(Base::Subreddit(name.to_owned()))
+  }
+
+  /// Any path, taken as it is apart from encoding the characters that
+  /// can't appear in a url; it has to start with "/"
+  pub fn raw(path: &str) -> RequestBuilder {
+    RequestBuilder::with_base(Base::Raw(path.to_owned()))
+  }
+
+  fn with_base(base: Base) -> RequestBuilder {
+    RequestBuilder { base, segments : Vec::new(), params : Vec::new() }
+  }
+
+  /// the listing to read, with t= for the sorts that look back over a
+  /// time range
+  pub fn listing(mut self, sort: Sort) -> RequestBuilder {
+    self.segments.push(sort.as_str().to_owned());
+    match sort.time_range() {
+      Some(range) => self.param("t", range.as_str()),
+      None => self,
+    }
+  }
+
+  /// more of the path, e.g. "wiki/some page"; slashes separate segments
+  pub fn segment(mut self, segment: &str) -> RequestBuilder {
+    self.segments.extend(segment.split('/')
+      .filter(|part| !part.is_empty())
+      .map(|part| part.to_owned()));
+    self
+  }
+
+  /// sets a query parameter, replacing an earlier value for the same key
+  pub fn param(mut self, key: &str, value: &str) -> RequestBuilder {
+    match self.params.iter_mut().find(|pair| pair.0 == key) {
+      Some(pair) => pair.1 = value.to_owned(),
+      None => self.params.push((key.to_owned(), value.to_owned())),
+    }
+    self
+  }
+
+  /// The encoded path. Fails with RedditError::InvalidName for a bad
+  /// subreddit name and RedditError::InvalidArgument for a raw path that
+  /// does not start with "/".
+  pub fn path(&self) -> Result<String, RedditError> {
+    let extra = self.segments.iter()
+      .map(|segment| encode(segment, SEGMENT))
+      .collect::<Vec<String>>();
+
+    let path = match self.base {
+      Base::Subreddit(ref name) => {
+        validate_subreddit_name(name)?;
+        let mut parts = vec!["r".to_owned(), name.clone()];
+        parts.extend(extra);
+        format!("/{}/.json", parts.join("/"))
+      },
+      Base::Raw(ref path) => {
+        if !path.starts_with('/') {
+          return Err(RedditError::InvalidArgument(
+            format!("request path {:?} does not start with /", path)));
+        }
+        let (base, query) = match path.find('?') {
+          Some(at) => (&path[..at], Some(&path[at + 1..])),
+          None => (path.as_str(), None),
+        };
+        let mut out = encode(base, PATH);
+        for segment in &extra {
+          out = format!("{}/{}", out.trim_end_matches('/'), segment);
+        }
+        if let Some(query) = query {
+          out = format!("{}?{}", out, encode(query, QUERY));
+        }
+        out
+      },
+    };
+
+    let mut params = QueryParams::new();
+    for (key, value) in self.params.iter().filter(|pair| !pair.1.is_empty()) {
+      params.set(&encode(key, COMPONENT), &encode(value, COMPONENT));
+    }
+    Ok(params.apply_to(&path))
+  }
+}
+
+/// What path_query and friends take as a path: a plain string, used as it
+/// is, or a RequestBuilder
+pub trait IntoPath {
+  fn into_path(self) -> Result<String, RedditError>;
+}
+
+impl IntoPath for &str {
+  fn into_path(self) -> Result<String, RedditError> {
+    Ok(self.to_owned())
+  }
+}
+
+impl IntoPath for &String {
+  fn into_path(self) -> Result<String, RedditError> {
+    Ok(self.clone())
+  }
+}
+
+impl IntoPath for RequestBuilder {
+  fn into_path(self) -> Result<String, RedditError> {
+    self.path()
+  }
+}
+
+impl IntoPath for &RequestBuilder {
+  fn into_path(self) -> Result<String, RedditError> {
+    self.path()
+  }
+}
+
+/// characters kept as they are, besides the unreserved ones, in each part
+const SEGMENT: &str = "!$&'()*+,;=:@";
+const PATH: &str = "!$&'()*+,;=:@/";
+const QUERY: &str = "!$&'()*+,;=:@/?";
+const COMPONENT: &str = "!$'()*,:@/?";
+
+/// percent-encodes everything outside the unreserved characters and
+/// `keep`, leaving well formed %XX escapes alone
+fn encode(input: &str, keep: &str) -> String {
+  let bytes = input.as_bytes();
+  let mut out = String::with_capacity(input.len());
+  for (index, &byte) in bytes.iter().enumerate() {
+    let escape = byte == b'%' && bytes.len() > index + 2
+      && bytes[index + 1].is_ascii_hexdigit() && bytes[index + 2].is_ascii_hexdigit();
+    match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' =>
+        out.push(byte as char),
+      _ if escape => out.push('%'),
+      _ if byte.is_ascii() && keep.contains(byte as char) => out.push(byte as char),
+      _ => out.push_str(&format!("%{:02X}", byte)),
+    }
+  }
+  out
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_request {
+
+  use api::*;
+  use error::RedditError;
+  use mock::{MockServer, MockResponse};
+
+  #[test]
+  fn test_subreddit_listing_paths() {
+    assert_eq!(RequestBuilder::subreddit("rust").path().unwrap(), "/r/rust/.json");
+    assert_eq!(
+      RequestBuilder::subreddit("rust").listing(Sort::Top(TimeRange::Week))
+        .param("limit", "100").path().unwrap(),
+      "/r/rust/top/.json?t=week&limit=100");
+    assert_eq!(
+      RequestBuilder::subreddit("rust").segment("wiki/größe & co").path().unwrap(),
+      "/r/rust/wiki/gr%C3%B6%C3%9Fe%20&%20co/.json");
+    assert!(matches!(RequestBuilder::subreddit("bad name").path(),
+      Err(RedditError::InvalidName(_))));
+  }
+
+  #[test]
+  fn test_duplicate_and_empty_params() {
+    let path = RequestBuilder::subreddit("rust").listing(Sort::Top(TimeRange::Day))
+      .param("t", "all")
+      .param("after", "")
+      .param("q", "a&b=c #1")
+      .path().unwrap();
+    assert_eq!(path, "/r/rust/top/.json?t=all&q=a%26b%3Dc%20%231");
+  }
+
+  #[test]
+  fn test_raw_paths() {
+    assert_eq!(RequestBuilder::raw("/search/.json?q=borrow checker#x").path().unwrap(),
+      "/search/.json?q=borrow%20checker%23x");
+    // escapes already there are not encoded a second time
+    assert_eq!(RequestBuilder::raw("/search/.json?q=borrow%20checker").path().unwrap(),
+      "/search/.json?q=borrow%20checker");
+    assert_eq!(RequestBuilder::raw("/r/rust/wiki/100%/").param("v", "50%25").path().unwrap(),
+      "/r/rust/wiki/100%25/?v=50%25");
+    assert!(matches!(RequestBuilder::raw("r/rust/.json").path(),
+      Err(RedditError::InvalidArgument(_))));
+  }
+
+  #[test]
+  fn test_path_query_takes_a_builder() {
+    let server = MockServer::start(vec![MockResponse::json("{}"), MockResponse::json("{}")]);
+    let args = server.args();
+
+    path_query(RequestBuilder::subreddit("rust").listing(Sort::New), args.clone()).unwrap();
+    path_query("/r/rust/.json", args).unwrap();
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/r/rust/new/.json?raw_json=1");
+    assert_eq!(requests[1].path, "/r/rust/.json?raw_json=1");
+  }
+}
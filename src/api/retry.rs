@@ -0,0 +1,248 @@
+
+// external imports
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde_json::Value;
+
+// internal imports
+use api::path_query;
+use config::{Args, RetryPolicy};
+use error::RedditError;
+
+////////////////////////////////////////////////////////////////////////////////
+/// Backoff
+/// Full jitter exponential backoff: the delay before retry n is picked
+/// uniformly from 0..=min(max_delay, base_delay * 2^n), so many clients
+/// that failed together don't all come back at the same moment
+pub struct Backoff {
+  base : u64,
+  cap : u64,
+  rng : u64,
+}
+
+/// mixed into unseeded backoffs so ones created in the same instant differ
+static UNSEEDED: AtomicUsize = AtomicUsize::new(0);
+
+impl Backoff {
+  pub fn new(policy: &RetryPolicy) -> Backoff {
+    let seed = policy.seed.unwrap_or_else(|| {
+      let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+      nanos ^ UNSEEDED.fetch_add(1, Ordering::Relaxed) as u64
+    });
+
+    Backoff {
+      base : policy.base_delay_ms,
+      cap : policy.max_delay_ms,
+      rng : seed,
+    }
+  }
+
+  /// how long to sleep before retry number `attempt`, counting from 0
+  pub fn delay(&mut self, attempt: u32) -> Duration {
+    let ceiling = self.base
+      .saturating_mul(2u64.saturating_pow(attempt))
+      .min(self.cap);
+    Duration::from_millis(self.next_u64() % ceiling.saturating_add(1))
+  }
+
+  /// splitmix64, plenty for spreading out retries
+  fn next_u64(&mut self) -> u64 {
+    self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = self.rng;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+  }
+}
+
+/// path_query, retried according to args.retry whenever the failure is
+/// transient (curl errors, 5xx) or reddit asked us to slow down (429). A
+/// Retry-After header is waited out instead of the backoff delay, up to
+/// max_delay_ms. Other failures, e.g. a 403, are returned right away;
+/// max_retries 0 turns retrying off.
+pub fn path_query_retry(path: &str, args: Args) -> Result<Value, RedditError> {
+  let mut backoff = Backoff::new(&args.retry);
+  let mut attempt = 0;
+
+  loop {
+    match path_query(path, args.clone()) {
+      Err(ref err) if attempt < args.retry.max_retries
+        && (err.is_transient() || err.is_rate_limited()) => {
+        let delay = backoff.delay(attempt);
+        let delay = err.retry_after()
+          .map(|after| after.min(Duration::from_millis(args.retry.max_delay_ms)))
+          .unwrap_or(delay);
+        info!("retrying {} in {:?}, attempt {} failed: {}", path, delay, attempt + 1, err);
+        thread::sleep(delay);
+        attempt += 1;
+      },
+      result => return result,
+    }
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_retry {
+
+  use api::*;
+  use config::{Args, RetryPolicy};
+  use error::RedditError;
+  use mock::{MockServer, MockResponse};
+
+  fn seeded(seed: u64) -> RetryPolicy {
+    RetryPolicy {
+      max_retries : 5,
+      base_delay_ms : 100,
+      max_delay_ms : 1000,
+      seed : Some(seed),
+    }
+  }
+
+  #[test]
+  fn test_seeded_backoff_is_deterministic() {
+    let mut backoff = Backoff::new(&seeded(42));
+    let delays: Vec<u64> = (0..6)
+      .map(|attempt| backoff.delay(attempt).as_millis() as u64)
+      .collect();
+    assert_eq!(delays, vec![23, 49, 111, 657, 475, 592]);
+
+    let mut again = Backoff::new(&seeded(42));
+    assert_eq!(again.delay(0).as_millis() as u64, delays[0]);
+  }
+
+  #[test]
+  fn test_backoff_stays_within_ceiling() {
+    let mut backoff = Backoff::new(&seeded(7));
+    for attempt in 0..40 {
+      let ceiling = (100u64 << attempt.min(20)).min(1000);
+      assert!(backoff.delay(attempt).as_millis() as u64 <= ceiling);
+    }
+  }
+
+  #[test]
+  fn test_unbounded_max_delay_does_not_overflow() {
+    let mut backoff = Backoff::new(&RetryPolicy { max_delay_ms: u64::MAX, ..seeded(3) });
+    for attempt in 0..70 {
+      backoff.delay(attempt);
+    }
+  }
+
+  #[test]
+  fn test_retry_gives_up_after_max_retries() {
+    use std::net::TcpListener;
+
+    // grab a free port and let it go again, so connecting is refused
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let args = Args {
+      base_url : format!("http://{}", addr),
+      retry : RetryPolicy { max_retries: 2, base_delay_ms: 1, ..seeded(1) },
+      ..Args::default()
+    };
+
+    let err = path_query_retry("/r/rust/.json", args).unwrap_err();
+    assert!(matches!(*err.root(), RedditError::CouldNotConnect(_)));
+  }
+
+  #[test]
+  fn test_retry_survives_connection_resets() {
+    let server = MockServer::start(vec![
+      MockResponse::reset(),
+      MockResponse::reset(),
+      MockResponse::reset(),
+      MockResponse::json(r#"{"ok": true}"#),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      retry : RetryPolicy { base_delay_ms: 1, ..seeded(1) },
+      ..Args::default()
+    };
+
+    assert!(matches!(*path_query("/r/rust/.json", args.clone()).unwrap_err().root(),
+      RedditError::ConnectionReset(_)));
+    // reset twice more, then answered
+    assert_eq!(path_query_retry("/r/rust/.json", args).unwrap()["ok"], true);
+    assert_eq!(server.requests().len(), 1);
+  }
+
+  #[test]
+  fn test_retry_skips_permanent_failures() {
+    let html = MockResponse::status(200, "<html></html>").header("Content-Type", "text/html");
+    let server = MockServer::start(vec![html.clone(), html]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      retry : RetryPolicy { base_delay_ms: 1, ..seeded(1) },
+      ..Args::default()
+    };
+
+    let err = path_query_retry("/r/rust/.json", args).unwrap_err();
+    assert!(matches!(*err.root(), RedditError::UnexpectedContentType { .. }));
+    assert_eq!(server.requests().len(), 1);
+  }
+
+  #[test]
+  fn test_retry_flaky_endpoint_honors_retry_after() {
+    use std::time::{Duration, Instant};
+
+    let server = MockServer::start(vec![
+      MockResponse::status(503, "our CDN was unable to reach our servers"),
+      MockResponse::status(429, "slow down").header("Retry-After", "1"),
+      MockResponse::json(r#"{"ok": true}"#),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      retry : RetryPolicy { base_delay_ms: 1, ..seeded(1) },
+      ..Args::default()
+    };
+
+    let started = Instant::now();
+    assert_eq!(path_query_retry("/r/rust/.json", args).unwrap()["ok"], true);
+    assert!(started.elapsed() >= Duration::from_millis(950), "{:?}", started.elapsed());
+    assert_eq!(server.requests().len(), 3);
+  }
+
+  #[test]
+  fn test_retry_after_is_capped_at_max_delay() {
+    use std::time::{Duration, Instant};
+
+    let server = MockServer::start(vec![
+      MockResponse::status(429, "slow down").header("Retry-After", "3600"),
+      MockResponse::json(r#"{"ok": true}"#),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      retry : RetryPolicy { base_delay_ms: 1, max_delay_ms: 50, ..seeded(1) },
+      ..Args::default()
+    };
+
+    let started = Instant::now();
+    assert_eq!(path_query_retry("/r/rust/.json", args).unwrap()["ok"], true);
+    assert!(started.elapsed() < Duration::from_secs(5), "{:?}", started.elapsed());
+    assert_eq!(server.requests().len(), 2);
+  }
+
+  #[test]
+  fn test_retry_after_is_read_from_responses() {
+    let server = MockServer::start(vec![
+      MockResponse::status(503, "busy").header("Retry-After", "7"),
+      MockResponse::status(403, "<html>nope</html>").header("Content-Type", "text/html"),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      retry : RetryPolicy { max_retries: 0, ..seeded(1) },
+      ..Args::default()
+    };
+
+    let err = path_query_retry("/r/rust/.json", args.clone()).unwrap_err();
+    assert_eq!(err.retry_after(), Some(::std::time::Duration::from_secs(7)));
+    assert_eq!(err.status_code(), Some(503));
+    assert!(err.is_transient());
+
+    let retried = Args { retry : RetryPolicy { base_delay_ms: 1, ..seeded(1) }, ..args };
+    assert!(path_query_retry("/r/rust/.json", retried).is_err());
+    assert_eq!(server.requests().len(), 2);
+  }
+}
@@ -0,0 +1,457 @@
+
+// external imports
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// internal imports
+use api::{paginate, validate_subreddit_list, Backoff};
+use config::Args;
+use error::RedditError;
+use models::Post;
+
+////////////////////////////////////////////////////////////////////////////////
+// Streams
+// Long running polls of a subreddit's /new feed
+
+/// how many posts spawn_new_stream buffers before the poller blocks
+const STREAM_BUFFER: usize = 100;
+
+/// the shortest args.poll_interval stream_new goes with
+pub const MIN_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// how many fullnames a NewStream remembers
+const SEEN_CAPACITY: usize = 5000;
+
+////////////////////////////////////////////////////////////////////////////////
+/// NewWatcher
+/// Remembers the newest post seen on a subreddit's /new feed so that each
+/// poll only hands back what was posted since
+pub struct NewWatcher {
+  path : String,
+  args : Args,
+  newest : Option<u64>,
+}
+
+impl NewWatcher {
+  pub fn new(subreddit: &str, args: Args) -> Result<NewWatcher, RedditError> {
+    validate_subreddit_list(subreddit)?;
+
+    Ok(NewWatcher {
+      path : format!("/r/{}/new/.json", subreddit),
+      args,
+      newest : None,
+    })
+  }
+
+  /// Returns the posts made since the previous poll, oldest first
+  ///
+  /// The first poll returns the first page of the feed. Later polls keep
+  /// paging back until they reach the newest post already returned, so a
+  /// burst of more than a page of posts between polls is not lost.
+  pub fn poll(&mut self) -> Result<Vec<Post>, RedditError> {
+    let mut fresh = Vec::new();
+
+    'pages: for page in paginate::<Post>(&self.path, self.args.clone()) {
+      for post in page?.children {
+        match (post_id(&post), self.newest) {
+          (Some(id), Some(newest)) if id <= newest => break 'pages,
+          _ => fresh.push(post),
+        }
+      }
+      if self.newest.is_none() {
+        break;
+      }
+    }
+
+    if let Some(id) = fresh.iter().filter_map(post_id).max() {
+      self.newest = Some(self.newest.map_or(id, |newest| newest.max(id)));
+    }
+
+    // /new lists newest first
+    fresh.reverse();
+    Ok(fresh)
+  }
+}
+
+/// the numeric part of a post's fullname; reddit hands ids out in order,
+/// base 36 encoded, so "t3_b" is newer than "t3_a"
+fn post_id(post: &Post) -> Option<u64> {
+  let (_, id) = post.name.split_once('_')?;
+  u64::from_str_radix(id, 36).ok()
+}
+
+/// Iterator over the posts showing up in a subreddit's /new feed, see
+/// poll_new. It never ends on its own; a failed poll is yielded as an
+/// error and polling carries on after the next interval.
+pub struct NewPosts {
+  watcher : NewWatcher,
+  interval : Duration,
+  pending : VecDeque<Post>,
+  polled : bool,
+}
+
+impl Iterator for NewPosts {
+  type Item = Result<Post, RedditError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      if let Some(post) = self.pending.pop_front() {
+        return Some(Ok(post));
+      }
+
+      if self.polled {
+        thread::sleep(self.interval);
+      }
+      self.polled = true;
+
+      match self.watcher.poll() {
+        Ok(posts) => self.pending.extend(posts),
+        Err(err) => return Some(Err(err)),
+      }
+    }
+  }
+}
+
+/// Polls a subreddit's /new feed every `interval`, yielding each post once,
+/// oldest first. The posts already on the feed come out of the first poll.
+pub fn poll_new(subreddit: &str, interval: Duration, args: Args)
+  -> Result<NewPosts, RedditError>
+{
+  Ok(NewPosts {
+    watcher : NewWatcher::new(subreddit, args)?,
+    interval,
+    pending : VecDeque::new(),
+    polled : false,
+  })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// SeenSet
+/// The last `capacity` fullnames handed out, oldest forgotten first
+pub struct SeenSet {
+  order : VecDeque<String>,
+  names : HashSet<String>,
+  capacity : usize,
+}
+
+impl SeenSet {
+  pub fn new(capacity: usize) -> SeenSet {
+    SeenSet { order : VecDeque::new(), names : HashSet::new(), capacity }
+  }
+
+  /// Remembers a fullname, false when it was already remembered. Once
+  /// full, the oldest name makes room.
+  pub fn insert(&mut self, name: &str) -> bool {
+    if self.capacity == 0 || self.names.contains(name) {
+      return false;
+    }
+    if self.order.len() == self.capacity {
+      if let Some(oldest) = self.order.pop_front() {
+        self.names.remove(&oldest);
+      }
+    }
+    self.order.push_back(name.to_owned());
+    self.names.insert(name.to_owned());
+    true
+  }
+
+  pub fn len(&self) -> usize {
+    self.order.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.order.is_empty()
+  }
+}
+
+/// Iterator over the new posts of a subreddit, see stream_new
+pub struct NewStream {
+  watcher : NewWatcher,
+  interval : Duration,
+  seen : SeenSet,
+  pending : VecDeque<Post>,
+  backoff : Backoff,
+  polled : bool,
+}
+
+impl Iterator for NewStream {
+  type Item = Result<Post, RedditError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let mut failures = 0;
+    loop {
+      if let Some(post) = self.pending.pop_front() {
+        return Some(Ok(post));
+      }
+
+      if self.polled {
+        thread::sleep(self.interval);
+      }
+      self.polled = true;
+
+      match self.watcher.poll() {
+        Ok(posts) => {
+          failures = 0;
+          let seen = &mut self.seen;
+          self.pending.extend(posts.into_iter().filter(|post| seen.insert(&post.name)));
+        },
+        Err(ref err) if err.is_transient() || err.is_rate_limited() => {
+          let delay = self.backoff.delay(failures);
+          info!("polling again in {:?} after: {}", delay, err);
+          thread::sleep(delay);
+          failures += 1;
+        },
+        Err(err) => return Some(Err(err)),
+      }
+    }
+  }
+}
+
+/// Polls a subreddit's /new feed every args.poll_interval (at least
+/// MIN_POLL_INTERVAL), yielding each post once by its fullname, oldest
+/// first. The last 5000 fullnames are remembered for that. Transient
+/// failures are waited out with backoff (args.retry) without ending the
+/// stream; other errors are yielded and polling carries on.
+pub fn stream_new(subreddit: &str, args: Args) -> Result<NewStream, RedditError> {
+  let interval = args.poll_interval.max(MIN_POLL_INTERVAL);
+  let backoff = Backoff::new(&args.retry);
+  Ok(NewStream {
+    watcher : NewWatcher::new(subreddit, args)?,
+    interval,
+    seen : SeenSet::new(SEEN_CAPACITY),
+    pending : VecDeque::new(),
+    backoff,
+    polled : false,
+  })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// StreamHandle
+/// Controls the thread behind spawn_new_stream
+pub struct StreamHandle {
+  thread : JoinHandle<()>,
+  stop : Arc<AtomicBool>,
+}
+
+impl StreamHandle {
+  /// Asks the thread to finish. A thread waiting out the interval wakes up
+  /// right away; one blocked on a full channel stops once it can send.
+  pub fn stop(&self) {
+    self.stop.store(true, Ordering::SeqCst);
+    self.thread.thread().unpark();
+  }
+
+  /// waits for the thread to finish, see stop
+  pub fn join(self) -> thread::Result<()> {
+    self.thread.join()
+  }
+}
+
+/// Runs poll_new on a background thread, sending what it yields through a
+/// bounded channel
+///
+/// The thread runs until StreamHandle::stop is called, or until the
+/// receiver is dropped and it next has something to send. An invalid
+/// subreddit name is sent as the only message.
+pub fn spawn_new_stream(subreddit: &str, interval: Duration, args: Args)
+  -> (StreamHandle, Receiver<Result<Post, RedditError>>)
+{
+  let (sender, receiver) = sync_channel(STREAM_BUFFER);
+  let watcher = NewWatcher::new(subreddit, args);
+  let stop = Arc::new(AtomicBool::new(false));
+  let stopped = stop.clone();
+
+  let thread = thread::spawn(move || {
+    let mut watcher = match watcher {
+      Ok(watcher) => watcher,
+      Err(err) => {
+        let _ = sender.send(Err(err));
+        return;
+      },
+    };
+
+    while !stopped.load(Ordering::SeqCst) {
+      let sent = match watcher.poll() {
+        Ok(posts) => posts.into_iter().all(|post| sender.send(Ok(post)).is_ok()),
+        Err(err) => sender.send(Err(err)).is_ok(),
+      };
+      if !sent {
+        return;
+      }
+
+      // parked rather than slept so that stop can cut the wait short
+      let deadline = Instant::now() + interval;
+      while !stopped.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+          break;
+        }
+        thread::park_timeout(deadline - now);
+      }
+    }
+  });
+
+  (StreamHandle { thread, stop }, receiver)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_stream {
+
+  use api::*;
+  use config::Args;
+  use error::RedditError;
+  use mock::{MockServer, MockResponse};
+  use std::time::Duration;
+
+  fn new_page(names: &[&str]) -> MockResponse {
+    new_page_after(names, None)
+  }
+
+  fn new_page_after(names: &[&str], after: Option<&str>) -> MockResponse {
+    let children: Vec<String> = names.iter()
+      .map(|name| format!(r#"{{"kind": "t3", "data": {{"name": "{}"}}}}"#, name))
+      .collect();
+    let after = after.map_or("null".to_owned(), |after| format!("\"{}\"", after));
+    MockResponse::json(&format!(
+      r#"{{"kind": "Listing", "data": {{"after": {}, "children": [{}]}}}}"#,
+      after, children.join(",")))
+  }
+
+  fn names(posts: Vec<::models::Post>) -> Vec<String> {
+    posts.into_iter().map(|post| post.name).collect()
+  }
+
+  #[test]
+  fn test_new_watcher_returns_only_newer_posts() {
+    let server = MockServer::start(vec![
+      new_page_after(&["t3_b", "t3_a"], Some("t3_a")),
+      new_page(&["t3_d", "t3_c", "t3_b", "t3_a"]),
+      new_page(&["t3_d", "t3_c"]),
+    ]);
+    let args = server.args();
+
+    let mut watcher = NewWatcher::new("rust", args).unwrap();
+    assert_eq!(names(watcher.poll().unwrap()), vec!["t3_a", "t3_b"]);
+    assert_eq!(names(watcher.poll().unwrap()), vec!["t3_c", "t3_d"]);
+    assert!(watcher.poll().unwrap().is_empty());
+    // the first poll does not page back through the whole feed
+    assert_eq!(server.requests().len(), 3);
+  }
+
+  #[test]
+  fn test_new_watcher_pages_back_to_last_seen() {
+    let server = MockServer::start(vec![
+      new_page(&["t3_a"]),
+      new_page_after(&["t3_e", "t3_d"], Some("t3_d")),
+      new_page_after(&["t3_c", "t3_b", "t3_a"], Some("t3_9")),
+    ]);
+    let args = server.args();
+
+    let mut watcher = NewWatcher::new("rust", args).unwrap();
+    watcher.poll().unwrap();
+    assert_eq!(names(watcher.poll().unwrap()), vec!["t3_b", "t3_c", "t3_d", "t3_e"]);
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[2].path, "/r/rust/new/.json?after=t3_d&raw_json=1");
+  }
+
+  #[test]
+  fn test_seen_set_forgets_oldest() {
+    let mut seen = SeenSet::new(2);
+    assert!(seen.insert("t3_a"));
+    assert!(!seen.insert("t3_a"));
+    assert!(seen.insert("t3_b"));
+    assert!(seen.insert("t3_c"));
+    assert_eq!(seen.len(), 2);
+    // t3_a made room for t3_c
+    assert!(seen.insert("t3_a"));
+    assert!(!seen.insert("t3_c"));
+  }
+
+  #[test]
+  fn test_stream_new_dedups_and_survives_transient_errors() {
+    use config::RetryPolicy;
+
+    let server = MockServer::start(vec![
+      new_page(&["t3_b", "t3_a"]),
+      MockResponse::status(503, "busy"),
+      MockResponse::reset(),
+      new_page(&["t3_d", "t3_c", "t3_b"]),
+    ]);
+    let args = Args {
+      base_url : server.base_url.clone(),
+      poll_interval : Duration::from_millis(1),
+      retry : RetryPolicy { base_delay_ms: 1, max_delay_ms: 5, ..RetryPolicy::default() },
+      ..Args::default()
+    };
+
+    let mut stream = stream_new("rust", args).unwrap();
+    assert_eq!(stream.interval, MIN_POLL_INTERVAL);
+    // the floor is for reddit's sake, the mock can take a faster pace
+    stream.interval = Duration::from_millis(1);
+
+    let names: Vec<String> = stream.by_ref().take(4).map(|post| post.unwrap().name).collect();
+    assert_eq!(names, vec!["t3_a", "t3_b", "t3_c", "t3_d"]);
+    assert_eq!(stream.seen.len(), 4);
+    assert_eq!(server.requests().len(), 3);
+  }
+
+  #[test]
+  fn test_spawn_new_stream_sends_new_posts() {
+    let server = MockServer::start(vec![
+      new_page(&["t3_b", "t3_a"]),
+      new_page(&["t3_c", "t3_b", "t3_a"]),
+    ]);
+    let args = server.args();
+
+    let (handle, receiver) = spawn_new_stream("rust", Duration::from_millis(10), args);
+    let names: Vec<String> = receiver.iter()
+      .take(3)
+      .map(|post| post.unwrap().name)
+      .collect();
+    assert_eq!(names, vec!["t3_a", "t3_b", "t3_c"]);
+    assert_eq!(server.requests()[0].path, "/r/rust/new/.json?raw_json=1");
+
+    // the server is out of responses, so the next poll fails and the
+    // attempt to send that error notices the receiver is gone
+    drop(receiver);
+    handle.join().unwrap();
+  }
+
+  #[test]
+  fn test_spawn_new_stream_invalid_name() {
+    let (handle, receiver) = spawn_new_stream("../api", Duration::from_millis(10),
+      Args::default());
+
+    let messages: Vec<_> = receiver.iter().collect();
+    assert_eq!(messages.len(), 1);
+    assert!(matches!(messages[0], Err(RedditError::InvalidName(_))));
+    handle.join().unwrap();
+  }
+
+  #[test]
+  fn test_stop_interrupts_the_wait() {
+    use std::time::Instant;
+
+    let server = MockServer::start(vec![new_page(&["t3_a"])]);
+    let args = server.args();
+
+    let (handle, receiver) = spawn_new_stream("rust", Duration::from_secs(60), args);
+    assert_eq!(receiver.recv().unwrap().unwrap().name, "t3_a");
+
+    // the thread is now waiting out its 60 second interval
+    let started = Instant::now();
+    handle.stop();
+    handle.join().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(receiver.recv().is_err());
+    assert_eq!(server.requests().len(), 1);
+  }
+}
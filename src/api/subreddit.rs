@@ -0,0 +1,763 @@
+
+// external imports
+use std::cmp::min;
+use std::collections::VecDeque;
+use curl::easy::Easy;
+use serde_json;
+use serde_json::Value;
+
+// internal imports
+use api::{append_param, check_content_type, header_list, parse_json, parse_json_envelope,
+  path_query, path_query_response, prepare_get, response_for,
+  setup_connection, status_error, url_encode, validate_subreddit_list, validate_subreddit_name};
+use config::Args;
+use error::RedditError;
+use models::{comment_tree, comment_tree_to_depth, expect_array, expect_object, Comment,
+  CommentTree, FromChild, Listing, MoreComments, Post, Rule, Thing, WikiPage, MAX_COMMENT_DEPTH};
+
+/// the most ids /api/morechildren takes in one request
+const MORE_CHILDREN_BATCH: usize = 100;
+
+////////////////////////////////////////////////////////////////////////////////
+// Subreddit reads
+// Helpers for endpoints scoped to a single subreddit
+
+/// Fetches a random post from a subreddit
+///
+/// reddit answers /r/<sub>/random with a redirect to the post's comments
+/// page, so redirects are followed regardless of args.follow_redirects.
+/// Subreddits that have random disabled come back as RedditError::NotFound,
+/// whether reddit says so with an empty listing or with a 404.
+pub fn random_post(subreddit: &str, args: Args) -> Result<Post, RedditError> {
+  validate_subreddit_list(subreddit)?;
+
+  let args = Args { follow_redirects : true, ..args };
+  let value = match path_query(&format!("/r/{}/random/.json", subreddit), args) {
+    Err(ref err) if err.status_code() == Some(404) => return Err(RedditError::NotFound),
+    result => result?,
+  };
+  post_from_comments_page(value)
+}
+
+/// Resolves a short link such as "redd.it/abc" to the permalink it
+/// redirects to. Links given without a scheme are fetched over https; a
+/// link that does not redirect anywhere is RedditError::NotFound.
+pub fn resolve_short_link(short: &str, args: Args) -> Result<String, RedditError> {
+  let url = if short.starts_with("http://") || short.starts_with("https://") {
+    short.to_owned()
+  } else {
+    format!("https://{}", short)
+  };
+
+  let (request, args) = prepare_get(&url, &Args { follow_redirects : true, ..args })?;
+  let mut easy = Easy::new();
+  easy.url(&request.url)?;
+  easy.http_headers(header_list(&request.headers)?)?;
+  setup_connection(&mut easy, &args)?;
+
+  let response = response_for(&mut easy, "GET", &args)?;
+  if response.status == 404 || easy.redirect_count()? == 0 {
+    return Err(RedditError::NotFound);
+  }
+  if !(200..300).contains(&response.status) {
+    return Err(status_error(response));
+  }
+
+  easy.effective_url()?
+    .map(|url| url.to_owned())
+    .ok_or(RedditError::NotFound)
+}
+
+/// Fetches a page of a subreddit's wiki, e.g. "index" or "config/sidebar".
+/// A page that does not exist (a 404, or reddit's PAGE_NOT_CREATED reason)
+/// is RedditError::NotFound; a wiki or page the account may not see comes
+/// back as RedditError::Restricted, or RedditError::Http when reddit gives
+/// no reason, with status 403.
+pub fn wiki_page(subreddit: &str, page: &str, args: Args) -> Result<WikiPage, RedditError> {
+  validate_subreddit_name(subreddit)?;
+  let valid_page = !page.is_empty() && page.split('/')
+    .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+  if !valid_page {
+    return Err(RedditError::InvalidName(page.to_owned()));
+  }
+
+  let response = path_query_response(&format!("/r/{}/wiki/{}/.json", subreddit, page), args)?;
+  let not_created = serde_json::from_str::<Value>(&response.body)
+    .map(|body| body["reason"] == "PAGE_NOT_CREATED")
+    .unwrap_or(false);
+  match response.status {
+    _ if not_created => return Err(RedditError::NotFound),
+    404 => return Err(RedditError::NotFound),
+    200..=299 => (),
+    _ => return Err(status_error(response)),
+  }
+  check_content_type(response.header("Content-Type"))?;
+
+  Ok(WikiPage::from_child(parse_json(&response.body)?)?)
+}
+
+/// A subreddit's rules in the order the moderators listed them; empty for
+/// a subreddit without any. reddit's site-wide rules are left out.
+pub fn subreddit_rules(subreddit: &str, args: Args) -> Result<Vec<Rule>, RedditError> {
+  validate_subreddit_name(subreddit)?;
+
+  let mut value = path_query(&format!("/r/{}/about/rules/.json", subreddit), args)?;
+  expect_object(&value)?;
+  match value["rules"].take() {
+    Value::Null => Ok(Vec::new()),
+    rules => Ok(serde_json::from_value(rules)?),
+  }
+}
+
+/// Order of the comments in a thread
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommentSort {
+  Best,
+  Top,
+  New,
+  Controversial,
+}
+
+impl CommentSort {
+  fn as_str(&self) -> &'static str {
+    match *self {
+      CommentSort::Best => "best",
+      CommentSort::Top => "top",
+      CommentSort::New => "new",
+      CommentSort::Controversial => "controversial",
+    }
+  }
+}
+
+/// Options for comments, anything left as None is up to reddit
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommentOpts {
+  pub sort : Option<CommentSort>,
+  pub limit : Option<u32>,
+  pub depth : Option<u32>,
+  /// levels of the tree parsed from the response, models::MAX_COMMENT_DEPTH
+  /// when None; unlike depth this is applied locally, not sent to reddit
+  pub max_depth : Option<u32>,
+}
+
+/// Fetches a post together with its comment tree. The post id may be given
+/// with or without its "t3_" prefix.
+pub fn comments(subreddit: &str, post_id: &str, opts: CommentOpts, args: Args)
+  -> Result<(Post, Vec<Comment>), RedditError>
+{
+  let (post, tree) = comment_thread(Some(subreddit), post_id, opts, args)?;
+  Ok((post, tree.comments))
+}
+
+/// Fetches the comment tree of a post by its id alone, leaving reddit to
+/// work out the subreddit. "more" stubs stay in the tree, see
+/// get_comments_full to have them loaded.
+pub fn get_comments(article_id: &str, args: Args) -> Result<CommentTree, RedditError> {
+  Ok(comment_thread(None, article_id, CommentOpts::default(), args)?.1)
+}
+
+/// get_comments with the "more" stubs expanded like comments_full does,
+/// within a budget of `max_requests` requests
+pub fn get_comments_full(article_id: &str, args: Args, max_requests: usize)
+  -> Result<CommentTree, RedditError>
+{
+  expand_thread(None, article_id, args, max_requests, false)
+}
+
+/// Other posts of the same link, e.g. cross-posts to other subreddits. The
+/// post id may be given with or without its "t3_" prefix; a link nobody
+/// posted again gives an empty list.
+pub fn duplicates(post_id: &str, args: Args) -> Result<Vec<Post>, RedditError> {
+  let id = validate_id(post_id, "t3_")?;
+
+  // [listing holding the original post, listing holding its duplicates]
+  let mut value = path_query(&format!("/duplicates/{}/.json", id), args)?;
+  expect_array(&value)?;
+  let others = value.get_mut(1).map(Value::take).ok_or(RedditError::NotFound)?;
+  Ok(Listing::<Post>::from_value(others)?.children)
+}
+
+/// Fetches the comments reddit left out of a thread as "more" stubs, given
+/// the post's fullname and the ids from MoreComments::children. Comments
+/// come back flat, each one after its parent, possibly mixed with stubs.
+pub fn more_children(link_id: &str, children: &[String], args: Args)
+  -> Result<Vec<Thing>, RedditError>
+{
+  let path = format!("/api/morechildren/.json?api_type=json&link_id={}&children={}",
+    url_encode(link_id), url_encode(&children.join(",")));
+
+  let envelope = parse_json_envelope(&path_query(&path, args)?)?;
+  Ok(serde_json::from_value(Value::Array(envelope.things))?)
+}
+
+/// Fetches the part of a thread reddit cut off behind a "continue this
+/// thread" link: the comment itself, with its replies
+pub fn continue_thread(subreddit: &str, link_id: &str, comment_id: &str, args: Args)
+  -> Result<Vec<Comment>, RedditError>
+{
+  let mut value = path_query(&continue_thread_path(subreddit, link_id, comment_id)?, args)?;
+  expect_array(&value)?;
+  let comments = value.get_mut(1).map(Value::take).ok_or(RedditError::NotFound)?;
+  let comments: Listing<Value> = serde_json::from_value(comments)?;
+  Ok(comment_tree(comments)?.comments)
+}
+
+/// the comments page a "continue this thread" link points at, focused on
+/// the comment the thread was cut off under
+pub fn continue_thread_path(subreddit: &str, link_id: &str, comment_id: &str)
+  -> Result<String, RedditError>
+{
+  validate_subreddit_list(subreddit)?;
+  let link = validate_id(link_id, "t3_")?;
+  let comment = validate_id(comment_id, "t1_")?;
+  Ok(format!("/r/{}/comments/{}/comment/{}/.json", subreddit, link, comment))
+}
+
+/// Fetches a thread and expands its "more" stubs breadth first, spending at
+/// most `max_requests` requests on /api/morechildren. Stubs left over once
+/// the budget runs out stay in the tree where they were.
+pub fn comments_full(subreddit: &str, post_id: &str, args: Args, max_requests: usize)
+  -> Result<CommentTree, RedditError>
+{
+  expand_thread(Some(subreddit), post_id, args, max_requests, false)
+}
+
+/// Like comments_full, but "continue this thread" links are followed too,
+/// each one costing a request from the same budget
+pub fn comments_full_deep(subreddit: &str, post_id: &str, args: Args, max_requests: usize)
+  -> Result<CommentTree, RedditError>
+{
+  expand_thread(Some(subreddit), post_id, args, max_requests, true)
+}
+
+fn expand_thread(subreddit: Option<&str>, post_id: &str, args: Args, max_requests: usize,
+  follow_continue: bool) -> Result<CommentTree, RedditError>
+{
+  let (post, mut tree) = comment_thread(subreddit, post_id, CommentOpts::default(),
+    args.clone())?;
+
+  let mut stubs = Vec::new();
+  take_stubs(&mut tree, &mut stubs);
+  stubs.sort_by_key(|stub| stub.depth);
+  let mut queue: VecDeque<MoreComments> = stubs.into();
+
+  let mut spent = 0;
+  let mut leftover = Vec::new();
+  while let Some(mut stub) = queue.pop_front() {
+    if spent >= max_requests {
+      leftover.push(stub);
+      continue;
+    }
+
+    // "continue this thread" stubs carry no ids, only the comment the
+    // thread was cut off under
+    if stub.children.is_empty() {
+      if !follow_continue || !stub.is_continue_thread() {
+        leftover.push(stub);
+        continue;
+      }
+      spent += 1;
+
+      let subreddit = subreddit.unwrap_or(&post.subreddit);
+      let continued = continue_thread(subreddit, &post.name, &stub.parent_id, args.clone())?;
+      if let Some(mut focus) = continued.into_iter().find(|c| c.name == stub.parent_id) {
+        let mut stubs = Vec::new();
+        take_stubs(&mut focus.replies, &mut stubs);
+        queue.extend(stubs);
+        subtree_mut(&mut tree, &stub.parent_id).comments.append(&mut focus.replies.comments);
+      }
+      continue;
+    }
+
+    let batch: Vec<String> = stub.children
+      .drain(..min(MORE_CHILDREN_BATCH, stub.children.len()))
+      .collect();
+    spent += 1;
+
+    for thing in more_children(&post.name, &batch, args.clone())? {
+      match thing {
+        Thing::Comment(comment) => {
+          let parent = comment.parent_id.clone();
+          subtree_mut(&mut tree, &parent).comments.push(comment);
+        },
+        Thing::More(more) => queue.push_back(more),
+        _ => (),
+      }
+    }
+
+    if !stub.children.is_empty() {
+      queue.push_front(stub);
+    }
+  }
+
+  for stub in leftover {
+    let parent = stub.parent_id.clone();
+    subtree_mut(&mut tree, &parent).more.push(stub);
+  }
+  Ok(tree)
+}
+
+/// moves every stub in a tree out into `stubs`
+fn take_stubs(tree: &mut CommentTree, stubs: &mut Vec<MoreComments>) {
+  stubs.append(&mut tree.more);
+  for comment in &mut tree.comments {
+    take_stubs(&mut comment.replies, stubs);
+  }
+}
+
+/// the replies of the comment with this fullname, or the top level of the
+/// thread when no such comment is in the tree (e.g. the post's fullname)
+fn subtree_mut<'a>(tree: &'a mut CommentTree, parent: &str) -> &'a mut CommentTree {
+  // searched twice, as returning the first match directly upsets the borrow
+  // checker when falling back to `tree`
+  if find_subtree(tree, parent).is_some() {
+    return find_subtree(tree, parent).unwrap();
+  }
+  tree
+}
+
+fn find_subtree<'a>(tree: &'a mut CommentTree, parent: &str) -> Option<&'a mut CommentTree> {
+  for comment in &mut tree.comments {
+    if comment.name == parent {
+      return Some(&mut comment.replies);
+    }
+    if let Some(found) = find_subtree(&mut comment.replies, parent) {
+      return Some(found);
+    }
+  }
+  None
+}
+
+/// fetches and splits a comments page into the post and its comment tree
+/// (when no subreddit is given, through /comments/{id} instead)
+fn comment_thread(subreddit: Option<&str>, post_id: &str, opts: CommentOpts, args: Args)
+  -> Result<(Post, CommentTree), RedditError>
+{
+  let id = validate_id(post_id, "t3_")?;
+  let mut path = match subreddit {
+    Some(subreddit) => {
+      validate_subreddit_list(subreddit)?;
+      format!("/r/{}/comments/{}/.json", subreddit, id)
+    },
+    None => format!("/comments/{}/.json", id),
+  };
+  if let Some(sort) = opts.sort {
+    path = append_param(&path, "sort", sort.as_str());
+  }
+  if let Some(limit) = opts.limit {
+    path = append_param(&path, "limit", &limit.to_string());
+  }
+  if let Some(depth) = opts.depth {
+    path = append_param(&path, "depth", &depth.to_string());
+  }
+
+  let mut value = path_query(&path, args)?;
+  expect_array(&value)?;
+  let comments = value.get_mut(1).map(Value::take).ok_or(RedditError::NotFound)?;
+  let comments: Listing<Value> = serde_json::from_value(comments)?;
+  let post = post_from_comments_page(value)?;
+  let max_depth = opts.max_depth.unwrap_or(MAX_COMMENT_DEPTH);
+  Ok((post, comment_tree_to_depth(comments, max_depth)?))
+}
+
+/// strips the kind prefix off an id, which must be alphanumeric after that
+pub(crate) fn validate_id<'a>(id: &'a str, prefix: &str) -> Result<&'a str, RedditError> {
+  let bare = id.trim_start_matches(prefix);
+  if bare.is_empty() || !bare.chars().all(|c| c.is_ascii_alphanumeric()) {
+    return Err(RedditError::InvalidName(id.to_owned()));
+  }
+  Ok(bare)
+}
+
+/// Pulls the post out of a comments page, which is a two element array of
+/// [listing holding the post, listing holding the comments]
+fn post_from_comments_page(mut value: Value) -> Result<Post, RedditError> {
+  let listing = match value.as_array_mut().and_then(|pair| pair.get_mut(0)) {
+    Some(listing) => listing.take(),
+    None => return Err(RedditError::NotFound),
+  };
+
+  let listing: Listing<Post> = serde_json::from_value(listing)?;
+  listing.children.into_iter().next().ok_or(RedditError::NotFound)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_subreddit {
+
+  use api::*;
+  use config::Args;
+  use error::{RedditError, Restriction};
+  use mock::{MockServer, MockResponse};
+
+  const COMMENTS_PAGE: &str = r#"[
+    {"kind": "Listing", "data": {"after": null, "before": null, "children": [
+      {"kind": "t3", "data": {"id": "abc", "name": "t3_abc", "title": "random!"}}]}},
+    {"kind": "Listing", "data": {"after": null, "before": null, "children": []}}
+  ]"#;
+
+  #[test]
+  fn test_subreddit_rules() {
+    use models::Rule;
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"rules": [
+        {"kind": "link", "short_name": "On topic", "description": "Posts must be about Rust",
+          "priority": 0, "violation_reason": "Off topic"},
+        {"kind": "all", "short_name": "Be civil", "description": "", "priority": 1}],
+        "site_rules": ["Spam"]}"#),
+      MockResponse::json(r#"{"rules": [], "site_rules": ["Spam"]}"#),
+    ]);
+
+    let rules = subreddit_rules("rust", server.args()).unwrap();
+    assert_eq!(rules, vec![
+      Rule {
+        short_name : "On topic".to_owned(),
+        description : "Posts must be about Rust".to_owned(),
+        kind : "link".to_owned(),
+      },
+      Rule { short_name : "Be civil".to_owned(), kind : "all".to_owned(), ..Rule::default() },
+    ]);
+    assert_eq!(server.requests()[0].path, "/r/rust/about/rules/.json?raw_json=1");
+
+    assert!(subreddit_rules("rust", server.args()).unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_duplicates() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"[
+        {"kind": "Listing", "data": {"children": [
+          {"kind": "t3", "data": {"name": "t3_abc", "subreddit": "rust"}}]}},
+        {"kind": "Listing", "data": {"after": null, "children": [
+          {"kind": "t3", "data": {"name": "t3_def", "subreddit": "programming"}},
+          {"kind": "t3", "data": {"name": "t3_ghi", "subreddit": "ferris"}}]}}
+      ]"#),
+      MockResponse::json(r#"[
+        {"kind": "Listing", "data": {"children": [{"kind": "t3", "data": {"name": "t3_x"}}]}},
+        {"kind": "Listing", "data": {"children": []}}
+      ]"#),
+    ]);
+
+    let posts = duplicates("t3_abc", server.args()).unwrap();
+    let subs: Vec<&str> = posts.iter().map(|p| p.subreddit.as_str()).collect();
+    assert_eq!(subs, vec!["programming", "ferris"]);
+    assert_eq!(server.requests()[0].path, "/duplicates/abc/.json?raw_json=1");
+
+    assert!(duplicates("x", server.args()).unwrap().is_empty());
+    assert!(matches!(duplicates("../x", server.args()), Err(RedditError::InvalidName(_))));
+  }
+
+  #[test]
+  fn test_random_post_follows_redirect() {
+    let server = MockServer::start(vec![
+      MockResponse::status(302, "")
+        .header("Location", "/r/rust/comments/abc/random/.json"),
+      MockResponse::json(COMMENTS_PAGE),
+    ]);
+
+    let post = random_post("rust", server.args()).unwrap();
+    assert_eq!(post.name, "t3_abc");
+    assert_eq!(post.title, "random!");
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/r/rust/random/.json?raw_json=1");
+    assert_eq!(requests[1].path, "/r/rust/comments/abc/random/.json");
+  }
+
+  #[test]
+  fn test_resolve_short_link() {
+    let server = MockServer::start(vec![
+      MockResponse::status(301, "")
+        .header("Location", "/r/rust/comments/abc/a_thread/"),
+      MockResponse::status(200, "<html></html>").header("Content-Type", "text/html"),
+    ]);
+
+    let short = format!("{}/abc", server.base_url);
+    let resolved = resolve_short_link(&short, server.args()).unwrap();
+    assert_eq!(resolved, format!("{}/r/rust/comments/abc/a_thread/", server.base_url));
+    assert_eq!(server.requests()[0].path, "/abc");
+  }
+
+  #[test]
+  fn test_resolve_short_link_not_found() {
+    let server = MockServer::start(vec![
+      MockResponse::status(404, "").header("Content-Type", "text/html"),
+      MockResponse::status(200, "<html></html>").header("Content-Type", "text/html"),
+    ]);
+
+    for _ in 0..2 {
+      let short = format!("{}/nope", server.base_url);
+      let err = resolve_short_link(&short, server.args()).unwrap_err();
+      assert!(matches!(err, RedditError::NotFound));
+    }
+  }
+
+  #[test]
+  fn test_wiki_page() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"kind": "wikipage", "data": {
+        "content_md": "Rules\n1. be kind", "revision_date": 1700000000,
+        "revision_by": {"kind": "t2", "data": {"name": "automod"}}, "may_revise": false}}"#),
+    ]);
+
+    let page = wiki_page("rust", "config/sidebar", server.args()).unwrap();
+    assert_eq!(page.content_md, "Rules\n1. be kind");
+    assert_eq!(page.revision_date, Some(1700000000.0));
+    assert_eq!(page.revision_by, Some("automod".to_owned()));
+    assert_eq!(server.requests()[0].path, "/r/rust/wiki/config/sidebar/.json?raw_json=1");
+  }
+
+  #[test]
+  fn test_wiki_page_missing_and_private() {
+    let server = MockServer::start(vec![
+      MockResponse::status(404, r#"{"reason": "PAGE_NOT_CREATED"}"#)
+        .header("Content-Type", "application/json"),
+      MockResponse::status(403, r#"{"reason": "WIKI_DISABLED"}"#)
+        .header("Content-Type", "application/json"),
+      MockResponse::json(r#"{"reason": "PAGE_NOT_CREATED", "message": "Not Found"}"#),
+      MockResponse::json(r#"{"kind": "wikipage", "data": {"content_md": ""}}"#),
+    ]);
+
+    let missing = wiki_page("rust", "nope", server.args()).unwrap_err();
+    assert!(matches!(missing, RedditError::NotFound));
+
+    let private = wiki_page("rust", "index", server.args()).unwrap_err();
+    assert_eq!(private.status_code(), Some(403));
+    assert!(matches!(private, RedditError::Restricted { reason : Restriction::Other(ref r), .. }
+      if r == "WIKI_DISABLED"), "{:?}", private);
+
+    let not_created = wiki_page("rust", "later", server.args()).unwrap_err();
+    assert!(matches!(not_created, RedditError::NotFound));
+
+    let blank = wiki_page("rust", "blank", server.args()).unwrap();
+    assert_eq!(blank.revision_date, None);
+    assert_eq!(blank.revision_by, None);
+
+    assert!(matches!(wiki_page("rust", "../about", server.args()),
+      Err(RedditError::InvalidName(_))));
+  }
+
+  #[test]
+  fn test_comments_page_must_be_array() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"kind": "Listing", "data": {"children": []}}"#),
+    ]);
+
+    let err = comments("rust", "abc", CommentOpts::default(), server.args()).unwrap_err();
+    match err {
+      RedditError::UnexpectedShape { expected, got } =>
+        assert_eq!((expected, got), ("array", "object")),
+      other => panic!("unexpected {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_random_post_unsupported() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"kind": "Listing", "data": {"children": []}}"#),
+      MockResponse::status(404, r#"{"message": "Not Found", "error": 404}"#)
+        .header("Content-Type", "application/json"),
+      MockResponse::status(404, r#"{"reason": "banned", "message": "Not Found"}"#)
+        .header("Content-Type", "application/json"),
+    ]);
+
+    for _ in 0..3 {
+      let err = random_post("rust", server.args()).unwrap_err();
+      assert!(matches!(err, RedditError::NotFound), "{:?}", err);
+    }
+    assert_eq!(server.requests().len(), 3);
+  }
+
+  #[test]
+  fn test_comments_parses_post_and_tree() {
+    let page = r#"[
+      {"kind": "Listing", "data": {"children": [
+        {"kind": "t3", "data": {"name": "t3_abc", "title": "a thread"}}]}},
+      {"kind": "Listing", "data": {"children": [
+        {"kind": "t1", "data": {"name": "t1_c1", "body": "first", "replies": {
+          "kind": "Listing", "data": {"children": [
+            {"kind": "t1", "data": {"name": "t1_c2", "body": "reply", "replies": ""}},
+            {"kind": "more", "data": {"count": 12, "children": ["c3"]}}]}}}},
+        {"kind": "t1", "data": {"name": "t1_c4", "body": "second", "replies": ""}},
+        {"kind": "more", "data": {"count": 40, "children": ["c5", "c6"]}}]}}
+    ]"#;
+    let server = MockServer::start(vec![MockResponse::json(page)]);
+    let opts = CommentOpts {
+      sort : Some(CommentSort::Top),
+      limit : Some(50),
+      depth : Some(2),
+      max_depth : None,
+    };
+
+    let (post, comments) = comments("rust", "t3_abc", opts, server.args()).unwrap();
+    assert_eq!(post.title, "a thread");
+    assert_eq!(comments.len(), 2);
+    assert_eq!(comments[0].replies.comments.len(), 1);
+    assert_eq!(comments[0].replies.comments[0].body, "reply");
+    assert_eq!(comments[0].replies.more[0].count, 12);
+    assert!(comments[1].replies.comments.is_empty());
+
+    assert_eq!(server.requests()[0].path,
+      "/r/rust/comments/abc/.json?sort=top&limit=50&depth=2&raw_json=1");
+  }
+
+  #[test]
+  fn test_comments_rejects_bad_post_id() {
+    let err = comments("rust", "abc/../../api", CommentOpts::default(), Args::default())
+      .unwrap_err();
+    assert!(matches!(err, RedditError::InvalidName(_)));
+  }
+
+  #[test]
+  fn test_comments_full_expands_breadth_first_within_budget() {
+    let thread = r#"[
+      {"kind": "Listing", "data": {"children": [
+        {"kind": "t3", "data": {"name": "t3_abc"}}]}},
+      {"kind": "Listing", "data": {"children": [
+        {"kind": "t1", "data": {"name": "t1_c1", "replies": {
+          "kind": "Listing", "data": {"children": [
+            {"kind": "more", "data": {"name": "t1_m1", "parent_id": "t1_c1", "depth": 1,
+              "count": 1, "children": ["c2"]}}]}}}},
+        {"kind": "more", "data": {"name": "t1_m0", "parent_id": "t3_abc", "depth": 0,
+          "count": 2, "children": ["c3", "c4"]}}]}}
+    ]"#;
+    let first = r#"{"json": {"errors": [], "data": {"things": [
+      {"kind": "t1", "data": {"name": "t1_c3", "parent_id": "t3_abc", "replies": ""}},
+      {"kind": "t1", "data": {"name": "t1_c4", "parent_id": "t1_c3", "replies": ""}},
+      {"kind": "more", "data": {"name": "t1_m2", "parent_id": "t1_c4", "depth": 2,
+        "count": 1, "children": ["c5"]}}]}}}"#;
+    let second = r#"{"json": {"errors": [], "data": {"things": [
+      {"kind": "t1", "data": {"name": "t1_c2", "parent_id": "t1_c1", "replies": ""}}]}}}"#;
+    let server = MockServer::start(vec![
+      MockResponse::json(thread),
+      MockResponse::json(first),
+      MockResponse::json(second),
+    ]);
+
+    let tree = comments_full("rust", "abc", server.args(), 2).unwrap();
+
+    let top: Vec<&str> = tree.comments.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(top, vec!["t1_c1", "t1_c3"]);
+    assert!(tree.more.is_empty());
+    assert_eq!(tree.comments[0].replies.comments[0].name, "t1_c2");
+    assert!(tree.comments[0].replies.more.is_empty());
+
+    // the budget ran out before the stub under c4 could be expanded
+    let c4 = &tree.comments[1].replies.comments[0];
+    assert_eq!(c4.name, "t1_c4");
+    assert_eq!(c4.replies.more[0].name, "t1_m2");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[1].path,
+      "/api/morechildren/.json?api_type=json&link_id=t3_abc&children=c3%2Cc4&raw_json=1");
+  }
+
+  #[test]
+  fn test_get_comments_by_id_with_deleted_and_stubs() {
+    let thread = r#"[
+      {"kind": "Listing", "data": {"children": [
+        {"kind": "t3", "data": {"name": "t3_abc", "subreddit": "rust"}}]}},
+      {"kind": "Listing", "data": {"children": [
+        {"kind": "t1", "data": {"name": "t1_c1", "author": "[deleted]", "body": null,
+          "score": 3, "replies": ""}},
+        {"kind": "t1", "data": {"name": "t1_c2", "author": "ferris", "body": "hi",
+          "replies": {"kind": "Listing", "data": {"children": [
+            {"kind": "more", "data": {"name": "t1_m1", "parent_id": "t1_c2", "depth": 1,
+              "count": 0, "children": []}}]}}}},
+        {"kind": "more", "data": {"name": "t1_m0", "parent_id": "t3_abc", "depth": 0,
+          "count": 1, "children": ["c3"]}}]}}
+    ]"#;
+    let more = r#"{"json": {"errors": [], "data": {"things": [
+      {"kind": "t1", "data": {"name": "t1_c3", "parent_id": "t3_abc", "author": "bors",
+        "body": "merged", "replies": ""}}]}}}"#;
+    let server = MockServer::start(vec![
+      MockResponse::json(thread),
+      MockResponse::json(thread),
+      MockResponse::json(more),
+    ]);
+
+    let tree = get_comments("t3_abc", server.args()).unwrap();
+    assert!(tree.comments[0].is_deleted());
+    assert_eq!(tree.comments[0].body, "");
+    assert_eq!(tree.comments[0].score, 3);
+    assert_eq!(tree.comments[1].replies.more[0].name, "t1_m1");
+    assert_eq!(tree.more[0].children, vec!["c3"]);
+
+    let tree = get_comments_full("abc", server.args(), 10).unwrap();
+    let top: Vec<&str> = tree.comments.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(top, vec!["t1_c1", "t1_c2", "t1_c3"]);
+    // an empty stub has nothing to load and is left where it was
+    assert_eq!(tree.comments[1].replies.more[0].name, "t1_m1");
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/comments/abc/.json?raw_json=1");
+    assert_eq!(requests.len(), 3);
+  }
+
+  const TRUNCATED_THREAD: &str = r#"[
+    {"kind": "Listing", "data": {"children": [
+      {"kind": "t3", "data": {"name": "t3_abc"}}]}},
+    {"kind": "Listing", "data": {"children": [
+      {"kind": "t1", "data": {"name": "t1_c1", "replies": {
+        "kind": "Listing", "data": {"children": [
+          {"kind": "more", "data": {"name": "t1__", "id": "_", "parent_id": "t1_c1",
+            "depth": 1, "count": 0, "children": []}}]}}}}]}}
+  ]"#;
+
+  const CONTINUED: &str = r#"[
+    {"kind": "Listing", "data": {"children": [
+      {"kind": "t3", "data": {"name": "t3_abc"}}]}},
+    {"kind": "Listing", "data": {"children": [
+      {"kind": "t1", "data": {"name": "t1_c1", "replies": {
+        "kind": "Listing", "data": {"children": [
+          {"kind": "t1", "data": {"name": "t1_c2", "parent_id": "t1_c1", "replies": ""}}
+        ]}}}}]}}
+  ]"#;
+
+  #[test]
+  fn test_continue_thread() {
+    let server = MockServer::start(vec![MockResponse::json(CONTINUED)]);
+
+    let comments = continue_thread("rust", "t3_abc", "t1_c1", server.args()).unwrap();
+    assert_eq!(comments[0].name, "t1_c1");
+    assert_eq!(comments[0].replies.comments[0].name, "t1_c2");
+    assert_eq!(server.requests()[0].path, "/r/rust/comments/abc/comment/c1/.json?raw_json=1");
+  }
+
+  #[test]
+  fn test_continue_thread_path() {
+    assert_eq!(continue_thread_path("rust", "abc", "t1_c9").unwrap(),
+      "/r/rust/comments/abc/comment/c9/.json");
+    assert!(continue_thread_path("rust", "t1_abc", "c9").is_err());
+  }
+
+  #[test]
+  fn test_comments_full_deep_follows_continue_links() {
+    let server = MockServer::start(vec![
+      MockResponse::json(TRUNCATED_THREAD),
+      MockResponse::json(CONTINUED),
+    ]);
+
+    let tree = comments_full_deep("rust", "abc", server.args(), 5).unwrap();
+    let c1 = &tree.comments[0];
+    assert_eq!(c1.replies.comments[0].name, "t1_c2");
+    assert!(c1.replies.more.is_empty());
+    assert_eq!(server.requests().len(), 2);
+  }
+
+  #[test]
+  fn test_comments_full_leaves_continue_links() {
+    let server = MockServer::start(vec![MockResponse::json(TRUNCATED_THREAD)]);
+
+    let tree = comments_full("rust", "abc", server.args(), 5).unwrap();
+    assert!(tree.comments[0].replies.comments.is_empty());
+    assert_eq!(tree.comments[0].replies.more[0].name, "t1__");
+
+    let server = MockServer::start(vec![MockResponse::json(TRUNCATED_THREAD)]);
+    let tree = comments_full_deep("rust", "abc", server.args(), 0).unwrap();
+    assert_eq!(tree.comments[0].replies.more[0].name, "t1__");
+    assert_eq!(server.requests().len(), 1);
+  }
+}
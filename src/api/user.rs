@@ -0,0 +1,246 @@
+
+// external imports
+use serde_json::Value;
+
+// internal imports
+use api::{append_param, paginate, path_query, validate_username, ListingIter, Sort};
+use config::Args;
+use error::RedditError;
+use models::{expect_array, FromChild, Listing, Post, Trophy};
+
+////////////////////////////////////////////////////////////////////////////////
+/// UserListing
+/// Builds the path of one of a user's listings, e.g.
+/// UserListing::submitted("spez").sort(Sort::Top(TimeRange::All)).limit(10)
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserListing {
+  pub username : String,
+  /// "submitted" or "comments"
+  pub section : &'static str,
+  pub sort : Option<Sort>,
+  pub limit : Option<u32>,
+  /// fullname of the last thing on the previous page
+  pub after : Option<String>,
+}
+
+impl UserListing {
+  /// the posts a user submitted
+  pub fn submitted(username: &str) -> UserListing {
+    UserListing::new(username, "submitted")
+  }
+
+  /// the comments a user wrote
+  pub fn comments(username: &str) -> UserListing {
+    UserListing::new(username, "comments")
+  }
+
+  fn new(username: &str, section: &'static str) -> UserListing {
+    UserListing {
+      username : username.to_owned(),
+      section,
+      sort : None,
+      limit : None,
+      after : None,
+    }
+  }
+
+  /// hot, new, top or controversial; reddit has no rising for users
+  pub fn sort(mut self, sort: Sort) -> UserListing {
+    self.sort = Some(sort);
+    self
+  }
+
+  /// how many things each page holds, reddit caps this at 100
+  pub fn limit(mut self, limit: u32) -> UserListing {
+    self.limit = Some(limit);
+    self
+  }
+
+  pub fn after(mut self, fullname: &str) -> UserListing {
+    self.after = Some(fullname.to_owned());
+    self
+  }
+
+  /// the request path, e.g. "/user/spez/submitted/.json?sort=top&t=all"
+  pub fn path(&self) -> Result<String, RedditError> {
+    validate_username(&self.username)?;
+
+    let mut path = format!("/user/{}/{}/.json", self.username, self.section);
+    if let Some(sort) = self.sort {
+      if sort == Sort::Rising {
+        return Err(RedditError::InvalidSort(sort.as_str().to_owned()));
+      }
+      path = append_param(&path, "sort", sort.as_str());
+      if let Some(range) = sort.time_range() {
+        path = append_param(&path, "t", range.as_str());
+      }
+    }
+    if let Some(limit) = self.limit {
+      path = append_param(&path, "limit", &limit.to_string());
+    }
+    if let Some(ref after) = self.after {
+      path = append_param(&path, "after", after);
+    }
+    Ok(path)
+  }
+
+  /// a single page, Listing<Post> for submitted and Listing<Comment> for
+  /// comments
+  pub fn send<T: FromChild>(&self, args: Args) -> Result<Listing<T>, RedditError> {
+    Listing::from_value(user_query(&self.username, &self.path()?, args)?)
+  }
+
+  /// pages through the listing from self.after on, see paginate
+  pub fn pages<T: FromChild>(&self, args: Args) -> Result<ListingIter<T>, RedditError> {
+    Ok(paginate(&self.path()?, args))
+  }
+}
+
+/// The newest page of posts a user submitted
+pub fn user_submitted(username: &str, args: Args) -> Result<Listing<Post>, RedditError> {
+  UserListing::submitted(username).send(args)
+}
+
+/// The trophies on a user's profile, in the order reddit shows them
+pub fn user_trophies(username: &str, args: Args) -> Result<Vec<Trophy>, RedditError> {
+  validate_username(username)?;
+
+  let path = format!("/api/v1/user/{}/trophies", username);
+  let mut value = user_query(username, &path, args)?;
+  let trophies = value["data"]["trophies"].take();
+  expect_array(&trophies)?;
+
+  let mut out = Vec::new();
+  if let Value::Array(children) = trophies {
+    for child in children {
+      out.push(Trophy::from_child(child)?);
+    }
+  }
+  Ok(out)
+}
+
+/// path_query for a page about a user, turning reddit's 404 for a missing,
+/// suspended or shadowbanned account into RedditError::UserNotFound. The
+/// 404 comes with a json body, {"message": "Not Found", "error": 404}.
+pub(crate) fn user_query(username: &str, path: &str, args: Args)
+  -> Result<Value, RedditError>
+{
+  let not_found = || RedditError::UserNotFound(username.to_owned());
+  match path_query(path, args) {
+    Ok(ref value) if value["error"] == 404 => Err(not_found()),
+    Err(ref err) if matches!(*err.root(), RedditError::Http { status : 404, .. }) =>
+      Err(not_found()),
+    result => result,
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_user {
+
+  use api::*;
+  use error::RedditError;
+  use mock::{MockServer, MockResponse};
+  use models::{Comment, Listing, Post};
+
+  const SUBMITTED: &str = r#"{"kind": "Listing", "data": {"after": "t3_b", "children": [
+    {"kind": "t3", "data": {"name": "t3_a", "title": "first", "author": "ferris",
+      "subreddit": "rust", "score": 12}},
+    {"kind": "t3", "data": {"name": "t3_b", "title": "second", "author": "ferris",
+      "subreddit": "rust", "score": 3}}]}}"#;
+
+  const COMMENTS: &str = r#"{"kind": "Listing", "data": {"after": null, "children": [
+    {"kind": "t1", "data": {"name": "t1_c", "body": "nice crab", "author": "ferris",
+      "link_id": "t3_a", "score": 5}}]}}"#;
+
+  const TROPHIES: &str = r#"{"kind": "TrophyList", "data": {"trophies": [
+    {"kind": "t6", "data": {"icon_70": "https://www.redditstatic.com/awards2/3_year_club-70.png",
+      "granted_at": null, "url": null, "icon_40":
+      "https://www.redditstatic.com/awards2/3_year_club-40.png", "name": "Three-Year Club",
+      "award_id": null, "id": null, "description": null}},
+    {"kind": "t6", "data": {"icon_70": "https://www.redditstatic.com/awards2/verified-70.png",
+      "granted_at": 1500000000, "url": null, "icon_40": "", "name": "Verified Email",
+      "award_id": "o", "id": "1q2w3e", "description": null}}]}}"#;
+
+  const NOT_FOUND: &str = r#"{"message": "Not Found", "error": 404}"#;
+
+  #[test]
+  fn test_user_listing_path() {
+    assert_eq!(UserListing::submitted("spez").path().unwrap(), "/user/spez/submitted/.json");
+    assert_eq!(UserListing::comments("some-user_1")
+      .sort(Sort::Top(TimeRange::Week))
+      .limit(25)
+      .after("t1_x")
+      .path().unwrap(),
+      "/user/some-user_1/comments/.json?sort=top&t=week&limit=25&after=t1_x");
+
+    assert!(matches!(UserListing::submitted("two words").path(),
+      Err(RedditError::InvalidName(_))));
+    assert!(matches!(UserListing::submitted("spez").sort(Sort::Rising).path(),
+      Err(RedditError::InvalidSort(_))));
+  }
+
+  #[test]
+  fn test_user_submitted_and_comments() {
+    let server = MockServer::start(vec![
+      MockResponse::json(SUBMITTED),
+      MockResponse::json(COMMENTS),
+    ]);
+
+    let posts = user_submitted("ferris", server.args()).unwrap();
+    assert_eq!(posts.after.as_deref(), Some("t3_b"));
+    assert_eq!(posts.children.iter().map(|p| p.title.as_str()).collect::<Vec<_>>(),
+      ["first", "second"]);
+
+    let comments: Listing<Comment> = UserListing::comments("ferris")
+      .sort(Sort::New)
+      .send(server.args()).unwrap();
+    assert_eq!(comments.children[0].body, "nice crab");
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/user/ferris/submitted/.json?raw_json=1");
+    assert_eq!(requests[1].path, "/user/ferris/comments/.json?sort=new&raw_json=1");
+  }
+
+  #[test]
+  fn test_user_listing_pages() {
+    let server = MockServer::start(vec![
+      MockResponse::json(SUBMITTED),
+      MockResponse::json(r#"{"kind": "Listing", "data": {"after": null, "children": []}}"#),
+    ]);
+
+    let pages = UserListing::submitted("ferris").limit(2).pages::<Post>(server.args())
+      .unwrap();
+    assert_eq!(pages.map(|page| page.unwrap().children.len()).collect::<Vec<_>>(), [2, 0]);
+    assert_eq!(server.requests()[1].path,
+      "/user/ferris/submitted/.json?limit=2&after=t3_b&raw_json=1");
+  }
+
+  #[test]
+  fn test_user_trophies() {
+    let server = MockServer::start(vec![MockResponse::json(TROPHIES)]);
+
+    let trophies = user_trophies("ferris", server.args()).unwrap();
+    assert_eq!(trophies.len(), 2);
+    assert_eq!(trophies[0].name, "Three-Year Club");
+    assert_eq!(trophies[0].award_id, None);
+    assert_eq!(trophies[1].award_id.as_deref(), Some("o"));
+    assert_eq!(trophies[1].granted_at, Some(1500000000.0));
+    assert_eq!(server.requests()[0].path, "/api/v1/user/ferris/trophies?raw_json=1");
+  }
+
+  #[test]
+  fn test_missing_user_is_user_not_found() {
+    let missing = || MockResponse::status(404, NOT_FOUND)
+      .header("Content-Type", "application/json; charset=UTF-8");
+    let server = MockServer::start(vec![missing(), missing(), missing()]);
+
+    let name_of = |err: RedditError| match err {
+      RedditError::UserNotFound(name) => name,
+      other => panic!("{:?}", other),
+    };
+    assert_eq!(name_of(user_about("gone", server.args()).unwrap_err()), "gone");
+    assert_eq!(name_of(user_submitted("gone", server.args()).unwrap_err()), "gone");
+    assert_eq!(name_of(user_trophies("gone", server.args()).unwrap_err()), "gone");
+  }
+}
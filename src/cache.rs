@@ -0,0 +1,154 @@
+
+// external imports
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+// internal imports
+use clock::{Clock, SystemClock};
+use error::RedditError;
+
+////////////////////////////////////////////////////////////////////////////////
+/// CachedResponse
+/// A response body as it was received, plus when it was stored
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+  pub body : String,
+  pub etag : Option<String>,
+  /// the Last-Modified header the response came with
+  pub last_modified : Option<String>,
+  pub stored_at : SystemTime,
+}
+
+impl CachedResponse {
+  /// wraps a freshly received body, stamped with the current time
+  pub fn new(body: &str) -> CachedResponse {
+    CachedResponse::at(body, SystemTime::now())
+  }
+
+  /// wraps a body stamped with the given time
+  pub fn at(body: &str, stored_at: SystemTime) -> CachedResponse {
+    CachedResponse {
+      body : body.to_owned(),
+      etag : None,
+      last_modified : None,
+      stored_at,
+    }
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// ResponseCache
+/// Pluggable storage for responses, keyed by request uri. Implementations
+/// decide for themselves when an entry is too old to hand back.
+pub trait ResponseCache {
+  fn get(&self, key: &str) -> Option<CachedResponse>;
+  fn put(&mut self, key: &str, resp: CachedResponse);
+
+  /// An entry regardless of its age, for asking reddit whether it changed
+  /// (If-None-Match, If-Modified-Since) instead of fetching it again.
+  /// Caches that drop old entries can leave this as None.
+  fn get_stale(&self, _key: &str) -> Option<CachedResponse> {
+    None
+  }
+
+  /// writes out anything held back, for caches that persist somewhere;
+  /// see RedditClient::flush
+  fn flush(&mut self) -> Result<(), RedditError> {
+    Ok(())
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// HashMapCache
+/// In-memory cache that forgets entries once they are older than its ttl
+pub struct HashMapCache {
+  pub ttl : Duration,
+  clock : Box<dyn Clock>,
+  entries : HashMap<String, CachedResponse>,
+}
+
+impl HashMapCache {
+  pub fn new(ttl: Duration) -> HashMapCache {
+    HashMapCache {
+      ttl,
+      clock : Box::new(SystemClock),
+      entries : HashMap::new(),
+    }
+  }
+
+  /// builder style setter for the clock used to judge freshness
+  pub fn with_clock(mut self, clock: Box<dyn Clock>) -> HashMapCache {
+    self.clock = clock;
+    self
+  }
+
+  /// whether an entry stored at `stored_at` is still within the ttl
+  fn is_fresh(&self, stored_at: SystemTime) -> bool {
+    match self.clock.now().duration_since(stored_at) {
+      Ok(age) => age < self.ttl,
+      // stored "in the future", the clock moved backwards; trust it
+      Err(_) => true,
+    }
+  }
+}
+
+impl ResponseCache for HashMapCache {
+  fn get(&self, key: &str) -> Option<CachedResponse> {
+    self.entries.get(key)
+      .filter(|resp| self.is_fresh(resp.stored_at))
+      .cloned()
+  }
+
+  fn put(&mut self, key: &str, resp: CachedResponse) {
+    self.entries.insert(key.to_owned(), resp);
+  }
+
+  fn get_stale(&self, key: &str) -> Option<CachedResponse> {
+    self.entries.get(key).cloned()
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_cache {
+
+  use cache::*;
+  use clock::Clock;
+  use std::time::{Duration, SystemTime};
+
+  #[test]
+  fn test_hashmap_cache_hit() {
+    let mut cache = HashMapCache::new(Duration::from_secs(60));
+    cache.put("/r/rust/.json", CachedResponse::new("{}"));
+
+    let hit = cache.get("/r/rust/.json").unwrap();
+    assert_eq!(hit.body, "{}");
+    assert!(cache.get("/r/other/.json").is_none());
+  }
+
+  #[test]
+  fn test_hashmap_cache_expired_miss() {
+    let mut cache = HashMapCache::new(Duration::from_secs(60));
+    let mut stale = CachedResponse::new("{}");
+    stale.stored_at = SystemTime::now() - Duration::from_secs(120);
+    cache.put("/r/rust/.json", stale);
+
+    assert!(cache.get("/r/rust/.json").is_none());
+  }
+
+  #[test]
+  fn test_hashmap_cache_expires_with_clock() {
+    use clock::ManualClock;
+
+    let clock = ManualClock::new(SystemTime::now());
+    let mut cache = HashMapCache::new(Duration::from_secs(60))
+      .with_clock(Box::new(clock.clone()));
+    cache.put("/r/rust/.json", CachedResponse::at("{}", clock.now()));
+
+    clock.advance(Duration::from_secs(59));
+    assert!(cache.get("/r/rust/.json").is_some());
+
+    clock.advance(Duration::from_secs(1));
+    assert!(cache.get("/r/rust/.json").is_none());
+  }
+}
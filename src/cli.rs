@@ -1,30 +1,25 @@
+// Command line front end, behind the "cli" feature. Args and friends live
+// in config and are re-exported here so existing cli:: paths keep working.
 
-use argparse::{ArgumentParser, Store, StoreTrue};
+// external imports
+use std::env;
+use std::io::{self, Write};
+use std::process;
+use argparse::{ArgumentParser, IncrBy, List, Store, StoreOption, StoreTrue};
+use log::{self, LevelFilter, Log, Metadata, Record};
+use serde_json::Value;
 
-/// Struct for gathering cli arguments.
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Args {
-  pub key: String,
-  pub headers: String,
-  pub nocapture: bool,
-}
-
-/// Default args are generic and probably won't work on default.
-///
-impl Default for Args {
-  fn default() -> Args {
-    Args {
-      key: "".to_string(),
-      headers: "".to_string(),
-      nocapture: false,
-    }
-  }
-}
+// internal imports
+use api::{path_query, validate_id, validate_subreddit_list, ListingQuery, SearchRequest,
+  SearchSort, Sort, TimeRange};
+use error::RedditError;
+pub use config::*;
 
 /// Gets the arguments from the command line, in case you are 
-/// leveraging this as a command line tool.
+/// leveraging this as a command line tool. Flags override what
+/// Args::from_config loads from the config file and environment.
 ///
-/// ```
+/// ```no_run
 /// extern crate rust_reddit;
 /// use rust_reddit::cli;
 ///
@@ -35,7 +30,21 @@ impl Default for Args {
 /// ```
 ///
 pub fn get_args() -> Args {
-  let mut args = Args::default();
+  let (args, verbosity) = parse_args_over(load_config(), env::args().collect(),
+    &mut io::stdout(), &mut io::stderr())
+    .unwrap_or_else(|code| process::exit(code));
+  init_logger(verbosity);
+  args
+}
+
+/// get_args over the given args and argv, handing back the -v count too;
+/// exit codes come back the way parse_command_line_over hands them back
+fn parse_args_over(mut args: Args, argv: Vec<String>, stdout: &mut dyn Write,
+  stderr: &mut dyn Write) -> Result<(Args, usize), i32>
+{
+  let mut headers = String::new();
+  let mut format = String::new();
+  let mut verbosity = 0;
   {
     let mut ap = ArgumentParser::new();
     ap.set_description("Rust Library for Reddit API");
@@ -45,54 +54,456 @@ pub fn get_args() -> Args {
         StoreTrue,
         "Pass nocapture to the cargo test toolchain",
         );
-    ap.refer(&mut args.key)
+    ap.refer(&mut args.subreddits_file)
       .add_option(
-        &["-k", "--api-key"],
-        Store,
-        "Your Reddit API key (for authorized-only calls)"
+        &["--subreddits-file"],
+        StoreOption,
+        "File of newline separated subreddits to print a digest of top posts for"
         );
-    ap.refer(&mut args.headers)
+    shared_options(&mut ap, &mut args.key, &mut headers, &mut args.user_agent, &mut format,
+      &mut verbosity);
+    ap.parse(argv, stdout, stderr)?;
+  }
+  if let Err(err) = apply_shared(&mut args, &headers, &format) {
+    let _ = writeln!(stderr, "rust_reddit: {}", err);
+    return Err(2);
+  }
+  Ok((args, verbosity))
+}
+
+/// the options every subcommand takes: key, headers, user agent, format
+/// and verbosity
+fn shared_options<'a>(ap: &mut ArgumentParser<'a>, key: &'a mut String,
+  headers: &'a mut String, user_agent: &'a mut Option<String>, format: &'a mut String,
+  verbosity: &'a mut usize)
+{
+  ap.refer(key)
+    .add_option(
+      &["-k", "--api-key"],
+      Store,
+      "Your Reddit API key (for authorized-only calls)"
+      );
+  ap.refer(headers)
+    .add_option(
+      &["-H", "--headers"],
+      Store,
+      "Headers for the request, delimited by \",\" between full header lines"
+      );
+  ap.refer(user_agent)
+    .add_option(
+      &["-A", "--user-agent"],
+      StoreOption,
+      "User-Agent to send, in place of one given with --headers"
+      );
+  ap.refer(format)
+    .add_option(
+      &["-f", "--format"],
+      Store,
+      "How to print responses: json (the default), pretty or table"
+      );
+  ap.refer(verbosity)
+    .add_option(
+      &["-v", "--verbose"],
+      IncrBy(1),
+      "Log to stderr: retries and failures, -vv every request, -vvv response bodies"
+      );
+}
+
+/// Writes the crate's log records to stderr, for --verbose
+struct StderrLogger;
+
+impl Log for StderrLogger {
+  fn enabled(&self, metadata: &Metadata) -> bool {
+    metadata.level() <= log::max_level()
+  }
+
+  fn log(&self, record: &Record) {
+    if self.enabled(record.metadata()) {
+      eprintln!("rust_reddit: {} {}", record.level(), record.args());
+    }
+  }
+
+  fn flush(&self) {}
+}
+
+static STDERR_LOGGER: StderrLogger = StderrLogger;
+
+/// The log level for a count of -v flags: none logs nothing, then info,
+/// debug and trace
+pub fn verbosity_level(verbosity: usize) -> LevelFilter {
+  match verbosity {
+    0 => LevelFilter::Off,
+    1 => LevelFilter::Info,
+    2 => LevelFilter::Debug,
+    _ => LevelFilter::Trace,
+  }
+}
+
+/// Installs a logger writing to stderr at verbosity_level(verbosity),
+/// unless verbosity is 0 or a logger is already installed
+pub fn init_logger(verbosity: usize) {
+  let level = verbosity_level(verbosity);
+  if level != LevelFilter::Off && log::set_logger(&STDERR_LOGGER).is_ok() {
+    log::set_max_level(level);
+  }
+}
+
+/// moves the shared options that need parsing onto args
+fn apply_shared(args: &mut Args, headers: &str, format: &str) -> Result<(), RedditError> {
+  if !headers.is_empty() {
+    args.headers = Args::parse_headers(headers)?;
+  }
+  if !format.is_empty() {
+    args.format = format.parse()?;
+  }
+  Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Command
+/// What the binary was asked to do, e.g. `rust_reddit top rust --limit 25
+/// --time week`; see parse_command_line
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+  Top { subreddit : String, time : TimeRange, limit : Option<u32> },
+  Hot { subreddit : String, limit : Option<u32> },
+  New { subreddit : String, limit : Option<u32> },
+  /// a post and its comments, by post id with or without "t3_"
+  Comments { post_id : String },
+  Search { subreddit : String, query : String, sort : Option<SearchSort>, limit : Option<u32> },
+  /// no subcommand, for the --subreddits-file digest
+  Digest,
+}
+
+impl Command {
+  /// the request path the command reads, e.g. "/r/rust/top/.json?t=week"
+  pub fn path(&self) -> Result<String, RedditError> {
+    let listing = |subreddit: &str, sort: Sort, limit: Option<u32>| {
+      validate_subreddit_list(subreddit)?;
+      let query = ListingQuery::new(subreddit, sort);
+      Ok(match limit {
+        Some(limit) => query.limit(limit),
+        None => query,
+      }.path())
+    };
+
+    match *self {
+      Command::Top { ref subreddit, time, limit } => listing(subreddit, Sort::Top(time), limit),
+      Command::Hot { ref subreddit, limit } => listing(subreddit, Sort::Hot, limit),
+      Command::New { ref subreddit, limit } => listing(subreddit, Sort::New, limit),
+      Command::Comments { ref post_id } =>
+        Ok(format!("/comments/{}/.json", validate_id(post_id, "t3_")?)),
+      Command::Search { ref subreddit, ref query, sort, limit } => {
+        let mut request = SearchRequest::new(query).subreddit(subreddit);
+        if let Some(sort) = sort {
+          request = request.sort(sort);
+        }
+        if let Some(limit) = limit {
+          request = request.limit(limit);
+        }
+        request.path()
+      },
+      Command::Digest =>
+        Err(RedditError::InvalidArgument("the digest reads more than one path".to_owned())),
+    }
+  }
+
+  /// runs the command, handing back the json reddit answered with
+  pub fn run(&self, args: Args) -> Result<Value, RedditError> {
+    path_query(&self.path()?, args)
+  }
+}
+
+/// A parsed command line: the command and the options shared by all of them
+#[derive(Debug)]
+pub struct CommandLine {
+  pub command : Command,
+  pub args : Args,
+  /// how many times -v was given, see init_logger
+  pub verbosity : usize,
+}
+
+const COMMANDS: &str = "top, hot, new, comments or search";
+
+/// Parses a full command line, program name first. Usage and errors go to
+/// stderr and --help to stdout, after which the exit code to use comes
+/// back as the error: 0 for help, 2 for anything wrong with the arguments.
+pub fn parse_command_line(argv: Vec<String>, stdout: &mut dyn Write, stderr: &mut dyn Write)
+  -> Result<CommandLine, i32>
+{
+  parse_command_line_over(Args::default(), argv, stdout, stderr)
+}
+
+/// parse_command_line with flags applied over args instead of
+/// Args::default, e.g. over what Args::from_config loaded
+pub fn parse_command_line_over(mut args: Args, argv: Vec<String>, stdout: &mut dyn Write,
+  stderr: &mut dyn Write) -> Result<CommandLine, i32>
+{
+  let program = argv.first().cloned().unwrap_or_else(|| "rust_reddit".to_owned());
+  let mut headers = String::new();
+  let mut format = String::new();
+  let mut verbosity = 0;
+  let mut command = String::new();
+  let mut rest: Vec<String> = Vec::new();
+  {
+    let mut ap = ArgumentParser::new();
+    ap.set_description("Reads reddit from the command line, printing the json it answers with");
+    ap.stop_on_first_argument(true);
+    ap.refer(&mut args.subreddits_file)
       .add_option(
-        &["-H", "--headers"],
-        Store,
-        "Headers for the request, delimited by \",\" between full header lines"
+        &["--subreddits-file"],
+        StoreOption,
+        "File of newline separated subreddits to print a digest of top posts for"
         );
-    ap.parse_args_or_exit();
+    shared_options(&mut ap, &mut args.key, &mut headers, &mut args.user_agent, &mut format,
+      &mut verbosity);
+    ap.refer(&mut command)
+      .add_argument("command", Store, "One of top, hot, new, comments and search");
+    ap.refer(&mut rest)
+      .add_argument("arguments", List, "Arguments for the command");
+    ap.parse(argv, stdout, stderr)?;
   }
-  args
+
+
+  if command.is_empty() {
+    if args.subreddits_file.is_none() {
+      return Err(usage_error(stderr, &program, &format!("missing command, try {}", COMMANDS)));
+    }
+    apply_shared(&mut args, &headers, &format)
+      .map_err(|err| usage_error(stderr, &program, &err.to_string()))?;
+    return Ok(CommandLine { command : Command::Digest, args, verbosity });
+  }
+
+  let mut subreddit = String::new();
+  let mut target = String::new();
+  let mut limit: Option<u32> = None;
+  let mut time = String::from("day");
+  let mut sort: Option<String> = None;
+  {
+    let mut ap = ArgumentParser::new();
+    match command.as_str() {
+      "top" | "hot" | "new" => {
+        ap.set_description("Reads a subreddit listing");
+        ap.refer(&mut subreddit).add_argument("subreddit", Store, "Subreddit to read").required();
+        if command == "top" {
+          ap.refer(&mut time)
+            .add_option(&["-t", "--time"], Store, "hour, day, week, month, year or all");
+        }
+      },
+      "comments" => {
+        ap.set_description("Reads a post and its comments");
+        ap.refer(&mut target).add_argument("post-id", Store, "Id of the post").required();
+      },
+      "search" => {
+        ap.set_description("Searches a subreddit");
+        ap.refer(&mut subreddit).add_argument("subreddit", Store, "Subreddit to search")
+          .required();
+        ap.refer(&mut target).add_argument("query", Store, "What to search for").required();
+        ap.refer(&mut sort)
+          .add_option(&["-s", "--sort"], StoreOption, "relevance, hot, new, top or comments");
+      },
+      other => return Err(usage_error(stderr, &program,
+        &format!("unknown command {:?}, try {}", other, COMMANDS))),
+    }
+    if command != "comments" {
+      ap.refer(&mut limit).add_option(&["-l", "--limit"], StoreOption, "How many posts to read");
+    }
+    shared_options(&mut ap, &mut args.key, &mut headers, &mut args.user_agent, &mut format,
+      &mut verbosity);
+
+    let mut sub_argv = vec![format!("{} {}", program, command)];
+    sub_argv.extend(rest);
+    ap.parse(sub_argv, stdout, stderr)?;
+  }
+  apply_shared(&mut args, &headers, &format)
+    .map_err(|err| usage_error(stderr, &program, &err.to_string()))?;
+
+  let command = match command.as_str() {
+    "top" => match time.parse() {
+      Ok(time) => Command::Top { subreddit, time, limit },
+      Err(err) => return Err(usage_error(stderr, &program, &err.to_string())),
+    },
+    "hot" => Command::Hot { subreddit, limit },
+    "new" => Command::New { subreddit, limit },
+    "comments" => Command::Comments { post_id : target },
+    _ => match sort.map(|sort| sort.parse()).transpose() {
+      Ok(sort) => Command::Search { subreddit, query : target, sort, limit },
+      Err(err) => return Err(usage_error(stderr, &program, &err.to_string())),
+    },
+  };
+  if let Err(err) = command.path() {
+    return Err(usage_error(stderr, &program, &err.to_string()));
+  }
+  Ok(CommandLine { command, args, verbosity })
+}
+
+/// prints usage and the problem to stderr, giving back exit code 2
+fn usage_error(stderr: &mut dyn Write, program: &str, message: &str) -> i32 {
+  let _ = writeln!(stderr, "Usage: {} [options] <command> [arguments]", program);
+  let _ = writeln!(stderr, "{}: {}", program, message);
+  2
+}
+
+/// parse_command_line over the process's own arguments and the config
+/// Args::from_config loads, exiting with usage on anything it can't make
+/// sense of
+pub fn get_command_line() -> CommandLine {
+  parse_command_line_over(load_config(), env::args().collect(), &mut io::stdout(),
+    &mut io::stderr())
+    .unwrap_or_else(|code| process::exit(code))
+}
+
+/// Args::from_config, exiting on a config file that doesn't parse
+fn load_config() -> Args {
+  Args::from_config().unwrap_or_else(|err| {
+    eprintln!("rust_reddit: {}", err);
+    process::exit(2);
+  })
 }
 
 #[cfg(test)]
 mod tests {
   extern crate serde_json;
   use serde_json::to_string as json_to_string;
-  use cli::Args;
+  use api::TimeRange;
+  use output::Format;
+  use cli::{parse_command_line, Args, Command, CommandLine};
 
   #[test]
   fn test_get_args() {
-    // Since I am not mocking ArgumentParser, it is not tested properly
-    // instead, all I am doing is demonstrating that the args returned
-    // will remain default as get_args returns args and by default
-    // they are Args::default()
-    // This is... okay for now
+    use cli::parse_args_over;
+
+    // no config file and none of the developer's RUST_REDDIT_* variables
+    let env = |name: &str| match name {
+      "RUST_REDDIT_CONFIG" => Some("/nonexistent/rust_reddit/config.toml".to_owned()),
+      _ => None,
+    };
+    let loaded = Args::from_config_with(&env).unwrap();
+    assert_eq!(json_to_string(&loaded).unwrap(), json_to_string(&Args::default()).unwrap());
+
+    let argv = vec!["rust_reddit".to_owned(), "--nocapture".to_owned(), "-vv".to_owned()];
+    let (args, verbosity) = parse_args_over(loaded, argv, &mut Vec::new(), &mut Vec::new())
+      .unwrap();
+    let expected = Args { nocapture: true, ..Args::default() };
+    assert_eq!(json_to_string(&args).unwrap(), json_to_string(&expected).unwrap());
+    assert_eq!(verbosity, 2);
 
-    use cli::get_args;
-    let args = get_args();
-    let mut expected = Args::default();
+    let keyed = Args::from_config_with(&|name| match name {
+      "RUST_REDDIT_KEY" => Some("abc".to_owned()),
+      _ => env(name),
+    }).unwrap();
+    assert_eq!(keyed.key, "abc");
 
-    // strangely enough, in order to pass nocapture without failure, we
-    // must set the "expected / default" value to whatever is being passed
-    // or else the test will fail when we pass nocapture in a test
-    // thereby defeating the purpose of nocapture
-    expected.nocapture = args.nocapture;
+    let mut err = Vec::new();
+    let bad = vec!["rust_reddit".to_owned(), "-f".to_owned(), "xml".to_owned()];
+    assert_eq!(parse_args_over(Args::default(), bad, &mut Vec::new(), &mut err).unwrap_err(), 2);
+    assert!(String::from_utf8(err).unwrap().starts_with("rust_reddit: "));
+  }
+
+  fn parse(line: &[&str]) -> (Result<CommandLine, i32>, String) {
+    let mut out = Vec::new();
+    let mut err = Vec::new();
+    let argv = line.iter().map(|arg| arg.to_string()).collect();
+    let result = parse_command_line(argv, &mut out, &mut err);
+    (result, String::from_utf8(err).unwrap())
+  }
+
+  fn path_of(line: &[&str]) -> String {
+    parse(line).0.unwrap().command.path().unwrap()
+  }
+
+  #[test]
+  fn test_listing_commands() {
+    let parsed = parse(&["rust_reddit", "top", "rust", "--limit", "25", "--time", "week"]).0
+      .unwrap();
+    assert_eq!(parsed.command,
+      Command::Top { subreddit : "rust".to_owned(), time : TimeRange::Week, limit : Some(25) });
+    assert_eq!(parsed.command.path().unwrap(), "/r/rust/top/.json?t=week&limit=25");
+
+    assert_eq!(path_of(&["rust_reddit", "top", "rust"]), "/r/rust/top/.json?t=day");
+    assert_eq!(path_of(&["rust_reddit", "hot", "rust+programming"]),
+      "/r/rust+programming/hot/.json");
+    assert_eq!(path_of(&["rust_reddit", "new", "rust", "-l", "5"]), "/r/rust/new/.json?limit=5");
+    assert_eq!(path_of(&["rust_reddit", "comments", "t3_abc"]), "/comments/abc/.json");
+    assert_eq!(path_of(&["rust_reddit", "search", "rust", "async traits", "--sort", "top"]),
+      "/r/rust/search/.json?q=async%20traits&restrict_sr=on&sort=top&t=all");
+  }
+
+  #[test]
+  fn test_shared_options_before_and_after_the_command() {
+    let parsed = parse(&["rust_reddit", "-k", "token", "hot", "rust", "-A", "my-bot/1.0",
+      "-H", "Accept: application/json, text/html"]).0.unwrap();
+    assert_eq!(parsed.args.key, "token");
+    assert_eq!(parsed.args.user_agent, Some("my-bot/1.0".to_owned()));
+    assert_eq!(parsed.args.headers,
+      vec![("Accept".to_owned(), "application/json, text/html".to_owned())]);
+
+    let table = parse(&["rust_reddit", "top", "rust", "--format", "table"]).0.unwrap();
+    assert_eq!(table.args.format, Format::Table);
 
-    let args_s = json_to_string(&args).unwrap();
-    let args_expected = json_to_string(&expected).unwrap();
+    let digest = parse(&["rust_reddit", "--subreddits-file", "subs.txt"]).0.unwrap();
+    assert_eq!(digest.command, Command::Digest);
+  }
+
+  #[test]
+  fn test_flags_override_loaded_config() {
+    use cli::parse_command_line_over;
+
+    let loaded = Args {
+      key : "config-key".to_owned(),
+      user_agent : Some("config-agent/1.0".to_owned()),
+      ..Args::default()
+    };
+    let over = |line: &[&str]| {
+      let argv = line.iter().map(|arg| arg.to_string()).collect();
+      parse_command_line_over(loaded.clone(), argv, &mut Vec::new(), &mut Vec::new())
+        .unwrap().args
+    };
+
+    let kept = over(&["rust_reddit", "hot", "rust"]);
+    assert_eq!(kept.key, "config-key");
+    assert_eq!(kept.user_agent.as_deref(), Some("config-agent/1.0"));
+    assert_eq!(kept.headers, Args::default().headers);
+
+    let flagged = over(&["rust_reddit", "-k", "flag-key", "hot", "rust", "-A", "flag-agent"]);
+    assert_eq!(flagged.key, "flag-key");
+    assert_eq!(flagged.user_agent.as_deref(), Some("flag-agent"));
+  }
+
+  #[test]
+  fn test_bad_command_lines_exit_with_usage() {
+    let bad: &[&[&str]] = &[
+      &["rust_reddit"],
+      &["rust_reddit", "best", "rust"],
+      &["rust_reddit", "top"],
+      &["rust_reddit", "search", "rust"],
+      &["rust_reddit", "top", "rust", "--time", "fortnight"],
+      &["rust_reddit", "search", "rust", "x", "--sort", "best"],
+      &["rust_reddit", "hot", "bad name"],
+      &["rust_reddit", "comments", "t3_a/b"],
+      &["rust_reddit", "new", "rust", "--limit", "lots"],
+      &["rust_reddit", "hot", "rust", "-H", "bogus"],
+      &["rust_reddit", "-f", "yaml", "hot", "rust"],
+    ];
+    for line in bad {
+      let (result, stderr) = parse(line);
+      assert_eq!(result.err(), Some(2), "{:?}", line);
+      assert!(stderr.contains("Usage:"), "{:?}: {}", line, stderr);
+    }
+  }
 
-    println!("{}", args_s);
-    println!("{}", args_expected);
+  #[test]
+  fn test_verbose_flags_count_up() {
+    use cli::verbosity_level;
+    use log::LevelFilter;
 
-    assert!(args_s == args_expected);
+    assert_eq!(parse(&["rust_reddit", "hot", "rust"]).0.unwrap().verbosity, 0);
+    assert_eq!(parse(&["rust_reddit", "-v", "hot", "rust", "--verbose"]).0.unwrap().verbosity, 2);
+    assert_eq!(parse(&["rust_reddit", "hot", "rust", "-vvv"]).0.unwrap().verbosity, 3);
 
+    assert_eq!(verbosity_level(0), LevelFilter::Off);
+    assert_eq!(verbosity_level(1), LevelFilter::Info);
+    assert_eq!(verbosity_level(2), LevelFilter::Debug);
+    assert_eq!(verbosity_level(9), LevelFilter::Trace);
   }
 }
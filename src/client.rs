@@ -0,0 +1,687 @@
+
+// external imports
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+
+// internal imports
+use api::{check_content_type, parse_json_with, prepare_get, status_error,
+  validate_subreddit_name, validate_username};
+use cache::{CachedResponse, ResponseCache};
+use config::Args;
+use clock::{Clock, SystemClock};
+use error::RedditError;
+use middleware::{run_stack, Middleware};
+use models::{Comment, Listing, Post};
+use transport::{CurlTransport, PreparedRequest, Transport};
+
+////////////////////////////////////////////////////////////////////////////////
+/// RedditClient
+/// Owns the transport requests go through, curl unless another one is
+/// given, along with anything else that should outlive a single call
+///
+/// The curl transport keeps its connection open between requests, so a
+/// change in reddit's addresses is only picked up once that connection
+/// closes and args.dns_cache_timeout has passed; args.fresh_connect forces
+/// a new connection, and a fresh lookup, every time.
+pub struct RedditClient<T: Transport = CurlTransport> {
+  pub args : Args,
+  pub cache : Option<Box<dyn ResponseCache>>,
+  pub clock : Box<dyn Clock>,
+  /// called after every request that went over the network
+  pub on_request : RequestHook,
+  /// wrapped around every request on its way to the transport, first
+  /// entry outermost; cache hits never reach them
+  pub middleware : Vec<Box<dyn Middleware>>,
+  transport : T,
+  bytes_downloaded : u64,
+  last_cached : bool,
+}
+
+/// What on_request hears about each request, enough for metrics or tracing
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestInfo {
+  pub method : String,
+  pub path : String,
+  pub status : u32,
+  pub duration : Duration,
+  pub bytes : u64,
+  /// args.request_id at the time of the request
+  pub request_id : Option<String>,
+}
+
+pub type RequestHook = Arc<dyn Fn(&RequestInfo) + Send + Sync>;
+
+impl RedditClient {
+  pub fn new(args: Args) -> RedditClient {
+    let transport = CurlTransport::new(&args);
+    RedditClient::with_transport(args, transport)
+  }
+
+  /// Asks for HTTP/2 on https connections, which reddit supports, so
+  /// requests can share one connection. Servers that only speak HTTP/1.1
+  /// get that instead, and plain http is always HTTP/1.1.
+  pub fn http2(mut self, enable: bool) -> RedditClient {
+    self.transport.set_http2(enable);
+    self
+  }
+}
+
+impl<T: Transport> RedditClient<T> {
+  /// a client sending its requests through a transport of choice
+  pub fn with_transport(args: Args, transport: T) -> RedditClient<T> {
+    RedditClient {
+      args,
+      cache : None,
+      clock : Box::new(SystemClock),
+      on_request : Arc::new(|_: &RequestInfo| ()),
+      middleware : Vec::new(),
+      transport,
+      bytes_downloaded : 0,
+      last_cached : false,
+    }
+  }
+
+  /// builder style setter for the response cache
+  pub fn with_cache(mut self, cache: Box<dyn ResponseCache>) -> RedditClient<T> {
+    self.cache = Some(cache);
+    self
+  }
+
+  /// builder style setter for the clock, mostly useful for tests
+  pub fn with_clock(mut self, clock: Box<dyn Clock>) -> RedditClient<T> {
+    self.clock = clock;
+    self
+  }
+
+  /// builder style setter for the on_request hook
+  pub fn with_on_request(mut self, hook: RequestHook) -> RedditClient<T> {
+    self.on_request = hook;
+    self
+  }
+
+  /// pushes a middleware onto the stack, inside any added before it
+  pub fn with_middleware(mut self, middleware: Box<dyn Middleware>) -> RedditClient<T> {
+    self.middleware.push(middleware);
+    self
+  }
+
+  /// total size of every response body fetched over the network by this
+  /// client; answers served from the cache are not counted
+  pub fn total_bytes_downloaded(&self) -> u64 {
+    self.bytes_downloaded
+  }
+
+  /// whether the last query was answered from the cache, either outright
+  /// or because reddit said with a 304 that the cached copy was current
+  pub fn last_was_cached(&self) -> bool {
+    self.last_cached
+  }
+
+  /// Queries a path such as "/r/rust/top/.json", answering from the cache
+  /// when it holds a fresh copy of the response
+  ///
+  /// A cached copy that is no longer fresh is revalidated: its ETag and
+  /// Last-Modified go out as If-None-Match and If-Modified-Since, and on a
+  /// 304 the cached body is used. Entries are kept apart per access token.
+  /// Any other answer outside 2xx is an error and is never cached.
+  pub fn query(&mut self, path: &str) -> Result<Value, RedditError> {
+    let (request, args) = prepare_get(path, &self.args)?;
+    let key = cache_key(&request.url, &args);
+    self.last_cached = false;
+
+    let mut stale = None;
+    if let Some(ref cache) = self.cache {
+      if let Some(hit) = cache.get(&key) {
+        self.last_cached = true;
+        return parse_json_with(&hit.body, &self.args);
+      }
+      stale = cache.get_stale(&key);
+    }
+
+    let PreparedRequest { url : uri, mut headers, .. } = request;
+    if let Some(ref stale) = stale {
+      if let Some(ref etag) = stale.etag {
+        headers.push(("If-None-Match".to_owned(), etag.clone()));
+      }
+      if let Some(ref modified) = stale.last_modified {
+        headers.push(("If-Modified-Since".to_owned(), modified.clone()));
+      }
+    }
+    let request = PreparedRequest::get(&uri, headers);
+    let method = request.method.clone();
+    let started = Instant::now();
+    let response = run_stack(&self.middleware, request,
+      &|req| self.transport.execute_with(&req, &self.args))?;
+    let bytes = response.body.len() as u64;
+    self.bytes_downloaded += bytes;
+    (self.on_request)(&RequestInfo {
+      method : method.clone(),
+      path : path.to_owned(),
+      status : response.status,
+      duration : started.elapsed(),
+      bytes,
+      request_id : self.args.request_id.clone(),
+    });
+
+    if let (304, Some(mut stale)) = (response.status, stale) {
+      stale.stored_at = self.clock.now();
+      let value = parse_json_with(&stale.body, &self.args);
+      if let Some(ref mut cache) = self.cache {
+        cache.put(&key, stale);
+      }
+      self.last_cached = true;
+      return value;
+    }
+
+    let response = response.into_response()?;
+    if !(200..300).contains(&response.status) {
+      return Err(status_error(response).for_request(&method, &uri));
+    }
+    check_content_type(response.header("Content-Type"))?;
+    let etag = response.header("ETag").map(|v| v.to_owned());
+    let last_modified = response.header("Last-Modified").map(|v| v.to_owned());
+    let output = response.body;
+
+    if let Some(ref mut cache) = self.cache {
+      let mut entry = CachedResponse::at(&output, self.clock.now());
+      entry.etag = etag;
+      entry.last_modified = last_modified;
+      cache.put(&key, entry);
+    }
+
+    parse_json_with(&output, &self.args)
+  }
+
+  /// Writes out the cookie jar (args.cookie_jar) and anything the cache
+  /// holds back. Done on drop as well, where errors go unreported.
+  pub fn flush(&mut self) -> Result<(), RedditError> {
+    self.transport.flush(&self.args)?;
+    if let Some(ref mut cache) = self.cache {
+      cache.flush()?;
+    }
+    Ok(())
+  }
+
+  /// Like query, with `f` applied to a copy of args for this one request,
+  /// e.g. a longer timeout for a slow endpoint; self.args is left as it was
+  pub fn query_with<F: FnOnce(&mut Args)>(&mut self, path: &str, f: F)
+    -> Result<Value, RedditError>
+  {
+    let mut args = self.args.clone();
+    f(&mut args);
+
+    let base = mem::replace(&mut self.args, args);
+    let result = self.query(path);
+    self.args = base;
+    result
+  }
+
+  /// a subreddit's listings, fetched through this client
+  pub fn subreddit(&mut self, name: &str) -> SubredditHandle<'_, T> {
+    SubredditHandle { client : self, name : name.to_owned() }
+  }
+
+  /// a user's listings, fetched through this client
+  pub fn user(&mut self, name: &str) -> UserHandle<'_, T> {
+    UserHandle { client : self, name : name.to_owned() }
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// SubredditHandle and UserHandle
+/// Shorthands for common listings, e.g. `client.subreddit("rust").top(25)`;
+/// the names are checked when a listing is asked for
+pub struct SubredditHandle<'a, T: Transport + 'a> {
+  client : &'a mut RedditClient<T>,
+  name : String,
+}
+
+impl<'a, T: Transport> SubredditHandle<'a, T> {
+  pub fn hot(self, limit: u32) -> Result<Listing<Post>, RedditError> {
+    self.listing("hot", limit)
+  }
+
+  /// the new listing; not called `new` so it isn't mistaken for a constructor
+  pub fn newest(self, limit: u32) -> Result<Listing<Post>, RedditError> {
+    self.listing("new", limit)
+  }
+
+  pub fn top(self, limit: u32) -> Result<Listing<Post>, RedditError> {
+    self.listing("top", limit)
+  }
+
+  fn listing(self, sort: &str, limit: u32) -> Result<Listing<Post>, RedditError> {
+    validate_subreddit_name(&self.name)?;
+    let path = format!("/r/{}/{}/.json?limit={}", self.name, sort, limit);
+    Listing::from_value(self.client.query(&path)?)
+  }
+}
+
+pub struct UserHandle<'a, T: Transport + 'a> {
+  client : &'a mut RedditClient<T>,
+  name : String,
+}
+
+impl<'a, T: Transport> UserHandle<'a, T> {
+  /// posts the user submitted, newest first
+  pub fn submitted(self) -> Result<Listing<Post>, RedditError> {
+    validate_username(&self.name)?;
+    Listing::from_value(self.client.query(&format!("/user/{}/submitted/.json", self.name))?)
+  }
+
+  /// comments the user wrote, newest first
+  pub fn comments(self) -> Result<Listing<Comment>, RedditError> {
+    validate_username(&self.name)?;
+    Listing::from_value(self.client.query(&format!("/user/{}/comments/.json", self.name))?)
+  }
+}
+
+/// the cache key for a request: its uri, plus a hash of the access token
+/// when there is one so accounts never see each other's responses
+fn cache_key(uri: &str, args: &Args) -> String {
+  if args.key.is_empty() {
+    return uri.to_owned();
+  }
+  let mut hasher = DefaultHasher::new();
+  args.key.hash(&mut hasher);
+  format!("{} (token {:016x})", uri, hasher.finish())
+}
+
+impl<T: Transport> Drop for RedditClient<T> {
+  fn drop(&mut self) {
+    let _ = self.flush();
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_client {
+
+  use cache::{CachedResponse, HashMapCache, ResponseCache};
+  use client::{cache_key, RedditClient};
+  use config::Args;
+  use mock::{MockServer, MockResponse};
+  use std::time::{Duration, SystemTime};
+
+  #[test]
+  fn test_cache_hit_skips_network() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"hello": "world"}"#),
+    ]);
+    let cache = HashMapCache::new(Duration::from_secs(60));
+    let mut client = RedditClient::new(server.args())
+      .with_cache(Box::new(cache));
+
+    let first = client.query("/r/rust/.json").unwrap();
+    let second = client.query("/r/rust/.json").unwrap();
+
+    assert_eq!(first, second);
+    assert_eq!(server.requests().len(), 1);
+  }
+
+  #[test]
+  fn test_expired_cache_entry_refetches() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"fresh": true}"#),
+    ]);
+    let args = server.args();
+    let uri = format!("{}/r/rust/.json", server.base_url);
+
+    let mut cache = HashMapCache::new(Duration::from_secs(60));
+    let mut stale = CachedResponse::new(r#"{"fresh": false}"#);
+    stale.stored_at = SystemTime::now() - Duration::from_secs(120);
+    cache.put(&uri, stale);
+
+    let mut client = RedditClient::new(args).with_cache(Box::new(cache));
+    let value = client.query("/r/rust/.json").unwrap();
+
+    assert_eq!(value["fresh"], true);
+    assert_eq!(server.requests().len(), 1);
+  }
+
+  #[test]
+  fn test_injected_clock_drives_cache_expiry() {
+    use clock::ManualClock;
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"page": 1}"#),
+      MockResponse::json(r#"{"page": 2}"#),
+    ]);
+    let clock = ManualClock::new(SystemTime::now());
+    let cache = HashMapCache::new(Duration::from_secs(60))
+      .with_clock(Box::new(clock.clone()));
+    let mut client = RedditClient::new(server.args())
+      .with_cache(Box::new(cache))
+      .with_clock(Box::new(clock.clone()));
+
+    assert_eq!(client.query("/r/rust/.json").unwrap()["page"], 1);
+
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(client.query("/r/rust/.json").unwrap()["page"], 1);
+
+    clock.advance(Duration::from_secs(31));
+    assert_eq!(client.query("/r/rust/.json").unwrap()["page"], 2);
+    assert_eq!(server.requests().len(), 2);
+  }
+
+  #[test]
+  fn test_stale_entry_revalidates_with_etag() {
+    use clock::ManualClock;
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"page": 1}"#)
+        .header("ETag", "\"abc\"")
+        .header("Last-Modified", "Wed, 14 Oct 2026 08:00:00 GMT"),
+      MockResponse::status(304, ""),
+    ]);
+    let clock = ManualClock::new(SystemTime::now());
+    let cache = HashMapCache::new(Duration::from_secs(60))
+      .with_clock(Box::new(clock.clone()));
+    let mut client = RedditClient::new(server.args())
+      .with_cache(Box::new(cache))
+      .with_clock(Box::new(clock.clone()));
+
+    assert_eq!(client.query("/r/rust/.json").unwrap()["page"], 1);
+    assert!(!client.last_was_cached());
+
+    clock.advance(Duration::from_secs(61));
+    assert_eq!(client.query("/r/rust/.json").unwrap()["page"], 1);
+    assert!(client.last_was_cached());
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].header("If-None-Match"), None);
+    assert_eq!(requests[1].header("If-None-Match"), Some("\"abc\"".to_owned()));
+    assert_eq!(requests[1].header("If-Modified-Since"),
+      Some("Wed, 14 Oct 2026 08:00:00 GMT".to_owned()));
+
+    // the 304 refreshed the entry, so this one never leaves the client
+    assert_eq!(client.query("/r/rust/.json").unwrap()["page"], 1);
+    assert_eq!(server.requests().len(), 2);
+  }
+
+  #[test]
+  fn test_cache_entries_are_kept_per_token() {
+    let uri = "https://oauth.reddit.com/r/rust/.json";
+    let anonymous = Args { key : String::new(), ..Args::default() };
+    let first = Args { key : "token-a".to_owned(), ..Args::default() };
+    let second = Args { key : "token-b".to_owned(), ..Args::default() };
+
+    assert_eq!(cache_key(uri, &anonymous), uri);
+    assert_ne!(cache_key(uri, &first), cache_key(uri, &second));
+    assert!(!cache_key(uri, &first).contains("token-a"));
+  }
+
+  #[test]
+  fn test_total_bytes_downloaded() {
+    let body = r#"{"kind": "Listing"}"#;
+    let server = MockServer::start(vec![
+      MockResponse::json(body),
+      MockResponse::json(body),
+    ]);
+    let mut client = RedditClient::new(server.args());
+    assert_eq!(client.total_bytes_downloaded(), 0);
+
+    client.query("/r/rust/.json").unwrap();
+    assert_eq!(client.total_bytes_downloaded(), body.len() as u64);
+
+    client.query("/r/rust/.json").unwrap();
+    assert_eq!(client.total_bytes_downloaded(), 2 * body.len() as u64);
+  }
+
+  #[test]
+  fn test_mock_transport_needs_no_network() {
+    use transport::{MockTransport, RawResponse};
+
+    let transport = MockTransport::new(vec![RawResponse::json(r#"{"canned": true}"#)]);
+    let mut client = RedditClient::with_transport(Args::default(), transport.clone());
+
+    assert_eq!(client.query("/r/rust/.json").unwrap()["canned"], true);
+
+    let requests = transport.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, "GET");
+    assert_eq!(requests[0].url, "https://www.reddit.com/r/rust/.json?raw_json=1");
+    assert!(requests[0].headers.iter().any(|h| h.0 == "Accept"));
+  }
+
+  #[test]
+  fn test_client_reuses_its_connection() {
+    let page = r#"{"kind": "Listing", "data": {"children": [
+      {"kind": "t3", "data": {"name": "t3_a", "title": "hello"}}]}}"#;
+    let server = MockServer::start_keep_alive(vec![
+      MockResponse::json(page), MockResponse::json(page), MockResponse::json(page),
+    ]);
+    let mut client = RedditClient::new(server.args());
+
+    assert_eq!(client.subreddit("rust").top(25).unwrap().children[0].title, "hello");
+    client.user("spez").submitted().unwrap();
+    client.query("/r/rust/.json").unwrap();
+    assert_eq!(server.connections(), 1);
+
+    let requests = server.requests();
+    assert_eq!(requests[0].path, "/r/rust/top/.json?limit=25&raw_json=1");
+    assert_eq!(requests[1].path, "/user/spez/submitted/.json?raw_json=1");
+  }
+
+  #[test]
+  fn test_handles_check_names() {
+    use error::RedditError;
+    use transport::MockTransport;
+
+    let mut client = RedditClient::with_transport(Args::default(), MockTransport::new(vec![]));
+    assert!(matches!(client.subreddit("not a sub").hot(10), Err(RedditError::InvalidName(_))));
+    assert!(matches!(client.user("no/such").comments(), Err(RedditError::InvalidName(_))));
+  }
+
+  #[test]
+  fn test_error_status_is_not_cached() {
+    use error::RedditError;
+
+    let server = MockServer::start(vec![
+      MockResponse::status(404, r#"{"message": "Not Found", "error": 404}"#)
+        .header("Content-Type", "application/json; charset=UTF-8"),
+      MockResponse::status(429, r#"{"message": "Too Many Requests", "error": 429}"#)
+        .header("Content-Type", "application/json; charset=UTF-8"),
+      MockResponse::json(r#"{"kind": "Listing"}"#),
+    ]);
+    let cache = HashMapCache::new(Duration::from_secs(60));
+    let mut client = RedditClient::new(server.args()).with_cache(Box::new(cache));
+
+    let err = client.query("/r/rust/.json").unwrap_err();
+    assert!(matches!(err, RedditError::Request { .. }), "{:?}", err);
+    assert_eq!(err.status_code(), Some(404));
+    assert_eq!(client.query("/r/rust/.json").unwrap_err().status_code(), Some(429));
+    assert!(!client.last_was_cached());
+    assert_eq!(client.query("/r/rust/.json").unwrap()["kind"], "Listing");
+    assert_eq!(server.requests().len(), 3);
+  }
+
+  #[test]
+  fn test_transport_content_type_is_checked() {
+    use error::RedditError;
+    use transport::{MockTransport, RawResponse};
+
+    let html = RawResponse {
+      status : 200,
+      headers : vec![("content-type".to_owned(), "text/html".to_owned())],
+      body : b"<html></html>".to_vec(),
+      ..RawResponse::default()
+    };
+    let mut client = RedditClient::with_transport(
+      Args::default(), MockTransport::new(vec![html]));
+
+    let err = client.query("/r/rust/.json").unwrap_err();
+    assert!(matches!(err, RedditError::UnexpectedContentType { .. }));
+  }
+
+  #[test]
+  fn test_on_request_hook() {
+    use client::RequestInfo;
+    use std::sync::{Arc, Mutex};
+
+    let body = r#"{"kind": "Listing"}"#;
+    let server = MockServer::start(vec![MockResponse::json(body)]);
+    let seen: Arc<Mutex<Vec<RequestInfo>>> = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    let mut client = RedditClient::new(server.args())
+      .with_cache(Box::new(HashMapCache::new(Duration::from_secs(60))))
+      .with_on_request(Arc::new(move |info: &RequestInfo| sink.lock().unwrap().push(info.clone())));
+
+    client.query("/r/rust/.json").unwrap();
+    // answered from the cache, so the hook stays quiet
+    client.query("/r/rust/.json").unwrap();
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 1);
+    assert_eq!(seen[0].method, "GET");
+    assert_eq!(seen[0].path, "/r/rust/.json");
+    assert_eq!(seen[0].status, 200);
+    assert_eq!(seen[0].bytes, body.len() as u64);
+    assert!(seen[0].duration > Duration::from_secs(0));
+    assert_eq!(seen[0].request_id, None);
+  }
+
+  #[test]
+  fn test_http2_falls_back_to_http1() {
+    use curl;
+
+    // the mock only speaks HTTP/1.1 over plain http
+    let server = MockServer::start(vec![MockResponse::json(r#"{"ok": true}"#)]);
+    let mut client = RedditClient::new(server.args()).http2(true);
+    assert_eq!(client.transport.uses_http2(), curl::Version::get().feature_http2());
+
+    assert_eq!(client.query("/r/rust/.json").unwrap()["ok"], true);
+    assert!(!RedditClient::new(Args::default()).transport.uses_http2());
+  }
+
+  #[test]
+  fn test_query_with_overrides_one_call() {
+    use error::RedditError;
+
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"slow": true}"#).delayed(Duration::from_millis(800)),
+      MockResponse::json(r#"{"page": 1}"#),
+      MockResponse::json(r#"{"page": 2}"#),
+    ]);
+    let args = Args {
+      headers : vec![("User-Agent".to_owned(), "base".to_owned())],
+      ..server.args()
+    };
+    let mut client = RedditClient::new(args);
+
+    let err = client.query_with("/r/rust/.json", |args| {
+      args.timeout = Some(Duration::from_millis(200));
+    }).unwrap_err();
+    assert!(matches!(err, RedditError::Timeout(_)), "{:?}", err);
+
+    let value = client.query_with("/r/rust/.json", |args| {
+      args.headers = vec![("User-Agent".to_owned(), "override".to_owned())];
+    }).unwrap();
+    assert_eq!(value["page"], 1);
+    assert_eq!(client.query("/r/rust/.json").unwrap()["page"], 2);
+
+    assert_eq!(client.args.timeout, Some(Duration::from_secs(30)));
+    let requests = server.requests();
+    assert_eq!(requests[1].header("User-Agent").unwrap(), "override");
+    assert_eq!(requests[2].header("User-Agent").unwrap(), "base");
+  }
+
+  #[test]
+  fn test_flush_writes_latest_cookies() {
+    use cache::ResponseCache;
+    use error::RedditError;
+    use std::env;
+    use std::fs;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingCache(Arc<AtomicUsize>);
+
+    impl ResponseCache for CountingCache {
+      fn get(&self, _: &str) -> Option<CachedResponse> {
+        None
+      }
+      fn put(&mut self, _: &str, _: CachedResponse) {}
+      fn flush(&mut self) -> Result<(), RedditError> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+      }
+    }
+
+    let jar = env::temp_dir().join(format!("rust_reddit_cookies_{}.txt", std::process::id()));
+    let _ = fs::remove_file(&jar);
+    let server = MockServer::start(vec![
+      MockResponse::json("{}").header("Set-Cookie", "session=first; Path=/"),
+      MockResponse::json("{}").header("Set-Cookie", "session=second; Path=/"),
+    ]);
+    let flushes = Arc::new(AtomicUsize::new(0));
+    let args = Args { cookie_jar: Some(jar.clone()), ..server.args() };
+    let mut client = RedditClient::new(args)
+      .with_cache(Box::new(CountingCache(flushes.clone())));
+
+    client.query("/r/rust/.json").unwrap();
+    client.flush().unwrap();
+    assert!(fs::read_to_string(&jar).unwrap().contains("session\tfirst"));
+    assert_eq!(flushes.load(Ordering::SeqCst), 1);
+
+    client.query("/r/rust/.json").unwrap();
+    drop(client);
+    let written = fs::read_to_string(&jar).unwrap();
+    assert!(written.contains("session\tsecond"), "{}", written);
+    assert!(!written.contains("session\tfirst"), "{}", written);
+    assert_eq!(flushes.load(Ordering::SeqCst), 2);
+    fs::remove_file(&jar).unwrap();
+  }
+
+  #[test]
+  fn test_middleware_adds_header() {
+    use error::RedditError;
+    use middleware::{LoggingMiddleware, Middleware, Next};
+    use transport::{PreparedRequest, RawResponse};
+
+    struct Trace;
+
+    impl Middleware for Trace {
+      fn handle(&self, mut req: PreparedRequest, next: Next)
+        -> Result<RawResponse, RedditError>
+      {
+        req.headers.push(("X-Trace".to_owned(), "abc".to_owned()));
+        next(req)
+      }
+    }
+
+    let server = MockServer::start(vec![MockResponse::json(r#"{"ok": true}"#)]);
+    let mut client = RedditClient::new(server.args())
+      .with_middleware(Box::new(LoggingMiddleware))
+      .with_middleware(Box::new(Trace));
+
+    assert_eq!(client.query("/r/rust/.json").unwrap()["ok"], true);
+    assert_eq!(server.requests()[0].header("X-Trace").unwrap(), "abc");
+  }
+
+  #[test]
+  fn test_request_id_reaches_on_request() {
+    use client::RequestInfo;
+    use std::sync::{Arc, Mutex};
+
+    let server = MockServer::start(vec![MockResponse::json("{}")]);
+    let seen: Arc<Mutex<Vec<RequestInfo>>> = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    let args = Args { request_id: Some("crawl-42".to_owned()), ..server.args() };
+    let mut client = RedditClient::new(args)
+      .with_on_request(Arc::new(move |info: &RequestInfo| sink.lock().unwrap().push(info.clone())));
+
+    client.query("/r/rust/.json?raw_json=1").unwrap();
+
+    assert_eq!(seen.lock().unwrap()[0].request_id, Some("crawl-42".to_owned()));
+    // only for the caller's logs, nothing about it goes over the wire
+    let request = &server.requests()[0];
+    assert_eq!(request.path, "/r/rust/.json?raw_json=1");
+    assert!(request.headers.iter().all(|h| !h.1.contains("crawl-42")));
+  }
+}
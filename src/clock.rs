@@ -0,0 +1,66 @@
+
+// external imports
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+////////////////////////////////////////////////////////////////////////////////
+/// Clock
+/// Source of the current time for everything time-dependent (cache ttl,
+/// token expiry, throttling), so tests can move time along instantly
+pub trait Clock: Send + Sync {
+  fn now(&self) -> SystemTime;
+}
+
+/// The real wall clock, used unless something else is injected
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> SystemTime {
+    SystemTime::now()
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// ManualClock
+/// A clock that only moves when told to. Clones share the same time, so one
+/// copy can be handed to a client while the test keeps another to advance.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+  now : Arc<Mutex<SystemTime>>,
+}
+
+impl ManualClock {
+  pub fn new(start: SystemTime) -> ManualClock {
+    ManualClock { now : Arc::new(Mutex::new(start)) }
+  }
+
+  /// moves the clock forward
+  pub fn advance(&self, by: Duration) {
+    let mut now = self.now.lock().unwrap();
+    *now += by;
+  }
+}
+
+impl Clock for ManualClock {
+  fn now(&self) -> SystemTime {
+    *self.now.lock().unwrap()
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_clock {
+
+  use clock::*;
+  use std::time::{Duration, UNIX_EPOCH};
+
+  #[test]
+  fn test_manual_clock_clones_share_time() {
+    let clock = ManualClock::new(UNIX_EPOCH);
+    let handle = clock.clone();
+
+    handle.advance(Duration::from_secs(90));
+    assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(90));
+  }
+}
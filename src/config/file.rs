@@ -0,0 +1,538 @@
+// Loading Args from a config file and RUST_REDDIT_* environment variables,
+// so credentials don't have to be passed on every invocation
+
+// external imports
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// internal imports
+use config::{header_pair, Args};
+use error::RedditError;
+
+////////////////////////////////////////////////////////////////////////////////
+// Config files
+
+/// the sections apply_config_file knows
+const SECTIONS: &[&str] = &["auth", "request", "headers", "timeouts"];
+
+/// Environment variables applied after the config file, each to the Args
+/// field of the same name; RUST_REDDIT_HEADERS takes the cli's
+/// "Name: value,Name: value" form
+pub const ENV_VARS: &[&str] = &[
+  "RUST_REDDIT_KEY",
+  "RUST_REDDIT_CLIENT_ID",
+  "RUST_REDDIT_CLIENT_SECRET",
+  "RUST_REDDIT_USERNAME",
+  "RUST_REDDIT_PASSWORD",
+  "RUST_REDDIT_REFRESH_TOKEN",
+  "RUST_REDDIT_USER_AGENT",
+  "RUST_REDDIT_HEADERS",
+];
+
+/// Where Args::from_config looks for its file: $RUST_REDDIT_CONFIG when set,
+/// else rust_reddit/config.toml under $XDG_CONFIG_HOME, falling back to
+/// ~/.config. None when there is no home directory to look in.
+pub fn config_path() -> Option<PathBuf> {
+  config_path_from(&|name| env::var(name).ok())
+}
+
+fn config_path_from(env: &dyn Fn(&str) -> Option<String>) -> Option<PathBuf> {
+  let var = |name| env(name).filter(|value| !value.is_empty());
+  if let Some(path) = var("RUST_REDDIT_CONFIG") {
+    return Some(PathBuf::from(path));
+  }
+  let config_dir = var("XDG_CONFIG_HOME").map(PathBuf::from)
+    .or_else(|| var("HOME").map(|home| Path::new(&home).join(".config")))?;
+  Some(config_dir.join("rust_reddit").join("config.toml"))
+}
+
+impl Args {
+  /// Args::default, then the config file at config_path, then the
+  /// RUST_REDDIT_* environment variables, each overriding the last. A
+  /// missing file is skipped; a malformed one is a RedditError::Config
+  /// naming the file, line and key. See apply_config_file for the format.
+  pub fn from_config() -> Result<Args, RedditError> {
+    Args::from_config_with(&|name| env::var(name).ok())
+  }
+
+  /// from_config reading its variables from env instead of the process
+  pub(crate) fn from_config_with(env: &dyn Fn(&str) -> Option<String>)
+    -> Result<Args, RedditError>
+  {
+    match config_path_from(env) {
+      Some(path) => Args::load_with(&path, env),
+      None => {
+        let mut args = Args::default();
+        args.apply_env(env)?;
+        Ok(args)
+      },
+    }
+  }
+
+  /// Like from_config, with the config file at path
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Args, RedditError> {
+    Args::load_with(path.as_ref(), &|name| env::var(name).ok())
+  }
+
+  fn load_with(path: &Path, env: &dyn Fn(&str) -> Option<String>)
+    -> Result<Args, RedditError>
+  {
+    let mut args = Args::default();
+    args.apply_config_file(path)?;
+    args.apply_env(env)?;
+    Ok(args)
+  }
+
+  /// Sets whatever the config file at path sets, leaving the rest alone;
+  /// nothing happens when there is no file. The file is a small subset of
+  /// TOML: sections holding `name = value` lines, where a value is a
+  /// "string", a 'literal string', a number or true/false, and # starts a
+  /// comment. Every key is optional, anything not listed here is an error:
+  ///
+  /// ```toml
+  /// [auth]
+  /// key = "bearer token"
+  /// client_id = "..."
+  /// client_secret = "..."
+  /// username = "..."
+  /// password = "..."
+  /// refresh_token = "..."
+  ///
+  /// [request]
+  /// user_agent = "my-bot/1.0 (by u/me)"
+  /// base_url = "https://old.reddit.com"
+  /// oauth_url = "https://oauth.reddit.com"
+  /// proxy = "http://proxy:3128"
+  /// follow_redirects = true
+  ///
+  /// # sent with every request, replacing a default header of the same name
+  /// [headers]
+  /// Accept-Language = "en"
+  ///
+  /// # in seconds, 0 to wait forever
+  /// [timeouts]
+  /// request = 30
+  /// connect = 2.5
+  /// ```
+  pub fn apply_config_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), RedditError> {
+    let path = path.as_ref();
+    let text = match fs::read_to_string(path) {
+      Ok(text) => text,
+      Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+      Err(err) => return Err(RedditError::Config {
+        path : path.to_owned(),
+        line : None,
+        message : err.to_string(),
+      }),
+    };
+
+    let mut section = None;
+    for (number, line) in text.lines().enumerate() {
+      let fail = |message: String| RedditError::Config {
+        path : path.to_owned(),
+        line : Some(number + 1),
+        message,
+      };
+      match parse_line(line).map_err(&fail)? {
+        Line::Blank => {},
+        Line::Section(name) => section = Some(name),
+        Line::Entry(key, value) => {
+          let section = section.ok_or_else(|| fail(format!(
+            "{:?} must go under a section such as [auth] or [request]", key)))?;
+          self.apply_entry(section, &key, value).map_err(&fail)?;
+        },
+      }
+    }
+    Ok(())
+  }
+
+  fn apply_entry(&mut self, section: &str, key: &str, value: Value) -> Result<(), String> {
+    let name = format!("{}.{}", section, key);
+    match (section, key) {
+      ("auth", "key") => self.key = value.string(&name)?,
+      ("auth", "client_id") => self.client_id = value.string(&name)?,
+      ("auth", "client_secret") => self.client_secret = value.string(&name)?,
+      ("auth", "username") => self.username = value.string(&name)?,
+      ("auth", "password") => self.password = value.string(&name)?,
+      ("auth", "refresh_token") => self.refresh_token = value.string(&name)?,
+      ("request", "user_agent") => self.user_agent = Some(value.string(&name)?),
+      ("request", "base_url") => self.set_base_url(&value.string(&name)?)
+        .map_err(|err| format!("{}: {}", name, err))?,
+      ("request", "oauth_url") => self.oauth_url = value.string(&name)?,
+      ("request", "proxy") => self.proxy = Some(value.string(&name)?),
+      ("request", "follow_redirects") => self.follow_redirects = value.boolean(&name)?,
+      ("headers", _) => {
+        let header = header_pair(key, &value.string(&name)?).map_err(|err| err.to_string())?;
+        self.set_header(header);
+      },
+      ("timeouts", "request") => self.timeout = value.seconds(&name)?,
+      ("timeouts", "connect") => self.connect_timeout = value.seconds(&name)?,
+      _ => return Err(format!("unknown key {:?}", name)),
+    }
+    Ok(())
+  }
+
+  /// Sets the RUST_REDDIT_* variables found through env, skipping unset
+  /// and empty ones
+  fn apply_env(&mut self, env: &dyn Fn(&str) -> Option<String>) -> Result<(), RedditError> {
+    for &name in ENV_VARS {
+      let value = match env(name) {
+        Some(ref value) if !value.is_empty() => value.clone(),
+        _ => continue,
+      };
+      match name {
+        "RUST_REDDIT_KEY" => self.key = value,
+        "RUST_REDDIT_CLIENT_ID" => self.client_id = value,
+        "RUST_REDDIT_CLIENT_SECRET" => self.client_secret = value,
+        "RUST_REDDIT_USERNAME" => self.username = value,
+        "RUST_REDDIT_PASSWORD" => self.password = value,
+        "RUST_REDDIT_REFRESH_TOKEN" => self.refresh_token = value,
+        "RUST_REDDIT_USER_AGENT" => self.user_agent = Some(value),
+        _ => {
+          let headers = Args::parse_headers(&value).map_err(|err|
+            RedditError::InvalidArgument(format!("{}: {}", name, err)))?;
+          for header in headers {
+            self.set_header(header);
+          }
+        },
+      }
+    }
+    Ok(())
+  }
+
+  /// replaces any header of the same name, else adds it at the end
+  fn set_header(&mut self, header: (String, String)) {
+    match self.headers.iter_mut().find(|h| h.0.eq_ignore_ascii_case(&header.0)) {
+      Some(existing) => *existing = header,
+      None => self.headers.push(header),
+    }
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Parsing
+// One line of a config file at a time; TOML's multi-line strings, arrays
+// and dotted keys aren't supported, nothing in Args needs them
+
+enum Line {
+  Blank,
+  Section(&'static str),
+  Entry(String, Value),
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+  Str(String),
+  Number(f64),
+  Bool(bool),
+}
+
+impl Value {
+  fn string(self, name: &str) -> Result<String, String> {
+    match self {
+      Value::Str(s) => Ok(s),
+      other => Err(format!("{} must be a string, got {}", name, other.describe())),
+    }
+  }
+
+  fn boolean(self, name: &str) -> Result<bool, String> {
+    match self {
+      Value::Bool(b) => Ok(b),
+      other => Err(format!("{} must be true or false, got {}", name, other.describe())),
+    }
+  }
+
+  /// a number of seconds, 0 for no timeout
+  fn seconds(self, name: &str) -> Result<Option<Duration>, String> {
+    match self {
+      Value::Number(0.0) => Ok(None),
+      Value::Number(n) if n > 0.0 && n.is_finite() => Ok(Some(Duration::from_secs_f64(n))),
+      Value::Number(n) => Err(format!("{} must be a positive number of seconds, got {}", name, n)),
+      other => Err(format!("{} must be a number of seconds, got {}", name, other.describe())),
+    }
+  }
+
+  fn describe(&self) -> &'static str {
+    match *self {
+      Value::Str(_) => "a string",
+      Value::Number(_) => "a number",
+      Value::Bool(_) => "a boolean",
+    }
+  }
+}
+
+fn parse_line(line: &str) -> Result<Line, String> {
+  let line = line.trim();
+  if line.is_empty() || line.starts_with('#') {
+    return Ok(Line::Blank);
+  }
+
+  if let Some(rest) = line.strip_prefix('[') {
+    let end = rest.find(']').ok_or_else(|| format!("unclosed section header {:?}", line))?;
+    expect_comment(&rest[end + 1..])?;
+    let name = rest[..end].trim();
+    return SECTIONS.iter().find(|&&section| section == name)
+      .map(|&section| Line::Section(section))
+      .ok_or_else(|| format!("unknown section [{}], expected one of [{}]", name,
+        SECTIONS.join("], [")));
+  }
+
+  let eq = line.find('=').ok_or_else(|| format!("expected `name = value`, got {:?}", line))?;
+  let key = line[..eq].trim();
+  let bare = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+  if key.is_empty() || !key.chars().all(bare) {
+    return Err(format!("invalid key {:?}, only letters, digits, _ and - are allowed", key));
+  }
+  Ok(Line::Entry(key.to_owned(), parse_value(line[eq + 1..].trim(), key)?))
+}
+
+fn parse_value(text: &str, key: &str) -> Result<Value, String> {
+  let (value, rest) = match text.chars().next() {
+    Some('"') => parse_basic_string(&text[1..], key)?,
+    Some('\'') => {
+      let end = text[1..].find('\'')
+        .ok_or_else(|| format!("unterminated string for {:?}", key))?;
+      (Value::Str(text[1..end + 1].to_owned()), &text[end + 2..])
+    },
+    _ => {
+      let end = text.find('#').unwrap_or(text.len());
+      let token = text[..end].trim();
+      let value = match token {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        "" => return Err(format!("{:?} has no value", key)),
+        _ => token.replace('_', "").parse().map(Value::Number).map_err(|_| format!(
+          "invalid value {:?} for {:?}, strings need quotes", token, key))?,
+      };
+      (value, &text[end..])
+    },
+  };
+  expect_comment(rest)?;
+  Ok(value)
+}
+
+/// a "string" with TOML's escapes, from just after the opening quote;
+/// hands back what follows the closing one
+fn parse_basic_string<'a>(text: &'a str, key: &str) -> Result<(Value, &'a str), String> {
+  let mut out = String::new();
+  let mut chars = text.char_indices();
+  while let Some((i, c)) = chars.next() {
+    match c {
+      '"' => return Ok((Value::Str(out), &text[i + 1..])),
+      '\\' => {
+        let escaped = match chars.next().map(|(_, c)| c) {
+          Some('"') => '"',
+          Some('\\') => '\\',
+          Some('n') => '\n',
+          Some('t') => '\t',
+          Some('r') => '\r',
+          Some(u @ 'u') | Some(u @ 'U') => {
+            let len = if u == 'u' { 4 } else { 8 };
+            let hex: String = chars.by_ref().take(len).map(|(_, c)| c).collect();
+            u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32)
+              .filter(|_| hex.len() == len)
+              .ok_or_else(|| format!("invalid \\{} escape in {:?}", u, key))?
+          },
+          other => return Err(format!("invalid escape \\{} in {:?}",
+            other.map(String::from).unwrap_or_default(), key)),
+        };
+        out.push(escaped);
+      },
+      c => out.push(c),
+    }
+  }
+  Err(format!("unterminated string for {:?}", key))
+}
+
+fn expect_comment(rest: &str) -> Result<(), String> {
+  let rest = rest.trim();
+  if rest.is_empty() || rest.starts_with('#') {
+    Ok(())
+  } else {
+    Err(format!("unexpected {:?} after the value", rest))
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_file {
+
+  use std::collections::HashMap;
+  use std::fs;
+  use std::path::{Path, PathBuf};
+  use std::process;
+  use std::time::Duration;
+  use config::{Args, DEFAULT_USER_AGENT};
+  use error::RedditError;
+  use super::config_path_from;
+
+  /// a config file under the system temp dir, removed when dropped
+  struct TempConfig(PathBuf);
+
+  impl TempConfig {
+    fn new(name: &str, contents: &str) -> TempConfig {
+      let dir = ::std::env::temp_dir().join(format!("rust_reddit-{}-{}", process::id(), name));
+      fs::create_dir_all(&dir).unwrap();
+      let path = dir.join("config.toml");
+      fs::write(&path, contents).unwrap();
+      TempConfig(path)
+    }
+  }
+
+  impl Drop for TempConfig {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(self.0.parent().unwrap());
+    }
+  }
+
+  fn env_of(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> = vars.iter()
+      .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+      .collect();
+    move |name| vars.get(name).cloned()
+  }
+
+  const CONFIG: &str = r#"
+# credentials for the bot
+[auth]
+key = "file-key"
+client_id = 'file-id'   # a literal string
+client_secret = "s3cr\"t"
+
+[request]
+user_agent = "file-agent/1.0"
+base_url = "https://old.reddit.com/"
+follow_redirects = false
+
+[headers]
+User-Agent = "header-agent"
+Accept-Language = "en"
+
+[timeouts]
+request = 2.5
+connect = 0
+"#;
+
+  #[test]
+  fn test_load_reads_every_section() {
+    let config = TempConfig::new("sections", CONFIG);
+    let args = Args::load_with(&config.0, &env_of(&[])).unwrap();
+
+    assert_eq!(args.key, "file-key");
+    assert_eq!(args.client_id, "file-id");
+    assert_eq!(args.client_secret, "s3cr\"t");
+    assert_eq!(args.user_agent.as_deref(), Some("file-agent/1.0"));
+    assert_eq!(args.base_url, "https://old.reddit.com");
+    assert!(!args.follow_redirects);
+    assert_eq!(args.headers, [
+      ("User-Agent".to_owned(), "header-agent".to_owned()),
+      ("Accept-Language".to_owned(), "en".to_owned()),
+    ]);
+    assert_eq!(args.timeout, Some(Duration::from_millis(2500)));
+    assert_eq!(args.connect_timeout, None);
+    // what the file leaves out keeps its default
+    assert_eq!(args.oauth_url, Args::default().oauth_url);
+  }
+
+  #[test]
+  fn test_env_overrides_file_overrides_defaults() {
+    let config = TempConfig::new("precedence", CONFIG);
+    let env = env_of(&[
+      ("RUST_REDDIT_KEY", "env-key"),
+      ("RUST_REDDIT_USER_AGENT", "env-agent/2.0"),
+      ("RUST_REDDIT_CLIENT_ID", ""),
+      ("RUST_REDDIT_HEADERS", "Accept-Language: de,X-Trace: 1"),
+    ]);
+    let args = Args::load_with(&config.0, &env).unwrap();
+
+    assert_eq!(args.key, "env-key");
+    assert_eq!(args.user_agent.as_deref(), Some("env-agent/2.0"));
+    // an empty variable doesn't clear what the file set
+    assert_eq!(args.client_id, "file-id");
+    assert_eq!(args.headers, [
+      ("User-Agent".to_owned(), "header-agent".to_owned()),
+      ("Accept-Language".to_owned(), "de".to_owned()),
+      ("X-Trace".to_owned(), "1".to_owned()),
+    ]);
+
+    let args = Args::load_with(Path::new("/nonexistent/rust_reddit/config.toml"), &env).unwrap();
+    assert_eq!(args.key, "env-key");
+    assert_eq!(args.client_secret, "");
+    assert_eq!(args.headers[0], ("User-Agent".to_owned(), DEFAULT_USER_AGENT.to_owned()));
+  }
+
+  #[test]
+  fn test_missing_file_is_defaults() {
+    let config = TempConfig::new("missing", "");
+    let missing = config.0.with_file_name("absent.toml");
+
+    let args = Args::load_with(&missing, &env_of(&[])).unwrap();
+    assert_eq!(format!("{:?}", args), format!("{:?}", Args::default()));
+    let args = Args::load_with(&config.0, &env_of(&[])).unwrap();
+    assert_eq!(format!("{:?}", args), format!("{:?}", Args::default()));
+  }
+
+  #[test]
+  fn test_errors_name_file_line_and_key() {
+    let error_for = |name: &str, contents: &str| {
+      let config = TempConfig::new(name, contents);
+      let err = Args::load_with(&config.0, &env_of(&[])).unwrap_err();
+      match err {
+        RedditError::Config { ref path, line, .. } => {
+          assert_eq!(path, &config.0);
+          assert!(line.is_some());
+        },
+        ref other => panic!("{:?}", other),
+      }
+      let message = err.to_string();
+      assert!(message.starts_with(&config.0.display().to_string()), "{}", message);
+      message
+    };
+
+    let message = error_for("unknown-key", "[auth]\nkey = \"k\"\ntiemout = 3\n");
+    assert!(message.ends_with(":3: unknown key \"auth.tiemout\""), "{}", message);
+
+    let message = error_for("unknown-section", "[oauth]\n");
+    assert!(message.contains(":1: unknown section [oauth]"), "{}", message);
+
+    let message = error_for("wrong-type", "[timeouts]\nrequest = \"30\"\n");
+    assert!(message.contains(":2: timeouts.request must be a number of seconds"), "{}", message);
+
+    let message = error_for("unquoted", "[auth]\nkey = abc\n");
+    assert!(message.contains(":2: invalid value \"abc\" for \"key\""), "{}", message);
+
+    let message = error_for("unterminated", "[auth]\n\nkey = \"abc\n");
+    assert!(message.contains(":3: unterminated string for \"key\""), "{}", message);
+
+    let message = error_for("no-section", "key = \"abc\"\n");
+    assert!(message.contains(":1: \"key\" must go under a section"), "{}", message);
+
+    let message = error_for("bad-header", "[headers]\nX-Bad = \"a\\nb\"\n");
+    assert!(message.contains(":2: invalid argument: line break in header X-Bad"), "{}", message);
+
+    error_for("trailing", "[auth]\nkey = \"abc\" extra\n");
+    error_for("bad-escape", "[auth]\nkey = \"\\q\"\n");
+    error_for("negative", "[timeouts]\nconnect = -1\n");
+  }
+
+  #[test]
+  fn test_config_path() {
+    assert_eq!(config_path_from(&env_of(&[("HOME", "/home/ferris")])),
+      Some(PathBuf::from("/home/ferris/.config/rust_reddit/config.toml")));
+    assert_eq!(config_path_from(&env_of(&[("HOME", "/home/ferris"),
+      ("XDG_CONFIG_HOME", "/xdg")])), Some(PathBuf::from("/xdg/rust_reddit/config.toml")));
+    assert_eq!(config_path_from(&env_of(&[("HOME", "/home/ferris"),
+      ("RUST_REDDIT_CONFIG", "/etc/bot.toml")])), Some(PathBuf::from("/etc/bot.toml")));
+    assert_eq!(config_path_from(&env_of(&[])), None);
+  }
+
+  #[test]
+  fn test_unicode_escapes() {
+    let config = TempConfig::new("escapes", "[auth]\nusername = \"f\\u00e9rris\\t\"\n");
+    let args = Args::load_with(&config.0, &env_of(&[])).unwrap();
+    assert_eq!(args.username, "férris\t");
+  }
+}
@@ -0,0 +1,120 @@
+
+// external imports
+use std::fs;
+use std::io;
+use serde_json;
+
+// internal imports
+use api::{query_many, validate_subreddit_list};
+use config::Args;
+use error::RedditError;
+use models::{Listing, Post};
+
+////////////////////////////////////////////////////////////////////////////////
+// Digest
+// The top listing of a list of subreddits, fetched in one batch and
+// grouped per subreddit
+
+/// One subreddit's part of a digest; a subreddit that failed carries its
+/// error instead of posts, the rest of the digest is unaffected
+#[derive(Debug)]
+pub struct DigestSection {
+  pub subreddit : String,
+  pub posts : Result<Vec<Post>, RedditError>,
+}
+
+/// Reads newline separated subreddit names, see parse_subreddit_list
+pub fn read_subreddits_file(path: &str) -> io::Result<Vec<String>> {
+  Ok(parse_subreddit_list(&fs::read_to_string(path)?))
+}
+
+/// Splits a subreddit list into names, skipping blank lines and # comments
+pub fn parse_subreddit_list(contents: &str) -> Vec<String> {
+  contents.lines()
+    .map(|line| line.trim())
+    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    .map(|line| line.to_owned())
+    .collect()
+}
+
+/// Fetches the top listing of every subreddit concurrently; only a failure
+/// to set up the batch itself aborts the digest
+pub fn fetch_digest(subreddits: &[String], args: Args)
+  -> Result<Vec<DigestSection>, RedditError>
+{
+  let (valid, invalid): (Vec<&String>, Vec<&String>) = subreddits.iter()
+    .partition(|name| validate_subreddit_list(name).is_ok());
+
+  let paths: Vec<String> = valid.iter()
+    .map(|name| format!("/r/{}/top/.json", name))
+    .collect();
+  let paths: Vec<&str> = paths.iter().map(|path| path.as_str()).collect();
+  let mut results = query_many(&paths, args)?.into_iter();
+
+  Ok(subreddits.iter()
+    .map(|name| {
+      let posts = if invalid.contains(&name) {
+        Err(RedditError::InvalidName(name.clone()))
+      } else {
+        results.next()
+          .unwrap_or(Err(RedditError::Aborted))
+          .and_then(|value| Ok(serde_json::from_value::<Listing<Post>>(value)?.children))
+      };
+      DigestSection { subreddit : name.clone(), posts }
+    })
+    .collect())
+}
+
+/// Renders a digest as plain text, one block per subreddit
+pub fn format_digest(sections: &[DigestSection]) -> String {
+  let mut out = String::new();
+  for section in sections {
+    out.push_str(&format!("r/{}\n", section.subreddit));
+    match section.posts {
+      Ok(ref posts) if posts.is_empty() => out.push_str("  (no posts)\n"),
+      Ok(ref posts) => for post in posts {
+        out.push_str(&format!("  {:>6}  {}\n", post.score, post.title));
+      },
+      Err(ref err) => out.push_str(&format!("  error: {}\n", err)),
+    }
+    out.push('\n');
+  }
+  out
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_digest {
+
+  use digest::*;
+  use error::RedditError;
+  use mock::{MockServer, MockResponse};
+
+  #[test]
+  fn test_parse_subreddit_list() {
+    let names = parse_subreddit_list("rust\n\n# languages\n  golang  \n#haskell\n");
+    assert_eq!(names, vec!["rust", "golang"]);
+  }
+
+  #[test]
+  fn test_digest_reports_failures_individually() {
+    let page = r#"{"kind": "Listing", "data": {"children": [
+      {"kind": "t3", "data": {"title": "top post", "score": 42}}]}}"#;
+    let server = MockServer::start(vec![MockResponse::json(page), MockResponse::json(page)]);
+    let args = server.args();
+
+    let subreddits: Vec<String> = vec!["rust".to_owned(), "not a sub".to_owned(),
+      "golang".to_owned()];
+    let digest = fetch_digest(&subreddits, args).unwrap();
+
+    assert_eq!(digest.len(), 3);
+    assert_eq!(digest[0].posts.as_ref().unwrap()[0].title, "top post");
+    assert!(matches!(digest[1].posts, Err(RedditError::InvalidName(_))));
+    assert_eq!(digest[2].posts.as_ref().unwrap().len(), 1);
+
+    let text = format_digest(&digest);
+    assert!(text.contains("r/rust\n      42  top post\n"));
+    assert!(text.contains("r/not a sub\n  error: invalid name"));
+    assert_eq!(server.requests().len(), 2);
+  }
+}
@@ -0,0 +1,510 @@
+
+// external imports
+use std::fmt;
+use std::error::Error;
+use std::io;
+use std::path::PathBuf;
+use std::str::Utf8Error;
+use std::time::Duration;
+use curl;
+use serde_json;
+
+////////////////////////////////////////////////////////////////////////////////
+/// RedditError
+/// Everything that can go wrong between building a request and handing
+/// back parsed data
+///
+/// The common transport failures get their own variants so callers can pick
+/// a retry policy per class, e.g. retry a Timeout but not a mistyped host;
+/// any other curl failure lands in RedditError::Curl
+#[derive(Debug)]
+pub enum RedditError {
+  CouldNotResolveHost(curl::Error),
+  CouldNotConnect(curl::Error),
+  NetworkUnreachable(curl::Error),
+  Timeout(curl::Error),
+  /// the connection broke while sending or receiving (CURLE_SEND_ERROR,
+  /// CURLE_RECV_ERROR), which reddit does to some connections under load
+  ConnectionReset(curl::Error),
+  Curl(curl::Error),
+  Multi(curl::MultiError),
+  Utf8(Utf8Error),
+  Io(io::Error),
+  Json { source: serde_json::Error, snippet: Option<String> },
+  Http { status: u32, body: String },
+  /// reddit won't show the page and said why, e.g. a 403 with
+  /// {"reason": "private", "message": "Forbidden", "error": 403}
+  Restricted { status: u32, reason: Restriction, message: String },
+  /// a 429 or 5xx that came with a Retry-After header, in seconds
+  RetryAfter { status: u32, after: Duration, body: String },
+  AuthRequired,
+  /// the token endpoint turned the credentials down, e.g. "invalid_grant"
+  /// for a wrong password or a suspended account
+  AuthFailed { reason: String },
+  InsecureTransport { url: String },
+  MissingScope { needed: String },
+  InvalidName(String),
+  InvalidArgument(String),
+  /// a config file Args::load could not make sense of, with the line the
+  /// problem is on when there is one
+  Config { path: PathBuf, line: Option<usize>, message: String },
+  InvalidSort(String),
+  InvalidTimeRange(String),
+  Aborted,
+  Cancelled,
+  NotFound,
+  /// reddit answers alike for an account that never existed and one that
+  /// was suspended or shadowbanned: a 404 with a json body
+  UserNotFound(String),
+  MissingField(String),
+  UnexpectedContentType { got: String },
+  UnexpectedShape { expected: &'static str, got: &'static str },
+  Blocked { reason: String },
+  Api(Vec<ApiError>),
+  /// another error, tagged with the request it came from
+  Request { method: String, url: String, source: Box<RedditError> },
+}
+
+/// Why reddit refuses to show a subreddit, from the "reason" of its error
+/// body. Reasons this crate doesn't know yet are kept as Other.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Restriction {
+  Private,
+  Banned,
+  Quarantined,
+  GoldOnly,
+  Other(String),
+}
+
+impl Restriction {
+  pub fn from_reason(reason: &str) -> Restriction {
+    match reason {
+      "private" => Restriction::Private,
+      "banned" => Restriction::Banned,
+      "quarantined" => Restriction::Quarantined,
+      "gold_only" => Restriction::GoldOnly,
+      other => Restriction::Other(other.to_owned()),
+    }
+  }
+}
+
+impl fmt::Display for Restriction {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Restriction::Private => write!(f, "private"),
+      Restriction::Banned => write!(f, "banned"),
+      Restriction::Quarantined => write!(f, "quarantined"),
+      Restriction::GoldOnly => write!(f, "only open to premium members"),
+      Restriction::Other(ref reason) => write!(f, "{}", reason),
+    }
+  }
+}
+
+/// One entry of the `json.errors` array reddit's write endpoints return,
+/// e.g. ["RATELIMIT", "you are doing that too much", "ratelimit"]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiError {
+  pub code : String,
+  pub message : String,
+  pub field : Option<String>,
+}
+
+impl fmt::Display for ApiError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.field {
+      Some(ref field) => write!(f, "{}: {} ({})", self.code, self.message, field),
+      None => write!(f, "{}: {}", self.code, self.message),
+    }
+  }
+}
+
+impl fmt::Display for RedditError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      RedditError::CouldNotResolveHost(ref err) =>
+        write!(f, "could not resolve host: {}", err),
+      RedditError::CouldNotConnect(ref err) =>
+        write!(f, "could not connect: {}", err),
+      RedditError::NetworkUnreachable(ref err) =>
+        write!(f, "network unreachable: {}", err),
+      RedditError::Timeout(ref err) => write!(f, "request timed out: {}", err),
+      RedditError::ConnectionReset(ref err) => write!(f, "connection reset: {}", err),
+      RedditError::Curl(ref err) => write!(f, "curl error: {}", err),
+      RedditError::Multi(ref err) => write!(f, "curl multi error: {}", err),
+      RedditError::Utf8(ref err) => write!(f, "invalid utf-8 in response: {}", err),
+      RedditError::Io(ref err) => write!(f, "i/o error: {}", err),
+      RedditError::Json { ref source, snippet: Some(ref snippet) } =>
+        write!(f, "could not parse response: {} near `{}`", source, snippet),
+      RedditError::Json { ref source, snippet: None } =>
+        write!(f, "could not parse response: {}", source),
+      RedditError::Http { status, ref body } => match reddit_message(body) {
+        Some(message) => write!(f, "reddit responded with http {} ({})", status, message),
+        None => write!(f, "reddit responded with http {}", status),
+      },
+      RedditError::Restricted { status, ref reason, ref message } =>
+        write!(f, "reddit responded with http {} ({}): {}", status, message, reason),
+      RedditError::RetryAfter { status, after, .. } =>
+        write!(f, "reddit responded with http {}, retry after {:?}", status, after),
+      RedditError::AuthRequired =>
+        write!(f, "this endpoint requires authentication, set args.key"),
+      RedditError::AuthFailed { ref reason } => write!(f, "authentication failed: {}", reason),
+      RedditError::InsecureTransport { ref url } =>
+        write!(f, "refusing to send credentials over plain http to {}, use https or set \
+          args.danger_allow_insecure", url),
+      RedditError::MissingScope { ref needed } =>
+        write!(f, "the access token was not granted the {:?} scope", needed),
+      RedditError::InvalidName(ref name) => write!(f, "invalid name: {:?}", name),
+      RedditError::InvalidArgument(ref why) => write!(f, "invalid argument: {}", why),
+      RedditError::Config { ref path, line : Some(line), ref message } =>
+        write!(f, "{}:{}: {}", path.display(), line, message),
+      RedditError::Config { ref path, line : None, ref message } =>
+        write!(f, "{}: {}", path.display(), message),
+      RedditError::InvalidSort(ref sort) => write!(f, "unknown listing sort: {:?}", sort),
+      RedditError::InvalidTimeRange(ref range) => write!(f, "unknown time range: {:?}", range),
+      RedditError::Aborted => write!(f, "request was aborted before it completed"),
+      RedditError::Cancelled => write!(f, "request was cancelled through args.cancel"),
+      RedditError::NotFound => write!(f, "reddit has nothing at that location"),
+      RedditError::UserNotFound(ref name) =>
+        write!(f, "no user {:?}, or the account is suspended or shadowbanned", name),
+      RedditError::UnexpectedContentType { ref got } =>
+        write!(f, "expected a json response, got content type {:?}", got),
+      RedditError::UnexpectedShape { expected, got } =>
+        write!(f, "expected a json {}, got a json {}", expected, got),
+      RedditError::Blocked { ref reason } =>
+        write!(f, "request was blocked before reaching reddit ({}), try a more \
+          descriptive User-Agent or slowing down", reason),
+      RedditError::MissingField(ref pointer) =>
+        write!(f, "response has no value at {:?}", pointer),
+      RedditError::Api(ref errors) => {
+        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        write!(f, "reddit rejected the request: {}", errors.join(", "))
+      },
+      RedditError::Request { ref method, ref url, ref source } =>
+        write!(f, "{} {}: {}", method, url, source),
+    }
+  }
+}
+
+impl Error for RedditError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match *self {
+      RedditError::CouldNotResolveHost(ref err)
+        | RedditError::CouldNotConnect(ref err)
+        | RedditError::NetworkUnreachable(ref err)
+        | RedditError::Timeout(ref err)
+        | RedditError::ConnectionReset(ref err)
+        | RedditError::Curl(ref err) => Some(err),
+      RedditError::Multi(ref err) => Some(err),
+      RedditError::Utf8(ref err) => Some(err),
+      RedditError::Io(ref err) => Some(err),
+      RedditError::Json { ref source, .. } => Some(source),
+      RedditError::Request { ref source, .. } => Some(&**source),
+      _ => None,
+    }
+  }
+}
+
+impl RedditError {
+  /// shorthand for a RedditError::Http, handy in tests and mocks
+  pub fn http(status: u32, body: &str) -> RedditError {
+    RedditError::Http { status, body : body.to_owned() }
+  }
+
+  /// The error for a response that wasn't a 2xx: RedditError::Restricted
+  /// when the body is reddit's {"reason": .., "message": ..} shape, else
+  /// RedditError::Http with the body kept as is
+  pub fn from_status(status: u32, body: String) -> RedditError {
+    let parsed = serde_json::from_str::<serde_json::Value>(&body).ok();
+    let reason = parsed.as_ref().and_then(|value| value["reason"].as_str());
+    match reason {
+      Some(reason) => RedditError::Restricted {
+        status,
+        reason : Restriction::from_reason(reason),
+        message : parsed.as_ref()
+          .and_then(|value| value["message"].as_str())
+          .unwrap_or_default()
+          .to_owned(),
+      },
+      None => RedditError::Http { status, body },
+    }
+  }
+
+  /// wraps the error in RedditError::Request, unless it already is one
+  pub fn for_request(self, method: &str, url: &str) -> RedditError {
+    match self {
+      RedditError::Request { .. } => self,
+      other => RedditError::Request {
+        method : method.to_owned(),
+        url : url.to_owned(),
+        source : Box::new(other),
+      },
+    }
+  }
+
+  /// the error itself, with any RedditError::Request context taken off;
+  /// match on this to tell failures apart
+  pub fn root(&self) -> &RedditError {
+    match *self {
+      RedditError::Request { ref source, .. } => source.root(),
+      ref other => other,
+    }
+  }
+
+  /// the http status reddit answered with, when the error carries one
+  pub fn status_code(&self) -> Option<u16> {
+    match *self.root() {
+      RedditError::Http { status, .. }
+        | RedditError::RetryAfter { status, .. }
+        | RedditError::Restricted { status, .. } => Some(status as u16),
+      _ => None,
+    }
+  }
+
+  /// how long reddit asked to be left alone through Retry-After
+  pub fn retry_after(&self) -> Option<Duration> {
+    match *self.root() {
+      RedditError::RetryAfter { after, .. } => Some(after),
+      _ => None,
+    }
+  }
+
+  /// whether reddit turned the request away for going too fast, either
+  /// with a 429 or a RATELIMIT entry in json.errors
+  pub fn is_rate_limited(&self) -> bool {
+    match *self.root() {
+      RedditError::Http { status: 429, .. } | RedditError::RetryAfter { status: 429, .. } => true,
+      RedditError::Api(ref errors) => errors.iter().any(|e| e.code == "RATELIMIT"),
+      _ => false,
+    }
+  }
+
+  /// Whether trying again later could reasonably succeed: timeouts,
+  /// connection failures and resets, and 5xx responses. Rate limiting is
+  /// reported separately by is_rate_limited. A reset write may already
+  /// have reached reddit, so only reads are safe to repeat blindly.
+  pub fn is_transient(&self) -> bool {
+    match *self.root() {
+      RedditError::Timeout(_)
+        | RedditError::ConnectionReset(_)
+        | RedditError::CouldNotConnect(_)
+        | RedditError::NetworkUnreachable(_) => true,
+      RedditError::Http { status, .. } | RedditError::RetryAfter { status, .. } =>
+        (500..600).contains(&status),
+      _ => false,
+    }
+  }
+}
+
+/// the "message" of reddit's {"message": "Not Found", "error": 404} bodies
+fn reddit_message(body: &str) -> Option<String> {
+  let value = serde_json::from_str::<serde_json::Value>(body).ok()?;
+  value.get("error")?;
+  value["message"].as_str().map(|message| message.to_owned())
+}
+
+impl From<curl::Error> for RedditError {
+  fn from(err: curl::Error) -> RedditError {
+    if err.is_couldnt_resolve_host() {
+      RedditError::CouldNotResolveHost(err)
+    } else if err.is_couldnt_connect() && is_unreachable(&err) {
+      RedditError::NetworkUnreachable(err)
+    } else if err.is_couldnt_connect() {
+      RedditError::CouldNotConnect(err)
+    } else if err.is_operation_timedout() {
+      RedditError::Timeout(err)
+    } else if err.is_recv_error() || err.is_send_error() {
+      RedditError::ConnectionReset(err)
+    } else {
+      RedditError::Curl(err)
+    }
+  }
+}
+
+/// curl reports ENETUNREACH/EHOSTUNREACH as a plain connect failure, the
+/// only trace of the cause is in the extra error text
+fn is_unreachable(err: &curl::Error) -> bool {
+  match err.extra_description() {
+    Some(extra) => {
+      let extra = extra.to_lowercase();
+      extra.contains("unreachable") || extra.contains("no route to host")
+    },
+    None => false,
+  }
+}
+
+impl From<curl::MultiError> for RedditError {
+  fn from(err: curl::MultiError) -> RedditError {
+    RedditError::Multi(err)
+  }
+}
+
+impl From<io::Error> for RedditError {
+  fn from(err: io::Error) -> RedditError {
+    RedditError::Io(err)
+  }
+}
+
+impl From<Utf8Error> for RedditError {
+  fn from(err: Utf8Error) -> RedditError {
+    RedditError::Utf8(err)
+  }
+}
+
+impl From<serde_json::Error> for RedditError {
+  fn from(err: serde_json::Error) -> RedditError {
+    RedditError::Json { source : err, snippet : None }
+  }
+}
+
+/// how many characters either side of a parse error end up in the snippet
+const SNIPPET_RADIUS: usize = 40;
+
+impl RedditError {
+  /// Wraps a parse failure together with the part of the body around the
+  /// line and column serde gave up at, since whole bodies are far too large
+  /// to read in an error message
+  pub fn json_with_body(err: serde_json::Error, body: &str) -> RedditError {
+    let snippet = body.lines()
+      .nth(err.line().saturating_sub(1))
+      .map(|line| {
+        let chars: Vec<char> = line.chars().collect();
+        let at = err.column().saturating_sub(1).min(chars.len());
+        let start = at.saturating_sub(SNIPPET_RADIUS);
+        let end = (at + SNIPPET_RADIUS).min(chars.len());
+        chars[start..end].iter().collect::<String>()
+      });
+
+    RedditError::Json { source : err, snippet }
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_error {
+
+  use error::RedditError;
+  use serde_json;
+
+  #[test]
+  fn test_json_error_display() {
+    let err: RedditError = serde_json::from_str::<serde_json::Value>("{")
+      .unwrap_err()
+      .into();
+
+    let actual = format!("{}", err);
+    assert!(actual.starts_with("could not parse response"));
+  }
+
+  #[test]
+  fn test_request_context_keeps_classification() {
+    let err = RedditError::http(503, "busy")
+      .for_request("GET", "https://www.reddit.com/r/rust/.json")
+      .for_request("GET", "https://elsewhere");
+    assert!(matches!(err, RedditError::Request { ref url, .. } if url.contains("reddit.com")));
+    assert!(matches!(*err.root(), RedditError::Http { status: 503, .. }));
+    assert_eq!(err.status_code(), Some(503));
+    assert!(err.is_transient());
+  }
+
+  #[test]
+  fn test_curl_error_mapping() {
+    use curl;
+
+    let mapped: RedditError = curl::Error::new(6).into();
+    assert!(matches!(mapped, RedditError::CouldNotResolveHost(_)));
+
+    let mapped: RedditError = curl::Error::new(7).into();
+    assert!(matches!(mapped, RedditError::CouldNotConnect(_)));
+
+    let mut unreachable = curl::Error::new(7);
+    unreachable.set_extra(
+      "Failed to connect to www.reddit.com port 443: Network is unreachable".to_owned());
+    let mapped: RedditError = unreachable.into();
+    assert!(matches!(mapped, RedditError::NetworkUnreachable(_)));
+
+    let mapped: RedditError = curl::Error::new(28).into();
+    assert!(matches!(mapped, RedditError::Timeout(_)));
+
+    for code in &[55, 56] {
+      let mapped: RedditError = curl::Error::new(*code).into();
+      assert!(matches!(mapped, RedditError::ConnectionReset(_)));
+      assert!(mapped.is_transient());
+    }
+
+    // CURLE_SSL_CONNECT_ERROR has no dedicated variant
+    let mapped: RedditError = curl::Error::new(35).into();
+    assert!(matches!(mapped, RedditError::Curl(_)));
+  }
+
+  #[test]
+  fn test_json_error_includes_body_snippet() {
+    let body = format!(
+      "{{\"kind\": \"Listing\",\n\"data\": {}<html>oops</html>{}}}",
+      "x".repeat(100), "y".repeat(100));
+    let err = serde_json::from_str::<serde_json::Value>(&body).unwrap_err();
+    let err = RedditError::json_with_body(err, &body);
+
+    let actual = format!("{}", err);
+    println!("{}", actual);
+    assert!(actual.starts_with("could not parse response"));
+    assert!(actual.contains("near `"));
+    assert!(actual.contains("xxxx"));
+    // the snippet is a window, not the whole body
+    assert!(!actual.contains(&"x".repeat(100)));
+  }
+
+  #[test]
+  fn test_error_helpers() {
+    use curl;
+    use error::ApiError;
+    use std::error::Error;
+
+    let server_error = RedditError::http(503, "");
+    assert_eq!(server_error.status_code(), Some(503));
+    assert!(server_error.is_transient());
+    assert!(!server_error.is_rate_limited());
+
+    let too_fast = RedditError::http(429, "");
+    assert!(too_fast.is_rate_limited());
+    assert!(!too_fast.is_transient());
+
+    let not_found = RedditError::http(404, "");
+    assert!(!not_found.is_transient());
+
+    let ratelimit = RedditError::Api(vec![ApiError {
+      code : "RATELIMIT".to_owned(),
+      message : "you are doing that too much".to_owned(),
+      field : None,
+    }]);
+    assert!(ratelimit.is_rate_limited());
+    assert_eq!(ratelimit.status_code(), None);
+
+    let timeout: RedditError = curl::Error::new(28).into();
+    assert!(timeout.is_transient());
+    assert!(timeout.source().is_some());
+
+    let dns: RedditError = curl::Error::new(6).into();
+    assert!(!dns.is_transient());
+
+    assert!(RedditError::AuthRequired.source().is_none());
+  }
+
+  #[test]
+  fn test_from_status() {
+    use error::Restriction;
+
+    let err = RedditError::from_status(403, r#"{"reason": "gold_only", "message": "Forbidden"}"#
+      .to_owned());
+    assert!(matches!(err, RedditError::Restricted { reason : Restriction::GoldOnly, .. }));
+    let err = RedditError::from_status(403, r#"{"reason": "gated", "message": "Forbidden"}"#
+      .to_owned());
+    assert!(matches!(err,
+      RedditError::Restricted { reason : Restriction::Other(ref r), .. } if r == "gated"));
+    assert_eq!(err.to_string(), "reddit responded with http 403 (Forbidden): gated");
+
+    let html = RedditError::from_status(500, "<html>oops</html>".to_owned());
+    assert!(matches!(html, RedditError::Http { status : 500, .. }));
+    assert_eq!(html.to_string(), "reddit responded with http 500");
+  }
+
+}
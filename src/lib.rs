@@ -1,11 +1,11 @@
 //! `rust_reddit` is a library that supports api calls to reddit via rust code.
 //!
-//!	This repository is in development.
+//! This repository is in development.
 //!
 //! ---
 //! # Examples
 //!
-//! ```
+//! ```no_run
 //! // simple use of the macro "rquery"
 //! #[macro_use]
 //! extern crate rust_reddit;
@@ -24,14 +24,31 @@
 
 
 extern crate curl;
+#[cfg(feature = "cli")]
 extern crate argparse;
 extern crate serde;
 extern crate serde_json;
 
 #[macro_use] 
 extern crate serde_derive;
+#[macro_use]
+extern crate log;
 
+pub mod error;
+pub mod models;
 #[macro_use]
 pub mod api;
+pub mod config;
+#[cfg(feature = "cli")]
 pub mod cli;
+pub mod cache;
+pub mod clock;
+pub mod client;
+pub mod digest;
+pub mod middleware;
+pub mod output;
+pub mod transport;
+
+#[cfg(test)]
+mod mock;
 
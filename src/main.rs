@@ -0,0 +1,37 @@
+extern crate rust_reddit;
+
+use std::process;
+use rust_reddit::{cli, digest, output};
+use rust_reddit::cli::Command;
+
+fn main() {
+  let cli::CommandLine { command, args, verbosity } = cli::get_command_line();
+  cli::init_logger(verbosity);
+
+  if command != Command::Digest {
+    let format = args.format;
+    match command.run(args) {
+      Ok(value) => println!("{}", output::render(&value, format)),
+      Err(err) => {
+        eprintln!("rust_reddit: {}", err);
+        process::exit(1);
+      },
+    }
+    return;
+  }
+
+  if let Some(path) = args.subreddits_file.clone() {
+    let subreddits = digest::read_subreddits_file(&path).unwrap_or_else(|err| {
+      eprintln!("could not read {}: {}", path, err);
+      process::exit(1);
+    });
+
+    match digest::fetch_digest(&subreddits, args) {
+      Ok(sections) => print!("{}", digest::format_digest(&sections)),
+      Err(err) => {
+        eprintln!("could not fetch digest: {}", err);
+        process::exit(1);
+      },
+    }
+  }
+}
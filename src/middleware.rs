@@ -0,0 +1,98 @@
+
+// external imports
+use std::time::Instant;
+
+// internal imports
+use error::RedditError;
+use transport::{PreparedRequest, RawResponse};
+
+/// The rest of the chain from a middleware's point of view: the middleware
+/// after it, or the transport once the stack runs out
+pub type Next<'a> = &'a dyn Fn(PreparedRequest) -> Result<RawResponse, RedditError>;
+
+////////////////////////////////////////////////////////////////////////////////
+/// Middleware
+/// Wraps every request a RedditClient sends over its transport. A
+/// middleware may change the request before passing it on through `next`,
+/// change the response on the way back, or answer by itself without
+/// calling `next` at all.
+pub trait Middleware {
+  fn handle(&self, req: PreparedRequest, next: Next) -> Result<RawResponse, RedditError>;
+}
+
+/// Runs `req` through `stack`, first entry outermost, ending in `terminal`
+pub fn run_stack(stack: &[Box<dyn Middleware>], req: PreparedRequest, terminal: Next)
+  -> Result<RawResponse, RedditError>
+{
+  match stack.split_first() {
+    Some((first, rest)) => first.handle(req, &|req| run_stack(rest, req, terminal)),
+    None => terminal(req),
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// LoggingMiddleware
+/// Logs one line per request at info level: method, url, status (or
+/// error) and how long it took; nothing shows without a logger installed
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoggingMiddleware;
+
+impl Middleware for LoggingMiddleware {
+  fn handle(&self, req: PreparedRequest, next: Next) -> Result<RawResponse, RedditError> {
+    let line = format!("{} {}", req.method, req.url);
+    let started = Instant::now();
+    let result = next(req);
+    match result {
+      Ok(ref response) => info!("{} -> {} in {:?}", line, response.status, started.elapsed()),
+      Err(ref err) => info!("{} -> {} in {:?}", line, err, started.elapsed()),
+    }
+    result
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_middleware {
+
+  use error::RedditError;
+  use middleware::*;
+  use transport::{PreparedRequest, RawResponse};
+
+  struct Tag(&'static str);
+
+  impl Middleware for Tag {
+    fn handle(&self, mut req: PreparedRequest, next: Next) -> Result<RawResponse, RedditError> {
+      req.headers.push(("X-Order".to_owned(), self.0.to_owned()));
+      next(req)
+    }
+  }
+
+  struct Canned;
+
+  impl Middleware for Canned {
+    fn handle(&self, _: PreparedRequest, _: Next) -> Result<RawResponse, RedditError> {
+      Ok(RawResponse::json(r#"{"canned": true}"#))
+    }
+  }
+
+  #[test]
+  fn test_stack_runs_outermost_first() {
+    let stack: Vec<Box<dyn Middleware>> = vec![Box::new(Tag("outer")), Box::new(Tag("inner"))];
+
+    let response = run_stack(&stack, PreparedRequest::default(), &|req| {
+      let order: Vec<&str> = req.headers.iter().map(|h| h.1.as_str()).collect();
+      assert_eq!(order, vec!["outer", "inner"]);
+      Ok(RawResponse::json("{}"))
+    }).unwrap();
+    assert_eq!(response.status, 200);
+  }
+
+  #[test]
+  fn test_middleware_can_short_circuit() {
+    let stack: Vec<Box<dyn Middleware>> = vec![Box::new(LoggingMiddleware), Box::new(Canned)];
+
+    let response = run_stack(&stack, PreparedRequest::default(),
+      &|_| panic!("the transport should not be reached")).unwrap();
+    assert_eq!(response.body, br#"{"canned": true}"#.to_vec());
+  }
+}
@@ -0,0 +1,355 @@
+
+#![allow(dead_code)]
+
+// external imports
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// internal imports
+use config::Args;
+
+////////////////////////////////////////////////////////////////////////////////
+/// MockResponse and RecordedRequest struct definitions
+/// A tiny http server used by the unit tests so that the curl paths can be
+/// exercised without ever talking to reddit
+#[derive(Clone, Debug)]
+pub struct MockResponse {
+  pub status : u16,
+  pub headers : Vec<(String, String)>,
+  pub body : Vec<u8>,
+  pub delay : Option<Duration>,
+  /// when set the body goes out a byte at a time, this far apart
+  pub trickle : Option<Duration>,
+  /// drop the connection without answering, see MockResponse::reset
+  pub reset : bool,
+  /// answer with the request's own body, see MockResponse::echo
+  pub echo : bool,
+}
+
+impl MockResponse {
+  /// a 200 response carrying a json body
+  pub fn json(body: &str) -> MockResponse {
+    MockResponse::status(200, body)
+      .header("Content-Type", "application/json; charset=UTF-8")
+  }
+
+  /// a response with an arbitrary status code
+  pub fn status(status: u16, body: &str) -> MockResponse {
+    MockResponse {
+      status,
+      headers : Vec::new(),
+      body : body.as_bytes().to_vec(),
+      delay : None,
+      trickle : None,
+      reset : false,
+      echo : false,
+    }
+  }
+
+  /// holds the response back for a while before sending it
+  pub fn delayed(mut self, delay: Duration) -> MockResponse {
+    self.delay = Some(delay);
+    self
+  }
+
+  /// Closes the connection with the request still unread, which makes the
+  /// kernel answer with a RST; the client sees a connection reset. The
+  /// request is not recorded.
+  pub fn reset() -> MockResponse {
+    MockResponse { reset : true, ..MockResponse::status(0, "") }
+  }
+
+  /// A 200 carrying the request's body and Content-Type straight back, for
+  /// checking what was uploaded; only MockServer::start echoes
+  pub fn echo() -> MockResponse {
+    MockResponse { echo : true, ..MockResponse::status(200, "") }
+  }
+
+  /// sends the body one byte at a time, `interval` apart, like a stalled
+  /// connection that never quite goes quiet
+  pub fn trickled(mut self, interval: Duration) -> MockResponse {
+    self.trickle = Some(interval);
+    self
+  }
+
+  /// appends a response header
+  pub fn header(mut self, name: &str, value: &str) -> MockResponse {
+    self.headers.push((name.to_owned(), value.to_owned()));
+    self
+  }
+}
+
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+  pub method : String,
+  pub path : String,
+  pub headers : Vec<(String, String)>,
+  pub body : Vec<u8>,
+}
+
+impl RecordedRequest {
+  /// case-insensitive lookup of the first header with this name
+  pub fn header(&self, name: &str) -> Option<String> {
+    self.headers.iter()
+      .find(|h| h.0.eq_ignore_ascii_case(name))
+      .map(|h| h.1.clone())
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// MockServer
+/// Serves the given responses in order, one per connection, then shuts down
+pub struct MockServer {
+  pub base_url : String,
+  requests : Arc<Mutex<Vec<RecordedRequest>>>,
+  peak : Arc<AtomicUsize>,
+  connections : Arc<AtomicUsize>,
+}
+
+impl MockServer {
+  pub fn start(responses: Vec<MockResponse>) -> MockServer {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorder = requests.clone();
+
+    thread::spawn(move || {
+      for response in responses {
+        let mut stream = match listener.accept() {
+          Ok((stream, _)) => stream,
+          Err(_) => return,
+        };
+
+        if response.reset {
+          // give the request time to arrive, so it is left unread
+          thread::sleep(Duration::from_millis(50));
+          drop(stream);
+          continue;
+        }
+        let mut response = response;
+        if let Some(request) = read_request(&mut stream) {
+          if response.echo {
+            if let Some(content_type) = request.header("Content-Type") {
+              response = response.header("Content-Type", &content_type);
+            }
+            response.body = request.body.clone();
+          }
+          recorder.lock().unwrap().push(request);
+        }
+
+        if let Some(delay) = response.delay {
+          thread::sleep(delay);
+        }
+        send_response(&mut stream, &response, false);
+      }
+    });
+
+    MockServer {
+      base_url,
+      requests,
+      peak : Arc::new(AtomicUsize::new(1)),
+      connections : Arc::new(AtomicUsize::new(0)),
+    }
+  }
+
+  /// Like start, but keeps each connection open between responses the way
+  /// reddit does, so connection reuse can be observed through connections
+  pub fn start_keep_alive(responses: Vec<MockResponse>) -> MockServer {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorder = requests.clone();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+
+    thread::spawn(move || {
+      let mut responses = responses.into_iter().peekable();
+      while responses.peek().is_some() {
+        let mut stream = match listener.accept() {
+          Ok((stream, _)) => stream,
+          Err(_) => return,
+        };
+        counter.fetch_add(1, Ordering::SeqCst);
+
+        // answer on this connection until the client hangs up
+        while let Some(request) = read_request(&mut stream) {
+          recorder.lock().unwrap().push(request);
+          match responses.next() {
+            Some(response) => send_response(&mut stream, &response, true),
+            None => return,
+          }
+        }
+      }
+    });
+
+    MockServer { base_url, requests, peak : Arc::new(AtomicUsize::new(1)), connections }
+  }
+
+  /// how many connections start_keep_alive accepted so far
+  pub fn connections(&self) -> usize {
+    self.connections.load(Ordering::SeqCst)
+  }
+
+  /// Like start, but answers every connection on its own thread so that
+  /// overlapping requests can be observed through peak_concurrency.
+  /// Responses are still handed out in the order connections arrive.
+  pub fn start_concurrent(responses: Vec<MockResponse>) -> MockServer {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorder = requests.clone();
+    let peak = Arc::new(AtomicUsize::new(0));
+    let peak_recorder = peak.clone();
+    let active = Arc::new(AtomicUsize::new(0));
+
+    thread::spawn(move || {
+      for response in responses {
+        let mut stream = match listener.accept() {
+          Ok((stream, _)) => stream,
+          Err(_) => return,
+        };
+        let recorder = recorder.clone();
+        let peak = peak_recorder.clone();
+        let active = active.clone();
+
+        thread::spawn(move || {
+          let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+          peak.fetch_max(now, Ordering::SeqCst);
+
+          if let Some(request) = read_request(&mut stream) {
+            recorder.lock().unwrap().push(request);
+          }
+          if let Some(delay) = response.delay {
+            thread::sleep(delay);
+          }
+
+          // counted as done before replying, so the client can't have
+          // moved on to its next connection while this one still counts
+          active.fetch_sub(1, Ordering::SeqCst);
+          send_response(&mut stream, &response, false);
+        });
+      }
+    });
+
+    MockServer { base_url, requests, peak, connections : Arc::new(AtomicUsize::new(0)) }
+  }
+
+  /// the most requests that were being handled at the same time
+  pub fn peak_concurrency(&self) -> usize {
+    self.peak.load(Ordering::SeqCst)
+  }
+
+  /// every request the server has seen so far, in arrival order
+  pub fn requests(&self) -> Vec<RecordedRequest> {
+    self.requests.lock().unwrap().clone()
+  }
+
+  /// Args::default with every public request going to this server
+  pub fn args(&self) -> Args {
+    Args { base_url : self.base_url.clone(), ..Args::default() }
+  }
+
+  /// Args for the authenticated endpoints, with the token "token" and the
+  /// oauth host pointed here; credentials may go over the plain http
+  pub fn oauth_args(&self) -> Args {
+    Args {
+      key : "token".to_owned(),
+      oauth_url : self.base_url.clone(),
+      danger_allow_insecure : true,
+      ..Args::default()
+    }
+  }
+}
+
+fn send_response<S: Write>(stream: &mut S, response: &MockResponse, keep_alive: bool) {
+  let rendered = render_response(response, keep_alive);
+  match response.trickle {
+    Some(interval) => {
+      let body_start = rendered.len() - response.body.len();
+      let _ = stream.write_all(&rendered[..body_start]);
+      for byte in &rendered[body_start..] {
+        let _ = stream.flush();
+        thread::sleep(interval);
+        if stream.write_all(&[*byte]).is_err() {
+          return;
+        }
+      }
+    },
+    None => {
+      let _ = stream.write_all(&rendered);
+    },
+  }
+  let _ = stream.flush();
+}
+
+fn render_response(response: &MockResponse, keep_alive: bool) -> Vec<u8> {
+  let mut head = format!("HTTP/1.1 {} Mock\r\n", response.status);
+  for (name, value) in &response.headers {
+    head.push_str(&format!("{}: {}\r\n", name, value));
+  }
+  head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+  if keep_alive {
+    head.push_str("\r\n");
+  } else {
+    head.push_str("Connection: close\r\n\r\n");
+  }
+
+  let mut out = head.into_bytes();
+  out.extend_from_slice(&response.body);
+  out
+}
+
+fn read_request<S: Read>(stream: &mut S) -> Option<RecordedRequest> {
+  let mut raw: Vec<u8> = Vec::new();
+  let mut buf = [0u8; 4096];
+
+  let head_end = loop {
+    if let Some(pos) = find(&raw, b"\r\n\r\n") {
+      break pos;
+    }
+    match stream.read(&mut buf) {
+      Ok(0) | Err(_) => return None,
+      Ok(n) => raw.extend_from_slice(&buf[..n]),
+    }
+  };
+
+  let head = String::from_utf8_lossy(&raw[..head_end]).into_owned();
+  let mut lines = head.split("\r\n");
+  let mut request_line = lines.next().unwrap_or("").split(' ');
+  let method = request_line.next().unwrap_or("").to_owned();
+  let path = request_line.next().unwrap_or("").to_owned();
+
+  let headers: Vec<(String, String)> = lines
+    .filter_map(|line| {
+      let mut parts = line.splitn(2, ':');
+      match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) =>
+          Some((name.trim().to_owned(), value.trim().to_owned())),
+        _ => None,
+      }
+    })
+    .collect();
+
+  let length = headers.iter()
+    .find(|h| h.0.eq_ignore_ascii_case("Content-Length"))
+    .and_then(|h| h.1.parse::<usize>().ok())
+    .unwrap_or(0);
+
+  let mut body = raw[head_end + 4..].to_vec();
+  while body.len() < length {
+    match stream.read(&mut buf) {
+      Ok(0) | Err(_) => break,
+      Ok(n) => body.extend_from_slice(&buf[..n]),
+    }
+  }
+
+  Some(RecordedRequest { method, path, headers, body })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+  haystack.windows(needle.len()).position(|w| w == needle)
+}
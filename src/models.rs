@@ -0,0 +1,1153 @@
+
+// external imports
+use serde_json;
+use serde_json::Value;
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer};
+use serde::de::DeserializeOwned;
+
+// internal imports
+use error::RedditError;
+
+////////////////////////////////////////////////////////////////////////////////
+// JSON shapes
+// Listings are objects, comment pages are arrays; indexing the wrong one
+// quietly yields Null, so parsers check what they got first
+
+/// the kind of json value, as it appears in RedditError::UnexpectedShape
+pub fn json_shape(value: &Value) -> &'static str {
+  match *value {
+    Value::Null => "null",
+    Value::Bool(_) => "bool",
+    Value::Number(_) => "number",
+    Value::String(_) => "string",
+    Value::Array(_) => "array",
+    Value::Object(_) => "object",
+  }
+}
+
+/// fails with RedditError::UnexpectedShape unless the value is an object
+pub fn expect_object(value: &Value) -> Result<(), RedditError> {
+  expect_shape(value, "object", value.is_object())
+}
+
+/// fails with RedditError::UnexpectedShape unless the value is an array
+pub fn expect_array(value: &Value) -> Result<(), RedditError> {
+  expect_shape(value, "array", value.is_array())
+}
+
+fn expect_shape(value: &Value, expected: &'static str, ok: bool) -> Result<(), RedditError> {
+  if !ok {
+    return Err(RedditError::UnexpectedShape { expected, got : json_shape(value) });
+  }
+  Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// FromChild
+/// Listing children arrive wrapped as {"kind": .., "data": ..}; anything
+/// that can be pulled out of that wrapper can live in a Listing
+pub trait FromChild: Sized {
+  fn from_child(child: Value) -> Result<Self, serde_json::Error>;
+}
+
+/// implements FromChild for a struct that holds a child's "data" object
+macro_rules! from_child_data {
+  ( $($t:ty),* ) => {
+    $(
+      impl FromChild for $t {
+        fn from_child(mut child: Value) -> Result<Self, serde_json::Error> {
+          serde_json::from_value(child["data"].take())
+        }
+      }
+    )*
+  };
+}
+
+impl FromChild for Value {
+  fn from_child(child: Value) -> Result<Self, serde_json::Error> {
+    Ok(child)
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Listing
+/// The paginated envelope reddit wraps around almost every collection
+#[derive(Debug)]
+pub struct Listing<T> {
+  pub after : Option<String>,
+  pub before : Option<String>,
+  pub children : Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct ListingEnvelope {
+  data : ListingData,
+}
+
+#[derive(Deserialize)]
+struct ListingData {
+  #[serde(default, deserialize_with = "empty_as_none")]
+  after : Option<String>,
+  #[serde(default, deserialize_with = "empty_as_none")]
+  before : Option<String>,
+  #[serde(default)]
+  children : Vec<Value>,
+}
+
+/// reddit marks the end of a listing with null, but an empty string has
+/// been seen too; both mean there is no further page
+fn empty_as_none<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+  where D: Deserializer<'de>
+{
+  let token = Option::<String>::deserialize(deserializer)?;
+  Ok(token.filter(|t| !t.is_empty()))
+}
+
+impl<'de, T: FromChild> Deserialize<'de> for Listing<T> {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de>
+  {
+    use serde::de::Error;
+
+    let envelope = ListingEnvelope::deserialize(deserializer)?;
+    let children = envelope.data.children.into_iter()
+      .map(T::from_child)
+      .collect::<Result<Vec<T>, serde_json::Error>>()
+      .map_err(D::Error::custom)?;
+
+    Ok(Listing {
+      after : envelope.data.after,
+      before : envelope.data.before,
+      children,
+    })
+  }
+}
+
+impl<T> Listing<T> {
+  pub fn len(&self) -> usize {
+    self.children.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.children.is_empty()
+  }
+
+  /// the children in order, same as iterating over &listing
+  pub fn iter(&self) -> ::std::slice::Iter<'_, T> {
+    self.children.iter()
+  }
+}
+
+/// iterating a listing walks its children
+impl<T> IntoIterator for Listing<T> {
+  type Item = T;
+  type IntoIter = ::std::vec::IntoIter<T>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.children.into_iter()
+  }
+}
+
+impl<'a, T> IntoIterator for &'a Listing<T> {
+  type Item = &'a T;
+  type IntoIter = ::std::slice::Iter<'a, T>;
+
+  fn into_iter(self) -> Self::IntoIter {
+    self.children.iter()
+  }
+}
+
+impl<T: FromChild> Listing<T> {
+  /// Types a listing that was already fetched as a Value, e.g. by
+  /// api::path_query, without asking reddit again
+  pub fn from_value(value: Value) -> Result<Listing<T>, RedditError> {
+    expect_object(&value)?;
+    Ok(serde_json::from_value(value)?)
+  }
+}
+
+/// Pulls a thing of the given kind out of a Value, which may be wrapped as
+/// {"kind": .., "data": ..} or be the bare data object
+fn thing_from_value<T: DeserializeOwned>(mut value: Value, kind: &str)
+  -> Result<T, RedditError>
+{
+  use serde::de::Error;
+
+  if value.get("kind").is_some() && value.get("data").is_some() {
+    if value["kind"] != kind {
+      return Err(serde_json::Error::custom(
+        format!("expected a {} but got a {}", kind, value["kind"])).into());
+    }
+    value = value["data"].take();
+  }
+  Ok(serde_json::from_value(value)?)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Post (t3) and Comment (t1)
+/// Only the commonly used fields are modeled, everything else is ignored
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Post {
+  pub id : String,
+  pub name : String,
+  pub title : String,
+  pub author : String,
+  /// display name of the subreddit the post was made in, e.g. "rust"
+  pub subreddit : String,
+  /// fullname of that subreddit, e.g. "t5_2s7lj"
+  pub subreddit_id : String,
+  #[serde(deserialize_with = "number_or_string")]
+  pub score : i64,
+  /// share of the votes that were upvotes, 0 to 1; 1 when reddit leaves
+  /// it out, as if there were only upvotes
+  #[serde(deserialize_with = "number_or_string")]
+  pub upvote_ratio : f64,
+  pub permalink : String,
+  pub url : String,
+  pub selftext : String,
+  #[serde(deserialize_with = "number_or_string")]
+  pub num_comments : u64,
+  #[serde(deserialize_with = "number_or_string")]
+  pub created_utc : f64,
+  pub stickied : bool,
+  pub locked : bool,
+  /// marked NSFW
+  pub over_18 : bool,
+  /// "moderator", "admin" or "special" when the post is distinguished
+  pub distinguished : Option<String>,
+  /// only present when the request was made with args.sr_detail
+  pub sr_detail : Option<SubredditInfo>,
+  /// the original post when this one is a crosspost
+  #[serde(rename(deserialize = "crosspost_parent_list"), deserialize_with = "crosspost_parent")]
+  pub crosspost_parent : Option<Box<Post>>,
+}
+
+/// how many crossposts of crossposts are followed before the chain is cut
+const MAX_CROSSPOST_DEPTH: usize = 3;
+
+/// reddit nests the original post as the first entry of
+/// crosspost_parent_list; chains longer than MAX_CROSSPOST_DEPTH are cut
+fn crosspost_parent<'de, D>(deserializer: D) -> Result<Option<Box<Post>>, D::Error>
+  where D: Deserializer<'de>
+{
+  use serde::de::Error;
+
+  let mut parent = match Value::deserialize(deserializer)? {
+    Value::Array(mut list) if !list.is_empty() => list.swap_remove(0),
+    _ => return Ok(None),
+  };
+
+  cut_crossposts(&mut parent, MAX_CROSSPOST_DEPTH - 1);
+  serde_json::from_value(parent).map(|post: Post| Some(Box::new(post))).map_err(D::Error::custom)
+}
+
+/// drops the crosspost_parent_list found `depth` levels below a post
+fn cut_crossposts(post: &mut Value, depth: usize) {
+  if depth == 0 {
+    if let Some(data) = post.as_object_mut() {
+      data.remove("crosspost_parent_list");
+    }
+  } else if let Some(next) = post.get_mut("crosspost_parent_list").and_then(|l| l.get_mut(0)) {
+    cut_crossposts(next, depth - 1);
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Comment {
+  pub id : String,
+  pub name : String,
+  /// "[deleted]" once the account is gone
+  #[serde(deserialize_with = "null_as_default")]
+  pub author : String,
+  /// empty when reddit sends null, as it does for some removed comments
+  #[serde(deserialize_with = "null_as_default")]
+  pub body : String,
+  #[serde(deserialize_with = "number_or_string")]
+  pub score : i64,
+  pub link_id : String,
+  pub parent_id : String,
+  #[serde(deserialize_with = "number_or_string")]
+  pub created_utc : f64,
+  /// replies that came with the thread
+  #[serde(deserialize_with = "comment_replies")]
+  pub replies : CommentTree,
+}
+
+impl Post {
+  /// a post from a t3 Value, with or without its kind/data wrapper
+  pub fn from_value(value: Value) -> Result<Post, RedditError> {
+    thing_from_value(value, "t3")
+  }
+
+  /// Comments per upvote. The score is counted as at least 1, so posts at
+  /// zero or below don't divide by zero.
+  pub fn engagement(&self) -> f64 {
+    self.num_comments as f64 / self.score.max(1) as f64
+  }
+}
+
+/// Written out rather than derived so a default post, like one parsed
+/// without an upvote_ratio, has a ratio of 1
+impl Default for Post {
+  fn default() -> Post {
+    Post {
+      id : String::new(),
+      name : String::new(),
+      title : String::new(),
+      author : String::new(),
+      subreddit : String::new(),
+      subreddit_id : String::new(),
+      score : 0,
+      upvote_ratio : 1.0,
+      permalink : String::new(),
+      url : String::new(),
+      selftext : String::new(),
+      num_comments : 0,
+      created_utc : 0.0,
+      stickied : false,
+      locked : false,
+      over_18 : false,
+      distinguished : None,
+      sr_detail : None,
+      crosspost_parent : None,
+    }
+  }
+}
+
+impl Comment {
+  /// a comment from a t1 Value, with or without its kind/data wrapper
+  pub fn from_value(value: Value) -> Result<Comment, RedditError> {
+    thing_from_value(value, "t1")
+  }
+
+  /// whether the comment or the account that wrote it was deleted
+  pub fn is_deleted(&self) -> bool {
+    self.author == "[deleted]" || self.body == "[deleted]" || self.body == "[removed]"
+  }
+}
+
+/// a null where a value is expected reads as the type's default
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+  where D: Deserializer<'de>, T: Deserialize<'de> + Default
+{
+  Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// A "load more comments" stub standing in for comments reddit left out of
+/// a thread; the ids in children can be fetched with api::more_children
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct MoreComments {
+  pub id : String,
+  pub name : String,
+  pub parent_id : String,
+  pub count : u64,
+  pub depth : u32,
+  pub children : Vec<String>,
+}
+
+impl MoreComments {
+  /// Whether this is a "continue this thread" link rather than a list of
+  /// ids: it has no children and hangs off a comment, so the rest has to
+  /// come from api::continue_thread instead of api::more_children
+  pub fn is_continue_thread(&self) -> bool {
+    self.children.is_empty() && self.parent_id.starts_with("t1_")
+  }
+}
+
+/// One level of a comment thread: the comments, and stubs for the ones
+/// reddit did not send
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct CommentTree {
+  pub comments : Vec<Comment>,
+  pub more : Vec<MoreComments>,
+}
+
+/// Some responses (old archived posts, embeds) carry numbers as strings
+/// such as "42"; both forms are accepted, anything non-numeric is an error
+fn number_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+  where D: Deserializer<'de>,
+        T: FromStr + Deserialize<'de>,
+        T::Err: Display
+{
+  use serde::de::Error;
+
+  match Value::deserialize(deserializer)? {
+    Value::String(text) => text.trim().parse().map_err(D::Error::custom),
+    other => T::deserialize(other).map_err(D::Error::custom),
+  }
+}
+
+/// reddit sends "" for a comment without replies and a listing otherwise
+fn comment_replies<'de, D>(deserializer: D) -> Result<CommentTree, D::Error>
+  where D: Deserializer<'de>
+{
+  use serde::de::Error;
+
+  match Value::deserialize(deserializer)? {
+    Value::Object(listing) => {
+      let listing: Listing<Value> = serde_json::from_value(Value::Object(listing))
+        .map_err(D::Error::custom)?;
+      comment_tree(listing).map_err(D::Error::custom)
+    },
+    _ => Ok(CommentTree::default()),
+  }
+}
+
+/// how many levels of replies comment_tree builds before cutting a thread off
+pub const MAX_COMMENT_DEPTH: u32 = 32;
+
+/// sorts the t1 and "more" children of a listing into a CommentTree,
+/// anything else is skipped; see comment_tree_to_depth
+pub fn comment_tree(listing: Listing<Value>) -> Result<CommentTree, serde_json::Error> {
+  comment_tree_to_depth(listing, MAX_COMMENT_DEPTH)
+}
+
+/// Like comment_tree, but keeps at most `max_depth` levels of comments (the
+/// top level always stays). Replies below that are not parsed, their ids go
+/// into a MoreComments stub under their parent so api::more_children can
+/// still fetch them; a hostile thread can't recurse any deeper than this.
+pub fn comment_tree_to_depth(listing: Listing<Value>, max_depth: u32)
+  -> Result<CommentTree, serde_json::Error>
+{
+  build_comment_tree(listing, 0, max_depth)
+}
+
+fn build_comment_tree(listing: Listing<Value>, depth: u32, max_depth: u32)
+  -> Result<CommentTree, serde_json::Error>
+{
+  let mut tree = CommentTree::default();
+  for mut child in listing.children {
+    match child["kind"].as_str() {
+      Some("t1") => {
+        // replies are handled here rather than by serde, to keep count of the depth
+        let replies = child.pointer_mut("/data/replies").map(Value::take);
+        let mut comment = Comment::from_child(child)?;
+        if let Some(replies @ Value::Object(_)) = replies {
+          let replies: Listing<Value> = serde_json::from_value(replies)?;
+          if depth + 1 < max_depth {
+            comment.replies = build_comment_tree(replies, depth + 1, max_depth)?;
+          } else if let Some(stub) = cut_off_replies(&comment, &replies, depth + 1) {
+            comment.replies.more.push(stub);
+          }
+        }
+        tree.comments.push(comment);
+      },
+      Some("more") => tree.more.push(MoreComments::from_child(child)?),
+      _ => (),
+    }
+  }
+  Ok(tree)
+}
+
+/// a "more" stub holding the ids of replies that were too deep to parse
+fn cut_off_replies(parent: &Comment, replies: &Listing<Value>, depth: u32)
+  -> Option<MoreComments>
+{
+  let mut children = Vec::new();
+  for reply in &replies.children {
+    let data = &reply["data"];
+    match reply["kind"].as_str() {
+      Some("t1") => children.extend(data["id"].as_str().map(|id| id.to_owned())),
+      Some("more") => children.extend(data["children"].as_array().into_iter().flatten()
+        .filter_map(|id| id.as_str().map(|id| id.to_owned()))),
+      _ => (),
+    }
+  }
+
+  let first = children.first()?.clone();
+  Some(MoreComments {
+    name : format!("t1_{}", first),
+    id : first,
+    parent_id : parent.name.clone(),
+    count : children.len() as u64,
+    depth,
+    children,
+  })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// SubredditInfo (t5)
+/// The subreddit "about" data, also embedded in posts as sr_detail
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SubredditInfo {
+  pub name : String,
+  pub display_name : String,
+  pub title : String,
+  pub public_description : String,
+  pub subscribers : u64,
+  pub over18 : bool,
+  pub url : String,
+  pub icon_img : String,
+}
+
+/// the name most of reddit's docs use for a t5
+pub type Subreddit = SubredditInfo;
+
+////////////////////////////////////////////////////////////////////////////////
+/// WikiPage
+/// A subreddit wiki page as markdown, with who last changed it and when
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct WikiPage {
+  pub content_md : String,
+  /// None for a page reddit has no revision for
+  pub revision_date : Option<f64>,
+  /// name of the account behind the latest revision
+  #[serde(deserialize_with = "account_name")]
+  pub revision_by : Option<String>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Rule
+/// One of a subreddit's rules, from /r/<sub>/about/rules
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Rule {
+  pub short_name : String,
+  /// markdown
+  pub description : String,
+  /// what the rule applies to: "link", "comment" or "all"
+  pub kind : String,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// FlairTemplate
+/// One of the link flairs a subreddit offers, from /api/link_flair_v2
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct FlairTemplate {
+  pub id : String,
+  pub text : String,
+  pub css_class : String,
+  /// whether the poster may change the text when picking this flair
+  #[serde(rename = "text_editable")]
+  pub editable : bool,
+}
+
+/// reddit embeds the whole t2 account, only its name is kept
+fn account_name<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+  where D: Deserializer<'de>
+{
+  let account = Value::deserialize(deserializer)?;
+  Ok(account["data"]["name"].as_str().map(|name| name.to_owned()))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Account (t2)
+/// A user's public profile. Suspended accounts come back as little more
+/// than {"name": .., "is_suspended": true}, so every field may be missing.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Account {
+  pub id : String,
+  pub name : String,
+  pub link_karma : i64,
+  pub comment_karma : i64,
+  pub total_karma : i64,
+  pub awarder_karma : i64,
+  pub awardee_karma : i64,
+  pub is_suspended : bool,
+  pub is_gold : bool,
+  pub is_mod : bool,
+  pub verified : bool,
+  pub icon_img : String,
+  pub created_utc : Option<f64>,
+}
+
+impl Account {
+  /// An account from a t2 Value. /user/x/about wraps it as {"kind": "t2",
+  /// "data": ..} while /api/v1/me sends the bare object; both are accepted.
+  pub fn from_value(value: Value) -> Result<Account, RedditError> {
+    thing_from_value(value, "t2")
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Trophy (t6)
+/// One of the trophies on a user's profile, e.g. "Verified Email"
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Trophy {
+  pub name : String,
+  /// e.g. "3 years" on a Reddit Premium trophy, None for most
+  pub description : Option<String>,
+  pub icon_70 : String,
+  pub icon_40 : String,
+  /// None for trophies every account can earn, like the age ones
+  pub award_id : Option<String>,
+  pub url : Option<String>,
+  pub granted_at : Option<f64>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Message (t4)
+/// A private message. Replies to the account's posts and comments share the
+/// inbox with these but come as t1 comments, see Thing.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Message {
+  pub id : String,
+  pub name : String,
+  /// empty for messages sent on behalf of a subreddit
+  #[serde(deserialize_with = "null_as_default")]
+  pub author : String,
+  pub dest : String,
+  pub subject : String,
+  pub body : String,
+  /// whether the message is still unread
+  pub new : bool,
+  /// the fullname of the message this one answers, None for the first
+  pub parent_id : Option<String>,
+  /// set on modmail and messages sent as a subreddit
+  pub subreddit : Option<String>,
+  #[serde(deserialize_with = "number_or_string")]
+  pub created_utc : f64,
+}
+
+from_child_data!(Post, Comment, MoreComments, SubredditInfo, Account, WikiPage, Trophy, Message);
+
+////////////////////////////////////////////////////////////////////////////////
+/// Multireddit (LabeledMulti)
+/// A user's named collection of subreddits, read through api::multi_info
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Multireddit {
+  pub name : String,
+  pub display_name : String,
+  /// the description as markdown
+  pub description_md : String,
+  pub owner : String,
+  /// "public", "private" or "hidden"
+  pub visibility : String,
+  /// e.g. "/user/ferris/m/rustlang/"
+  pub path : String,
+  /// the names of the subreddits in it; reddit sends [{"name": ..}, ..]
+  #[serde(deserialize_with = "subreddit_names")]
+  pub subreddits : Vec<String>,
+  #[serde(deserialize_with = "number_or_string")]
+  pub created_utc : f64,
+}
+
+impl Multireddit {
+  /// A multireddit from /api/multi's {"kind": "LabeledMulti", "data": ..}
+  pub fn from_value(value: Value) -> Result<Multireddit, RedditError> {
+    thing_from_value(value, "LabeledMulti")
+  }
+}
+
+fn subreddit_names<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+  where D: Deserializer<'de>
+{
+  #[derive(Deserialize)]
+  struct Entry {
+    name : String,
+  }
+  Ok(Vec::<Entry>::deserialize(deserializer)?.into_iter().map(|entry| entry.name).collect())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Thing
+/// A listing child of any kind, keyed on the "kind" field
+/// Kinds this crate does not model yet come back as Thing::Other
+// listings are mostly posts, boxing them would only add an allocation each
+#[allow(clippy::large_enum_variant)]
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", content = "data")]
+pub enum Thing {
+  #[serde(rename = "t1")]
+  Comment(Comment),
+  #[serde(rename = "t3")]
+  Post(Post),
+  #[serde(rename = "t4")]
+  Message(Message),
+  #[serde(rename = "more")]
+  More(MoreComments),
+  Other,
+}
+
+impl Thing {
+  /// the fullname (e.g. "t3_abc123") of the thing, if it has one
+  pub fn fullname(&self) -> Option<&str> {
+    match *self {
+      Thing::Comment(ref comment) => Some(&comment.name),
+      Thing::Post(ref post) => Some(&post.name),
+      Thing::Message(ref message) => Some(&message.name),
+      Thing::More(ref more) => Some(&more.name),
+      Thing::Other => None,
+    }
+  }
+}
+
+impl FromChild for Thing {
+  fn from_child(mut child: Value) -> Result<Self, serde_json::Error> {
+    let data = child["data"].take();
+    Ok(match child["kind"].as_str() {
+      Some("t1") => Thing::Comment(serde_json::from_value(data)?),
+      Some("t3") => Thing::Post(serde_json::from_value(data)?),
+      Some("t4") => Thing::Message(serde_json::from_value(data)?),
+      Some("more") => Thing::More(serde_json::from_value(data)?),
+      _ => Thing::Other,
+    })
+  }
+}
+
+impl<'de> Deserialize<'de> for Thing {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: Deserializer<'de>
+  {
+    use serde::de::Error;
+    Thing::from_child(Value::deserialize(deserializer)?).map_err(D::Error::custom)
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Kind
+/// The type prefixes reddit puts in front of ids to form fullnames
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Kind {
+  Comment,
+  Account,
+  Link,
+  Message,
+  Subreddit,
+  Award,
+}
+
+/// Tells what a fullname such as "t3_abc123" refers to from its prefix
+pub fn fullname_kind(fullname: &str) -> Option<Kind> {
+  let (prefix, id) = fullname.split_once('_')?;
+  if id.is_empty() {
+    return None;
+  }
+
+  match prefix {
+    "t1" => Some(Kind::Comment),
+    "t2" => Some(Kind::Account),
+    "t3" => Some(Kind::Link),
+    "t4" => Some(Kind::Message),
+    "t5" => Some(Kind::Subreddit),
+    "t6" => Some(Kind::Award),
+    _ => None,
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Fullname
+/// A fullname checked to have a known prefix and an alphanumeric id, so a
+/// comment id can not end up where a post was meant
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Fullname {
+  name : String,
+  kind : Kind,
+}
+
+impl Fullname {
+  /// the fullname of the post with this id, e.g. "abc123" gives "t3_abc123"
+  pub fn post(id: &str) -> Result<Fullname, RedditError> {
+    format!("t3_{}", id).parse()
+  }
+
+  /// the fullname of the comment with this id
+  pub fn comment(id: &str) -> Result<Fullname, RedditError> {
+    format!("t1_{}", id).parse()
+  }
+
+  pub fn kind(&self) -> Kind {
+    self.kind
+  }
+
+  /// the id without its type prefix
+  pub fn id(&self) -> &str {
+    &self.name[3..]
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.name
+  }
+}
+
+impl FromStr for Fullname {
+  type Err = RedditError;
+
+  fn from_str(name: &str) -> Result<Fullname, RedditError> {
+    let id_ok = name.get(3..).is_some_and(|id| id.chars().all(|c| c.is_ascii_alphanumeric()));
+    match fullname_kind(name) {
+      Some(kind) if id_ok => Ok(Fullname { name : name.to_owned(), kind }),
+      _ => Err(RedditError::InvalidName(name.to_owned())),
+    }
+  }
+}
+
+impl fmt::Display for Fullname {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&self.name)
+  }
+}
+
+impl AsRef<str> for Fullname {
+  fn as_ref(&self) -> &str {
+    &self.name
+  }
+}
+
+impl From<Fullname> for String {
+  fn from(fullname: Fullname) -> String {
+    fullname.name
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_models {
+
+  use error::RedditError;
+  use models::*;
+  use serde_json;
+  use serde_json::Value;
+
+  const MIXED: &str = r#"{
+    "kind": "Listing",
+    "data": {
+      "after": "t1_c2",
+      "before": null,
+      "children": [
+        {"kind": "t3", "data": {"id": "p1", "name": "t3_p1", "title": "hello"}},
+        {"kind": "t1", "data": {"id": "c2", "name": "t1_c2", "body": "world"}},
+        {"kind": "t9", "data": {"id": "x"}}
+      ]
+    }
+  }"#;
+
+  #[test]
+  fn test_listing_of_things() {
+    let listing: Listing<Thing> = serde_json::from_str(MIXED).unwrap();
+
+    assert_eq!(listing.after, Some("t1_c2".to_owned()));
+    assert_eq!(listing.before, None);
+    assert_eq!(listing.children.len(), 3);
+
+    let names: Vec<Option<&str>> = listing.children.iter()
+      .map(|thing| thing.fullname())
+      .collect();
+    assert_eq!(names, vec![Some("t3_p1"), Some("t1_c2"), None]);
+  }
+
+  #[test]
+  fn test_listing_into_iter() {
+    let listing: Listing<Thing> = serde_json::from_str(MIXED).unwrap();
+    assert_eq!(listing.len(), 3);
+    assert!(!listing.is_empty());
+
+    let mut borrowed = Vec::new();
+    for thing in &listing {
+      borrowed.push(thing.fullname());
+    }
+    assert_eq!(borrowed, vec![Some("t3_p1"), Some("t1_c2"), None]);
+    assert_eq!(listing.iter().count(), 3);
+
+    let mut posts = 0;
+    for thing in listing {
+      if let Thing::Post(post) = thing {
+        assert_eq!(post.title, "hello");
+        posts += 1;
+      }
+    }
+    assert_eq!(posts, 1);
+
+    let empty: Listing<Post> = serde_json::from_str(
+      r#"{"kind": "Listing", "data": {"children": []}}"#).unwrap();
+    assert!(empty.is_empty());
+    assert_eq!(empty.into_iter().count(), 0);
+  }
+
+  #[test]
+  fn test_from_value() {
+    let value: Value = serde_json::from_str(MIXED).unwrap();
+    let listing = Listing::<Value>::from_value(value).unwrap();
+    assert_eq!(listing.after, Some("t1_c2".to_owned()));
+
+    let mut children = listing.children.into_iter();
+    let post = children.next().unwrap();
+    assert_eq!(post["kind"], "t3");
+    assert_eq!(Post::from_value(post.clone()).unwrap().title, "hello");
+    assert_eq!(Post::from_value(post["data"].clone()).unwrap().title, "hello");
+    assert_eq!(Comment::from_value(children.next().unwrap()).unwrap().body, "world");
+
+    assert!(Comment::from_value(post).is_err());
+  }
+
+  #[test]
+  fn test_from_value_malformed() {
+    use error::RedditError;
+
+    let value: Value = serde_json::from_str(
+      r#"{"kind": "Listing", "data": {"children": {"not": "an array"}}}"#).unwrap();
+    let err = Listing::<Post>::from_value(value).unwrap_err();
+    assert!(matches!(err, RedditError::Json { .. }));
+
+    let value: Value = serde_json::from_str(r#"{"kind": "t3", "data": {"score": "lots"}}"#)
+      .unwrap();
+    assert!(matches!(Post::from_value(value), Err(RedditError::Json { .. })));
+  }
+
+  #[test]
+  fn test_listing_of_posts() {
+    let json = r#"{"kind": "Listing", "data": {"after": null, "before": null,
+      "children": [{"kind": "t3", "data": {"title": "hi", "score": 5}}]}}"#;
+    let listing: Listing<Post> = serde_json::from_str(json).unwrap();
+
+    assert_eq!(listing.children[0].title, "hi");
+    assert_eq!(listing.children[0].score, 5);
+  }
+
+  #[test]
+  fn test_numbers_sent_as_strings() {
+    let json = r#"{"kind": "Listing", "data": {"after": null, "before": null,
+      "children": [
+        {"kind": "t3", "data": {"score": 42, "num_comments": 7, "created_utc": 1500000000.0}},
+        {"kind": "t3", "data": {"score": "42", "num_comments": "7",
+          "created_utc": "1500000000.0"}}
+      ]}}"#;
+    let listing: Listing<Post> = serde_json::from_str(json).unwrap();
+
+    let (number, string) = (&listing.children[0], &listing.children[1]);
+    assert_eq!(number.score, 42);
+    assert_eq!(string.score, number.score);
+    assert_eq!(string.num_comments, number.num_comments);
+    assert_eq!(string.created_utc, number.created_utc);
+
+    let comment: Comment = serde_json::from_str(r#"{"score": "-3"}"#).unwrap();
+    assert_eq!(comment.score, -3);
+
+    assert!(serde_json::from_str::<Post>(r#"{"score": "lots"}"#).is_err());
+  }
+
+  #[test]
+  fn test_post_engagement() {
+    let posts: Listing<Post> = serde_json::from_str(r#"{"kind": "Listing", "data": {
+      "children": [
+        {"kind": "t3", "data": {"score": 200, "num_comments": 50, "upvote_ratio": 0.87}},
+        {"kind": "t3", "data": {"score": 0, "num_comments": 12, "upvote_ratio": 0.5}},
+        {"kind": "t3", "data": {"score": 0, "num_comments": 0}}
+      ]}}"#).unwrap();
+
+    let popular = &posts.children[0];
+    assert_eq!(popular.upvote_ratio, 0.87);
+    assert_eq!(popular.engagement(), 0.25);
+
+    let controversial = &posts.children[1];
+    assert_eq!(controversial.engagement(), 12.0);
+
+    let quiet = &posts.children[2];
+    assert_eq!(quiet.upvote_ratio, 1.0);
+    assert_eq!(quiet.engagement(), 0.0);
+    assert_eq!(Post::default().upvote_ratio, 1.0);
+  }
+
+  #[test]
+  fn test_post_subreddit() {
+    let json = r#"{"kind": "Listing", "data": {"after": null, "before": null,
+      "children": [{"kind": "t3", "data": {"title": "hi", "subreddit": "rust",
+        "subreddit_id": "t5_2s7lj", "subreddit_name_prefixed": "r/rust"}}]}}"#;
+    let listing: Listing<Post> = serde_json::from_str(json).unwrap();
+
+    assert_eq!(listing.children[0].subreddit, "rust");
+    assert_eq!(listing.children[0].subreddit_id, "t5_2s7lj");
+    assert_eq!(fullname_kind(&listing.children[0].subreddit_id), Some(Kind::Subreddit));
+  }
+
+  #[test]
+  fn test_crosspost_parent() {
+    let json = r#"{"kind": "Listing", "data": {"after": null, "before": null,
+      "children": [
+        {"kind": "t3", "data": {"name": "t3_xpost", "title": "look at this",
+          "crosspost_parent": "t3_orig",
+          "crosspost_parent_list": [{"name": "t3_orig", "title": "original",
+            "subreddit": "rust", "score": 900}]}},
+        {"kind": "t3", "data": {"name": "t3_plain", "crosspost_parent_list": []}}
+      ]}}"#;
+    let listing: Listing<Post> = serde_json::from_str(json).unwrap();
+
+    let parent = listing.children[0].crosspost_parent.as_ref().unwrap();
+    assert_eq!(parent.name, "t3_orig");
+    assert_eq!(parent.subreddit, "rust");
+    assert_eq!(parent.score, 900);
+    assert!(parent.crosspost_parent.is_none());
+    assert!(listing.children[1].crosspost_parent.is_none());
+  }
+
+  #[test]
+  fn test_crosspost_chain_is_cut() {
+    let mut data = r#"{"name": "t3_0"}"#.to_owned();
+    for level in 1..10 {
+      data = format!(r#"{{"name": "t3_{}", "crosspost_parent_list": [{}]}}"#, level, data);
+    }
+    let post: Post = serde_json::from_str(&data).unwrap();
+
+    let mut depth = 0;
+    let mut current = &post;
+    while let Some(ref parent) = current.crosspost_parent {
+      current = parent;
+      depth += 1;
+    }
+    assert_eq!(depth, 3);
+    assert_eq!(current.name, "t3_6");
+  }
+
+  #[test]
+  fn test_post_sr_detail() {
+    let json = r#"{"kind": "Listing", "data": {"after": null, "before": null,
+      "children": [
+        {"kind": "t3", "data": {"title": "with detail",
+          "sr_detail": {"display_name": "rust", "subscribers": 300000,
+                        "title": "The Rust Programming Language"}}},
+        {"kind": "t3", "data": {"title": "without detail"}}
+      ]}}"#;
+    let listing: Listing<Post> = serde_json::from_str(json).unwrap();
+
+    let detail = listing.children[0].sr_detail.as_ref().unwrap();
+    assert_eq!(detail.display_name, "rust");
+    assert_eq!(detail.subscribers, 300000);
+    assert!(listing.children[1].sr_detail.is_none());
+  }
+
+  #[test]
+  fn test_post_flags() {
+    let json = r#"{"kind": "Listing", "data": {"after": null, "before": null,
+      "children": [
+        {"kind": "t3", "data": {"title": "rules", "stickied": true, "locked": true,
+          "distinguished": "moderator"}},
+        {"kind": "t3", "data": {"title": "regular", "distinguished": null}}
+      ]}}"#;
+    let listing: Listing<Post> = serde_json::from_str(json).unwrap();
+
+    let rules = &listing.children[0];
+    assert!(rules.stickied && rules.locked);
+    assert_eq!(rules.distinguished, Some("moderator".to_owned()));
+
+    let regular = &listing.children[1];
+    assert!(!regular.stickied && !regular.locked);
+    assert_eq!(regular.distinguished, None);
+  }
+
+  #[test]
+  fn test_listing_null_and_empty_tokens() {
+    let null: Listing<Value> = serde_json::from_str(
+      r#"{"kind": "Listing", "data": {"after": null, "before": null, "children": []}}"#)
+      .unwrap();
+    assert_eq!(null.after, None);
+    assert_eq!(null.before, None);
+
+    let empty: Listing<Value> = serde_json::from_str(
+      r#"{"kind": "Listing", "data": {"after": "", "before": "", "children": []}}"#)
+      .unwrap();
+    assert_eq!(empty.after, None);
+    assert_eq!(empty.before, None);
+
+    let missing: Listing<Value> = serde_json::from_str(
+      r#"{"kind": "Listing", "data": {"children": []}}"#)
+      .unwrap();
+    assert_eq!(missing.after, None);
+  }
+
+  #[test]
+  fn test_more_comments_continue_thread() {
+    let stub: MoreComments = serde_json::from_str(
+      r#"{"id": "_", "name": "t1__", "parent_id": "t1_c9", "count": 3, "depth": 10,
+          "children": []}"#).unwrap();
+    assert!(stub.is_continue_thread());
+
+    let more: MoreComments = serde_json::from_str(
+      r#"{"id": "c3", "name": "t1_c3", "parent_id": "t1_c9", "count": 2,
+          "children": ["c3", "c4"]}"#).unwrap();
+    assert!(!more.is_continue_thread());
+
+    let empty_top_level = MoreComments { parent_id : "t3_abc".to_owned(), ..more };
+    assert!(!MoreComments { children : Vec::new(), ..empty_top_level }.is_continue_thread());
+  }
+
+  #[test]
+  fn test_account_from_either_shape() {
+    let about = serde_json::json!({"kind": "t2", "data": {"name": "archiver", "link_karma": 5}});
+    let me = serde_json::json!({"name": "archiver", "link_karma": 5});
+
+    for value in [about, me] {
+      let account = Account::from_value(value).unwrap();
+      assert_eq!((account.name.as_str(), account.link_karma), ("archiver", 5));
+    }
+    assert!(Account::from_value(serde_json::json!({"kind": "t5", "data": {}})).is_err());
+  }
+
+  #[test]
+  fn test_listing_from_array_is_unexpected_shape() {
+    use error::RedditError;
+
+    let err = Listing::<Post>::from_value(serde_json::json!([{"kind": "Listing"}])).unwrap_err();
+    assert!(matches!(err, RedditError::UnexpectedShape { expected: "object", got: "array" }));
+    assert_eq!(format!("{}", err), "expected a json object, got a json array");
+  }
+
+  #[test]
+  fn test_comment_tree_stops_at_max_depth() {
+    // a single chain of replies, 100 levels deep
+    let mut chain = Value::String(String::new());
+    for level in (0..100).rev() {
+      chain = serde_json::json!({"kind": "Listing", "data": {"children": [
+        {"kind": "t1", "data": {"id": format!("c{}", level), "name": format!("t1_c{}", level),
+          "replies": chain}}]}});
+    }
+    let listing: Listing<Value> = serde_json::from_value(chain).unwrap();
+
+    let tree = comment_tree_to_depth(listing, 3).unwrap();
+    let second = &tree.comments[0].replies.comments[0];
+    let third = &second.replies.comments[0];
+    assert_eq!(third.name, "t1_c2");
+    assert!(third.replies.comments.is_empty());
+
+    let stub = &third.replies.more[0];
+    assert_eq!(stub.parent_id, "t1_c2");
+    assert_eq!(stub.children, vec!["c3".to_owned()]);
+    assert_eq!(stub.depth, 3);
+  }
+
+  #[test]
+  fn test_fullname_kind() {
+    assert_eq!(fullname_kind("t1_c0ffee"), Some(Kind::Comment));
+    assert_eq!(fullname_kind("t2_1w72"), Some(Kind::Account));
+    assert_eq!(fullname_kind("t3_abc123"), Some(Kind::Link));
+    assert_eq!(fullname_kind("t4_xyz"), Some(Kind::Message));
+    assert_eq!(fullname_kind("t5_2qh0u"), Some(Kind::Subreddit));
+    assert_eq!(fullname_kind("t6_gold"), Some(Kind::Award));
+
+    for invalid in &["", "abc123", "t7_abc", "t3_", "T3_abc", "t3abc"] {
+      assert_eq!(fullname_kind(invalid), None, "{:?}", invalid);
+    }
+  }
+
+  #[test]
+  fn test_fullname_validation() {
+    let post = Fullname::post("abc123").unwrap();
+    assert_eq!(post.as_str(), "t3_abc123");
+    assert_eq!(post.kind(), Kind::Link);
+    assert_eq!(post.id(), "abc123");
+    assert_eq!(Fullname::comment("c0ffee").unwrap().kind(), Kind::Comment);
+
+    let parsed: Fullname = "t5_2qh0u".parse().unwrap();
+    assert_eq!(parsed.kind(), Kind::Subreddit);
+    assert_eq!(parsed.to_string(), "t5_2qh0u");
+    assert_eq!(String::from(parsed), "t5_2qh0u");
+
+    for invalid in &["", "abc123", "t9_abc", "t3_", "t3_abc/def", "t3_a b", "t3__x"] {
+      assert!(matches!(invalid.parse::<Fullname>(), Err(RedditError::InvalidName(_))),
+        "{:?}", invalid);
+    }
+    assert!(Fullname::post("t3_abc").is_err());
+  }
+}
@@ -0,0 +1,188 @@
+
+// external imports
+use std::env;
+use std::str::FromStr;
+use serde_json::{self, Value};
+
+// internal imports
+use error::RedditError;
+
+////////////////////////////////////////////////////////////////////////////////
+/// Format
+/// How a response is printed: compact json as reddit sent it, indented
+/// json, or a table of the posts in a listing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+  #[default]
+  Json,
+  Pretty,
+  Table,
+}
+
+/// parses "json", "pretty" or "table", as --format takes them
+impl FromStr for Format {
+  type Err = RedditError;
+
+  fn from_str(s: &str) -> Result<Format, RedditError> {
+    match s.to_ascii_lowercase().as_str() {
+      "json" => Ok(Format::Json),
+      "pretty" => Ok(Format::Pretty),
+      "table" => Ok(Format::Table),
+      _ => Err(RedditError::InvalidArgument(
+        format!("unknown format {:?}, try json, pretty or table", s))),
+    }
+  }
+}
+
+/// the width tables fit into when $COLUMNS doesn't say
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// titles are never cut shorter than this, however narrow the terminal
+const MIN_TITLE_WIDTH: usize = 10;
+
+/// Renders a response in a format. Tables fit the terminal width from
+/// $COLUMNS; anything that isn't a listing comes out as pretty json.
+pub fn render(value: &Value, format: Format) -> String {
+  match format {
+    Format::Json => value.to_string(),
+    Format::Pretty => pretty(value),
+    Format::Table => {
+      let width = env::var("COLUMNS").ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .unwrap_or(DEFAULT_WIDTH);
+      render_table(value, width).unwrap_or_else(|| pretty(value))
+    },
+  }
+}
+
+fn pretty(value: &Value) -> String {
+  serde_json::to_string_pretty(value).unwrap_or_else(|_| value.to_string())
+}
+
+/// One row per child of a listing: score, author, title and permalink,
+/// with titles cut to fit `width`. None when the value isn't a listing.
+/// Children without an author, such as promoted posts, show "-", and
+/// comments show their body in place of a title.
+pub fn render_table(value: &Value, width: usize) -> Option<String> {
+  if value["kind"] != "Listing" {
+    return None;
+  }
+  let children = value["data"]["children"].as_array()?;
+
+  let rows: Vec<[String; 4]> = children.iter()
+    .map(|child| {
+      let data = &child["data"];
+      let text = |key: &str| data[key].as_str().filter(|s| !s.is_empty());
+      let title = text("title").or_else(|| text("body")).unwrap_or("");
+      [
+        data["score"].as_i64().map(|score| score.to_string()).unwrap_or_else(|| "-".to_owned()),
+        text("author").unwrap_or("-").to_owned(),
+        title.lines().next().unwrap_or("").to_owned(),
+        text("permalink").unwrap_or("").to_owned(),
+      ]
+    })
+    .collect();
+
+  let header = ["SCORE", "AUTHOR", "TITLE", "PERMALINK"];
+  let column = |index: usize| rows.iter()
+    .map(|row| row[index].chars().count())
+    .chain(Some(header[index].len()))
+    .max()
+    .unwrap_or(0);
+  let (score, author, permalink) = (column(0), column(1), column(3));
+  let fixed = score + author + permalink + 6;
+  let title = column(2).min(width.saturating_sub(fixed).max(MIN_TITLE_WIDTH));
+
+  let mut out = String::new();
+  let header = header.iter().map(|cell| cell.to_string()).collect::<Vec<String>>();
+  for row in Some(&header[..]).into_iter().chain(rows.iter().map(|row| &row[..])) {
+    let line = format!("{:>score$}  {:<author$}  {:<title$}  {}",
+      row[0], row[1], truncate(&row[2], title), row[3],
+      score = score, author = author, title = title);
+    out.push_str(line.trim_end());
+    out.push('\n');
+  }
+  Some(out)
+}
+
+/// cuts text to `width` characters, ending in "..." when anything was cut
+fn truncate(text: &str, width: usize) -> String {
+  if text.chars().count() <= width {
+    return text.to_owned();
+  }
+  let kept: String = text.chars().take(width.saturating_sub(3)).collect();
+  format!("{}...", kept)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_output {
+
+  use output::*;
+  use serde_json::{self, Value};
+
+  const LISTING: &str = r#"{"kind": "Listing", "data": {"children": [
+    {"kind": "t3", "data": {"score": 1234, "author": "ferris",
+      "title": "Announcing Rust 1.0 and a long title", "permalink": "/r/rust/a/"}},
+    {"kind": "t3", "data": {"score": 7, "title": "Try our product", "permalink": "/r/rust/b/"}},
+    {"kind": "t1", "data": {"score": -2, "author": "crab", "body": "first!\nsecond line",
+      "permalink": "/r/rust/a/c/"}}]}}"#;
+
+  fn listing() -> Value {
+    serde_json::from_str(LISTING).unwrap()
+  }
+
+  #[test]
+  fn test_table_golden() {
+    assert_eq!(render_table(&listing(), 80).unwrap(), concat!(
+      "SCORE  AUTHOR  TITLE                                 PERMALINK\n",
+      " 1234  ferris  Announcing Rust 1.0 and a long title  /r/rust/a/\n",
+      "    7  -       Try our product                       /r/rust/b/\n",
+      "   -2  crab    first!                                /r/rust/a/c/\n",
+    ));
+  }
+
+  #[test]
+  fn test_table_cuts_titles_to_width() {
+    assert_eq!(render_table(&listing(), 50).unwrap(), concat!(
+      "SCORE  AUTHOR  TITLE                  PERMALINK\n",
+      " 1234  ferris  Announcing Rust 1....  /r/rust/a/\n",
+      "    7  -       Try our product        /r/rust/b/\n",
+      "   -2  crab    first!                 /r/rust/a/c/\n",
+    ));
+    assert!(render_table(&listing(), 50).unwrap().lines().all(|line| line.len() <= 50));
+
+    // however narrow, titles keep a few characters
+    assert!(render_table(&listing(), 10).unwrap().contains(" Announc...  "));
+  }
+
+  #[test]
+  fn test_json_and_pretty() {
+    let value: Value = serde_json::from_str(r#"{"a": [1, 2]}"#).unwrap();
+    assert_eq!(render(&value, Format::Json), r#"{"a":[1,2]}"#);
+    assert_eq!(render(&value, Format::Pretty), "{\n  \"a\": [\n    1,\n    2\n  ]\n}");
+  }
+
+  #[test]
+  fn test_table_falls_back_to_pretty() {
+    let about: Value = serde_json::from_str(r#"{"kind": "t5", "data": {}}"#).unwrap();
+    assert_eq!(render_table(&about, 80), None);
+    assert_eq!(render(&about, Format::Table), render(&about, Format::Pretty));
+
+    let broken: Value = serde_json::from_str(r#"{"kind": "Listing", "data": {}}"#).unwrap();
+    assert_eq!(render(&broken, Format::Table), render(&broken, Format::Pretty));
+
+    let empty: Value = serde_json::from_str(
+      r#"{"kind": "Listing", "data": {"children": []}}"#).unwrap();
+    assert_eq!(render_table(&empty, 80).unwrap(), "SCORE  AUTHOR  TITLE  PERMALINK\n");
+  }
+
+  #[test]
+  fn test_format_from_str() {
+    assert_eq!("pretty".parse::<Format>().unwrap(), Format::Pretty);
+    assert_eq!("TABLE".parse::<Format>().unwrap(), Format::Table);
+    assert!("yaml".parse::<Format>().is_err());
+    assert_eq!(Format::default(), Format::Json);
+  }
+}
@@ -0,0 +1,510 @@
+// external imports
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+use curl;
+use log;
+use curl::easy::{Easy, HttpVersion};
+
+// internal imports
+use api::{header_list, setup_connection, transfer_response, Response};
+use config::Args;
+use error::RedditError;
+
+////////////////////////////////////////////////////////////////////////////////
+/// PreparedRequest and RawResponse
+/// What goes over the wire and what comes back, with nothing reddit
+/// specific left in them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PreparedRequest {
+  pub method : String,
+  pub url : String,
+  pub headers : Vec<(String, String)>,
+  pub body : Vec<u8>,
+}
+
+impl PreparedRequest {
+  pub fn get(url: &str, headers: Vec<(String, String)>) -> PreparedRequest {
+    PreparedRequest {
+      method : "GET".to_owned(),
+      url : url.to_owned(),
+      headers,
+      body : Vec::new(),
+    }
+  }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RawResponse {
+  pub status : u32,
+  pub headers : Vec<(String, String)>,
+  /// the url after redirects, empty when the transport doesn't know it
+  pub effective_url : String,
+  pub body : Vec<u8>,
+}
+
+impl RawResponse {
+  /// a 200 with a json content type, the usual answer from reddit
+  pub fn json(body: &str) -> RawResponse {
+    RawResponse {
+      status : 200,
+      headers : vec![("Content-Type".to_owned(), "application/json".to_owned())],
+      body : body.as_bytes().to_vec(),
+      ..RawResponse::default()
+    }
+  }
+
+  /// case-insensitive lookup of the first header with this name
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self.headers.iter()
+      .find(|h| h.0.eq_ignore_ascii_case(name))
+      .map(|h| h.1.as_str())
+  }
+
+  /// decodes the body, giving the Response the request helpers work with
+  pub fn into_response(self) -> Result<Response, RedditError> {
+    let body = String::from_utf8(self.body)
+      .map_err(|err| RedditError::Utf8(err.utf8_error()))?;
+    Ok(Response {
+      status : self.status,
+      headers : self.headers,
+      raw_headers : Vec::new(),
+      effective_url : self.effective_url,
+      body,
+    })
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// Transport
+/// Carries out a prepared http request. RedditClient only deals with
+/// reddit semantics and leaves the http mechanics to one of these.
+pub trait Transport {
+  fn execute(&self, req: &PreparedRequest) -> Result<RawResponse, RedditError>;
+
+  /// execute, with connection settings (timeouts and the like) taken from
+  /// `args` for this one request; transports without any ignore them
+  fn execute_with(&self, req: &PreparedRequest, _args: &Args)
+    -> Result<RawResponse, RedditError>
+  {
+    self.execute(req)
+  }
+
+  /// persists whatever state the transport keeps, such as cookies, to
+  /// where `args` says
+  fn flush(&self, _args: &Args) -> Result<(), RedditError> {
+    Ok(())
+  }
+}
+
+/// The default transport, a single curl handle so connections are reused
+/// between requests. Redirects are followed when args.follow_redirects, see
+/// api::setup_connection.
+pub struct CurlTransport {
+  easy : RefCell<Easy>,
+  args : Args,
+  http2 : bool,
+  cancel : Option<Arc<AtomicBool>>,
+}
+
+impl CurlTransport {
+  /// connection settings (timeout, fresh_connect) are taken from args
+  pub fn new(args: &Args) -> CurlTransport {
+    CurlTransport {
+      easy : RefCell::new(Easy::new()),
+      args : args.clone(),
+      http2 : false,
+      cancel : None,
+    }
+  }
+
+  /// A flag of the transport's own that stops its transfers once set, the
+  /// same as args.cancel; both are checked, so neither replaces the other
+  pub fn set_cancel(&mut self, cancel: Arc<AtomicBool>) {
+    self.cancel = Some(cancel);
+  }
+
+  /// Offers HTTP/2 on https connections, see RedditClient::http2. Ignored
+  /// when the linked libcurl was built without HTTP/2 support.
+  pub fn set_http2(&mut self, enable: bool) {
+    self.http2 = enable;
+  }
+
+  /// whether requests will actually offer HTTP/2
+  pub fn uses_http2(&self) -> bool {
+    self.http2 && curl::Version::get().feature_http2()
+  }
+}
+
+impl Transport for CurlTransport {
+  fn execute(&self, req: &PreparedRequest) -> Result<RawResponse, RedditError> {
+    self.execute_with(req, &self.args)
+  }
+
+  fn execute_with(&self, req: &PreparedRequest, args: &Args)
+    -> Result<RawResponse, RedditError>
+  {
+    let mut easy = self.easy.borrow_mut();
+    // reset clears what the previous request set but keeps its connections
+    easy.reset();
+    setup_connection(&mut *easy, args)?;
+    easy.url(&req.url)?;
+    easy.http_headers(header_list(&req.headers)?)?;
+    if let Some(ref jar) = args.cookie_jar {
+      // the handle keeps its cookies across reset, the file only seeds them
+      easy.cookie_file(jar)?;
+      easy.cookie_jar(jar)?;
+    }
+    if self.uses_http2() {
+      easy.http_version(HttpVersion::V2TLS)?;
+    }
+    match req.method.as_str() {
+      "GET" => (),
+      "POST" => easy.post(true)?,
+      method => easy.custom_request(method)?,
+    }
+    if !req.body.is_empty() {
+      easy.post_fields_copy(&req.body)?;
+    }
+
+    log_request(&req.method, &req.url, Some(&req.headers), args);
+    let started = Instant::now();
+    let cancel: Vec<&AtomicBool> = args.cancel.as_deref().into_iter()
+      .chain(self.cancel.as_deref())
+      .collect();
+    let result = transfer_response(&mut easy, false, &cancel, None)
+      .map(RawResponse::from);
+    log_response(&req.method, &req.url,
+      result.as_ref().map(|response| (response.status, &response.body[..])),
+      started.elapsed(), args);
+    report_complete(args, req, &result, started.elapsed());
+    result
+  }
+
+  /// writes the handle's cookies to args.cookie_jar, when one is set
+  fn flush(&self, args: &Args) -> Result<(), RedditError> {
+    if let Some(ref jar) = args.cookie_jar {
+      let mut easy = self.easy.borrow_mut();
+      easy.cookie_jar(jar)?;
+      easy.cookie_list("FLUSH")?;
+    }
+    Ok(())
+  }
+}
+
+impl From<Response> for RawResponse {
+  fn from(response: Response) -> RawResponse {
+    RawResponse {
+      status : response.status,
+      headers : response.headers,
+      effective_url : response.effective_url,
+      body : response.body.into_bytes(),
+    }
+  }
+}
+
+/// Hands a finished transfer to args.on_complete. The callback is outside
+/// code, so a panic in it is logged and swallowed rather than taking the
+/// request down with it.
+pub(crate) fn report_complete(args: &Args, req: &PreparedRequest,
+  result: &Result<RawResponse, RedditError>, elapsed: Duration)
+{
+  if let Some(ref hook) = args.on_complete {
+    if catch_unwind(AssertUnwindSafe(|| hook(req, result, elapsed))).is_err() {
+      warn!("args.on_complete panicked for {} {}", req.method, redact(&req.url, args));
+    }
+  }
+}
+
+/// headers whose values are credentials and never make it into a log line
+const SECRET_HEADERS: [&str; 4] = ["Authorization", "Proxy-Authorization", "Cookie", "Set-Cookie"];
+
+/// json fields reddit hands credentials back in, e.g. from access_token
+const SECRET_FIELDS: [&str; 2] = ["access_token", "refresh_token"];
+
+/// "Name: value" lines for a log line, credentials redacted
+pub(crate) fn loggable_headers(headers: &[(String, String)]) -> String {
+  headers.iter()
+    .map(|(name, value)| {
+      let secret = SECRET_HEADERS.iter().any(|s| s.eq_ignore_ascii_case(name.trim()));
+      format!("{}: {}", name.trim(), if secret { "<redacted>" } else { value.trim() })
+    })
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// a log line with args.key, and any token reddit sent back in a json
+/// body, replaced by <redacted>
+pub(crate) fn redact(line: &str, args: &Args) -> String {
+  let mut line = if args.key.is_empty() {
+    line.to_owned()
+  } else {
+    line.replace(&args.key, "<redacted>")
+  };
+  for field in &SECRET_FIELDS {
+    let quoted = format!("\"{}\"", field);
+    let mut from = 0;
+    while let Some(found) = line[from..].find(&quoted) {
+      let after = from + found + quoted.len();
+      // only a string value is a token; null and friends are left alone
+      let rest = line[after..].trim_start();
+      let value = rest.strip_prefix(':').map(str::trim_start)
+        .filter(|value| value.starts_with('"'))
+        .map(|value| line.len() - value.len() + 1);
+      let end = value.and_then(|start| line[start..].find('"').map(|len| start + len));
+      match (value, end) {
+        (Some(start), Some(end)) => {
+          line.replace_range(start..end, "<redacted>");
+          from = start;
+        },
+        (Some(_), None) => break,
+        _ => from = after,
+      }
+    }
+  }
+  line
+}
+
+/// Logs a request about to go out: the url and, when known, its headers
+pub(crate) fn log_request(method: &str, url: &str, headers: Option<&[(String, String)]>,
+  args: &Args)
+{
+  if !log_enabled!(log::Level::Debug) {
+    return;
+  }
+  let line = match headers {
+    Some(headers) => format!("{} {} [{}]", method, url, loggable_headers(headers)),
+    None => format!("{} {}", method, url),
+  };
+  debug!("{}", redact(&line, args));
+}
+
+/// Logs how a request went: a warning for failures and non-2xx statuses,
+/// the start of the body, up to args.log_body_limit bytes, at trace level
+pub(crate) fn log_response(method: &str, url: &str, outcome: Result<(u32, &[u8]), &RedditError>,
+  elapsed: Duration, args: &Args)
+{
+  match outcome {
+    Err(err) => warn!("{}", redact(&format!("{} {} failed after {:?}: {}",
+      method, url, elapsed, err), args)),
+    Ok((status, _)) if !(200..300).contains(&status) =>
+      warn!("{}", redact(&format!("{} {} answered http {} in {:?}",
+        method, url, status, elapsed), args)),
+    Ok((status, _)) =>
+      debug!("{}", redact(&format!("{} {} answered http {} in {:?}",
+        method, url, status, elapsed), args)),
+  }
+  if let Ok((_, body)) = outcome {
+    if log_enabled!(log::Level::Trace) {
+      let shown = &body[..body.len().min(args.log_body_limit)];
+      let cut = if shown.len() < body.len() { "..." } else { "" };
+      trace!("{}", redact(&format!("{} {} body: {}{}",
+        method, url, String::from_utf8_lossy(shown), cut), args));
+    }
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+/// MockTransport
+/// Answers with canned responses, in order, without touching the network.
+/// Clones share the same state, so one copy can be handed to a client while
+/// the test keeps another to look at the requests that were made.
+#[derive(Clone, Debug, Default)]
+pub struct MockTransport {
+  responses : Arc<Mutex<VecDeque<RawResponse>>>,
+  requests : Arc<Mutex<Vec<PreparedRequest>>>,
+}
+
+impl MockTransport {
+  pub fn new(responses: Vec<RawResponse>) -> MockTransport {
+    MockTransport {
+      responses : Arc::new(Mutex::new(responses.into_iter().collect())),
+      requests : Arc::new(Mutex::new(Vec::new())),
+    }
+  }
+
+  /// every request executed so far, oldest first
+  pub fn requests(&self) -> Vec<PreparedRequest> {
+    self.requests.lock().unwrap().clone()
+  }
+}
+
+impl Transport for MockTransport {
+  /// panics once the canned responses run out, which in a test means more
+  /// requests were made than expected
+  fn execute(&self, req: &PreparedRequest) -> Result<RawResponse, RedditError> {
+    self.requests.lock().unwrap().push(req.clone());
+    let response = self.responses.lock().unwrap().pop_front();
+    Ok(response.unwrap_or_else(|| panic!("no canned response left for {}", req.url)))
+  }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod test_transport {
+
+  use transport::*;
+  use config::Args;
+  use log::{self, LevelFilter, Log, Metadata, Record};
+  use mock::{MockServer, MockResponse};
+  use std::sync::{Mutex, Once};
+
+  #[test]
+  fn test_curl_transport_round_trip() {
+    let server = MockServer::start(vec![
+      MockResponse::json(r#"{"ok": true}"#).header("X-Test", "yes"),
+    ]);
+    let transport = CurlTransport::new(&Args::default());
+
+    let request = PreparedRequest::get(
+      &format!("{}/r/rust/.json", server.base_url),
+      vec![("User-Agent".to_owned(), "transport-test".to_owned())]);
+    let response = transport.execute(&request).unwrap();
+
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("x-test"), Some("yes"));
+    assert_eq!(response.body, br#"{"ok": true}"#.to_vec());
+
+    let recorded = &server.requests()[0];
+    assert_eq!(recorded.method, "GET");
+    assert_eq!(recorded.path, "/r/rust/.json");
+    assert_eq!(recorded.header("User-Agent").unwrap(), "transport-test");
+  }
+
+  #[test]
+  fn test_on_complete_sees_success_and_failure() {
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    let server = MockServer::start(vec![MockResponse::json("{}")]);
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let log = seen.clone();
+    let args = Args {
+      on_complete : Some(Arc::new(move |req: &PreparedRequest, result: &Result<_, _>, _| {
+        log.lock().unwrap().push((req.url.clone(), result.is_ok()));
+      })),
+      ..Args::default()
+    };
+    let transport = CurlTransport::new(&args);
+
+    let ok_url = format!("{}/ok", server.base_url);
+    transport.execute(&PreparedRequest::get(&ok_url, Vec::new())).unwrap();
+
+    let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let failed_url = format!("http://{}/down", closed);
+    assert!(transport.execute(&PreparedRequest::get(&failed_url, Vec::new())).is_err());
+
+    assert_eq!(*seen.lock().unwrap(), vec![(ok_url, true), (failed_url, false)]);
+  }
+
+  #[test]
+  fn test_panicking_on_complete_does_not_fail_request() {
+    use std::sync::Arc;
+
+    let server = MockServer::start(vec![MockResponse::json(r#"{"ok": true}"#)]);
+    let args = Args {
+      on_complete : Some(Arc::new(|_: &PreparedRequest, _: &Result<_, _>, _| {
+        panic!("metrics backend is down")
+      })),
+      ..Args::default()
+    };
+
+    captured_lines("");
+    let request = PreparedRequest::get(&format!("{}/ok", server.base_url), Vec::new());
+    let response = CurlTransport::new(&args).execute(&request).unwrap();
+    assert_eq!(response.body, br#"{"ok": true}"#.to_vec());
+    // reported through the log facade, not printed
+    assert!(captured_lines(&server.base_url).iter()
+      .any(|l| l.starts_with("WARN args.on_complete panicked for GET")));
+  }
+
+  #[test]
+  fn test_mock_transport_replays_in_order() {
+    let transport = MockTransport::new(vec![RawResponse::json("1"), RawResponse::json("2")]);
+    let handle = transport.clone();
+
+    let request = PreparedRequest::get("https://example.invalid/a", Vec::new());
+    assert_eq!(transport.execute(&request).unwrap().body, b"1".to_vec());
+    assert_eq!(transport.execute(&request).unwrap().body, b"2".to_vec());
+    assert_eq!(handle.requests().len(), 2);
+  }
+
+  /// keeps every log record, for the redaction tests; installed once for
+  /// the whole test binary
+  struct CapturingLogger(Mutex<Vec<String>>);
+
+  impl Log for CapturingLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+      true
+    }
+
+    fn log(&self, record: &Record) {
+      self.0.lock().unwrap().push(format!("{} {}", record.level(), record.args()));
+    }
+
+    fn flush(&self) {}
+  }
+
+  static CAPTURED: CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+
+  fn captured_lines(containing: &str) -> Vec<String> {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+      log::set_logger(&CAPTURED).unwrap();
+      log::set_max_level(LevelFilter::Trace);
+    });
+    CAPTURED.0.lock().unwrap().iter().filter(|line| line.contains(containing)).cloned().collect()
+  }
+
+  #[test]
+  fn test_logging_redacts_credentials() {
+    captured_lines("");
+    let server = MockServer::start(vec![
+      MockResponse::status(401, r#"{"access_token": "minted-secret", "expires_in": 3600}"#),
+    ]);
+    let args = Args { key : "sekrit-key".to_owned(), ..Args::default() };
+    let url = format!("{}/api/v1/me?token=sekrit-key", server.base_url);
+    let request = PreparedRequest::get(&url, vec![
+      ("Authorization".to_owned(), "bearer sekrit-key".to_owned()),
+      ("User-Agent".to_owned(), "log-test".to_owned()),
+    ]);
+    CurlTransport::new(&args).execute_with(&request, &args).unwrap();
+
+    let lines = captured_lines(&server.base_url);
+    assert!(lines.iter().any(|l| l.starts_with("DEBUG") && l.contains("User-Agent: log-test")
+      && l.contains("Authorization: <redacted>")), "{:?}", lines);
+    assert!(lines.iter().any(|l| l.starts_with("WARN") && l.contains("http 401")), "{:?}", lines);
+    assert!(lines.iter().any(|l| l.starts_with("TRACE") && l.contains("expires_in")),
+      "{:?}", lines);
+    assert!(lines.iter().all(|l| !l.contains("sekrit") && !l.contains("minted")), "{:?}", lines);
+  }
+
+  #[test]
+  fn test_redact_skips_null_tokens() {
+    use transport::redact;
+
+    let args = Args::default();
+    let line = r#"{"access_token": null, "refresh_token": "abc", "scope": "read"}"#;
+    assert_eq!(redact(line, &args),
+      r#"{"access_token": null, "refresh_token": "<redacted>", "scope": "read"}"#);
+    assert_eq!(redact(r#"{"access_token":"xyz","expires_in":3600}"#, &args),
+      r#"{"access_token":"<redacted>","expires_in":3600}"#);
+    // the name showing up as a value is not a field
+    assert_eq!(redact(r#"["access_token", "kept"]"#, &args), r#"["access_token", "kept"]"#);
+  }
+
+  #[test]
+  fn test_logged_body_is_truncated() {
+    captured_lines("");
+    let server = MockServer::start(vec![MockResponse::json(r#"{"kind": "Listing"}"#)]);
+    let args = Args { log_body_limit : 8, ..Args::default() };
+    let request = PreparedRequest::get(&format!("{}/r/rust/.json", server.base_url), Vec::new());
+    CurlTransport::new(&args).execute_with(&request, &args).unwrap();
+
+    let lines = captured_lines(&server.base_url);
+    assert!(lines.iter().any(|l| l.ends_with(r#"body: {"kind":..."#)), "{:?}", lines);
+  }
+}